@@ -106,6 +106,23 @@ BOOST_AUTO_TEST_CASE(new_constraint_json_schema) {
   require_no_error(constraint.get());
 }
 
+BOOST_AUTO_TEST_CASE(new_constraint_json_custom_format) {
+  auto tokenizer = make_byte_tokenizer();
+  auto init = make_constraint_init(tokenizer.get());
+  const char *schema = R"({"type":"string","format":"sku"})";
+
+  auto unknown = make_constraint(llg_new_constraint_json(&init, schema));
+  BOOST_REQUIRE(llg_get_error(unknown.get()) != nullptr);
+
+  init.json_formats = R"({"sku":"[A-Z]{3}-[0-9]{4}"})";
+  auto constraint = make_constraint(llg_new_constraint_json(&init, schema));
+  require_no_error(constraint.get());
+
+  init.json_formats = "[]";
+  auto invalid = make_constraint(llg_new_constraint_json(&init, schema));
+  BOOST_REQUIRE(llg_get_error(invalid.get()) != nullptr);
+}
+
 BOOST_AUTO_TEST_CASE(new_constraint_lark) {
   auto tokenizer = make_byte_tokenizer();
   auto init = make_constraint_init(tokenizer.get());
@@ -115,6 +132,26 @@ BOOST_AUTO_TEST_CASE(new_constraint_lark) {
   require_no_error(constraint.get());
 }
 
+BOOST_AUTO_TEST_CASE(new_constraint_gbnf_valid) {
+  auto tokenizer = make_byte_tokenizer();
+  auto init = make_constraint_init(tokenizer.get());
+  auto constraint = make_constraint(
+      llg_new_constraint_gbnf(&init, R"(root ::= "a" [0-9]+)"));
+
+  require_no_error(constraint.get());
+}
+
+BOOST_AUTO_TEST_CASE(new_constraint_gbnf_invalid) {
+  auto tokenizer = make_byte_tokenizer();
+  auto init = make_constraint_init(tokenizer.get());
+  auto constraint =
+      make_constraint(llg_new_constraint_gbnf(&init, "root ::= foo"));
+
+  const char *error = llg_get_error(constraint.get());
+  BOOST_REQUIRE(error != nullptr);
+  BOOST_CHECK(std::strlen(error) > 0);
+}
+
 BOOST_AUTO_TEST_CASE(new_constraint_any_regex) {
   auto tokenizer = make_byte_tokenizer();
   auto init = make_constraint_init(tokenizer.get());
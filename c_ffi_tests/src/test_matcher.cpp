@@ -428,6 +428,118 @@ BOOST_AUTO_TEST_CASE(matcher_get_mask_byte_size_before_compute) {
   BOOST_CHECK(byte_size % sizeof(uint32_t) == 0);
 }
 
+BOOST_AUTO_TEST_CASE(compute_masks_draft) {
+  MatcherContext ctx;
+  auto matcher = ctx.make_matcher("regex", "ab|ac");
+
+  check_matcher_has_no_error(matcher.get());
+  const size_t mask_byte_size = llg_matcher_get_mask_byte_size(matcher.get());
+  const size_t row_elts = mask_byte_size / sizeof(uint32_t);
+  const uint32_t draft[] = {97, 98}; // 'a', 'b'
+  std::vector<uint32_t> masks(row_elts * (std::size(draft) + 1), 0);
+
+  BOOST_REQUIRE_EQUAL(
+      llg_matcher_compute_masks_draft(matcher.get(), draft, std::size(draft),
+                                      masks.data(),
+                                      masks.size() * sizeof(uint32_t)),
+      3);
+  check_matcher_has_no_error(matcher.get());
+
+  const uint32_t *row0 = masks.data();
+  const uint32_t *row1 = row0 + row_elts;
+  const uint32_t *row2 = row1 + row_elts;
+  BOOST_CHECK(mask_has_token(row0, 97));
+  BOOST_CHECK(!mask_has_token(row0, 98));
+  BOOST_CHECK(mask_has_token(row1, 98));
+  BOOST_CHECK(mask_has_token(row1, 99));
+  BOOST_CHECK(!mask_has_token(row1, 97));
+  BOOST_CHECK(mask_has_token(row2, 256)); // EOS
+  BOOST_CHECK(!mask_has_token(row2, 98));
+
+  // the matcher state is not changed
+  BOOST_REQUIRE_EQUAL(llg_matcher_compute_mask(matcher.get()), 0);
+  const uint32_t *mask = llg_matcher_get_mask(matcher.get());
+  BOOST_CHECK(mask_has_token(mask, 97));
+  BOOST_CHECK(!mask_has_token(mask, 98));
+}
+
+BOOST_AUTO_TEST_CASE(compute_masks_draft_stops_at_rejected_token) {
+  MatcherContext ctx;
+  auto matcher = ctx.make_matcher("regex", "ab|ac");
+
+  check_matcher_has_no_error(matcher.get());
+  const size_t mask_byte_size = llg_matcher_get_mask_byte_size(matcher.get());
+  const size_t row_elts = mask_byte_size / sizeof(uint32_t);
+  const uint32_t draft[] = {97, 120, 98}; // 'a', 'x', 'b'
+  std::vector<uint32_t> masks(row_elts * (std::size(draft) + 1), 0);
+
+  BOOST_REQUIRE_EQUAL(
+      llg_matcher_compute_masks_draft(matcher.get(), draft, std::size(draft),
+                                      masks.data(),
+                                      masks.size() * sizeof(uint32_t)),
+      2);
+  check_matcher_has_no_error(matcher.get());
+  BOOST_CHECK(mask_has_token(masks.data() + row_elts, 98));
+
+  // rows past the rejected token are left untouched
+  for (size_t i = 2 * row_elts; i < masks.size(); ++i) {
+    BOOST_CHECK_EQUAL(masks[i], 0u);
+  }
+}
+
+BOOST_AUTO_TEST_CASE(compute_masks_draft_buffer_too_small) {
+  MatcherContext ctx;
+  auto matcher = ctx.make_matcher("regex", "ab|ac");
+
+  check_matcher_has_no_error(matcher.get());
+  const size_t mask_byte_size = llg_matcher_get_mask_byte_size(matcher.get());
+  const uint32_t draft[] = {97, 98};
+  // room for only two of the three rows
+  std::vector<uint32_t> masks(2 * mask_byte_size / sizeof(uint32_t), 0);
+
+  BOOST_CHECK_EQUAL(
+      llg_matcher_compute_masks_draft(matcher.get(), draft, std::size(draft),
+                                      masks.data(),
+                                      masks.size() * sizeof(uint32_t)),
+      -1);
+  BOOST_CHECK(llg_matcher_is_error(matcher.get()));
+}
+
+BOOST_AUTO_TEST_CASE(validate_bytes_prefix) {
+  MatcherContext ctx;
+  auto matcher = ctx.make_matcher("regex", "[a-z]+");
+
+  check_matcher_has_no_error(matcher.get());
+  const uint8_t bytes[] = {'a', 'b', '1'};
+  BOOST_CHECK_EQUAL(
+      llg_matcher_validate_bytes(matcher.get(), bytes, std::size(bytes)), 2);
+
+  // validation does not consume anything
+  BOOST_CHECK(!llg_matcher_is_accepting(matcher.get()));
+  check_matcher_has_no_error(matcher.get());
+}
+
+BOOST_AUTO_TEST_CASE(consume_bytes_prefix) {
+  MatcherContext ctx;
+  auto matcher = ctx.make_matcher("regex", "ab[0-9]");
+
+  check_matcher_has_no_error(matcher.get());
+  const uint8_t bytes[] = {'a', 'b', 'c'};
+  BOOST_REQUIRE_EQUAL(
+      llg_matcher_consume_bytes(matcher.get(), bytes, std::size(bytes)), 2);
+  check_matcher_has_no_error(matcher.get());
+
+  BOOST_REQUIRE_EQUAL(llg_matcher_compute_mask(matcher.get()), 0);
+  const uint32_t *mask = llg_matcher_get_mask(matcher.get());
+  BOOST_CHECK(mask_has_token(mask, '5'));
+  BOOST_CHECK(!mask_has_token(mask, 'c'));
+
+  // consumed bytes are recorded as tokens and can be rolled back
+  BOOST_REQUIRE_EQUAL(llg_matcher_rollback(matcher.get(), 2), 0);
+  BOOST_REQUIRE_EQUAL(llg_matcher_compute_mask(matcher.get()), 0);
+  BOOST_CHECK(mask_has_token(llg_matcher_get_mask(matcher.get()), 'a'));
+}
+
 BOOST_AUTO_TEST_CASE(free_matcher_null) {
   llg_free_matcher(nullptr);
   BOOST_TEST(true);
@@ -2,6 +2,7 @@
 
 #include <array>
 #include <memory>
+#include <string>
 
 #include "test_helpers.h"
 #include "llguidance.h"
@@ -127,4 +128,47 @@ BOOST_AUTO_TEST_CASE(validate_unknown_type) {
   BOOST_TEST(result.message[0] != '\0');
 }
 
+BOOST_AUTO_TEST_CASE(validate_diagnostics_valid) {
+  auto tokenizer = make_byte_tokenizer();
+  BOOST_REQUIRE(tokenizer != nullptr);
+
+  const auto init = make_constraint_init(tokenizer.get());
+  std::array<char, 1024> output{};
+  const auto rc = llg_validate_grammar_diagnostics(
+      &init, "lark", R"(start: "hello")", output.data(), output.size());
+
+  BOOST_TEST(rc == 0);
+  BOOST_TEST(std::string(output.data()) == "[]");
+}
+
+BOOST_AUTO_TEST_CASE(validate_diagnostics_error) {
+  auto tokenizer = make_byte_tokenizer();
+  BOOST_REQUIRE(tokenizer != nullptr);
+
+  const auto init = make_constraint_init(tokenizer.get());
+  std::array<char, 1024> output{};
+  const auto rc = llg_validate_grammar_diagnostics(
+      &init, "lark", "start: foo", output.data(), output.size());
+
+  BOOST_TEST(rc == -1);
+  const std::string json(output.data());
+  BOOST_TEST(json.front() == '[');
+  BOOST_TEST(json.find(R"("severity":"error")") != std::string::npos);
+  BOOST_TEST(json.find(R"("span")") != std::string::npos);
+}
+
+BOOST_AUTO_TEST_CASE(validate_diagnostics_truncated) {
+  auto tokenizer = make_byte_tokenizer();
+  BOOST_REQUIRE(tokenizer != nullptr);
+
+  const auto init = make_constraint_init(tokenizer.get());
+  std::array<char, 8> output{};
+  output.fill('x');
+  const auto rc = llg_validate_grammar_diagnostics(
+      &init, "lark", "start: foo", output.data(), output.size());
+
+  BOOST_TEST(rc == -1);
+  BOOST_TEST(output.back() == '\0');
+}
+
 BOOST_AUTO_TEST_SUITE_END()
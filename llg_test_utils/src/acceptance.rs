@@ -6,7 +6,9 @@
 ///
 /// Helpers are provided for both raw Lark grammars ([`lark_str_test`],
 /// [`lark_ok`], [`lark_err_test`]) and JSON-schema grammars
-/// ([`json_schema_check`], [`json_test_many`]).
+/// ([`json_schema_check`], [`json_test_many`]).  Grammars in other formats
+/// (GBNF, ABNF) are checked byte-by-byte with [`byte_grammar_test_many`] and
+/// [`byte_grammar_err`].
 use anyhow::Result;
 use llguidance::{
     api::{GrammarInit, TopLevelGrammar},
@@ -15,7 +17,7 @@ use llguidance::{
 };
 use serde_json::Value;
 
-use crate::{get_parser_factory, get_tok_env};
+use crate::{get_byte_parser_factory, get_parser_factory, get_tok_env};
 
 // ── Types ────────────────────────────────────────────────────────────────────
 
@@ -205,3 +207,37 @@ pub fn lark_str_test_many(lark: &str, passing: &[&str], failing: &[&str]) {
 pub fn lark_str_test_many_quiet(lark: &str, passing: &[&str], failing: &[&str]) {
     lark_str_test_many_ext(true, lark, passing, failing);
 }
+
+// ── Single-byte tokenizer testing ────────────────────────────────────────────
+
+/// Check if `grm` accepts `input`, fed one byte per token.
+pub fn byte_grammar_accepts(grm: &TopLevelGrammar, input: &str) -> bool {
+    let factory = get_byte_parser_factory();
+    let mut parser = factory.create_parser(grm.clone()).unwrap();
+    parser.start_without_prompt();
+    for tok in factory.tok_env().tokenize(input) {
+        let m = parser.compute_mask().unwrap();
+        if !m.is_allowed(tok) {
+            return false;
+        }
+        consume(&mut parser, tok);
+    }
+    parser.is_accepting()
+}
+
+pub fn byte_grammar_test_many(grm: &TopLevelGrammar, passing: &[&str], failing: &[&str]) {
+    for s in passing {
+        assert!(byte_grammar_accepts(grm, s), "should accept {s:?}");
+    }
+    for s in failing {
+        assert!(!byte_grammar_accepts(grm, s), "should reject {s:?}");
+    }
+}
+
+/// Return the error from compiling `grm`; panics if it compiles.
+pub fn byte_grammar_err(grm: TopLevelGrammar) -> String {
+    match get_byte_parser_factory().create_parser(grm) {
+        Err(e) => e.to_string(),
+        Ok(_) => panic!("expected grammar error"),
+    }
+}
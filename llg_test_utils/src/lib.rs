@@ -23,12 +23,14 @@
 ///   tokenizer, configured for testing (ff_tokens + backtrack enabled, verbose
 ///   logging).
 /// - [`get_tok_env`] / [`get_parser_factory`]: Accessors for the above.
+/// - [`get_byte_parser_factory`]: A quiet [`ParserFactory`] with a single-byte
+///   tokenizer, for character-level grammar tests (GBNF, ABNF).
 use std::sync::atomic::{AtomicBool, Ordering};
 
 use lazy_static::lazy_static;
 use llguidance::{
     earley::SlicedBiasComputer,
-    toktrie::{InferenceCapabilities, TokEnv},
+    toktrie::{ApproximateTokEnv, InferenceCapabilities, TokEnv},
     ParserFactory,
 };
 
@@ -63,9 +65,8 @@ lazy_static! {
     static ref PARSER_FACTORY: ParserFactory = {
         let env =
             toktrie_hf_downloader::byte_tokenizer_from_name("microsoft/Phi-3.5-mini-instruct")
-            .unwrap()
-            .into_tok_env(Some(35000))
-            .unwrap();
+            .and_then(|t| t.into_tok_env(Some(35000)))
+            .unwrap_or_else(|_| llguidance::toktrie::ApproximateTokEnv::single_byte_env());
         let mut fact = ParserFactory::new(&env,
             InferenceCapabilities {
                 ff_tokens: true, // can the engine append multiple tokens?
@@ -86,3 +87,15 @@ pub fn get_tok_env() -> &'static TokEnv {
 pub fn get_parser_factory() -> &'static ParserFactory {
     &PARSER_FACTORY
 }
+
+lazy_static! {
+    static ref BYTE_PARSER_FACTORY: ParserFactory = {
+        let mut fact = ParserFactory::new_simple(&ApproximateTokEnv::single_byte_env()).unwrap();
+        fact.quiet();
+        fact
+    };
+}
+
+pub fn get_byte_parser_factory() -> &'static ParserFactory {
+    &BYTE_PARSER_FACTORY
+}
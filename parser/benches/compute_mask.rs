@@ -196,6 +196,49 @@ fn bench_token_generation(c: &mut Criterion) {
     group.finish();
 }
 
+/// Benchmark token generation with a token budget far from running out,
+/// compared to no budget; the budget should add little per token.
+fn bench_token_budget(c: &mut Criterion) {
+    use criterion::BatchSize;
+
+    let mut group = c.benchmark_group("token_budget");
+    let vocab_size = DEFAULT_VOCAB_SIZE;
+    let num_tokens = 20;
+
+    group.throughput(Throughput::Elements(num_tokens));
+
+    let tok_env = synthetic_tok_env(vocab_size);
+    for budget in [None, Some(1_000)] {
+        let name = match budget {
+            None => "none".to_string(),
+            Some(n) => n.to_string(),
+        };
+        group.bench_with_input(BenchmarkId::from_parameter(name), &budget, |b, &budget| {
+            b.iter_batched(
+                || {
+                    let mut m = create_matcher(&tok_env, blog_grammar(), PREFIX_IN_STRING);
+                    if let Some(n) = budget {
+                        m.set_token_budget(n).unwrap();
+                    }
+                    m
+                },
+                |mut m| {
+                    for _ in 0..num_tokens {
+                        let mask = m.compute_mask().unwrap();
+                        let tok = (b'a' as TokenId..=b'z' as TokenId)
+                            .find(|&t| mask.is_allowed(t))
+                            .unwrap_or(b'a' as TokenId);
+                        m.consume_token(tok).unwrap();
+                    }
+                    black_box(m)
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
 /// Benchmark cold-start: time to create parser and compute first mask.
 /// Important for latency-sensitive applications.
 fn bench_first_mask(c: &mut Criterion) {
@@ -338,6 +381,6 @@ criterion_group! {
         .warm_up_time(std::time::Duration::from_secs(2))
         .measurement_time(std::time::Duration::from_secs(5))
         .noise_threshold(0.05);
-    targets = bench_compute_mask, bench_compute_mask_positions, bench_token_generation, bench_first_mask, bench_lazy_lexeme, bench_lazy_lexeme_complex
+    targets = bench_compute_mask, bench_compute_mask_positions, bench_token_generation, bench_token_budget, bench_first_mask, bench_lazy_lexeme, bench_lazy_lexeme_complex
 }
 criterion_main!(benches);
@@ -165,6 +165,14 @@ typedef struct LlgConstraintInit {
    * Default values will be used for all fields that are 0.
    */
   struct LlgParserLimits limits;
+  /**
+   * Custom string formats for JSON schemas, as a JSON object mapping
+   * format names to regexes, for example `{"sku": "[A-Z]{3}-[0-9]{4}"}`.
+   * They are consulted before the built-in formats, and formats in the
+   * schema's `"x-guidance"` take precedence over these.
+   * Pass NULL to only use the built-in formats.
+   */
+  const char *json_formats;
 } LlgConstraintInit;
 
 /**
@@ -450,11 +458,20 @@ struct LlgConstraint *llg_new_constraint_json(const struct LlgConstraintInit *in
 struct LlgConstraint *llg_new_constraint_lark(const struct LlgConstraintInit *init,
                                               const char *lark);
 
+/**
+ * Create a new constraint from a GBNF grammar (as used by llama.cpp).
+ *
+ * Always returns a non-null value. Call [`llg_get_error()`] on the result to
+ * check for errors.
+ */
+struct LlgConstraint *llg_new_constraint_gbnf(const struct LlgConstraintInit *init,
+                                              const char *gbnf);
+
 /**
  * Create a new constraint with a specified type.
  *
  * `constraint_type` can be one of `"regex"`, `"json_schema"` (or `"json"`),
- * `"lark"`, `"llguidance"` (or `"guidance"`).
+ * `"lark"`, `"gbnf"`, `"abnf"`, `"llguidance"` (or `"guidance"`).
  *
  * Always returns a non-null value. Call [`llg_get_error()`] on the result to
  * check for errors.
@@ -763,6 +780,8 @@ void llg_free_stop_controller(struct LlgStopController *stop_ctrl);
  * - `"json_object"` — equivalent to JSON schema `{"type":"object"}`
  * - `"lark"` — data is a grammar in a variant of Lark syntax;
  *   see <https://github.com/guidance-ai/llguidance/blob/main/docs/syntax.md>
+ * - `"gbnf"` — data is a grammar in GBNF format (as used by llama.cpp)
+ * - `"abnf"` — data is a grammar in ABNF format (RFC 5234)
  * - `"llguidance"` or `"guidance"` — data is a list of Lark or JSON schemas
  *   in JSON format
  *
@@ -790,6 +809,28 @@ int32_t llg_validate_grammar(const struct LlgConstraintInit *init,
                              char *message,
                              size_t message_len);
 
+/**
+ * Like [`llg_validate_grammar()`], but the errors and warnings are written
+ * to `output` as a JSON array of diagnostics, each with `severity`
+ * (`"error"` or `"warning"`), `code`, `message`, and, when known,
+ * `span` (`start` and `end` byte offsets, and 1-based `line` and `column`
+ * in the grammar source), `json_pointer` into the JSON schema, and `notes`.
+ * The `code` tells the kind of the diagnostic, like `"unknown_name"` or
+ * `"unsupported_keyword"`; see `Diagnostic` in the Rust crate.
+ * Returns 0 when there are no diagnostics, −1 on error, and 1 on warning.
+ * The output is always NUL-terminated; if it does not fit in `output_len`
+ * bytes, it is truncated (and thus not valid JSON).
+ *
+ * - `constraint_type` and `data` must be valid NUL-terminated UTF-8 C strings.
+ * - `output` must point to a buffer of at least `output_len` bytes.
+ */
+LLGUIDANCE_NODISCARD
+int32_t llg_validate_grammar_diagnostics(const struct LlgConstraintInit *init,
+                                         const char *constraint_type,
+                                         const char *data,
+                                         char *output,
+                                         size_t output_len);
+
 /**
  * Compute the set of allowed tokens for the current state into a
  * caller-provided buffer.
@@ -805,6 +846,28 @@ int32_t llg_matcher_compute_mask_into(struct LlgMatcher *matcher,
                                       uint32_t *mask_dest,
                                       size_t mask_byte_len);
 
+/**
+ * Compute masks for speculative decoding into a caller-provided 2D buffer.
+ *
+ * Row 0 of `mask_dest` is the mask for the current state, and row `i` is
+ * the mask after consuming the first `i` draft tokens. Computation stops at
+ * the first rejected draft token (or when the matcher stops); the remaining
+ * rows are left untouched. The matcher state is not changed.
+ * Each row is [`llg_matcher_get_mask_byte_size()`] bytes, and `mask_byte_len`
+ * (the size of the whole buffer) must be at least `n_draft_tokens + 1` rows.
+ * Returns the number of masks written (at least 1), or −1 on error.
+ *
+ * - If `n_draft_tokens > 0`, `draft_tokens` must point to `n_draft_tokens`
+ *   valid `u32` values.
+ * - `mask_dest` must point to a buffer of at least `mask_byte_len` bytes.
+ */
+LLGUIDANCE_NODISCARD
+int32_t llg_matcher_compute_masks_draft(struct LlgMatcher *matcher,
+                                        const uint32_t *draft_tokens,
+                                        size_t n_draft_tokens,
+                                        uint32_t *mask_dest,
+                                        size_t mask_byte_len);
+
 /**
  * Compute the set of allowed tokens for the current state.
  *
@@ -908,6 +971,40 @@ int32_t llg_matcher_validate_tokens(struct LlgMatcher *matcher,
                                     const uint32_t *tokens,
                                     size_t n_tokens);
 
+/**
+ * Check how many bytes can be consumed from the given byte sequence.
+ * The bytes do not need to come from the tokenizer.
+ *
+ * Returns the length of the longest valid prefix, or −1 on error.
+ *
+ * - If `n_bytes > 0`, `bytes` must point to `n_bytes` valid bytes.
+ */
+LLGUIDANCE_NODISCARD
+int32_t llg_matcher_validate_bytes(struct LlgMatcher *matcher,
+                                   const uint8_t *bytes,
+                                   size_t n_bytes);
+
+/**
+ * Consume the longest valid prefix of the given byte sequence.
+ * The bytes do not need to come from the tokenizer; they are recorded
+ * as tokens of a greedy tokenization (e.g., for rollback), and only the tokens
+ * allowed by the grammar are consumed.
+ *
+ * Returns the length of the consumed prefix, or −1 on error.
+ * The length may be shorter than what llg_matcher_validate_bytes() returns,
+ * if some of the greedy tokens are not allowed; the remaining bytes
+ * can be passed again.
+ * If the prefix can't be represented as tokens of the vocabulary,
+ * nothing is consumed and −2 is returned; the matcher is not put
+ * in error state then.
+ *
+ * - If `n_bytes > 0`, `bytes` must point to `n_bytes` valid bytes.
+ */
+LLGUIDANCE_NODISCARD
+int32_t llg_matcher_consume_bytes(struct LlgMatcher *matcher,
+                                  const uint8_t *bytes,
+                                  size_t n_bytes);
+
 /**
  * Compute the fast-forward (forced) tokens for the current state.
  *
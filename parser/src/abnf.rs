@@ -0,0 +1,359 @@
+use anyhow::{anyhow, Result};
+
+use crate::{
+    gbnf::{compile_rules, push_class_char, Cursor, Expr, Rule},
+    grammar_builder::GrammarResult,
+    GrammarBuilder,
+};
+
+/// Core rules from RFC 5234, Appendix B.1; they can be overridden by the grammar.
+const CORE_RULES: &str = r#"
+ALPHA  = %x41-5A / %x61-7A
+BIT    = "0" / "1"
+CHAR   = %x01-7F
+CR     = %x0D
+CRLF   = CR LF
+CTL    = %x00-1F / %x7F
+DIGIT  = %x30-39
+DQUOTE = %x22
+HEXDIG = DIGIT / "A" / "B" / "C" / "D" / "E" / "F"
+HTAB   = %x09
+LF     = %x0A
+LWSP   = *(WSP / CRLF WSP)
+OCTET  = %x00-FF
+SP     = %x20
+VCHAR  = %x21-7E
+WSP    = SP / HTAB
+"#;
+
+/// Compile an ABNF grammar (RFC 5234 and RFC 7405) starting at its first rule.
+/// Rule names are case-insensitive; numeric values (`%x41`) are Unicode code points.
+pub fn abnf_to_llguidance(builder: GrammarBuilder, abnf: &str) -> Result<GrammarResult> {
+    let mut rules = parse_abnf(abnf)?;
+    let start = rules
+        .first()
+        .map(|r| r.name.clone())
+        .ok_or_else(|| anyhow!("no rules found in ABNF grammar"))?;
+    for rule in parse_abnf(CORE_RULES)? {
+        if !rules.iter().any(|r| r.name == rule.name) {
+            rules.push(rule);
+        }
+    }
+    compile_rules(builder, rules, &start, abnf.len())
+}
+
+fn parse_abnf(src: &str) -> Result<Vec<Rule>> {
+    let mut parser = Parser {
+        input: Cursor::new(src),
+    };
+    parser.parse()
+}
+
+struct Parser {
+    input: Cursor,
+}
+
+impl Parser {
+    fn skip_to_eol(&mut self) {
+        while self.input.cur().is_some_and(|c| c != '\r' && c != '\n') {
+            self.input.bump();
+        }
+    }
+
+    /// Skip empty and comment-only lines.
+    fn skip_empty_lines(&mut self) {
+        loop {
+            let line_start = self.input.mark();
+            while matches!(self.input.cur(), Some(' ' | '\t')) {
+                self.input.bump();
+            }
+            match self.input.cur() {
+                Some(';') => self.skip_to_eol(),
+                Some('\r' | '\n') => self.input.bump(),
+                None => break,
+                _ => {
+                    self.input.reset(line_start);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Skip whitespace and comments, including newlines followed by indented lines
+    /// (which continue the current rule).
+    fn skip_cwsp(&mut self) {
+        while let Some(c) = self.input.cur() {
+            match c {
+                ' ' | '\t' => self.input.bump(),
+                ';' => self.skip_to_eol(),
+                '\r' | '\n' => {
+                    let nl = self.input.mark();
+                    self.input.bump();
+                    if c == '\r' && self.input.cur() == Some('\n') {
+                        self.input.bump();
+                    }
+                    if !matches!(self.input.cur(), Some(' ' | '\t')) {
+                        self.input.reset(nl);
+                        break;
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn parse(&mut self) -> Result<Vec<Rule>> {
+        let mut rules: Vec<Rule> = vec![];
+        loop {
+            self.skip_empty_lines();
+            if self.input.cur().is_none() {
+                break;
+            }
+            let location = self.input.location();
+            let name = self.parse_rulename()?;
+            self.skip_cwsp();
+            self.input.expect('=')?;
+            let incremental = self.input.cur() == Some('/');
+            if incremental {
+                self.input.bump();
+            }
+            self.skip_cwsp();
+            let body = self.parse_alternation()?;
+            self.skip_cwsp();
+            if let Some(c) = self.input.cur().filter(|&c| c != '\r' && c != '\n') {
+                return Err(self.input.err(format!("unexpected {c:?}")));
+            }
+
+            if incremental {
+                let rule = rules.iter_mut().find(|r| r.name == name).ok_or_else(|| {
+                    location.augment(format!("rule {name:?} not defined before =/"))
+                })?;
+                let mut alternatives = match std::mem::replace(&mut rule.body, Expr::Any) {
+                    Expr::Alternatives(alts) => alts,
+                    e => vec![e],
+                };
+                match body {
+                    Expr::Alternatives(alts) => alternatives.extend(alts),
+                    e => alternatives.push(e),
+                }
+                rule.body = Expr::Alternatives(alternatives);
+            } else {
+                rules.push(Rule {
+                    name,
+                    location,
+                    body,
+                });
+            }
+        }
+        Ok(rules)
+    }
+
+    /// Rule names are case-insensitive, so they are lowercased.
+    fn parse_rulename(&mut self) -> Result<String> {
+        if !self.input.cur().is_some_and(|c| c.is_ascii_alphabetic()) {
+            return Err(self.input.err("expected rule name"));
+        }
+        Ok(self
+            .input
+            .take_while(|c| c.is_ascii_alphanumeric() || c == '-')
+            .to_ascii_lowercase())
+    }
+
+    fn parse_alternation(&mut self) -> Result<Expr> {
+        let mut alternatives = vec![self.parse_concatenation()?];
+        loop {
+            let save = self.input.mark();
+            self.skip_cwsp();
+            if self.input.cur() != Some('/') {
+                self.input.reset(save);
+                break;
+            }
+            self.input.bump();
+            self.skip_cwsp();
+            alternatives.push(self.parse_concatenation()?);
+        }
+        Ok(if alternatives.len() == 1 {
+            alternatives.pop().unwrap()
+        } else {
+            Expr::Alternatives(alternatives)
+        })
+    }
+
+    fn parse_concatenation(&mut self) -> Result<Expr> {
+        let mut items = vec![self.parse_repetition()?];
+        loop {
+            let save = self.input.mark();
+            self.skip_cwsp();
+            let starts_element = self
+                .input
+                .cur()
+                .is_some_and(|c| c.is_ascii_alphanumeric() || "*([\"%<".contains(c));
+            if self.input.mark() == save || !starts_element {
+                self.input.reset(save);
+                break;
+            }
+            items.push(self.parse_repetition()?);
+        }
+        Ok(if items.len() == 1 {
+            items.pop().unwrap()
+        } else {
+            Expr::Sequence(items)
+        })
+    }
+
+    fn parse_decimal(&mut self) -> Result<Option<u32>> {
+        let s = self.input.take_while(|c| c.is_ascii_digit());
+        if s.is_empty() {
+            return Ok(None);
+        }
+        s.parse()
+            .map(Some)
+            .map_err(|e| self.input.err(format!("invalid integer: {e}")))
+    }
+
+    fn parse_repetition(&mut self) -> Result<Expr> {
+        let min = self.parse_decimal()?;
+        let repeat = if self.input.cur() == Some('*') {
+            self.input.bump();
+            let max = self.parse_decimal()?;
+            let min = min.unwrap_or(0);
+            if let Some(max) = max {
+                if max < min {
+                    return Err(self.input.err(format!(
+                        "invalid repetition {min}*{max}: max is smaller than min"
+                    )));
+                }
+            }
+            Some((min, max))
+        } else {
+            min.map(|n| (n, Some(n)))
+        };
+        let element = self.parse_element()?;
+        Ok(match repeat {
+            Some((min, max)) => Expr::Repeat(Box::new(element), min, max),
+            None => element,
+        })
+    }
+
+    fn parse_element(&mut self) -> Result<Expr> {
+        match self.input.cur() {
+            Some(c) if c.is_ascii_alphabetic() => {
+                let location = self.input.location();
+                Ok(Expr::RuleRef(location, self.parse_rulename()?))
+            }
+            Some(c @ ('(' | '[')) => {
+                self.input.bump();
+                self.skip_cwsp();
+                let inner = self.parse_alternation()?;
+                self.skip_cwsp();
+                if c == '(' {
+                    self.input.expect(')')?;
+                    Ok(inner)
+                } else {
+                    self.input.expect(']')?;
+                    Ok(Expr::Repeat(Box::new(inner), 0, Some(1)))
+                }
+            }
+            Some('"') => self.parse_string(false),
+            Some('%') => {
+                self.input.bump();
+                match self.input.cur().map(|c| c.to_ascii_lowercase()) {
+                    Some('s') => {
+                        self.input.bump();
+                        self.parse_string(true)
+                    }
+                    Some('i') => {
+                        self.input.bump();
+                        self.parse_string(false)
+                    }
+                    Some('x') => self.parse_num_val(16),
+                    Some('d') => self.parse_num_val(10),
+                    Some('b') => self.parse_num_val(2),
+                    _ => Err(self.input.err("expected one of %x, %d, %b, %s, %i")),
+                }
+            }
+            Some('<') => Err(self.input.err("prose values (<...>) are not supported")),
+            Some(c) => Err(self.input.err(format!("unexpected {c:?}"))),
+            None => Err(self.input.err("unexpected end of input")),
+        }
+    }
+
+    /// Quoted strings are case-insensitive, unless prefixed with `%s`.
+    fn parse_string(&mut self, case_sensitive: bool) -> Result<Expr> {
+        self.input.expect('"')?;
+        let s = self
+            .input
+            .take_while(|c| c != '"' && c != '\r' && c != '\n');
+        if self.input.cur() != Some('"') {
+            return Err(self.input.err("unterminated string"));
+        }
+        self.input.bump();
+
+        if case_sensitive || !s.chars().any(|c| c.is_ascii_alphabetic()) {
+            return Ok(Expr::Literal(s));
+        }
+        let mut items = vec![];
+        let mut literal = String::new();
+        for c in s.chars() {
+            if c.is_ascii_alphabetic() {
+                if !literal.is_empty() {
+                    items.push(Expr::Literal(std::mem::take(&mut literal)));
+                }
+                items.push(Expr::CharClass(format!(
+                    "[{}{}]",
+                    c.to_ascii_lowercase(),
+                    c.to_ascii_uppercase()
+                )));
+            } else {
+                literal.push(c);
+            }
+        }
+        if !literal.is_empty() {
+            items.push(Expr::Literal(literal));
+        }
+        Ok(if items.len() == 1 {
+            items.pop().unwrap()
+        } else {
+            Expr::Sequence(items)
+        })
+    }
+
+    /// Parse `%x41`, `%x41-5A` or `%x0D.0A` (after the `%`).
+    fn parse_num_val(&mut self, base: u32) -> Result<Expr> {
+        self.input.bump();
+        let first = self.parse_num_char(base)?;
+        if self.input.cur() == Some('-') {
+            self.input.bump();
+            let last = self.parse_num_char(base)?;
+            if last < first {
+                return Err(self.input.err(format!(
+                    "invalid range: U+{:04X} is smaller than U+{:04X}",
+                    last as u32, first as u32
+                )));
+            }
+            let mut rx = String::from("[");
+            push_class_char(&mut rx, first);
+            rx.push('-');
+            push_class_char(&mut rx, last);
+            rx.push(']');
+            return Ok(Expr::CharClass(rx));
+        }
+        let mut s = String::from(first);
+        while self.input.cur() == Some('.') {
+            self.input.bump();
+            s.push(self.parse_num_char(base)?);
+        }
+        Ok(Expr::Literal(s))
+    }
+
+    fn parse_num_char(&mut self, base: u32) -> Result<char> {
+        let s = self.input.take_while(|c| c.is_digit(base));
+        if s.is_empty() {
+            return Err(self.input.err("expected number"));
+        }
+        u32::from_str_radix(&s, base)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| self.input.err(format!("invalid character value {s:?}")))
+    }
+}
@@ -7,7 +7,7 @@ use serde_json::{json, Value};
 
 use crate::{
     earley::{lexerspec::LexerSpec, Grammar},
-    regex_to_lark,
+    regex_to_lark, CompiledGrammar,
 };
 
 pub use crate::earley::ValidationResult;
@@ -26,6 +26,7 @@ pub struct TopLevelGrammar {
 pub enum GrammarInit {
     Serialized(TopLevelGrammar),
     Internal(Grammar, LexerSpec),
+    Compiled(CompiledGrammar),
 }
 
 /// cbindgen:ignore
@@ -85,6 +86,17 @@ pub struct LLGuidanceOptions {
     /// including nested sub-grammars.
     #[serde(default)]
     pub allow_initial_skip: bool,
+
+    /// If set, process_prompt() will remove trailing prompt tokens, whose bytes
+    /// could be extended into a longer token, and force the first generated
+    /// token(s) to start with the removed bytes (token healing).
+    /// For example, a prompt ending in `http:` can then be continued with
+    /// a `://` token.
+    /// Prompt tokens are always removed when the grammar itself allows
+    /// for a better tokenization; this option also removes them based on
+    /// the vocabulary alone.
+    #[serde(default)]
+    pub token_healing: bool,
 }
 
 impl LLGuidanceOptions {
@@ -98,6 +110,9 @@ impl LLGuidanceOptions {
         if other.allow_initial_skip {
             self.allow_initial_skip = true;
         }
+        if other.token_healing {
+            self.token_healing = true;
+        }
     }
 }
 
@@ -113,6 +128,14 @@ pub struct GrammarWithLexer {
     /// The Lark grammar that the grammar should generate.
     /// When this is set, nodes and rx_nodes must be empty.
     pub lark_grammar: Option<String>,
+
+    /// The GBNF grammar (as used by llama.cpp) that the grammar should generate.
+    /// It has to define a `root` rule.
+    pub gbnf_grammar: Option<String>,
+
+    /// The ABNF grammar (RFC 5234) that the grammar should generate.
+    /// The first rule is the start rule.
+    pub abnf_grammar: Option<String>,
     // #[serde(flatten)]
     // pub options: LLGuidanceOptions,
 }
@@ -124,6 +147,10 @@ impl Debug for GrammarWithLexer {
             "GrammarWithLexer [{}]",
             if self.lark_grammar.is_some() {
                 "lark"
+            } else if self.gbnf_grammar.is_some() {
+                "gbnf"
+            } else if self.abnf_grammar.is_some() {
+                "abnf"
             } else {
                 "json"
             }
@@ -190,12 +217,28 @@ pub struct GenGrammarOptions {
     pub temperature: Option<f32>,
 }
 
+// serialized as a plain string; unlike `untagged`, this also works with the binary
+// format of compiled grammars
 #[derive(Serialize, Deserialize, Hash, PartialEq, Eq, Clone, Debug)]
-#[serde(untagged)]
+#[serde(from = "String", into = "String")]
 pub enum GrammarId {
     Name(String),
 }
 
+impl From<String> for GrammarId {
+    fn from(name: String) -> Self {
+        GrammarId::Name(name)
+    }
+}
+
+impl From<GrammarId> for String {
+    fn from(id: GrammarId) -> Self {
+        match id {
+            GrammarId::Name(name) => name,
+        }
+    }
+}
+
 impl Display for GrammarId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -342,6 +385,14 @@ impl TopLevelGrammar {
         Self::from_grammar(GrammarWithLexer::from_lark(lark_grammar))
     }
 
+    pub fn from_gbnf(gbnf_grammar: String) -> Self {
+        Self::from_grammar(GrammarWithLexer::from_gbnf(gbnf_grammar))
+    }
+
+    pub fn from_abnf(abnf_grammar: String) -> Self {
+        Self::from_grammar(GrammarWithLexer::from_abnf(abnf_grammar))
+    }
+
     pub fn from_json_schema(json_schema: Value) -> Self {
         Self::from_grammar(GrammarWithLexer::from_json_schema(json_schema))
     }
@@ -361,6 +412,10 @@ impl TopLevelGrammar {
     /// - "json_object" - equivalent to JSON schema: {"type":"object"}
     /// - "lark" - data is grammar in a variant of Lark syntax
     ///   see <https://github.com/guidance-ai/llguidance/blob/main/docs/syntax.md>
+    /// - "gbnf" - data is grammar in GBNF format (as used by llama.cpp)
+    ///   see <https://github.com/ggml-org/llama.cpp/blob/master/grammars/README.md>
+    /// - "abnf" - data is grammar in ABNF format
+    ///   see <https://www.rfc-editor.org/rfc/rfc5234>
     /// - "llguidance" or "guidance" - data is a list of Lark or JSON schemas in JSON format
     pub fn from_tagged_str(tag: &str, data: &str) -> Result<Self> {
         match tag {
@@ -368,6 +423,8 @@ impl TopLevelGrammar {
             "json" | "json_schema" => Ok(Self::from_json_schema(serde_json::from_str(data)?)),
             "json_object" => Ok(Self::from_json_schema(json!({"type": "object"}))),
             "lark" => Ok(Self::from_lark(data.to_string())),
+            "gbnf" => Ok(Self::from_gbnf(data.to_string())),
+            "abnf" => Ok(Self::from_abnf(data.to_string())),
             "llguidance" | "guidance" => Self::from_lark_or_grammar_list(data),
             _ => bail!("unknown constraint type: {tag}"),
         }
@@ -383,6 +440,22 @@ impl GrammarWithLexer {
         }
     }
 
+    pub fn from_gbnf(gbnf_grammar: String) -> Self {
+        GrammarWithLexer {
+            name: Some("gbnf_grammar".to_string()),
+            gbnf_grammar: Some(gbnf_grammar),
+            ..GrammarWithLexer::default()
+        }
+    }
+
+    pub fn from_abnf(abnf_grammar: String) -> Self {
+        GrammarWithLexer {
+            name: Some("abnf_grammar".to_string()),
+            abnf_grammar: Some(abnf_grammar),
+            ..GrammarWithLexer::default()
+        }
+    }
+
     pub fn from_json_schema(json_schema: Value) -> Self {
         GrammarWithLexer {
             name: Some("json_schema".to_string()),
@@ -0,0 +1,188 @@
+use anyhow::{anyhow, ensure, Result};
+use toktrie::{SimpleVob, TokenId};
+
+use crate::Matcher;
+
+/// Runs operations on a batch of matchers, in parallel when the `rayon` feature
+/// is enabled (otherwise sequentially).
+///
+/// Results are reported per matcher. A matcher that fails is put in error
+/// state, and its mask is left empty (callers may want to allow EOS there).
+pub struct BatchMatcher {
+    #[cfg(feature = "rayon")]
+    pool: Option<rayon::ThreadPool>,
+}
+
+impl Default for BatchMatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BatchMatcher {
+    /// Create a batch matcher using the global rayon thread pool.
+    pub fn new() -> Self {
+        BatchMatcher {
+            #[cfg(feature = "rayon")]
+            pool: None,
+        }
+    }
+
+    /// Create a batch matcher with its own pool of `num_threads` threads.
+    #[cfg(feature = "rayon")]
+    pub fn with_num_threads(num_threads: usize) -> Result<Self> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .build()?;
+        Ok(BatchMatcher { pool: Some(pool) })
+    }
+
+    pub(crate) fn map<T: Send, R: Send>(
+        &self,
+        items: Vec<T>,
+        f: impl Fn(T) -> R + Sync + Send,
+    ) -> Vec<R> {
+        #[cfg(feature = "rayon")]
+        if items.len() > 1 {
+            use rayon::prelude::*;
+            let run = || items.into_par_iter().map(f).collect();
+            return match &self.pool {
+                Some(pool) => pool.install(run),
+                None => run(),
+            };
+        }
+        items.into_iter().map(f).collect()
+    }
+
+    /// Compute the mask of each matcher (as in [`Matcher::compute_mask_or_eos()`])
+    /// into the corresponding row of `masks`, which is `[matchers.len(), vocab_size / 32]`
+    /// (rounded up). Matchers whose vocabulary size doesn't match the row length fail.
+    pub fn compute_masks(&self, matchers: &mut [Matcher], masks: &mut [u32]) -> Vec<Result<()>> {
+        if matchers.is_empty() {
+            return vec![];
+        }
+        let row_len = masks.len() / matchers.len();
+        if row_len == 0 || row_len * matchers.len() != masks.len() {
+            return fail_all(matchers.len(), masks.len());
+        }
+        self.compute_masks_each(matchers.iter_mut().zip(masks.chunks_mut(row_len)).collect())
+    }
+
+    /// Like [`Self::compute_masks()`], but each matcher is given its own output row.
+    pub fn compute_masks_each(&self, rows: Vec<(&mut Matcher, &mut [u32])>) -> Vec<Result<()>> {
+        self.map(rows, |(matcher, row)| {
+            if let Err(e) = check_row_len(matcher, row.len()) {
+                copy_mask(None, row);
+                return Err(e);
+            }
+            let r = matcher.compute_mask_or_eos();
+            copy_mask(r.as_ref().ok(), row);
+            r.map(|_| ())
+        })
+    }
+
+    /// Compute masks for speculative decoding for each matcher,
+    /// as in [`Matcher::compute_masks_for_draft()`].
+    /// The output for each matcher has room for `draft.len() + 1` rows.
+    /// Returns the number of rows written and the result; on error, the rows
+    /// computed before it are kept, and the next row (if any) is left empty.
+    pub fn compute_masks_for_draft_each(
+        &self,
+        items: Vec<(&mut Matcher, &[TokenId], &mut [u32])>,
+    ) -> Vec<(usize, Result<()>)> {
+        self.map(items, |(matcher, draft, out)| {
+            let n_rows = draft.len() + 1;
+            if out.is_empty() || out.len() % n_rows != 0 {
+                let err = anyhow!(
+                    "output size {} is not a multiple of {} rows",
+                    out.len(),
+                    n_rows
+                );
+                return (0, Err(err));
+            }
+            let row_len = out.len() / n_rows;
+            if let Err(e) = check_row_len(matcher, row_len) {
+                copy_mask(None, &mut out[..row_len]);
+                return (0, Err(e));
+            }
+            let mut masks = vec![];
+            let r = matcher.compute_masks_for_draft_into(draft, &mut masks);
+            for (mask, row) in masks.iter().zip(out.chunks_mut(row_len)) {
+                copy_mask(Some(mask), row);
+            }
+            if r.is_err() && masks.len() < n_rows {
+                copy_mask(
+                    None,
+                    &mut out[masks.len() * row_len..(masks.len() + 1) * row_len],
+                );
+            }
+            (masks.len(), r)
+        })
+    }
+
+    /// Consume one token in each matcher.
+    /// EOS tokens are accepted (and ignored) by stopped matchers.
+    pub fn consume_tokens(&self, matchers: &mut [Matcher], tokens: &[TokenId]) -> Vec<Result<()>> {
+        if matchers.len() != tokens.len() {
+            return fail_all(matchers.len(), tokens.len());
+        }
+        self.consume_token_each(matchers.iter_mut().zip(tokens.iter().copied()).collect())
+    }
+
+    /// Like [`Self::consume_tokens()`], but for a list of (matcher, token) pairs.
+    pub fn consume_token_each(&self, items: Vec<(&mut Matcher, TokenId)>) -> Vec<Result<()>> {
+        self.map(items, |(matcher, token)| {
+            let is_eos = matcher
+                .tok_env()
+                .is_ok_and(|env| env.tok_trie().eos_tokens().contains(&token));
+            if is_eos && matcher.is_stopped() {
+                Ok(())
+            } else {
+                matcher.consume_token(token)
+            }
+        })
+    }
+
+    /// Roll back each matcher by the corresponding number of tokens.
+    pub fn rollback(&self, matchers: &mut [Matcher], num_tokens: &[usize]) -> Vec<Result<()>> {
+        if matchers.len() != num_tokens.len() {
+            return fail_all(matchers.len(), num_tokens.len());
+        }
+        let items = matchers
+            .iter_mut()
+            .zip(num_tokens.iter().copied())
+            .collect();
+        self.map(items, |(matcher, n): (&mut Matcher, usize)| {
+            matcher.rollback(n)
+        })
+    }
+}
+
+fn check_row_len(matcher: &Matcher, row_len: usize) -> Result<()> {
+    // matchers in error state fail anyway
+    if let Ok(env) = matcher.tok_env() {
+        let vocab_size = env.tok_trie().vocab_size();
+        ensure!(
+            row_len == vocab_size.div_ceil(32),
+            "mask row of {row_len} words doesn't match vocab size {vocab_size}"
+        );
+    }
+    Ok(())
+}
+
+fn fail_all<T>(n_matchers: usize, n_other: usize) -> Vec<Result<T>> {
+    (0..n_matchers)
+        .map(|_| {
+            Err(anyhow::anyhow!(
+                "batch size mismatch: {n_matchers} matchers and {n_other} elements"
+            ))
+        })
+        .collect()
+}
+
+pub(crate) fn copy_mask(mask: Option<&SimpleVob>, row: &mut [u32]) {
+    let src = mask.map_or(&[][..], |m| m.as_slice());
+    let n = std::cmp::min(src.len(), row.len());
+    row[..n].copy_from_slice(&src[..n]);
+    row[n..].fill(0);
+}
@@ -0,0 +1,191 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, ensure, Result};
+use serde::{Deserialize, Serialize};
+use toktrie::TokEnv;
+
+use crate::earley::CGrammar;
+
+const MAGIC: &[u8; 4] = b"LLGC";
+
+/// Grammar compiled by [`crate::ParserFactory::compile_grammar()`].
+///
+/// It can be serialized to a binary (postcard) blob with [`CompiledGrammar::to_bytes()`]
+/// and later loaded with [`CompiledGrammar::from_bytes()`], to skip
+/// grammar compilation when creating parsers with
+/// [`crate::ParserFactory::create_parser_from_compiled()`].
+/// The blob is only valid for the same tokenizer (checked via
+/// [`CompiledGrammar::tokenizer_hash()`]), the same slices of the factory,
+/// and the same version of llguidance.
+/// Lexer DFA states are not included; they are re-computed lazily as usual.
+#[derive(Clone)]
+pub struct CompiledGrammar {
+    pub(crate) grammar: Arc<CGrammar>,
+    pub(crate) max_tokens: Option<usize>,
+    pub(crate) tokenizer_hash: u64,
+    pub(crate) extra_lexemes: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Payload<G> {
+    llguidance_version: String,
+    tokenizer_hash: u64,
+    extra_lexemes: Vec<String>,
+    max_tokens: Option<usize>,
+    grammar: G,
+}
+
+impl CompiledGrammar {
+    /// Version of the binary format; blobs with other versions are rejected.
+    /// cbindgen:ignore
+    pub const FORMAT_VERSION: u32 = 3;
+
+    pub fn tokenizer_hash(&self) -> u64 {
+        self.tokenizer_hash
+    }
+
+    pub fn max_tokens(&self) -> Option<usize> {
+        self.max_tokens
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut res = MAGIC.to_vec();
+        res.extend_from_slice(&Self::FORMAT_VERSION.to_le_bytes());
+        let payload = Payload {
+            llguidance_version: env!("CARGO_PKG_VERSION").to_string(),
+            tokenizer_hash: self.tokenizer_hash,
+            extra_lexemes: self.extra_lexemes.clone(),
+            max_tokens: self.max_tokens,
+            grammar: &*self.grammar,
+        };
+        Ok(postcard::to_extend(&payload, res)?)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        ensure!(
+            data.len() >= 8 && &data[0..4] == MAGIC,
+            "not a compiled grammar"
+        );
+        let version = u32::from_le_bytes(data[4..8].try_into().unwrap());
+        if version != Self::FORMAT_VERSION {
+            bail!(
+                "compiled grammar format version mismatch: {} (expected {})",
+                version,
+                Self::FORMAT_VERSION
+            );
+        }
+        let payload: Payload<CGrammar> = postcard::from_bytes(&data[8..])?;
+        ensure!(
+            payload.llguidance_version == env!("CARGO_PKG_VERSION"),
+            "grammar compiled with llguidance {}, but this is {}",
+            payload.llguidance_version,
+            env!("CARGO_PKG_VERSION")
+        );
+        payload
+            .grammar
+            .validate()
+            .map_err(|e| anyhow!("invalid compiled grammar: {e}"))?;
+        Ok(CompiledGrammar {
+            grammar: Arc::new(payload.grammar),
+            max_tokens: payload.max_tokens,
+            tokenizer_hash: payload.tokenizer_hash,
+            extra_lexemes: payload.extra_lexemes,
+        })
+    }
+}
+
+/// Compute a stable (FNV-1a) hash of the tokenizer vocabulary and EOS tokens.
+pub fn tokenizer_hash(tok_env: &TokEnv) -> u64 {
+    const PRIME: u64 = 0x100000001b3;
+    let mut h: u64 = 0xcbf29ce484222325;
+    let mut add = |bytes: &[u8]| {
+        for b in bytes {
+            h ^= *b as u64;
+            h = h.wrapping_mul(PRIME);
+        }
+    };
+    let trie = tok_env.tok_trie();
+    add(&(trie.vocab_size() as u64).to_le_bytes());
+    for tok in trie.eos_tokens() {
+        add(&tok.to_le_bytes());
+    }
+    for idx in 0..trie.vocab_size() as u32 {
+        let bytes = trie.token(idx);
+        add(&(bytes.len() as u32).to_le_bytes());
+        add(bytes);
+    }
+    h
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CompiledGrammar, Payload};
+    use crate::{api::TopLevelGrammar, earley::CGrammar, ParserFactory};
+    use anyhow::Result;
+    use serde_json::{json, Value};
+    use toktrie::ApproximateTokEnv;
+
+    #[test]
+    fn test_invalid_indices() {
+        let mut factory = ParserFactory::new_simple(&ApproximateTokEnv::single_byte_env()).unwrap();
+        factory.quiet();
+        let compiled = factory
+            .compile_grammar(TopLevelGrammar::from_lark(
+                "start: \"a\" x\nx: /[0-9]+/".to_string(),
+            ))
+            .unwrap();
+        let bytes = compiled.to_bytes().unwrap();
+
+        // edit the payload as JSON, and encode it back
+        let payload: Payload<CGrammar> = postcard::from_bytes(&bytes[8..]).unwrap();
+        let payload = serde_json::to_value(&payload).unwrap();
+        let mutated = |path: &str, value: Value| -> Result<CompiledGrammar> {
+            let mut payload = payload.clone();
+            *payload.pointer_mut(path).unwrap() = value;
+            // the lexer spec is already checked when deserialized
+            let payload: Payload<CGrammar> = serde_json::from_value(payload)?;
+            let res = postcard::to_extend(&payload, bytes[0..8].to_vec()).unwrap();
+            CompiledGrammar::from_bytes(&res)
+        };
+
+        // sanity check
+        assert!(mutated("/max_tokens", json!(null)).is_ok());
+
+        let start = payload["grammar"]["start_symbol"].as_u64().unwrap() as usize;
+        let rule = format!("/grammar/symbols/{start}/rules/0");
+        for (path, value, msg) in [
+            ("/grammar/start_symbol", json!(1000), "invalid start symbol"),
+            ("/grammar/rhs_elements/1", json!(1000), "invalid symbol"),
+            ("/grammar/rhs_elements/3", json!(1), "not terminated"),
+            ("/grammar/rhs_params", json!(["Null"]), "rule parameters"),
+            ("/grammar/rhs_ptr_to_sym_idx/0", json!(1000), "lhs table"),
+            ("/grammar/symbols/1/idx", json!(2), "invalid symbol index"),
+            ("/grammar/symbols/1/lexeme", json!(1000), "invalid lexeme"),
+            (
+                "/grammar/symbols/1/props/grammar_id",
+                json!(5),
+                "invalid grammar id",
+            ),
+            (&rule, json!(2), "invalid rule"),
+            (&rule, json!(1000), "invalid rule"),
+            (
+                "/grammar/lexer_spec/lexemes/1/compiled_rx",
+                json!(1000),
+                "invalid regex id",
+            ),
+            (
+                "/grammar/lexer_spec/lexemes/1/class",
+                json!(5),
+                "invalid lexeme class",
+            ),
+            (
+                "/grammar/lexer_spec/regex_ops/4/Mk/0/ExprRef",
+                json!(1000),
+                "invalid regex id",
+            ),
+        ] {
+            let e = mutated(path, value).err().unwrap().to_string();
+            assert!(e.contains(msg), "{path}: expected {msg:?} in {e:?}");
+        }
+    }
+}
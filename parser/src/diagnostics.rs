@@ -0,0 +1,201 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+/// Severity of a [`Diagnostic`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Location of a diagnostic in the source of a Lark, GBNF or ABNF grammar.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceSpan {
+    /// Byte range in the grammar source.
+    pub start: usize,
+    pub end: usize,
+    /// 1-based line and column (in bytes) of `start`.
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A grammar error or warning, with its location in the grammar when known.
+///
+/// It displays the same way as the corresponding plain-text error or warning,
+/// except that warnings with a `span` are prefixed with their location, like errors.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Kind of the diagnostic, like `"unknown_name"`, `"duplicate_rule"` or
+    /// `"unsupported_keyword"`.
+    /// Errors not recognized as a specific kind have the code of where they come from:
+    /// `"source"` for Lark, GBNF or ABNF source, `"json_schema"` for JSON schemas,
+    /// `"grammar"` for other grammar errors, and `"runtime"` for errors after
+    /// the grammar was compiled.
+    pub code: String,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub span: Option<SourceSpan>,
+    /// JSON pointer (RFC 6901) to the offending subschema.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub json_pointer: Option<String>,
+    /// Additional information, like the source excerpt around `span`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: &str, message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity,
+            code: code.to_string(),
+            message: message.into(),
+            span: None,
+            json_pointer: None,
+            notes: vec![],
+        }
+    }
+
+    /// A warning, at `span` in the grammar source or `json_pointer` in the JSON schema,
+    /// if known.
+    pub fn warning(
+        code: &str,
+        message: impl Into<String>,
+        span: Option<SourceSpan>,
+        json_pointer: Option<String>,
+    ) -> Self {
+        let mut d = Self::new(Severity::Warning, code, message);
+        d.span = span;
+        d.json_pointer = json_pointer;
+        d
+    }
+
+    /// Convert a grammar compilation error, recovering its location if known.
+    pub fn from_error(err: &anyhow::Error) -> Self {
+        if let Some(e) = err.downcast_ref::<SourceError>() {
+            let mut d = Self::new(
+                Severity::Error,
+                error_code(&e.message, "source"),
+                &e.message,
+            );
+            d.span = Some(e.span.clone());
+            d.notes.push(e.excerpt.clone());
+            return d;
+        }
+        let message = err.to_string();
+        if let Some(e) = err.downcast_ref::<SchemaError>() {
+            let mut d = Self::new(
+                Severity::Error,
+                error_code(&message, "json_schema"),
+                message,
+            );
+            d.json_pointer = e.json_pointer.clone();
+            return d;
+        }
+        Self::new(Severity::Error, error_code(&message, "grammar"), message)
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(span) = &self.span {
+            write!(f, "at {}({}): ", span.line, span.column)?;
+        }
+        write!(f, "{}", self.message)?;
+        for note in &self.notes {
+            write!(f, "\n{note}")?;
+        }
+        Ok(())
+    }
+}
+
+// Codes of common errors, recognized by the start of their message;
+// the first column limits an entry to errors of the given origin.
+const ERROR_CODES: &[(Option<&str>, &str, &str)] = &[
+    (None, "unknown name", "unknown_name"),
+    (None, "duplicate rule", "duplicate_rule"),
+    (None, "duplicate token", "duplicate_token"),
+    (None, "circular reference", "circular_reference"),
+    (None, "lexer error", "lexer_error"),
+    (None, "invalid regex", "invalid_regex"),
+    (None, "template ", "invalid_template"),
+    (None, "module ", "unknown_module"),
+    (Some("source"), "Expected ", "syntax_error"),
+    (Some("source"), "Unexpected ", "syntax_error"),
+    (
+        Some("json_schema"),
+        "Unimplemented keys",
+        "unsupported_keyword",
+    ),
+    (Some("json_schema"), "Unknown format", "unknown_format"),
+    (Some("json_schema"), "Invalid type", "invalid_type"),
+];
+
+/// Code of the error with `message`, or `origin` if it is not of a known kind.
+fn error_code<'a>(message: &str, origin: &'a str) -> &'a str {
+    ERROR_CODES
+        .iter()
+        .find(|(o, prefix, _)| o.is_none_or(|o| o == origin) && message.starts_with(prefix))
+        .map_or(origin, |(_, _, code)| *code)
+}
+
+/// Error at a given place in grammar source, see `Location::augment()`.
+#[derive(Debug)]
+pub(crate) struct SourceError {
+    pub span: SourceSpan,
+    pub message: String,
+    pub excerpt: String,
+}
+
+impl Display for SourceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "at {}({}): {}\n{}",
+            self.span.line, self.span.column, self.message, self.excerpt
+        )
+    }
+}
+
+impl std::error::Error for SourceError {}
+
+/// Error in a JSON schema, attached as context to the original error
+/// (so it displays the same, and the original error can still be downcast to).
+#[derive(Debug)]
+pub(crate) struct SchemaError {
+    json_pointer: Option<String>,
+    message: String,
+}
+
+impl SchemaError {
+    /// Record `json_pointer` as the place of `err`, unless the error already has one.
+    pub fn at(err: anyhow::Error, json_pointer: Option<String>) -> anyhow::Error {
+        if err.is::<SchemaError>() {
+            return err;
+        }
+        let message = err.to_string();
+        err.context(SchemaError {
+            json_pointer,
+            message,
+        })
+    }
+
+    /// Mark `err` as coming from a JSON schema, without a known place.
+    pub fn tag(err: anyhow::Error) -> anyhow::Error {
+        Self::at(err, None)
+    }
+}
+
+impl Display for SchemaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for SchemaError {}
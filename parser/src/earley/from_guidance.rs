@@ -5,9 +5,10 @@ use super::grammar::SymIdx;
 use super::lexerspec::LexerSpec;
 use super::{CGrammar, Grammar};
 use crate::api::{GrammarId, GrammarInit, GrammarWithLexer, ParserLimits, TopLevelGrammar};
+use crate::diagnostics::{Diagnostic, SchemaError};
 use crate::earley::lexerspec::LexemeClass;
 use crate::Instant;
-use crate::{loginfo, JsonCompileOptions, Logger};
+use crate::{loginfo, ImportResolver, JsonCompileOptions, Logger};
 use crate::{GrammarBuilder, HashMap};
 use anyhow::{bail, ensure, Result};
 use toktrie::TokEnv;
@@ -22,14 +23,19 @@ impl CompileCtx {
     fn run_one(&mut self, input: GrammarWithLexer) -> Result<(SymIdx, LexemeClass)> {
         let builder = std::mem::take(&mut self.builder).unwrap();
 
+        ensure!(
+            input.lark_grammar.is_some() as usize
+                + input.gbnf_grammar.is_some() as usize
+                + input.abnf_grammar.is_some() as usize
+                + input.json_schema.is_some() as usize
+                <= 1,
+            "can only have one of lark_grammar, gbnf_grammar, abnf_grammar and json_schema"
+        );
+
         let res = if let Some(lark) = input.lark_grammar {
             #[cfg(feature = "lark")]
             {
                 use crate::lark::lark_to_llguidance;
-                ensure!(
-                    input.json_schema.is_none(),
-                    "cannot have both lark_grammar and json_schema"
-                );
                 lark_to_llguidance(builder, &lark)?
             }
             #[cfg(not(feature = "lark"))]
@@ -37,10 +43,36 @@ impl CompileCtx {
                 let _ = lark;
                 bail!("lark_grammar is not supported in this build")
             }
+        } else if let Some(gbnf) = input.gbnf_grammar {
+            #[cfg(feature = "lark")]
+            {
+                use crate::gbnf::gbnf_to_llguidance;
+                gbnf_to_llguidance(builder, &gbnf)?
+            }
+            #[cfg(not(feature = "lark"))]
+            {
+                let _ = gbnf;
+                bail!("gbnf_grammar is not supported in this build")
+            }
+        } else if let Some(abnf) = input.abnf_grammar {
+            #[cfg(feature = "lark")]
+            {
+                use crate::abnf::abnf_to_llguidance;
+                abnf_to_llguidance(builder, &abnf)?
+            }
+            #[cfg(not(feature = "lark"))]
+            {
+                let _ = abnf;
+                bail!("abnf_grammar is not supported in this build")
+            }
         } else if let Some(json_schema) = input.json_schema {
-            JsonCompileOptions::default().json_to_llg_with_overrides(builder, json_schema)?
+            JsonCompileOptions::default()
+                .json_to_llg_with_overrides(builder, json_schema)
+                .map_err(SchemaError::tag)?
         } else {
-            bail!("grammar must have either lark_grammar or json_schema");
+            bail!(
+                "grammar must have either lark_grammar, gbnf_grammar, abnf_grammar or json_schema"
+            );
         };
 
         res.builder.check_limits()?;
@@ -55,8 +87,12 @@ impl CompileCtx {
 
     fn run(mut self, input: TopLevelGrammar) -> Result<(Grammar, LexerSpec)> {
         for (idx, grm) in input.grammars.iter().enumerate() {
-            if grm.lark_grammar.is_none() && grm.json_schema.is_none() {
-                bail!("grammar must have either lark_grammar or json_schema");
+            if grm.lark_grammar.is_none()
+                && grm.gbnf_grammar.is_none()
+                && grm.abnf_grammar.is_none()
+                && grm.json_schema.is_none()
+            {
+                bail!("grammar must have either lark_grammar, gbnf_grammar, abnf_grammar or json_schema");
             }
             if let Some(n) = &grm.name {
                 let n = GrammarId::Name(n.to_string());
@@ -79,15 +115,11 @@ impl CompileCtx {
             .collect();
 
         let builder = self.builder.unwrap();
-        let warnings = builder.get_warnings();
         let mut grammar = builder.grammar;
         let mut lexer_spec = builder.regex.spec;
 
         grammar.resolve_grammar_refs(&mut lexer_spec, &grammar_by_idx)?;
 
-        assert!(lexer_spec.grammar_warnings.is_empty());
-        lexer_spec.grammar_warnings = warnings;
-
         Ok((grammar, lexer_spec))
     }
 }
@@ -95,12 +127,12 @@ impl CompileCtx {
 #[derive(Debug, Clone)]
 pub enum ValidationResult {
     Valid,
-    Warnings(Vec<String>),
-    Error(String),
+    Warnings(Vec<Diagnostic>),
+    Error(Diagnostic),
 }
 
 impl ValidationResult {
-    pub fn from_warning(w: Vec<String>) -> Self {
+    pub fn from_warnings(w: Vec<Diagnostic>) -> Self {
         if w.is_empty() {
             ValidationResult::Valid
         } else {
@@ -108,11 +140,15 @@ impl ValidationResult {
         }
     }
 
+    pub fn from_error(e: &anyhow::Error) -> Self {
+        ValidationResult::Error(Diagnostic::from_error(e))
+    }
+
     pub fn into_tuple(self) -> (bool, Vec<String>) {
         match self {
             ValidationResult::Valid => (false, vec![]),
-            ValidationResult::Warnings(w) => (false, w),
-            ValidationResult::Error(e) => (true, vec![e]),
+            ValidationResult::Warnings(w) => (false, w.iter().map(|w| w.to_string()).collect()),
+            ValidationResult::Error(e) => (true, vec![e.to_string()]),
         }
     }
 
@@ -120,7 +156,16 @@ impl ValidationResult {
         match self {
             ValidationResult::Valid => None,
             ValidationResult::Warnings(_) => None,
-            ValidationResult::Error(e) => Some(e),
+            ValidationResult::Error(e) => Some(e.to_string()),
+        }
+    }
+
+    /// All warnings and errors, with their location in the grammar when known.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            ValidationResult::Valid => vec![],
+            ValidationResult::Warnings(w) => w.clone(),
+            ValidationResult::Error(e) => vec![e.clone()],
         }
     }
 
@@ -147,14 +192,30 @@ impl GrammarInit {
         self,
         tok_env: Option<TokEnv>,
         limits: ParserLimits,
+    ) -> Result<(Grammar, LexerSpec)> {
+        self.to_internal_ext(tok_env, limits, None)
+    }
+
+    /// Like `to_internal()`, but Lark `%import` statements are resolved
+    /// with `import_resolver`.
+    pub fn to_internal_ext(
+        self,
+        tok_env: Option<TokEnv>,
+        limits: ParserLimits,
+        import_resolver: Option<Arc<dyn ImportResolver>>,
     ) -> Result<(Grammar, LexerSpec)> {
         match self {
             GrammarInit::Internal(g, l) => Ok((g, l)),
 
+            GrammarInit::Compiled(_) => {
+                bail!("compiled grammar cannot be converted back to internal representation")
+            }
+
             GrammarInit::Serialized(input) => {
                 ensure!(!input.grammars.is_empty(), "empty grammars array");
 
-                let builder = GrammarBuilder::new(tok_env, limits.clone());
+                let mut builder = GrammarBuilder::new(tok_env, limits.clone());
+                builder.set_import_resolver(import_resolver);
 
                 let ctx = CompileCtx {
                     builder: Some(builder),
@@ -168,9 +229,27 @@ impl GrammarInit {
     }
 
     pub fn validate(self, tok_env: Option<TokEnv>, limits: ParserLimits) -> ValidationResult {
-        match self.to_internal(tok_env, limits) {
-            Ok((_, lex_spec)) => ValidationResult::from_warning(lex_spec.render_warnings()),
-            Err(e) => ValidationResult::Error(e.to_string()),
+        self.validate_ext(tok_env, limits, None)
+    }
+
+    /// Like `validate()`, but Lark `%import` statements are resolved
+    /// with `import_resolver`.
+    pub fn validate_ext(
+        self,
+        tok_env: Option<TokEnv>,
+        limits: ParserLimits,
+        import_resolver: Option<Arc<dyn ImportResolver>>,
+    ) -> ValidationResult {
+        if let GrammarInit::Compiled(c) = &self {
+            return ValidationResult::from_warnings(c.grammar.lexer_spec().warning_diagnostics());
+        }
+        match self.to_internal_ext(tok_env, limits, import_resolver) {
+            Ok((grammar, mut lex_spec)) => {
+                // only for the warnings about rewritten rules
+                optimize_grammar(&grammar, &mut lex_spec);
+                ValidationResult::from_warnings(lex_spec.warning_diagnostics())
+            }
+            Err(e) => ValidationResult::from_error(&e),
         }
     }
 
@@ -180,18 +259,37 @@ impl GrammarInit {
         logger: &mut Logger,
         limits: ParserLimits,
         extra_lexemes: Vec<String>,
+        import_resolver: Option<Arc<dyn ImportResolver>>,
     ) -> Result<Arc<CGrammar>> {
+        if let GrammarInit::Compiled(c) = self {
+            ensure!(
+                c.extra_lexemes == extra_lexemes,
+                "compiled grammar was created with different slices"
+            );
+            return Ok(c.grammar);
+        }
         let t0 = Instant::now();
-        let (grammar, mut lexer_spec) = self.to_internal(tok_env, limits.clone())?;
+        let (grammar, mut lexer_spec) =
+            self.to_internal_ext(tok_env, limits.clone(), import_resolver)?;
         lexer_spec.add_extra_lexemes(&extra_lexemes);
         compile_grammar(t0, grammar, lexer_spec, logger, &limits)
     }
 }
 
+/// Run [`Grammar::optimize()`], recording its warnings in `lexer_spec`.
+fn optimize_grammar(grammar: &Grammar, lexer_spec: &mut LexerSpec) -> Grammar {
+    let mut warnings = vec![];
+    let grammar = grammar.optimize(&mut warnings);
+    for w in warnings {
+        lexer_spec.add_warning(&w.code, w.message, w.span, w.json_pointer);
+    }
+    grammar
+}
+
 fn compile_grammar(
     t0: Instant,
     mut grammar: Grammar,
-    lexer_spec: LexerSpec,
+    mut lexer_spec: LexerSpec,
     logger: &mut Logger,
     limits: &ParserLimits,
 ) -> Result<Arc<CGrammar>> {
@@ -214,7 +312,7 @@ fn compile_grammar(
     }
 
     let t1 = Instant::now();
-    grammar = grammar.optimize();
+    grammar = optimize_grammar(&grammar, &mut lexer_spec);
 
     if log_grammar {
         write!(
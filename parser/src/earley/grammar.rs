@@ -1,5 +1,6 @@
 use super::lexerspec::{LexemeClass, LexemeIdx, LexerSpec};
 use crate::api::{GenGrammarOptions, GrammarId, NodeProps, ParserLimits};
+use crate::diagnostics::{Diagnostic, SourceSpan};
 use crate::hashcons::{HashCons, HashId};
 use crate::{HashMap, HashSet};
 use anyhow::{bail, ensure, Result};
@@ -49,7 +50,7 @@ impl Symbol {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SymbolProps {
     pub max_tokens: usize,
     pub capture_name: Option<String>,
@@ -154,6 +155,8 @@ pub struct Grammar {
     symbols: Vec<Symbol>,
     symbol_count_cache: HashMap<String, usize>,
     symbol_by_name: HashMap<String, SymIdx>,
+    // where rules were defined in the grammar source, if known (for warnings)
+    rule_spans: HashMap<SymIdx, SourceSpan>,
 }
 
 #[derive(Clone, Default, Copy, PartialEq, Eq, Debug, Serialize, Deserialize, Hash)]
@@ -412,9 +415,15 @@ impl Grammar {
             symbols: vec![],
             symbol_by_name: HashMap::default(),
             symbol_count_cache: HashMap::default(),
+            rule_spans: HashMap::default(),
         }
     }
 
+    /// Record where the rule `sym` is defined in the grammar source.
+    pub fn set_rule_span(&mut self, sym: SymIdx, span: SourceSpan) {
+        self.rule_spans.insert(sym, span);
+    }
+
     pub fn start(&self) -> SymIdx {
         self.symbols[0].idx
     }
@@ -819,9 +828,81 @@ impl Grammar {
         outp
     }
 
-    pub fn optimize(&self) -> Self {
+    /// Rewrite right-recursive rules `A : x A | y` (for any number of `x` and `y` alternatives,
+    /// where `A` doesn't occur in `x` nor `y`) into the left-recursive form
+    /// `A : y | A#rec y`, `A#rec : x | A#rec x`, which is much more efficient in Earley.
+    /// Returns names of rewritten symbols.
+    fn right_to_left_recursion(&mut self) -> Vec<String> {
+        let mut rewritten = vec![];
+        for idx in 0..self.symbols.len() {
+            let sym = &self.symbols[idx];
+            if sym.is_terminal()
+                || sym.gen_grammar.is_some()
+                || sym.props.is_special()
+                || sym.props.parametric
+                || sym.rules.iter().any(|r| !r.condition.is_true())
+            {
+                continue;
+            }
+            let lhs = sym.idx;
+            let is_rec = |r: &Rule| {
+                r.rhs.len() > 1
+                    && r.rhs.last().unwrap().0 == lhs
+                    && r.rhs[..r.rhs.len() - 1].iter().all(|e| e.0 != lhs)
+            };
+            let is_base = |r: &Rule| r.rhs.iter().all(|e| e.0 != lhs);
+            let num_rec = sym.rules.iter().filter(|r| is_rec(r)).count();
+            if num_rec == 0 || num_rec == sym.rules.len() {
+                continue;
+            }
+            if !sym.rules.iter().all(|r| is_rec(r) || is_base(r)) {
+                continue;
+            }
+
+            let (rec, base): (Vec<Rule>, Vec<Rule>) = std::mem::take(&mut self.symbols[idx].rules)
+                .into_iter()
+                .partition(is_rec);
+            let name = self.symbols[idx].name.clone();
+            let props = SymbolProps {
+                grammar_id: self.symbols[idx].props.grammar_id,
+                temperature: self.symbols[idx].props.temperature,
+                ..Default::default()
+            };
+            let prefix = self.fresh_symbol_ext(&format!("{name}#rec"), props);
+            for mut r in rec {
+                r.rhs.pop();
+                let mut rhs = r.rhs.clone();
+                rhs.insert(0, (prefix, ParamExpr::Null));
+                self.add_rule_ext(prefix, ParamCond::True, r.rhs).unwrap();
+                self.add_rule_ext(prefix, ParamCond::True, rhs).unwrap();
+            }
+            for r in base {
+                let mut rhs = r.rhs.clone();
+                rhs.insert(0, (prefix, ParamExpr::Null));
+                self.add_rule_ext(lhs, ParamCond::True, r.rhs).unwrap();
+                self.add_rule_ext(lhs, ParamCond::True, rhs).unwrap();
+            }
+            rewritten.push(name);
+        }
+        rewritten
+    }
+
+    pub fn optimize(&self, warnings: &mut Vec<Diagnostic>) -> Self {
         let mut r = self.expand_shortcuts();
         r = r.expand_shortcuts();
+        for name in r.right_to_left_recursion() {
+            let span = self
+                .symbol_by_name
+                .get(&name)
+                .and_then(|idx| self.rule_spans.get(idx))
+                .cloned();
+            warnings.push(Diagnostic::warning(
+                "right_recursion",
+                format!("rewrote right-recursive rule {name:?} into left-recursive form"),
+                span,
+                None,
+            ));
+        }
         r.rename();
         r
     }
@@ -1004,7 +1085,7 @@ impl Debug for Grammar {
 }
 
 /// A unique ID of a symbol in the compiled grammar
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct CSymIdx(u16);
 
 impl CSymIdx {
@@ -1024,7 +1105,7 @@ impl CSymIdx {
 
 /// This is a pointer into rhs_elements[] array, and represents a particular
 /// element in the rhs of a rule (and thus by implication also a unique lhs).
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct RhsPtr(u32);
 
 impl RhsPtr {
@@ -1037,7 +1118,7 @@ impl RhsPtr {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct CSymbol {
     pub idx: CSymIdx,
     pub name: String,
@@ -1074,7 +1155,7 @@ impl CSymbol {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub struct SymFlags(u8);
 
 impl SymFlags {
@@ -1121,7 +1202,9 @@ impl SymFlags {
     }
 }
 
-#[derive(Clone)]
+/// The compiled grammar; it can be serialized (together with its [`LexerSpec`])
+/// to avoid re-compiling the grammar, see [`crate::CompiledGrammar`].
+#[derive(Clone, Serialize, Deserialize)]
 pub struct CGrammar {
     parametric: bool,
     start_symbol: CSymIdx,
@@ -1204,10 +1287,88 @@ impl CGrammar {
         self.start_symbol
     }
 
+    pub fn num_symbols(&self) -> usize {
+        self.symbols.len()
+    }
+
     pub fn rules_of(&self, sym: CSymIdx) -> &[RhsPtr] {
         &self.sym_data(sym).rules
     }
 
+    /// Check the internal indices of a deserialized grammar, so that a corrupted
+    /// blob results in an error instead of a panic in the parser.
+    pub(crate) fn validate(&self) -> Result<()> {
+        let num_symbols = self.symbols.len();
+        let num_rules = self.rhs_ptr_to_sym_idx.len();
+        let num_lexemes = self.lexer_spec.lexemes.len();
+        let num_classes = self.lexer_spec.skip_by_class.len();
+        let valid_sym = |s: CSymIdx| s.as_index() < num_symbols;
+
+        ensure!(num_symbols > 0, "no symbols");
+        ensure!(valid_sym(self.start_symbol), "invalid start symbol");
+        ensure!(
+            self.rhs_elements.first() == Some(&CSymIdx::NULL)
+                && self.rhs_elements.last() == Some(&CSymIdx::NULL),
+            "rule right-hand sides are not terminated"
+        );
+        ensure!(
+            self.rhs_params.len() == self.rhs_elements.len(),
+            "rule parameters do not match right-hand sides"
+        );
+        ensure!(
+            self.rhs_elements.iter().all(|&s| valid_sym(s)),
+            "invalid symbol in rule right-hand side"
+        );
+        ensure!(
+            self.rhs_ptr_to_sym_flags.len() == num_rules
+                && self.rhs_ptr_to_sym_idx.iter().all(|&s| valid_sym(s)),
+            "invalid rule lhs table"
+        );
+
+        for (idx, sym) in self.symbols.iter().enumerate() {
+            ensure!(sym.idx.as_index() == idx, "invalid symbol index {}", idx);
+            ensure!(
+                sym.rules_cond.len() == sym.rules.len(),
+                "rule conditions do not match rules of {}",
+                sym.name
+            );
+            ensure!(
+                sym.props.grammar_id.as_usize() < num_classes,
+                "invalid grammar id of {}",
+                sym.name
+            );
+            ensure!(
+                sym.gen_grammar.is_none()
+                    || (sym.rules.len() == 1
+                        && self.rhs_elements.get(sym.rules[0].as_index() + 1)
+                            == Some(&CSymIdx::NULL)),
+                "invalid nested grammar {}",
+                sym.name
+            );
+            if let Some(lex) = sym.lexeme {
+                ensure!(
+                    lex.as_usize() < num_lexemes,
+                    "invalid lexeme of {}",
+                    sym.name
+                );
+            }
+            for rule in &sym.rules {
+                let ptr = rule.as_index();
+                ensure!(
+                    ptr > 0
+                        && ptr < self.rhs_elements.len()
+                        && self.rhs_elements[ptr - 1] == CSymIdx::NULL
+                        && ptr >> RULE_SHIFT < num_rules
+                        && self.rhs_ptr_to_sym_idx[ptr >> RULE_SHIFT] == sym.idx,
+                    "invalid rule of {}",
+                    sym.name
+                );
+            }
+        }
+
+        Ok(())
+    }
+
     fn add_symbol(&mut self, mut sym: CSymbol) -> CSymIdx {
         let idx = CSymIdx::new_checked(self.symbols.len());
         sym.idx = idx;
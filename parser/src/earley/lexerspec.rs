@@ -1,11 +1,14 @@
-use anyhow::{ensure, Result};
+use anyhow::{bail, ensure, Result};
 use derivre::{raw::ExprSet, ExprRef, HashMap, JsonQuoteOptions, RegexAst, RegexBuilder};
+use serde::{Deserialize, Serialize};
 use std::{fmt::Debug, hash::Hash, ops::RangeInclusive};
 use toktrie::{bytes::limit_bytes, SimpleVob, TokTrie, TokenId};
 
 use crate::{
     api::{ParserLimits, SkipRepetition, SkipSpec},
+    diagnostics::{Diagnostic, SourceSpan},
     id32_type,
+    substring::substring,
 };
 
 use super::{
@@ -19,6 +22,7 @@ pub struct LexerSpec {
     pub regex_builder: RegexBuilder,
     pub no_forcing: bool,
     pub allow_initial_skip: bool,
+    pub token_healing: bool,
     pub num_extra_lexemes: usize,
     pub skip_by_class: Vec<LexemeIdx>,
     class_by_skip: HashMap<(ExprRef, SkipRepetition), LexemeClass>,
@@ -28,10 +32,14 @@ pub struct LexerSpec {
     pub has_stop: bool,
     pub has_max_tokens: bool,
     pub has_temperature: bool,
-    pub grammar_warnings: Vec<(String, usize)>,
+    // distinct warnings, sorted by message, with the number of times each was added
+    pub grammar_warnings: Vec<(Diagnostic, usize)>,
+    // all mutating operations on regex_builder, in order;
+    // replaying them re-creates the same ExprRef's (used for serialization)
+    regex_ops: Vec<RegexOp>,
 }
 
-#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub struct LexemeClass(u8);
 
 impl LexemeClass {
@@ -63,6 +71,8 @@ pub struct LexemeSpec {
     pub(crate) skip_repetition: SkipRepetition,
     json_options: Option<JsonQuoteOptions>,
     pub(crate) token_ranges: Vec<RangeInclusive<TokenId>>,
+    // when several lexemes match the same input, the highest priority wins
+    priority: i32,
 }
 
 // LexemeIdx is an index into the lexeme table.
@@ -113,6 +123,9 @@ impl LexemeSpec {
         if self.is_extra {
             f.push_str(" extra");
         }
+        if self.priority != 0 {
+            write!(f, " priority={}", self.priority).unwrap();
+        }
         if !self.token_ranges.is_empty() {
             write!(f, " tokens={}", token_ranges_to_string(&self.token_ranges)).unwrap();
         }
@@ -140,6 +153,7 @@ impl LexerSpec {
             regex_builder: RegexBuilder::new(),
             no_forcing: false,
             allow_initial_skip: false,
+            token_healing: false,
             num_extra_lexemes: 0,
             skip_by_class: Vec::new(),
             current_class: LexemeClass(0),
@@ -148,15 +162,85 @@ impl LexerSpec {
             has_max_tokens: false,
             has_temperature: false,
             grammar_warnings: Vec::new(),
+            regex_ops: Vec::new(),
         })
     }
 
+    /// Build a regex from AST; use this instead of `regex_builder.mk()`,
+    /// so that the operation can be replayed when deserializing.
+    pub fn mk(&mut self, ast: &RegexAst) -> Result<ExprRef> {
+        let r = self.regex_builder.mk(ast)?;
+        self.regex_ops
+            .push(RegexOp::Mk(SerializedRegexAst::from_ast(ast), r.as_u32()));
+        Ok(r)
+    }
+
+    pub fn mk_regex(&mut self, rx: &str) -> Result<ExprRef> {
+        let r = self.regex_builder.mk_regex(rx)?;
+        self.regex_ops
+            .push(RegexOp::MkRegex(rx.to_string(), r.as_u32()));
+        Ok(r)
+    }
+
+    pub fn mk_substring(&mut self, chunks: Vec<&str>) -> Result<ExprRef> {
+        let owned = chunks.iter().map(|s| s.to_string()).collect();
+        let r = substring(&mut self.regex_builder, chunks)?;
+        self.regex_ops.push(RegexOp::Substring(owned, r.as_u32()));
+        Ok(r)
+    }
+
+    fn json_quote(&mut self, e: ExprRef, opts: &JsonQuoteOptions) -> Result<ExprRef> {
+        let r = self.regex_builder.json_quote(e, opts)?;
+        self.regex_ops.push(RegexOp::JsonQuote(
+            e.as_u32(),
+            opts.allowed_escapes.clone(),
+            opts.raw_mode,
+            r.as_u32(),
+        ));
+        Ok(r)
+    }
+
+    /// Set both unicode and utf8 flags of the regex parser.
+    pub fn set_utf8(&mut self, utf8: bool) {
+        self.regex_builder.unicode(utf8);
+        self.regex_builder.utf8(utf8);
+        self.regex_ops.push(RegexOp::SetUtf8(utf8));
+    }
+
+    /// Record a warning, at `span` in the grammar source or `json_pointer`
+    /// in the JSON schema, if known.
+    pub fn add_warning(
+        &mut self,
+        code: &str,
+        msg: String,
+        span: Option<SourceSpan>,
+        json_pointer: Option<String>,
+    ) {
+        let w = Diagnostic::warning(code, msg, span, json_pointer);
+        match self.grammar_warnings.iter_mut().find(|(d, _)| *d == w) {
+            Some((_, count)) => *count += 1,
+            None => {
+                self.grammar_warnings.push((w, 1));
+                self.grammar_warnings
+                    .sort_by(|a, b| a.0.message.cmp(&b.0.message));
+            }
+        }
+    }
+
+    /// Warnings as plain text; the same message at different places is listed once.
     pub fn render_warnings(&self) -> Vec<String> {
+        let mut merged: Vec<(&str, usize)> = vec![];
+        for (d, count) in &self.grammar_warnings {
+            match merged.last_mut() {
+                Some((msg, n)) if *msg == d.message => *n += count,
+                _ => merged.push((&d.message, *count)),
+            }
+        }
         let mut total_len = 0;
         let mut r = vec![];
-        for (msg, count) in &self.grammar_warnings {
-            let mut s = msg.clone();
-            if count > &1 {
+        for (msg, count) in merged {
+            let mut s = msg.to_string();
+            if count > 1 {
                 s.push_str(&format!(" ({count} times)"));
             }
             total_len += s.len();
@@ -169,6 +253,25 @@ impl LexerSpec {
         r
     }
 
+    /// Warnings with their codes and locations.
+    pub fn warning_diagnostics(&self) -> Vec<Diagnostic> {
+        let mut total_len = 0;
+        let mut r = vec![];
+        for (d, count) in &self.grammar_warnings {
+            let mut d = d.clone();
+            if *count > 1 {
+                d.message.push_str(&format!(" ({count} times)"));
+            }
+            total_len += d.message.len();
+            r.push(d);
+            if total_len > 16 * 1024 {
+                r.push(Diagnostic::warning("truncated", "...", None, None));
+                break;
+            }
+        }
+        r
+    }
+
     pub fn can_rollback(&self) -> bool {
         !self.has_stop && !self.has_max_tokens
     }
@@ -197,7 +300,7 @@ impl LexerSpec {
             regex: skip,
             repetition: skip_repetition,
         } = skip;
-        let skip_node = self.regex_builder.mk(&skip)?; // validate first
+        let skip_node = self.mk(&skip)?; // validate first
 
         if !self.has_max_tokens && !self.has_temperature {
             if let Some(&cls) = self.class_by_skip.get(&(skip_node, skip_repetition)) {
@@ -283,7 +386,7 @@ impl LexerSpec {
             .iter()
             .map(|lex| RxLexeme {
                 rx: lex.compiled_rx,
-                priority: 0,
+                priority: lex.priority,
                 lazy: lex.lazy,
             })
             .collect();
@@ -304,12 +407,12 @@ impl LexerSpec {
                     RegexAst::Byte(TokTrie::SPECIAL_TOKEN_MARKER),
                     RegexAst::Regex(r"\[[0-9]+\]".to_string()),
                 ]);
-                let compiled = self.regex_builder.mk(&rx_ast)?;
+                let compiled = self.mk(&rx_ast)?;
                 self.special_token_rx = Some(compiled);
                 compiled
             }
         } else {
-            self.regex_builder.mk(&spec.rx)?
+            self.mk(&spec.rx)?
         };
 
         if !self.has_stop && !spec.is_suffix {
@@ -329,7 +432,7 @@ impl LexerSpec {
         }
 
         let compiled = if let Some(ref opts) = spec.json_options {
-            self.regex_builder.json_quote(compiled, opts)?
+            self.json_quote(compiled, opts)?
         } else {
             compiled
         };
@@ -342,6 +445,7 @@ impl LexerSpec {
                 && lex.is_extra == spec.is_extra
                 && lex.is_skip == spec.is_skip
                 && lex.skip_repetition == spec.skip_repetition
+                && lex.priority == spec.priority
         }) {
             return Ok(LexemeIdx::new(idx));
         }
@@ -378,6 +482,7 @@ impl LexerSpec {
             class: self.current_class,
             max_tokens: usize::MAX,
             token_ranges: vec![],
+            priority: 0,
         }
     }
 
@@ -439,6 +544,7 @@ impl LexerSpec {
         contextual: bool,
         json_options: Option<JsonQuoteOptions>,
         max_tokens: usize,
+        priority: i32,
     ) -> Result<LexemeIdx> {
         self.add_lexeme_spec(LexemeSpec {
             name,
@@ -446,6 +552,7 @@ impl LexerSpec {
             contextual,
             json_options,
             max_tokens,
+            priority,
             ..self.empty_spec()
         })
     }
@@ -616,3 +723,341 @@ impl Lexeme {
         &self.bytes
     }
 }
+
+/// A mirror of [`RegexAst`] that can be serialized.
+/// References to previously built expressions are stored as raw ids.
+#[derive(Clone, Serialize, Deserialize)]
+enum SerializedRegexAst {
+    And(Vec<SerializedRegexAst>),
+    Or(Vec<SerializedRegexAst>),
+    Concat(Vec<SerializedRegexAst>),
+    LookAhead(Box<SerializedRegexAst>),
+    Not(Box<SerializedRegexAst>),
+    Repeat(Box<SerializedRegexAst>, u32, u32),
+    MultipleOf(u32, u32),
+    EmptyString,
+    NoMatch,
+    Regex(String),
+    SearchRegex(String),
+    Literal(String),
+    ByteLiteral(Vec<u8>),
+    Byte(u8),
+    ByteSet(Vec<u32>),
+    JsonQuote(Box<SerializedRegexAst>, String, bool),
+    ExprRef(u32),
+}
+
+impl SerializedRegexAst {
+    fn from_ast(ast: &RegexAst) -> Self {
+        let list = |args: &[RegexAst]| args.iter().map(Self::from_ast).collect();
+        let boxed = |arg: &RegexAst| Box::new(Self::from_ast(arg));
+        match ast {
+            RegexAst::And(args) => Self::And(list(args)),
+            RegexAst::Or(args) => Self::Or(list(args)),
+            RegexAst::Concat(args) => Self::Concat(list(args)),
+            RegexAst::LookAhead(e) => Self::LookAhead(boxed(e)),
+            RegexAst::Not(e) => Self::Not(boxed(e)),
+            RegexAst::Repeat(e, min, max) => Self::Repeat(boxed(e), *min, *max),
+            RegexAst::MultipleOf(d, s) => Self::MultipleOf(*d, *s),
+            RegexAst::EmptyString => Self::EmptyString,
+            RegexAst::NoMatch => Self::NoMatch,
+            RegexAst::Regex(s) => Self::Regex(s.clone()),
+            RegexAst::SearchRegex(s) => Self::SearchRegex(s.clone()),
+            RegexAst::Literal(s) => Self::Literal(s.clone()),
+            RegexAst::ByteLiteral(s) => Self::ByteLiteral(s.clone()),
+            RegexAst::Byte(b) => Self::Byte(*b),
+            RegexAst::ByteSet(s) => Self::ByteSet(s.clone()),
+            RegexAst::JsonQuote(e, opts) => {
+                Self::JsonQuote(boxed(e), opts.allowed_escapes.clone(), opts.raw_mode)
+            }
+            RegexAst::ExprRef(e) => Self::ExprRef(e.as_u32()),
+        }
+    }
+
+    fn to_ast(&self, expr_ref: &impl Fn(u32) -> Result<ExprRef>) -> Result<RegexAst> {
+        let list = |args: &[SerializedRegexAst]| -> Result<Vec<RegexAst>> {
+            args.iter().map(|a| a.to_ast(expr_ref)).collect()
+        };
+        let boxed = |arg: &SerializedRegexAst| -> Result<Box<RegexAst>> {
+            Ok(Box::new(arg.to_ast(expr_ref)?))
+        };
+        Ok(match self {
+            Self::And(args) => RegexAst::And(list(args)?),
+            Self::Or(args) => RegexAst::Or(list(args)?),
+            Self::Concat(args) => RegexAst::Concat(list(args)?),
+            Self::LookAhead(e) => RegexAst::LookAhead(boxed(e)?),
+            Self::Not(e) => RegexAst::Not(boxed(e)?),
+            Self::Repeat(e, min, max) => RegexAst::Repeat(boxed(e)?, *min, *max),
+            Self::MultipleOf(d, s) => RegexAst::MultipleOf(*d, *s),
+            Self::EmptyString => RegexAst::EmptyString,
+            Self::NoMatch => RegexAst::NoMatch,
+            Self::Regex(s) => RegexAst::Regex(s.clone()),
+            Self::SearchRegex(s) => RegexAst::SearchRegex(s.clone()),
+            Self::Literal(s) => RegexAst::Literal(s.clone()),
+            Self::ByteLiteral(s) => RegexAst::ByteLiteral(s.clone()),
+            Self::Byte(b) => RegexAst::Byte(*b),
+            Self::ByteSet(s) => RegexAst::ByteSet(s.clone()),
+            Self::JsonQuote(e, allowed_escapes, raw_mode) => RegexAst::JsonQuote(
+                boxed(e)?,
+                JsonQuoteOptions {
+                    allowed_escapes: allowed_escapes.clone(),
+                    raw_mode: *raw_mode,
+                },
+            ),
+            Self::ExprRef(e) => RegexAst::ExprRef(expr_ref(*e)?),
+        })
+    }
+}
+
+/// A mutating operation on the regex builder; the last field is the resulting
+/// expression id.
+/// derivre does not expose the structure of its expression set, so instead
+/// of serializing it directly, we replay the operations that created it.
+/// The builder is deterministic, so the replay yields the same expression ids.
+#[derive(Clone, Serialize, Deserialize)]
+enum RegexOp {
+    Mk(SerializedRegexAst, u32),
+    MkRegex(String, u32),
+    Substring(Vec<String>, u32),
+    JsonQuote(u32, String, bool, u32),
+    SetUtf8(bool),
+}
+
+#[derive(Serialize, Deserialize)]
+struct SerializedLexeme {
+    name: String,
+    class: LexemeClass,
+    compiled_rx: u32,
+    ends_at_eos: bool,
+    lazy: bool,
+    contextual: bool,
+    max_tokens: usize,
+    is_extra: bool,
+    is_suffix: bool,
+    is_skip: bool,
+    skip_repetition: SkipRepetition,
+    token_ranges: Vec<RangeInclusive<TokenId>>,
+    #[serde(default)]
+    priority: i32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SerializedLexerSpec {
+    regex_ops: Vec<RegexOp>,
+    lexemes: Vec<SerializedLexeme>,
+    no_forcing: bool,
+    allow_initial_skip: bool,
+    token_healing: bool,
+    num_extra_lexemes: usize,
+    skip_by_class: Vec<LexemeIdx>,
+    class_by_skip: Vec<(u32, SkipRepetition, LexemeClass)>,
+    current_class: LexemeClass,
+    special_token_rx: Option<u32>,
+    has_stop: bool,
+    has_max_tokens: bool,
+    has_temperature: bool,
+    grammar_warnings: Vec<SerializedWarning>,
+}
+
+// Diagnostic skips empty fields when serialized, which only works with self-describing formats
+#[derive(Serialize, Deserialize)]
+struct SerializedWarning {
+    code: String,
+    message: String,
+    span: Option<SourceSpan>,
+    json_pointer: Option<String>,
+    count: usize,
+}
+
+impl LexerSpec {
+    fn to_serialized(&self) -> SerializedLexerSpec {
+        SerializedLexerSpec {
+            regex_ops: self.regex_ops.clone(),
+            lexemes: self
+                .lexemes
+                .iter()
+                .map(|lex| SerializedLexeme {
+                    name: lex.name.clone(),
+                    class: lex.class,
+                    compiled_rx: lex.compiled_rx.as_u32(),
+                    ends_at_eos: lex.ends_at_eos,
+                    lazy: lex.lazy,
+                    contextual: lex.contextual,
+                    max_tokens: lex.max_tokens,
+                    is_extra: lex.is_extra,
+                    is_suffix: lex.is_suffix,
+                    is_skip: lex.is_skip,
+                    skip_repetition: lex.skip_repetition,
+                    token_ranges: lex.token_ranges.clone(),
+                    priority: lex.priority,
+                })
+                .collect(),
+            no_forcing: self.no_forcing,
+            allow_initial_skip: self.allow_initial_skip,
+            token_healing: self.token_healing,
+            num_extra_lexemes: self.num_extra_lexemes,
+            skip_by_class: self.skip_by_class.clone(),
+            class_by_skip: self
+                .class_by_skip
+                .iter()
+                .map(|((e, rep), cls)| (e.as_u32(), *rep, *cls))
+                .collect(),
+            current_class: self.current_class,
+            special_token_rx: self.special_token_rx.map(|e| e.as_u32()),
+            has_stop: self.has_stop,
+            has_max_tokens: self.has_max_tokens,
+            has_temperature: self.has_temperature,
+            grammar_warnings: self
+                .grammar_warnings
+                .iter()
+                .map(|(d, count)| SerializedWarning {
+                    code: d.code.clone(),
+                    message: d.message.clone(),
+                    span: d.span.clone(),
+                    json_pointer: d.json_pointer.clone(),
+                    count: *count,
+                })
+                .collect(),
+        }
+    }
+
+    fn expr_ref(&self, e: u32) -> Result<ExprRef> {
+        let e = ExprRef::new(e);
+        ensure!(self.regex_builder.exprset().is_valid(e), "invalid regex id");
+        Ok(e)
+    }
+
+    fn from_serialized(ser: SerializedLexerSpec) -> Result<Self> {
+        let mut spec = LexerSpec::new()?;
+
+        for op in ser.regex_ops {
+            let (r, expected) = match &op {
+                RegexOp::Mk(ast, r) => {
+                    let ast = ast.to_ast(&|e| spec.expr_ref(e))?;
+                    (spec.regex_builder.mk(&ast)?, *r)
+                }
+                RegexOp::MkRegex(rx, r) => (spec.regex_builder.mk_regex(rx)?, *r),
+                RegexOp::Substring(chunks, r) => (
+                    substring(
+                        &mut spec.regex_builder,
+                        chunks.iter().map(|s| s.as_str()).collect(),
+                    )?,
+                    *r,
+                ),
+                RegexOp::JsonQuote(e, allowed_escapes, raw_mode, r) => {
+                    let opts = JsonQuoteOptions {
+                        allowed_escapes: allowed_escapes.clone(),
+                        raw_mode: *raw_mode,
+                    };
+                    let e = spec.expr_ref(*e)?;
+                    (spec.regex_builder.json_quote(e, &opts)?, *r)
+                }
+                RegexOp::SetUtf8(utf8) => {
+                    spec.regex_builder.unicode(*utf8);
+                    spec.regex_builder.utf8(*utf8);
+                    spec.regex_ops.push(op);
+                    continue;
+                }
+            };
+            ensure!(
+                r.as_u32() == expected,
+                "regex replay mismatch: expected #{}, got #{}; was the grammar compiled with a different {}?",
+                expected,
+                r.as_u32(),
+                derivre::VERSION
+            );
+            spec.regex_ops.push(op);
+        }
+
+        for (idx, lex) in ser.lexemes.into_iter().enumerate() {
+            let compiled_rx = spec.expr_ref(lex.compiled_rx)?;
+            let idx = LexemeIdx::new(idx);
+            spec.lexemes.push(LexemeSpec {
+                idx,
+                single_set: MatchingLexemes::One(idx),
+                name: lex.name,
+                // the source regex is not kept; keep NoMatch as is for skip lexemes
+                rx: if compiled_rx == ExprRef::NO_MATCH {
+                    RegexAst::NoMatch
+                } else {
+                    RegexAst::ExprRef(compiled_rx)
+                },
+                class: lex.class,
+                compiled_rx,
+                ends_at_eos: lex.ends_at_eos,
+                lazy: lex.lazy,
+                contextual: lex.contextual,
+                max_tokens: lex.max_tokens,
+                is_extra: lex.is_extra,
+                is_suffix: lex.is_suffix,
+                is_skip: lex.is_skip,
+                skip_repetition: lex.skip_repetition,
+                json_options: None,
+                token_ranges: lex.token_ranges,
+                priority: lex.priority,
+            });
+        }
+
+        for (e, rep, cls) in ser.class_by_skip {
+            let e = spec.expr_ref(e)?;
+            spec.class_by_skip.insert((e, rep), cls);
+        }
+        if let Some(e) = ser.special_token_rx {
+            spec.special_token_rx = Some(spec.expr_ref(e)?);
+        }
+
+        let num_lexemes = spec.lexemes.len();
+        ensure!(
+            ser.skip_by_class
+                .iter()
+                .all(|idx| idx.as_usize() < num_lexemes),
+            "invalid skip lexeme"
+        );
+        if ser.num_extra_lexemes > num_lexemes {
+            bail!("invalid number of extra lexemes");
+        }
+        let num_classes = ser.skip_by_class.len();
+        ensure!(
+            spec.lexemes
+                .iter()
+                .map(|lex| lex.class)
+                .chain(spec.class_by_skip.values().copied())
+                .chain([ser.current_class])
+                .all(|cls| cls.as_usize() < num_classes),
+            "invalid lexeme class"
+        );
+
+        spec.no_forcing = ser.no_forcing;
+        spec.allow_initial_skip = ser.allow_initial_skip;
+        spec.token_healing = ser.token_healing;
+        spec.num_extra_lexemes = ser.num_extra_lexemes;
+        spec.skip_by_class = ser.skip_by_class;
+        spec.current_class = ser.current_class;
+        spec.has_stop = ser.has_stop;
+        spec.has_max_tokens = ser.has_max_tokens;
+        spec.has_temperature = ser.has_temperature;
+        spec.grammar_warnings = ser
+            .grammar_warnings
+            .into_iter()
+            .map(|w| {
+                let d = Diagnostic::warning(&w.code, w.message, w.span, w.json_pointer);
+                (d, w.count)
+            })
+            .collect();
+
+        Ok(spec)
+    }
+}
+
+impl Serialize for LexerSpec {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_serialized().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for LexerSpec {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let ser = SerializedLexerSpec::deserialize(deserializer)?;
+        LexerSpec::from_serialized(ser).map_err(serde::de::Error::custom)
+    }
+}
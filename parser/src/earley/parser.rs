@@ -5,6 +5,8 @@
 // (Retrieved 18 Sep 2024).
 
 use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
     fmt::{Debug, Display},
     hash::Hash,
     ops::Range,
@@ -24,6 +26,7 @@ use toktrie::{
 
 use crate::{
     api::{ParserLimits, SkipRepetition, StopReason},
+    diagnostics::Diagnostic,
     earley::{lexer::Lexer, lexerspec::LexemeClass},
     id32_type,
 };
@@ -40,6 +43,14 @@ const TRACE: bool = false;
 const DEBUG: bool = true;
 pub(crate) const ITEM_TRACE: bool = false;
 
+// Limit on lexer states visited when looking for the end of a lexeme
+// while estimating the length of completions.
+const COMPLETION_MAX_LEXER_STATES: usize = 1_000;
+
+// Limit on (symbol, parameter) pairs followed at once when computing
+// minimal lengths of symbols in parametric grammars.
+const COMPLETION_MAX_PARAM_SYMBOLS: usize = 10_000;
+
 macro_rules! trace {
     ($($arg:tt)*) => {
         if cfg!(feature = "logging") && TRACE {
@@ -394,6 +405,12 @@ struct ParserState {
     // (common in long lexemes, e.g. the interior of JSON strings)
     bias_cache: Option<BiasCache>,
 
+    // Minimal number of bytes matched by each symbol, computed on first use
+    // of completion_estimate() or approximate_completion().
+    completion_lens: Option<Arc<Vec<u32>>>,
+    // Cache for completion_estimate(), keyed like bias_cache
+    completion_cache: Option<CompletionCache>,
+
     shared_box: Box<SharedState>,
 }
 
@@ -405,6 +422,24 @@ struct BiasCache {
     mask: SimpleVob,
 }
 
+#[derive(Clone)]
+struct CompletionCache {
+    lexer_state: StateID,
+    row_idx: u32,
+    has_pending_lexeme_bytes: bool,
+    estimate: Option<usize>,
+}
+
+// Memoized results of completion_cost(); the ones for an Earley set
+// are valid as long as the set is not popped.
+#[derive(Default)]
+struct CompletionMemo {
+    // see completion_row_costs()
+    rows: HashMap<usize, HashMap<(CSymIdx, ParamValue), u32>>,
+    // see sym_min_len(); only used in parametric grammars
+    param_lens: HashMap<(CSymIdx, ParamValue), u32>,
+}
+
 #[derive(Clone, Default)]
 struct SharedState {
     lexer_opt: Option<Lexer>,
@@ -663,6 +698,8 @@ impl ParserState {
             trie_grammar_stack: 0,
             parser_error: None,
             bias_cache: None,
+            completion_lens: None,
+            completion_cache: None,
             shared_box: Box::new(SharedState {
                 lexer_opt: Some(lexer),
             }),
@@ -1030,6 +1067,7 @@ impl ParserState {
         self.last_force_bytes_len = usize::MAX;
         self.lexer_stack_top_eos = false;
         self.rows_valid_end = self.num_rows();
+        self.completion_cache = None;
 
         self.assert_definitive();
 
@@ -1100,6 +1138,27 @@ impl ParserState {
         })
     }
 
+    pub fn validate_bytes(&mut self, bytes: &[u8]) -> usize {
+        self.assert_definitive();
+        self.run_speculative("validate_bytes", |state| {
+            let mut applied_idx = state.byte_to_token_idx.len();
+            let mut recog = ParserRecognizer { state };
+            for (bidx, &b) in bytes.iter().enumerate() {
+                if applied_idx < recog.state.bytes.len() {
+                    if recog.state.bytes[applied_idx] == b {
+                        applied_idx += 1;
+                    } else {
+                        return bidx;
+                    }
+                } else if b == TokTrie::SPECIAL_TOKEN_MARKER || !recog.try_push_byte(b) {
+                    // never push FF
+                    return bidx;
+                }
+            }
+            bytes.len()
+        })
+    }
+
     fn add_numeric_token(&mut self, idx: LexemeIdx, tok_bytes: &[u8]) -> Result<()> {
         let lexer_state = self.lexer_state();
         // the last lexer state will be pushed by advance_parser() below
@@ -1698,6 +1757,446 @@ impl ParserState {
         self.lexer_stack.truncate(state.lexer_stack_length);
     }
 
+    // Run 'f' with the given lexer fuel.
+    // Running out of lexer fuel or states is sticky, so if that happens,
+    // the lexer is restored to its state from before 'f' and None is returned.
+    fn with_lexer_backup<T>(&mut self, fuel: u64, f: impl FnOnce(&mut Self) -> T) -> Option<T> {
+        let backup = self.lexer().clone();
+        let max_states = self.limits.max_lexer_states;
+        let dfa = &mut self.lexer_mut().dfa;
+        dfa.set_fuel(fuel);
+        dfa.set_max_states(max_states);
+
+        let r = f(self);
+
+        if self.lexer().dfa.has_error() {
+            *self.lexer_mut() = backup;
+            None
+        } else {
+            Some(r)
+        }
+    }
+
+    // One byte from each class of bytes the lexer does not distinguish,
+    // preferring alphanumeric and then other printable ASCII bytes.
+    fn lexer_class_bytes(&self) -> Vec<u8> {
+        let alpha = self.lexer().dfa.alpha();
+        let mut classes = HashSet::default();
+        (b'a'..=b'z')
+            .chain(b'0'..=b'9')
+            .chain(b'A'..=b'Z')
+            .chain(b' '..=b'~')
+            .chain(0..=255)
+            .filter(|&b| b != TokTrie::SPECIAL_TOKEN_MARKER && classes.insert(alpha.map(b)))
+            .collect()
+    }
+
+    // Minimal number of bytes matched by each symbol (u32::MAX if none).
+    // Lengths of lexemes are found by searching the lexer DFA;
+    // if the lexer runs out of fuel or states, no lexeme gets a length.
+    fn completion_lens(&mut self) -> Arc<Vec<u32>> {
+        if let Some(lens) = &self.completion_lens {
+            return Arc::clone(lens);
+        }
+
+        let class_bytes = self.lexer_class_bytes();
+        let lexeme_lens = self
+            .with_lexer_backup(self.limits.initial_lexer_fuel, |state| {
+                let lexer = state.lexer_mut();
+                (0..lexer.lexer_spec().lexemes.len())
+                    .map(|idx| {
+                        let idx = LexemeIdx::new(idx);
+                        let mut allowed = lexer.lexer_spec().alloc_lexeme_set();
+                        allowed.add(idx);
+                        let start = lexer.start_state(&allowed);
+                        lexeme_end_distances(lexer, start, &class_bytes)
+                            .get(&idx)
+                            .copied()
+                            .unwrap_or(u32::MAX)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        let grammar = Arc::clone(&self.grammar);
+        let mut lens = vec![u32::MAX; grammar.num_symbols()];
+        loop {
+            let mut changed = false;
+            for (idx, len) in lens.clone().into_iter().enumerate() {
+                let sym = grammar.sym_data(CSymIdx::new_checked(idx));
+                let new_len = if let Some(lx) = sym.lexeme {
+                    lexeme_lens.get(lx.as_usize()).copied().unwrap_or(u32::MAX)
+                } else if sym.is_nullable
+                    || !sym.cond_nullable.is_empty()
+                    || sym.gen_grammar.is_some()
+                {
+                    0
+                } else {
+                    sym.rules
+                        .iter()
+                        .map(|&rule| rhs_min_len(&grammar, &lens, rule))
+                        .min()
+                        .unwrap_or(u32::MAX)
+                };
+                if new_len < len {
+                    lens[idx] = new_len;
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        let lens = Arc::new(lens);
+        self.completion_lens = Some(Arc::clone(&lens));
+        lens
+    }
+
+    fn item_arg(&self, item_idx: usize) -> ParamValue {
+        if self.scratch.parametric {
+            self.scratch.item_args[item_idx]
+        } else {
+            ParamValue::default()
+        }
+    }
+
+    // Minimal number of bytes matched by the rest of the rule at 'ptr',
+    // for an item with parameter 'param'.
+    fn rest_min_len(
+        &self,
+        lens: &[u32],
+        memo: &mut CompletionMemo,
+        mut ptr: RhsPtr,
+        param: ParamValue,
+    ) -> u32 {
+        let mut r = 0u32;
+        loop {
+            let sym = self.grammar.sym_idx_dot(ptr);
+            if sym == CSymIdx::NULL {
+                return r;
+            }
+            let param = self.grammar.param_value_dot(ptr).eval(param);
+            r = r.saturating_add(sym_min_len(&self.grammar, lens, memo, sym, param));
+            ptr = RhsPtr::from_index(ptr.as_index() as u32 + 1);
+        }
+    }
+
+    // For each symbol (with its parameter) predicted in Earley set 'row_idx',
+    // the minimal number of bytes needed to finish the parse once the symbol
+    // is completed.
+    // The results for 'row_idx' and the Earley sets it depends on
+    // are stored in 'memo'.
+    fn completion_row_costs(&self, lens: &[u32], row_idx: usize, memo: &mut CompletionMemo) {
+        let mut todo = vec![row_idx];
+        let mut needed = HashSet::default();
+        while let Some(r) = todo.pop() {
+            if memo.rows.contains_key(&r) || !needed.insert(r) {
+                continue;
+            }
+            for i in self.rows[r].item_indices() {
+                let start = self.scratch.items[i].start_pos();
+                if start < r {
+                    todo.push(start);
+                }
+            }
+        }
+
+        let mut needed = needed.into_iter().collect::<Vec<_>>();
+        needed.sort_unstable();
+        for r in needed {
+            let mut costs = HashMap::default();
+            if r == 0 {
+                costs.insert((self.grammar.start(), ParamValue::default()), 0);
+            }
+            // (lhs, symbol after dot, length of the rest of the rule)
+            // for items predicted in this row
+            let mut predicted = vec![];
+            for i in self.rows[r].item_indices() {
+                let item = self.scratch.items[i];
+                let ptr = item.rhs_ptr();
+                let sym = self.grammar.sym_idx_dot(ptr);
+                if sym == CSymIdx::NULL {
+                    continue;
+                }
+                let arg = self.item_arg(i);
+                let sym = (sym, self.grammar.param_value_dot(ptr).eval(arg));
+                let rest = self.rest_min_len(lens, memo, item.advance_dot().rhs_ptr(), arg);
+                let lhs = (self.item_lhs(&item), arg);
+                if item.start_pos() == r {
+                    predicted.push((lhs, sym, rest));
+                } else if let Some(&c) = memo.rows[&item.start_pos()].get(&lhs) {
+                    min_cost(&mut costs, sym, c.saturating_add(rest));
+                }
+            }
+            loop {
+                let mut changed = false;
+                for &(lhs, sym, rest) in &predicted {
+                    if let Some(&c) = costs.get(&lhs) {
+                        changed |= min_cost(&mut costs, sym, c.saturating_add(rest));
+                    }
+                }
+                if !changed {
+                    break;
+                }
+            }
+            memo.rows.insert(r, costs);
+        }
+    }
+
+    // Minimal number of bytes needed to finish the parse from the current state.
+    fn completion_cost(
+        &mut self,
+        lens: &[u32],
+        class_bytes: &[u8],
+        memo: &mut CompletionMemo,
+    ) -> Option<usize> {
+        let row_idx = self.num_rows() - 1;
+        self.completion_row_costs(lens, row_idx, memo);
+
+        // distances to the end of each lexeme the pending bytes can be part of
+        let pending = if self.has_pending_lexeme_bytes() {
+            let lexer_state = self.lexer_state().lexer_state;
+            Some(lexeme_end_distances(
+                self.lexer_mut(),
+                lexer_state,
+                class_bytes,
+            ))
+        } else {
+            None
+        };
+
+        let mut free = u32::MAX;
+        let mut after_lexeme = u32::MAX;
+        for i in self.curr_row().item_indices() {
+            let item = self.scratch.items[i];
+            let arg = self.item_arg(i);
+            let Some(&after) = memo.rows[&item.start_pos()].get(&(self.item_lhs(&item), arg))
+            else {
+                continue;
+            };
+            free = free.min(
+                self.rest_min_len(lens, memo, item.rhs_ptr(), arg)
+                    .saturating_add(after),
+            );
+            if let Some(dist) = &pending {
+                let sym_data = self.grammar.sym_data_dot(item.rhs_ptr());
+                if let Some(&d) = sym_data.lexeme.and_then(|lx| dist.get(&lx)) {
+                    let rest = self.rest_min_len(lens, memo, item.advance_dot().rhs_ptr(), arg);
+                    after_lexeme = after_lexeme.min(d.saturating_add(rest).saturating_add(after));
+                }
+            }
+        }
+
+        let best = match &pending {
+            None => free,
+            Some(dist) => {
+                // a skip lexeme keeps the parser in the same place
+                for (&lx, &d) in dist {
+                    if self.lexer_spec().lexeme_spec(lx).is_skip {
+                        after_lexeme = after_lexeme.min(d.saturating_add(free));
+                    }
+                }
+                after_lexeme
+            }
+        };
+        (best != u32::MAX).then_some(best as usize)
+    }
+
+    /// completion_estimate() gives the length in bytes of the shortest
+    /// completion of the parse, without trying any bytes.
+    /// It uses the minimal lengths of symbols and lexemes, and the Earley sets,
+    /// so it ignores conflicts between adjacent lexemes; conditions on grammar
+    /// parameters are only followed up to COMPLETION_MAX_PARAM_SYMBOLS symbols.
+    /// Thus, it never overestimates, but can underestimate.
+    /// Returns None if no completion is found, or if the lexer runs out of
+    /// fuel or states.
+    fn completion_estimate(&mut self) -> Option<usize> {
+        let curr = self.lexer_state();
+        let has_pending = self.has_pending_lexeme_bytes();
+        if let Some(cache) = &self.completion_cache {
+            if cache.lexer_state == curr.lexer_state
+                && cache.row_idx == curr.row_idx
+                && cache.has_pending_lexeme_bytes == has_pending
+            {
+                return cache.estimate;
+            }
+        }
+
+        let lens = self.completion_lens();
+        let class_bytes = self.lexer_class_bytes();
+        let estimate = self
+            .with_lexer_backup(self.limits.step_lexer_fuel, |state| {
+                state.completion_cost(&lens, &class_bytes, &mut CompletionMemo::default())
+            })
+            .flatten();
+
+        self.completion_cache = Some(CompletionCache {
+            lexer_state: curr.lexer_state,
+            row_idx: curr.row_idx,
+            has_pending_lexeme_bytes: has_pending,
+            estimate,
+        });
+        estimate
+    }
+
+    /// shortest_completion() finds the shortest sequence of bytes
+    /// that takes the parser to an accepting state.
+    /// It does an A* search over the states of the lexer and the Earley sets
+    /// pushed on top of the current one, using completion_cost() (which never
+    /// overestimates) as the heuristic and to prune states that cannot be completed.
+    /// Only one byte is tried from each class of bytes that the lexer
+    /// does not distinguish, and states with the same lexer state
+    /// and Earley sets are visited once.
+    /// Returns None if no completion is found within 'max_states' states,
+    /// or if the lexer runs out of fuel or states.
+    fn shortest_completion(&mut self, max_states: usize) -> Option<Vec<u8>> {
+        let lens = self.completion_lens();
+        let class_bytes = self.lexer_class_bytes();
+        let max_items = self.stats.all_items + self.limits.step_max_items;
+
+        self.with_lexer_backup(self.limits.step_lexer_fuel, |state| {
+            state.run_speculative("shortest_completion", |state| {
+                let base = state.save_state();
+                let first_row = state.num_rows();
+                let mut memo = CompletionMemo::default();
+                let estimate = state.completion_cost(&lens, &class_bytes, &mut memo)?;
+                let mut seen = HashSet::default();
+                // ordered by the estimated total length, then longest path first;
+                // the counter keeps the order of insertion among equal ones
+                let mut queue = BinaryHeap::from([Reverse((estimate, Reverse(0), 0, vec![]))]);
+                let mut num_pushed = 1;
+
+                for _ in 0..max_states {
+                    let path = loop {
+                        let Reverse((_, _, _, path)) = queue.pop()?;
+                        state.restore_state(base.clone());
+                        for &b in &path {
+                            let ok = ParserRecognizer { state }.try_push_byte(b);
+                            assert!(ok);
+                        }
+                        if seen.insert(state.completion_search_key(first_row)) {
+                            break path;
+                        }
+                    };
+
+                    let saved = state.save_state();
+                    if state.is_accepting_inner() {
+                        return Some(path);
+                    }
+                    state.restore_state(saved);
+
+                    for &b in &class_bytes {
+                        let mut r = ParserRecognizer { state };
+                        if r.try_push_byte(b) {
+                            let cost = r.state.completion_cost(&lens, &class_bytes, &mut memo);
+                            memo.rows.retain(|&row, _| row < first_row);
+                            r.pop_bytes(1);
+                            if let Some(cost) = cost {
+                                let mut next = path.clone();
+                                next.push(b);
+                                let key =
+                                    (next.len() + cost, Reverse(next.len()), num_pushed, next);
+                                queue.push(Reverse(key));
+                                num_pushed += 1;
+                            }
+                        }
+                    }
+
+                    if state.lexer().dfa.has_error() || state.stats.all_items > max_items {
+                        return None;
+                    }
+                }
+                None
+            })
+        })
+        .flatten()
+    }
+
+    // Identifies the parser state in shortest_completion(): the lexer state,
+    // and the items (with their parameters) of Earley sets added since 'first_row'.
+    fn completion_search_key(
+        &self,
+        first_row: usize,
+    ) -> (u32, bool, Vec<usize>, Vec<Item>, Vec<ParamValue>) {
+        let mut row_lens = vec![];
+        let mut items = vec![];
+        let mut args = vec![];
+        for row in &self.rows[first_row..self.num_rows()] {
+            row_lens.push(row.item_indices().len());
+            items.extend_from_slice(&self.scratch.items[row.item_indices()]);
+            if self.scratch.parametric {
+                args.extend_from_slice(&self.scratch.item_args[row.item_indices()]);
+            }
+        }
+        (
+            self.lexer_state().lexer_state.as_u32(),
+            self.has_pending_lexeme_bytes(),
+            row_lens,
+            items,
+            args,
+        )
+    }
+
+    /// approximate_completion() builds a short sequence of bytes that takes
+    /// the parser to an accepting state, by repeatedly picking the byte
+    /// that minimizes completion_estimate() afterwards.
+    /// Only one byte is tried from each class of bytes that the lexer
+    /// does not distinguish.
+    /// Returns None if no accepting state is reached this way.
+    fn approximate_completion(&mut self) -> Option<Vec<u8>> {
+        let lens = self.completion_lens();
+        let class_bytes = self.lexer_class_bytes();
+        let max_items = self.stats.all_items + self.limits.step_max_items;
+
+        self.with_lexer_backup(self.limits.step_lexer_fuel, |state| {
+            state.run_speculative("approximate_completion", |state| {
+                // Earley sets below this one do not change while searching
+                let stable_rows = state.num_rows();
+                let mut memo = CompletionMemo::default();
+                let estimate = state.completion_cost(&lens, &class_bytes, &mut memo)?;
+                let max_len = 2 * estimate + 16;
+                let mut path = vec![];
+
+                loop {
+                    let saved = state.save_state();
+                    if state.is_accepting_inner() {
+                        return Some(path);
+                    }
+                    state.restore_state(saved);
+
+                    if path.len() >= max_len
+                        || state.lexer().dfa.has_error()
+                        || state.stats.all_items > max_items
+                    {
+                        return None;
+                    }
+
+                    let mut best: Option<(usize, u8)> = None;
+                    for &b in &class_bytes {
+                        let mut r = ParserRecognizer { state };
+                        if r.try_push_byte(b) {
+                            let cost = r.state.completion_cost(&lens, &class_bytes, &mut memo);
+                            memo.rows.retain(|&row, _| row < stable_rows);
+                            r.pop_bytes(1);
+                            if let Some(cost) = cost {
+                                if best.is_none_or(|(c, _)| cost < c) {
+                                    best = Some((cost, b));
+                                }
+                            }
+                        }
+                    }
+
+                    let (_, b) = best?;
+                    let ok = ParserRecognizer { state }.try_push_byte(b);
+                    assert!(ok);
+                    path.push(b);
+                }
+            })
+        })
+        .flatten()
+    }
+
     /// Advance the parser as if the current lexeme (if any)
     /// finished right here.
     /// Returns true if the parser was able to advance (or there were no pending bytes for a lexeme).
@@ -2735,6 +3234,172 @@ fn item_to_string(g: &CGrammar, item: &Item, param: ParamValue) -> String {
     r
 }
 
+// Minimal number of bytes matched by the symbols from 'ptr' to the end of the rule.
+fn rhs_min_len(g: &CGrammar, lens: &[u32], mut ptr: RhsPtr) -> u32 {
+    let mut r = 0u32;
+    loop {
+        let sym = g.sym_idx_dot(ptr);
+        if sym == CSymIdx::NULL {
+            return r;
+        }
+        r = r.saturating_add(lens[sym.as_index()]);
+        ptr = RhsPtr::from_index(ptr.as_index() as u32 + 1);
+    }
+}
+
+// Minimal number of bytes matched by 'sym' with parameter 'param'.
+// Unlike 'lens' (see completion_lens()), this follows only the rules
+// whose conditions hold, through at most COMPLETION_MAX_PARAM_SYMBOLS
+// (symbol, parameter) pairs; pairs beyond that use 'lens'.
+fn sym_min_len(
+    g: &CGrammar,
+    lens: &[u32],
+    memo: &mut CompletionMemo,
+    sym: CSymIdx,
+    param: ParamValue,
+) -> u32 {
+    if !g.parametric() {
+        return lens[sym.as_index()];
+    }
+    if let Some(&len) = memo.param_lens.get(&(sym, param)) {
+        return len;
+    }
+
+    // (symbol, parameter) pairs that 'sym' can expand to, not in memo yet
+    let mut pairs = vec![(sym, param)];
+    let mut pair_idx = HashMap::default();
+    pair_idx.insert((sym, param), 0);
+    let mut i = 0;
+    while i < pairs.len() {
+        let (s, p) = pairs[i];
+        i += 1;
+        let sym_data = g.sym_data(s);
+        if sym_data.lexeme.is_some() || sym_data.gen_grammar.is_some() {
+            continue;
+        }
+        for (&rule, cond) in sym_data.rules.iter().zip(&sym_data.rules_cond) {
+            if !cond.eval(p) {
+                continue;
+            }
+            let mut ptr = rule;
+            while g.sym_idx_dot(ptr) != CSymIdx::NULL {
+                let key = (g.sym_idx_dot(ptr), g.param_value_dot(ptr).eval(p));
+                if pairs.len() < COMPLETION_MAX_PARAM_SYMBOLS
+                    && !memo.param_lens.contains_key(&key)
+                    && !pair_idx.contains_key(&key)
+                {
+                    pair_idx.insert(key, pairs.len());
+                    pairs.push(key);
+                }
+                ptr = RhsPtr::from_index(ptr.as_index() as u32 + 1);
+            }
+        }
+    }
+
+    // pairs are mostly discovered after the ones depending on them,
+    // so going backwards needs fewer passes
+    let mut pair_lens = vec![u32::MAX; pairs.len()];
+    loop {
+        let mut changed = false;
+        for (idx, &(s, p)) in pairs.iter().enumerate().rev() {
+            let sym_data = g.sym_data(s);
+            let new_len = if sym_data.lexeme.is_some() || sym_data.gen_grammar.is_some() {
+                lens[s.as_index()]
+            } else if sym_data.is_nullable || sym_data.cond_nullable.iter().any(|c| c.eval(p)) {
+                0
+            } else {
+                let mut best = u32::MAX;
+                for (&rule, cond) in sym_data.rules.iter().zip(&sym_data.rules_cond) {
+                    if !cond.eval(p) {
+                        continue;
+                    }
+                    let mut len = 0u32;
+                    let mut ptr = rule;
+                    while g.sym_idx_dot(ptr) != CSymIdx::NULL {
+                        let key = (g.sym_idx_dot(ptr), g.param_value_dot(ptr).eval(p));
+                        let elt_len = match pair_idx.get(&key) {
+                            Some(&j) => pair_lens[j],
+                            None => memo
+                                .param_lens
+                                .get(&key)
+                                .copied()
+                                .unwrap_or(lens[key.0.as_index()]),
+                        };
+                        len = len.saturating_add(elt_len);
+                        ptr = RhsPtr::from_index(ptr.as_index() as u32 + 1);
+                    }
+                    best = best.min(len);
+                }
+                best
+            };
+            if new_len < pair_lens[idx] {
+                pair_lens[idx] = new_len;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    memo.param_lens.extend(pairs.into_iter().zip(pair_lens));
+    memo.param_lens[&(sym, param)]
+}
+
+fn min_cost(
+    costs: &mut HashMap<(CSymIdx, ParamValue), u32>,
+    sym: (CSymIdx, ParamValue),
+    cost: u32,
+) -> bool {
+    if costs.get(&sym).is_none_or(|&c| cost < c) {
+        costs.insert(sym, cost);
+        true
+    } else {
+        false
+    }
+}
+
+// Number of bytes needed to get from lexer state 'start' to a state
+// accepting each of the lexemes possible in 'start'.
+// This is a breadth-first search, trying only 'class_bytes',
+// and visiting at most COMPLETION_MAX_LEXER_STATES states.
+fn lexeme_end_distances(
+    lexer: &mut Lexer,
+    start: StateID,
+    class_bytes: &[u8],
+) -> HashMap<LexemeIdx, u32> {
+    let num_possible = lexer.possible_lexemes(start).iter().count();
+    let mut dist = HashMap::default();
+    let mut seen = HashSet::default();
+    seen.insert(start);
+    let mut layer = vec![start];
+    let mut depth = 0;
+
+    while !layer.is_empty() && dist.len() < num_possible {
+        let mut next = vec![];
+        for s in layer {
+            let desc = lexer.dfa.state_desc(s);
+            for &idx in desc
+                .greedy_accepting
+                .as_slice()
+                .iter()
+                .chain(desc.lazy_accepting.as_slice())
+            {
+                dist.entry(idx).or_insert(depth);
+            }
+            for &b in class_bytes {
+                let s2 = lexer.dfa.transition(s, b);
+                if !s2.is_dead() && seen.len() < COMPLETION_MAX_LEXER_STATES && seen.insert(s2) {
+                    next.push(s2);
+                }
+            }
+        }
+        layer = next;
+        depth += 1;
+    }
+    dist
+}
+
 pub enum ParserError {
     LexerError(String),
     ParserError(String),
@@ -2853,6 +3518,24 @@ impl Parser {
         }
     }
 
+    /// Estimated length in bytes of the shortest completion of the parse;
+    /// see ParserState::completion_estimate().
+    pub fn completion_estimate(&mut self) -> Option<usize> {
+        self.with_shared(|state| state.completion_estimate())
+    }
+
+    /// Shortest sequence of bytes after which the parser would accept,
+    /// or None if not found after visiting `max_states` parser states.
+    pub fn shortest_completion(&mut self, max_states: usize) -> Option<Vec<u8>> {
+        self.with_shared(|state| state.shortest_completion(max_states))
+    }
+
+    /// Short sequence of bytes after which the parser would accept;
+    /// see ParserState::approximate_completion().
+    pub fn approximate_completion(&mut self) -> Option<Vec<u8>> {
+        self.with_shared(|state| state.approximate_completion())
+    }
+
     pub fn scan_eos(&mut self) -> bool {
         self.with_shared(|state| state.scan_eos())
     }
@@ -2861,6 +3544,10 @@ impl Parser {
         self.with_shared(|state| state.lexer_spec().render_warnings())
     }
 
+    pub fn grammar_warning_diagnostics(&mut self) -> Vec<Diagnostic> {
+        self.with_shared(|state| state.lexer_spec().warning_diagnostics())
+    }
+
     pub(crate) fn apply_forced(&mut self, byte_idx: usize) {
         self.state.byte_to_token_idx.resize(byte_idx, 0);
     }
@@ -2906,6 +3593,11 @@ impl Parser {
         })
     }
 
+    /// Returns the length of the longest prefix of `bytes` that can be applied.
+    pub fn validate_bytes(&mut self, bytes: &[u8]) -> usize {
+        self.with_shared(|state| state.validate_bytes(bytes))
+    }
+
     pub fn log_row_infos(&mut self, label: &str) {
         if cfg!(feature = "logging") && DEBUG {
             self.with_shared(|state| {
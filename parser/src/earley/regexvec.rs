@@ -205,7 +205,6 @@ pub struct RegexVec {
     next_byte: NextByteCache,
     relevance: RelevanceCache,
     alpha: AlphabetInfo,
-    #[allow(dead_code)]
     rx_lexemes: Vec<RxLexeme>,
     lazy: LexemeSet,
     subsumable: LexemeSet,
@@ -291,8 +290,9 @@ impl RegexVec {
         let mut res = None;
         let exprs = &self.exprs;
         for (idx2, e) in iter_state(&self.rx_sets, state) {
-            if res.is_none() && exprs.is_nullable(e) {
-                assert!(desc.greedy_accepting.contains(idx2));
+            // with priorities, some nullable lexemes may not be accepting
+            if res.is_none() && desc.greedy_accepting.contains(idx2) {
+                assert!(exprs.is_nullable(e));
                 res = Some(exprs.lookahead_len(e).unwrap_or(0));
             }
         }
@@ -400,6 +400,8 @@ impl RegexVec {
         let mut eois = MatchingLexemes::None;
 
         let mut lazies = MatchingLexemes::None;
+        let mut lazy_priority = 0;
+        let mut eoi_priority = 0;
 
         let mut hidden_len = 0;
 
@@ -425,11 +427,11 @@ impl RegexVec {
             // If this is the first lazy lexeme, we can cut things short.  The first
             // lazy lexeme is our lowest, or best, match.  We return it and are done.
             if self.lazy.contains(idx) {
-                if lazies.is_none() {
+                // with priorities, the first lazy lexeme of the highest priority wins
+                if self.add_prioritized(&mut lazies, &mut lazy_priority, idx) {
                     all_eoi = false;
                     hidden_len = self.exprs.possible_lookahead_len(e) as u32;
                 }
-                lazies.add(idx);
                 continue;
             }
 
@@ -439,7 +441,7 @@ impl RegexVec {
                 if self.next_byte.next_byte(&self.exprs, e) == NextByte::ForcedEOI {
                     // then, if we have not yet found a matching greedy lexeme, set
                     // this one to be our lowest match ...
-                    eois.add(idx);
+                    self.add_prioritized(&mut eois, &mut eoi_priority, idx);
                 } else {
                     // ... otherwise, if this greedy lexeme is not yet a match, then indicate
                     // that not all greedy lexemes are matches at this point.
@@ -565,7 +567,8 @@ impl RegexVec {
 pub(crate) struct RxLexeme {
     pub rx: ExprRef,
     pub lazy: bool,
-    #[allow(dead_code)]
+    /// When several lexemes match the same input, only the ones with
+    /// the highest priority are reported as accepting.
     pub priority: i32,
 }
 
@@ -660,6 +663,28 @@ impl RegexVec {
         self.rx_list[idx.as_usize()]
     }
 
+    /// Add `idx` to `set`, unless `set` already has lexemes of higher priority;
+    /// lexemes of lower priority are dropped from `set`.
+    /// Returns true if `idx` is now the first element of `set`.
+    fn add_prioritized(
+        &self,
+        set: &mut MatchingLexemes,
+        max_priority: &mut i32,
+        idx: LexemeIdx,
+    ) -> bool {
+        let priority = self.rx_lexemes[idx.as_usize()].priority;
+        if set.is_none() || priority > *max_priority {
+            *set = MatchingLexemes::One(idx);
+            *max_priority = priority;
+            true
+        } else {
+            if priority == *max_priority {
+                set.add(idx);
+            }
+            false
+        }
+    }
+
     fn append_state(&mut self, state_desc: StateDesc) {
         let mut new_states = vec![StateID::MISSING; self.alpha.len()];
         self.state_table.append(&mut new_states);
@@ -699,10 +724,11 @@ impl RegexVec {
             lazy_hidden_len: 0,
             has_special_token: false,
         };
+        let mut max_priority = 0;
         for (idx, e) in iter_state(&self.rx_sets, state) {
             res.possible.add(idx);
             if self.exprs.is_nullable(e) {
-                res.greedy_accepting.add(idx);
+                self.add_prioritized(&mut res.greedy_accepting, &mut max_priority, idx);
             }
         }
 
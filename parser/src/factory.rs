@@ -1,12 +1,13 @@
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 
 use anyhow::Result;
 use toktrie::{InferenceCapabilities, TokEnv};
 
 use crate::{
     api::{GrammarInit, ParserLimits, TopLevelGrammar},
+    compiled_grammar::tokenizer_hash,
     earley::{perf::ParserPerfCounters, SlicedBiasComputer},
-    Logger, TokenParser,
+    panic_utils, CompiledGrammar, ImportResolver, Logger, TokenParser,
 };
 
 /// Compiles grammars and holds shared tokenizer state.
@@ -22,6 +23,8 @@ pub struct ParserFactory {
     buffer_log_level: u32,
     limits: ParserLimits,
     perf_counters: Arc<ParserPerfCounters>,
+    tokenizer_hash: OnceLock<u64>,
+    import_resolver: Option<Arc<dyn ImportResolver>>,
 }
 
 impl ParserFactory {
@@ -39,6 +42,8 @@ impl ParserFactory {
             buffer_log_level: 0,
             limits: ParserLimits::default(),
             perf_counters: Arc::new(ParserPerfCounters::default()),
+            tokenizer_hash: OnceLock::new(),
+            import_resolver: None,
         })
     }
 
@@ -64,6 +69,8 @@ impl ParserFactory {
             buffer_log_level: self.buffer_log_level,
             limits: self.limits.clone(),
             perf_counters: self.perf_counters.clone(),
+            tokenizer_hash: self.tokenizer_hash.clone(),
+            import_resolver: self.import_resolver.clone(),
         })
     }
 
@@ -79,6 +86,14 @@ impl ParserFactory {
         &self.tok_env
     }
 
+    /// Stable hash of the tokenizer; compiled grammars can only be used
+    /// with factories having the same hash.
+    pub fn tokenizer_hash(&self) -> u64 {
+        *self
+            .tokenizer_hash
+            .get_or_init(|| tokenizer_hash(&self.tok_env))
+    }
+
     pub fn quiet(&mut self) -> &mut Self {
         self.stderr_log_level = 0;
         self.buffer_log_level = 0;
@@ -103,6 +118,17 @@ impl ParserFactory {
         self
     }
 
+    /// Set the resolver for `%import` statements in Lark grammars
+    /// (other than the built-in `%import common...`).
+    pub fn set_import_resolver(&mut self, resolver: Arc<dyn ImportResolver>) -> &mut Self {
+        self.import_resolver = Some(resolver);
+        self
+    }
+
+    pub fn import_resolver(&self) -> Option<Arc<dyn ImportResolver>> {
+        self.import_resolver.clone()
+    }
+
     pub fn extra_lexemes(&self) -> Vec<String> {
         self.slicer.extra_lexemes()
     }
@@ -115,6 +141,33 @@ impl ParserFactory {
         self.create_parser_from_init_default(GrammarInit::Serialized(grammar))
     }
 
+    /// Compile the grammar into a form that can be serialized and
+    /// later used with [`ParserFactory::create_parser_from_compiled()`].
+    pub fn compile_grammar(&self, grammar: TopLevelGrammar) -> Result<CompiledGrammar> {
+        let max_tokens = grammar.max_tokens;
+        let mut logger = Logger::new(self.buffer_log_level, self.stderr_log_level);
+        let extra_lexemes = self.extra_lexemes();
+        let cgrammar = panic_utils::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            GrammarInit::Serialized(grammar).to_cgrammar(
+                Some(self.tok_env.clone()),
+                &mut logger,
+                self.limits.clone(),
+                extra_lexemes.clone(),
+                self.import_resolver(),
+            )
+        }))?;
+        Ok(CompiledGrammar {
+            grammar: cgrammar,
+            max_tokens,
+            tokenizer_hash: self.tokenizer_hash(),
+            extra_lexemes,
+        })
+    }
+
+    pub fn create_parser_from_compiled(&self, compiled: CompiledGrammar) -> Result<TokenParser> {
+        self.create_parser_from_init_default(GrammarInit::Compiled(compiled))
+    }
+
     pub fn create_parser_from_init_default(&self, init: GrammarInit) -> Result<TokenParser> {
         self.create_parser_from_init(init, self.buffer_log_level, self.stderr_log_level)
     }
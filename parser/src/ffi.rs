@@ -31,6 +31,7 @@ use std::{
 };
 
 use anyhow::{bail, ensure, Result};
+use serde_json::{json, Value};
 use toktrie::{
     ApproximateTokEnv, InferenceCapabilities, SimpleVob, TokEnv, TokRxInfo, TokTrie, TokenizerEnv,
     INVALID_TOKEN,
@@ -39,8 +40,8 @@ use toktrie::{
 use crate::{
     api::{GrammarInit, ParserLimits, TopLevelGrammar},
     earley::{SlicedBiasComputer, ValidationResult},
-    panic_utils, CommitResult, Constraint, Logger, Matcher, ParserFactory, StopController,
-    TokenParser,
+    json_merge, panic_utils, CommitResult, Constraint, Logger, Matcher, ParserFactory,
+    StopController, TokenParser,
 };
 
 // ---------------------------------------------------------------------------
@@ -536,6 +537,12 @@ pub struct LlgConstraintInit {
     /// The resource limits for the parser.
     /// Default values will be used for all fields that are 0.
     pub limits: ParserLimits,
+    /// Custom string formats for JSON schemas, as a JSON object mapping
+    /// format names to regexes, for example `{"sku": "[A-Z]{3}-[0-9]{4}"}`.
+    /// They are consulted before the built-in formats, and formats in the
+    /// schema's `"x-guidance"` take precedence over these.
+    /// Pass NULL to only use the built-in formats.
+    pub json_formats: *const c_char,
 }
 
 impl LlgConstraintInit {
@@ -565,8 +572,31 @@ impl LlgConstraintInit {
         Ok(unsafe { &(*self.tokenizer).factory })
     }
 
+    /// Add [`json_formats`](Self::json_formats) to the `"x-guidance"` of
+    /// every JSON schema in `grammar`.
+    fn apply_json_formats(&self, grammar: &mut TopLevelGrammar) -> Result<()> {
+        if self.json_formats.is_null() {
+            return Ok(());
+        }
+        let formats = unsafe { c_str_to_str(self.json_formats, "json_formats") }?;
+        let formats: Value = serde_json::from_str(formats)
+            .map_err(|e| anyhow::anyhow!("Invalid JSON in json_formats: {e}"))?;
+        ensure!(formats.is_object(), "json_formats must be a JSON object");
+        for grm in grammar.grammars.iter_mut() {
+            if let Some(Value::Object(schema)) = grm.json_schema.as_mut() {
+                let mut options = json!({ "formats": formats });
+                if let Some(in_schema) = schema.get("x-guidance") {
+                    json_merge(&mut options, in_schema);
+                }
+                schema.insert("x-guidance".to_string(), options);
+            }
+        }
+        Ok(())
+    }
+
     /// Compile `grammar` into a [`TokenParser`].
-    pub fn build_parser(&self, grammar: TopLevelGrammar) -> Result<TokenParser> {
+    pub fn build_parser(&self, mut grammar: TopLevelGrammar) -> Result<TokenParser> {
+        self.apply_json_formats(&mut grammar)?;
         self.factory()?.create_parser_from_init_ext(
             GrammarInit::Serialized(grammar),
             self.logger(),
@@ -775,6 +805,7 @@ pub extern "C" fn llg_constraint_init_set_defaults(
         ff_tokens_ok: false,
         backtrack_ok: false,
         limits: ParserLimits::default(),
+        json_formats: std::ptr::null(),
     };
 }
 
@@ -842,10 +873,22 @@ pub extern "C" fn llg_new_constraint_lark(
     new_constraint_tagged(init, "lark", lark)
 }
 
+/// Create a new constraint from a GBNF grammar (as used by llama.cpp).
+///
+/// Always returns a non-null value. Call [`llg_get_error()`] on the result to
+/// check for errors.
+#[no_mangle]
+pub extern "C" fn llg_new_constraint_gbnf(
+    init: &LlgConstraintInit,
+    gbnf: *const c_char,
+) -> *mut LlgConstraint {
+    new_constraint_tagged(init, "gbnf", gbnf)
+}
+
 /// Create a new constraint with a specified type.
 ///
 /// `constraint_type` can be one of `"regex"`, `"json_schema"` (or `"json"`),
-/// `"lark"`, `"llguidance"` (or `"guidance"`).
+/// `"lark"`, `"gbnf"`, `"abnf"`, `"llguidance"` (or `"guidance"`).
 ///
 /// Always returns a non-null value. Call [`llg_get_error()`] on the result to
 /// check for errors.
@@ -1551,6 +1594,8 @@ impl LlgMatcher {
 /// - `"json_object"` — equivalent to JSON schema `{"type":"object"}`
 /// - `"lark"` — data is a grammar in a variant of Lark syntax;
 ///   see <https://github.com/guidance-ai/llguidance/blob/main/docs/syntax.md>
+/// - `"gbnf"` — data is a grammar in GBNF format (as used by llama.cpp)
+/// - `"abnf"` — data is a grammar in ABNF format (RFC 5234)
 /// - `"llguidance"` or `"guidance"` — data is a list of Lark or JSON schemas
 ///   in JSON format
 ///
@@ -1597,16 +1642,28 @@ pub unsafe extern "C" fn llg_new_matcher(
     }
 }
 
-fn validate_grammar(
+fn validation_result(
     init: &LlgConstraintInit,
     constraint_type: *const c_char,
     data: *const c_char,
-) -> Result<String> {
+) -> Result<ValidationResult> {
     let tp = unsafe { c_str_to_str(constraint_type, "constraint_type") }?;
     let data = unsafe { c_str_to_str(data, "data") }?;
     let grammar = TopLevelGrammar::from_tagged_str(tp, data)?;
-    let tok_env = init.factory()?.tok_env().clone();
-    match GrammarInit::Serialized(grammar).validate(Some(tok_env), init.limits.clone()) {
+    let factory = init.factory()?;
+    Ok(GrammarInit::Serialized(grammar).validate_ext(
+        Some(factory.tok_env().clone()),
+        init.limits.clone(),
+        factory.import_resolver(),
+    ))
+}
+
+fn validate_grammar(
+    init: &LlgConstraintInit,
+    constraint_type: *const c_char,
+    data: *const c_char,
+) -> Result<String> {
+    match validation_result(init, constraint_type, data)? {
         ValidationResult::Valid => Ok(String::new()),
         ValidationResult::Error(e) => bail!(e),
         r => Ok(r.render(true)),
@@ -1657,6 +1714,43 @@ pub unsafe extern "C" fn llg_validate_grammar(
     }
 }
 
+/// Like [`llg_validate_grammar()`], but the errors and warnings are written
+/// to `output` as a JSON array of diagnostics, each with `severity`
+/// (`"error"` or `"warning"`), `code`, `message`, and, when known,
+/// `span` (`start` and `end` byte offsets, and 1-based `line` and `column`
+/// in the grammar source), `json_pointer` into the JSON schema, and `notes`.
+/// The `code` tells the kind of the diagnostic, like `"unknown_name"` or
+/// `"unsupported_keyword"`; see `Diagnostic` in the Rust crate.
+/// Returns 0 when there are no diagnostics, −1 on error, and 1 on warning.
+/// The output is always NUL-terminated; if it does not fit in `output_len`
+/// bytes, it is truncated (and thus not valid JSON).
+///
+/// # Safety
+/// - `constraint_type` and `data` must be valid NUL-terminated UTF-8 C strings.
+/// - `output` must point to a buffer of at least `output_len` bytes.
+#[no_mangle]
+#[must_use]
+pub unsafe extern "C" fn llg_validate_grammar_diagnostics(
+    init: &LlgConstraintInit,
+    constraint_type: *const c_char,
+    data: *const c_char,
+    output: *mut c_char,
+    output_len: usize,
+) -> i32 {
+    let res = panic_utils::catch_unwind(AssertUnwindSafe(|| {
+        validation_result(init, constraint_type, data)
+    }))
+    .unwrap_or_else(|e| ValidationResult::from_error(&e));
+    let code = match res {
+        ValidationResult::Valid => 0,
+        ValidationResult::Warnings(_) => 1,
+        ValidationResult::Error(_) => -1,
+    };
+    let json = serde_json::to_string(&res.diagnostics()).unwrap();
+    unsafe { save_error_string(json, output, output_len) };
+    code
+}
+
 /// Compute the set of allowed tokens for the current state into a
 /// caller-provided buffer.
 ///
@@ -1693,6 +1787,53 @@ pub unsafe extern "C" fn llg_matcher_compute_mask_into(
     })
 }
 
+/// Compute masks for speculative decoding into a caller-provided 2D buffer.
+///
+/// Row 0 of `mask_dest` is the mask for the current state, and row `i` is
+/// the mask after consuming the first `i` draft tokens. Computation stops at
+/// the first rejected draft token (or when the matcher stops); the remaining
+/// rows are left untouched. The matcher state is not changed.
+/// Each row is [`llg_matcher_get_mask_byte_size()`] bytes, and `mask_byte_len`
+/// (the size of the whole buffer) must be at least `n_draft_tokens + 1` rows.
+/// Returns the number of masks written (at least 1), or −1 on error.
+///
+/// # Safety
+/// - If `n_draft_tokens > 0`, `draft_tokens` must point to `n_draft_tokens`
+///   valid `u32` values.
+/// - `mask_dest` must point to a buffer of at least `mask_byte_len` bytes.
+#[no_mangle]
+#[must_use]
+pub unsafe extern "C" fn llg_matcher_compute_masks_draft(
+    matcher: &mut LlgMatcher,
+    draft_tokens: *const u32,
+    n_draft_tokens: usize,
+    mask_dest: *mut u32,
+    mask_byte_len: usize,
+) -> i32 {
+    let n_elts = matcher.mask_elts();
+    let draft = unsafe { slice_from_ptr_or_empty(draft_tokens, n_draft_tokens) };
+    matcher.wrap(|m| {
+        let needed = (draft.len() + 1) * n_elts * 4;
+        ensure!(
+            mask_byte_len >= needed,
+            "mask_dest too small: expected at least {}, got {}",
+            needed,
+            mask_byte_len
+        );
+        ensure!(!mask_dest.is_null(), "mask_dest is null");
+        let masks = m.compute_masks_for_draft(draft)?;
+        for (i, vob) in masks.iter().enumerate() {
+            let slc = &vob.as_slice()[0..n_elts];
+            // SAFETY: mask_dest is non-null and has room for draft.len() + 1 rows;
+            // slc is freshly allocated and thus non-overlapping
+            unsafe {
+                std::ptr::copy_nonoverlapping(slc.as_ptr(), mask_dest.add(i * n_elts), n_elts);
+            }
+        }
+        Ok(masks.len() as i32)
+    })
+}
+
 /// Compute the set of allowed tokens for the current state.
 ///
 /// Use [`llg_matcher_get_mask()`] to retrieve the result.
@@ -1872,6 +2013,58 @@ pub unsafe extern "C" fn llg_matcher_validate_tokens(
     })
 }
 
+/// Check how many bytes can be consumed from the given byte sequence.
+/// The bytes do not need to come from the tokenizer.
+///
+/// Returns the length of the longest valid prefix, or −1 on error.
+///
+/// # Safety
+/// - If `n_bytes > 0`, `bytes` must point to `n_bytes` valid bytes.
+#[no_mangle]
+#[must_use]
+pub unsafe extern "C" fn llg_matcher_validate_bytes(
+    matcher: &mut LlgMatcher,
+    bytes: *const u8,
+    n_bytes: usize,
+) -> i32 {
+    let bytes = unsafe { slice_from_ptr_or_empty(bytes, n_bytes) };
+    matcher.wrap(|m| {
+        m.validate_bytes(bytes)
+            .map(|v| i32::try_from(v).unwrap_or(i32::MAX))
+    })
+}
+
+/// Consume the longest valid prefix of the given byte sequence.
+/// The bytes do not need to come from the tokenizer; they are recorded
+/// as tokens of a greedy tokenization (e.g., for rollback), and only the tokens
+/// allowed by the grammar are consumed.
+///
+/// Returns the length of the consumed prefix, or −1 on error.
+/// The length may be shorter than what llg_matcher_validate_bytes() returns,
+/// if some of the greedy tokens are not allowed; the remaining bytes
+/// can be passed again.
+/// If the prefix can't be represented as tokens of the vocabulary,
+/// nothing is consumed and −2 is returned; the matcher is not put
+/// in error state then.
+///
+/// # Safety
+/// - If `n_bytes > 0`, `bytes` must point to `n_bytes` valid bytes.
+#[no_mangle]
+#[must_use]
+pub unsafe extern "C" fn llg_matcher_consume_bytes(
+    matcher: &mut LlgMatcher,
+    bytes: *const u8,
+    n_bytes: usize,
+) -> i32 {
+    let bytes = unsafe { slice_from_ptr_or_empty(bytes, n_bytes) };
+    matcher.wrap(|m| match m.consume_bytes(bytes) {
+        Ok(v) => Ok(i32::try_from(v).unwrap_or(i32::MAX)),
+        // nothing was consumed, and the matcher is still usable
+        Err(_) if !m.is_error() => Ok(-2),
+        Err(e) => Err(e),
+    })
+}
+
 /// Compute the fast-forward (forced) tokens for the current state.
 ///
 /// The result is written to `output`. Returns the number of tokens written
@@ -1,8 +1,9 @@
 use std::ffi::c_void;
 use std::panic::AssertUnwindSafe;
 
+use crate::batch_matcher::copy_mask;
 use crate::ffi::{LlgCallback, LlgConstraintStep};
-use crate::panic_utils;
+use crate::{panic_utils, BatchMatcher};
 
 /// A `*const c_void` wrapper that is `Send`.
 ///
@@ -23,8 +24,8 @@ impl SendPtr {
 }
 
 fn par_compute_mask_inner(constraints: Vec<LlgConstraintStep>) {
-    use rayon::prelude::*;
-    constraints.into_par_iter().for_each(|step| {
+    // Constraints are not Matchers, so only the parallel map is shared with BatchMatcher.
+    BatchMatcher::new().map(constraints, |step| {
         // A null constraint pointer is a caller bug — skip silently since
         // there is no constraint handle to record an error on.
         if step.constraint.is_null() {
@@ -49,42 +50,23 @@ fn par_compute_mask_inner(constraints: Vec<LlgConstraintStep>) {
                 cc.set_error("llg_par_compute_mask: mask_dest is null");
                 return Ok(());
             }
-            let mask_elts = step.mask_byte_len / 4;
+            // SAFETY: mask_dest is non-null (checked above), and the caller
+            // guarantees it points to mask_byte_len bytes.
+            let row =
+                unsafe { std::slice::from_raw_parts_mut(step.mask_dest, step.mask_byte_len / 4) };
 
             if let Some(constraint) = &mut cc.constraint {
-                let mut num_copied = 0;
-                let mut add_eos = false;
                 let eos = constraint.tok_trie().eos_token() as usize;
                 match constraint.compute_mask() {
                     Ok(r) => {
-                        if let Some(m) = r.sample_mask.as_ref() {
-                            num_copied = std::cmp::min(m.len(), mask_elts);
-                            // SAFETY: mask_dest is non-null (checked above), and
-                            // mask_byte_len guarantees sufficient space.
-                            unsafe {
-                                std::ptr::copy_nonoverlapping(
-                                    m.as_ptr(),
-                                    step.mask_dest,
-                                    num_copied,
-                                );
-                            }
+                        copy_mask(r.sample_mask.as_ref(), row);
+                        if r.is_stop() && eos / 32 < row.len() {
+                            row[eos / 32] |= 1 << (eos % 32);
                         }
-                        add_eos = r.is_stop();
                     }
-                    Err(e) => cc.set_error(&e.to_string()),
-                }
-
-                let left = mask_elts - num_copied;
-                if left > 0 {
-                    // SAFETY: mask_dest + num_copied is within the buffer.
-                    unsafe {
-                        std::ptr::write_bytes(step.mask_dest.add(num_copied), 0, left);
-                    }
-                }
-                if add_eos && eos / 32 < mask_elts {
-                    // SAFETY: eos / 32 < mask_elts, so this is within bounds.
-                    unsafe {
-                        *step.mask_dest.add(eos / 32) |= 1 << (eos % 32);
+                    Err(e) => {
+                        copy_mask(None, row);
+                        cc.set_error(&e.to_string());
                     }
                 }
             }
@@ -0,0 +1,254 @@
+use anyhow::{anyhow, bail, Result};
+use derivre::RegexAst;
+
+use crate::{
+    api::LLGuidanceOptions,
+    grammar_builder::{GrammarResult, RegexId},
+    GrammarBuilder, HashMap, HashSet, NodeRef,
+};
+
+use super::parser::{parse_gbnf, Expr, Rule};
+
+struct Compiler {
+    builder: GrammarBuilder,
+    rules: HashMap<String, Rule>,
+    start: String,
+    // rules that only use terminals (and no recursion), compiled to regexes
+    terminal_rules: HashSet<String>,
+    node_ids: HashMap<String, NodeRef>,
+    regex_ids: HashMap<String, RegexId>,
+    in_progress: HashSet<String>,
+    // nodes already named after a rule; aliases (`a ::= b`) must not rename them
+    named_nodes: HashSet<NodeRef>,
+}
+
+/// Compile a GBNF grammar (as used by llama.cpp) starting at the `root` rule.
+pub fn gbnf_to_llguidance(builder: GrammarBuilder, gbnf: &str) -> Result<GrammarResult> {
+    let parsed = parse_gbnf(gbnf)?;
+    compile_rules(builder, parsed, "root", gbnf.len())
+}
+
+/// Compile rules parsed from GBNF or ABNF, starting at rule `start`.
+/// Rules that only reference terminals are compiled to lexemes.
+pub(crate) fn compile_rules(
+    mut builder: GrammarBuilder,
+    parsed: Vec<Rule>,
+    start: &str,
+    src_len: usize,
+) -> Result<GrammarResult> {
+    let n = std::cmp::min(src_len / 8, 1_000_000);
+    builder.regex.spec.regex_builder.reserve(n);
+
+    let mut rules = HashMap::default();
+    for rule in parsed {
+        if rules.contains_key(&rule.name) {
+            return Err(rule
+                .location
+                .augment(format!("duplicate rule {:?}", rule.name)));
+        }
+        rules.insert(rule.name.clone(), rule);
+    }
+
+    let c = Compiler {
+        builder,
+        rules,
+        start: start.to_string(),
+        terminal_rules: HashSet::default(),
+        node_ids: HashMap::default(),
+        regex_ids: HashMap::default(),
+        in_progress: HashSet::default(),
+        named_nodes: HashSet::default(),
+    };
+    c.execute()
+}
+
+impl Compiler {
+    fn execute(mut self) -> Result<GrammarResult> {
+        if !self.rules.contains_key(&self.start) {
+            bail!("no {} rule found", self.start);
+        }
+        for rule in self.rules.values() {
+            self.check_refs(&rule.body)?;
+        }
+        self.find_terminal_rules();
+
+        let id = self
+            .builder
+            .add_grammar(LLGuidanceOptions::default(), RegexAst::NoMatch)?;
+        let start = self.do_rule(&self.start.clone())?;
+        self.builder.set_start_node(start);
+        Ok(self.builder.finalize(id))
+    }
+
+    fn check_refs(&self, expr: &Expr) -> Result<()> {
+        match expr {
+            Expr::RuleRef(loc, name) => {
+                if !self.rules.contains_key(name) {
+                    return Err(loc.augment(format!("rule {name:?} not found")));
+                }
+            }
+            Expr::Sequence(items) | Expr::Alternatives(items) => {
+                for e in items {
+                    self.check_refs(e)?;
+                }
+            }
+            Expr::Repeat(e, _, _) => self.check_refs(e)?,
+            Expr::Literal(_) | Expr::CharClass(_) | Expr::Any => {}
+        }
+        Ok(())
+    }
+
+    /// Mark rules that only reference terminal rules as terminal, until fixpoint.
+    /// Recursive rules are never marked.
+    fn find_terminal_rules(&mut self) {
+        loop {
+            let new_terminals = self
+                .rules
+                .values()
+                .filter(|r| !self.terminal_rules.contains(&r.name) && self.is_terminal(&r.body))
+                .map(|r| r.name.clone())
+                .collect::<Vec<_>>();
+            if new_terminals.is_empty() {
+                break;
+            }
+            self.terminal_rules.extend(new_terminals);
+        }
+    }
+
+    fn is_terminal(&self, expr: &Expr) -> bool {
+        match expr {
+            Expr::Literal(_) | Expr::CharClass(_) | Expr::Any => true,
+            Expr::RuleRef(_, name) => self.terminal_rules.contains(name),
+            Expr::Sequence(items) | Expr::Alternatives(items) => {
+                items.iter().all(|e| self.is_terminal(e))
+            }
+            Expr::Repeat(e, _, _) => self.is_terminal(e),
+        }
+    }
+
+    fn do_regex(&mut self, expr: &Expr) -> Result<RegexId> {
+        self.builder.check_limits()?;
+        let regex = &mut self.builder.regex;
+        match expr {
+            Expr::Literal(s) => Ok(regex.literal(s.clone())),
+            Expr::CharClass(rx) => regex.regex(rx),
+            Expr::Any => regex.regex("(?s:.)"),
+            Expr::RuleRef(_, name) => {
+                if let Some(id) = self.regex_ids.get(name) {
+                    return Ok(*id);
+                }
+                let body = self.rules[name].body.clone();
+                let id = self.do_regex(&body)?;
+                self.regex_ids.insert(name.clone(), id);
+                Ok(id)
+            }
+            Expr::Sequence(items) => {
+                let ids = items
+                    .iter()
+                    .map(|e| self.do_regex(e))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(self.builder.regex.concat(ids))
+            }
+            Expr::Alternatives(items) => {
+                let ids = items
+                    .iter()
+                    .map(|e| self.do_regex(e))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(self.builder.regex.select(ids))
+            }
+            Expr::Repeat(e, min, max) => {
+                let id = self.do_regex(e)?;
+                Ok(self.builder.regex.repeat(id, *min, *max))
+            }
+        }
+    }
+
+    fn do_lexeme(&mut self, expr: &Expr) -> Result<NodeRef> {
+        match expr {
+            Expr::Literal(s) if s.is_empty() => Ok(self.builder.empty()),
+            Expr::Sequence(items) if items.is_empty() => Ok(self.builder.empty()),
+            _ => {
+                let rx = self.do_regex(expr)?;
+                Ok(self.builder.lexeme(rx))
+            }
+        }
+    }
+
+    fn do_expr(&mut self, expr: &Expr) -> Result<NodeRef> {
+        self.builder.check_limits()?;
+        if self.is_terminal(expr) {
+            return self.do_lexeme(expr);
+        }
+        match expr {
+            Expr::RuleRef(_, name) => self.do_rule(name),
+            Expr::Sequence(items) => {
+                // consecutive terminals form a single lexeme
+                let mut args = vec![];
+                let mut terminals = vec![];
+                for e in items {
+                    if self.is_terminal(e) {
+                        terminals.push(e.clone());
+                    } else {
+                        if !terminals.is_empty() {
+                            let seq = Expr::Sequence(std::mem::take(&mut terminals));
+                            args.push(self.do_lexeme(&seq)?);
+                        }
+                        args.push(self.do_expr(e)?);
+                    }
+                }
+                if !terminals.is_empty() {
+                    args.push(self.do_lexeme(&Expr::Sequence(terminals))?);
+                }
+                Ok(self.builder.join(&args))
+            }
+            Expr::Alternatives(items) => {
+                let options = items
+                    .iter()
+                    .map(|e| self.do_expr(e))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(self.builder.select(&options))
+            }
+            Expr::Repeat(e, min, max) => {
+                let inner = self.do_expr(e)?;
+                Ok(match (min, max) {
+                    (0, None) => self.builder.zero_or_more(inner),
+                    (1, None) => self.builder.one_or_more(inner),
+                    (0, Some(1)) => self.builder.optional(inner),
+                    _ => self
+                        .builder
+                        .repeat(inner, *min as usize, max.map(|m| m as usize)),
+                })
+            }
+            Expr::Literal(_) | Expr::CharClass(_) | Expr::Any => unreachable!(),
+        }
+    }
+
+    fn do_rule(&mut self, name: &str) -> Result<NodeRef> {
+        if let Some(id) = self.node_ids.get(name) {
+            return Ok(*id);
+        }
+        if self.in_progress.contains(name) {
+            let id = self.builder.new_node(&format!("{name}_"));
+            self.node_ids.insert(name.to_string(), id);
+            return Ok(id);
+        }
+
+        self.in_progress.insert(name.to_string());
+        let rule = self
+            .rules
+            .get(name)
+            .ok_or_else(|| anyhow!("rule {:?} not found", name))?;
+        let (location, body) = (rule.location.clone(), rule.body.clone());
+        let id = self.do_expr(&body).map_err(|e| location.augment(e))?;
+
+        if let Some(placeholder) = self.node_ids.get(name) {
+            self.builder.set_placeholder(*placeholder, id);
+        }
+        self.node_ids.insert(name.to_string(), id);
+        self.in_progress.remove(name);
+        if self.named_nodes.insert(id) {
+            self.builder.rename(id, name);
+        }
+        Ok(id)
+    }
+}
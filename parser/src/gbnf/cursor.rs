@@ -0,0 +1,99 @@
+use std::rc::Rc;
+
+use anyhow::Result;
+
+use crate::lark::Location;
+
+/// A position in the source, as returned by [`Cursor::mark()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Mark {
+    pos: usize,
+    line: usize,
+    column: usize,
+}
+
+/// Character-level input shared by the GBNF and ABNF parsers.
+/// The line and column (in bytes) are tracked as the cursor advances.
+pub(crate) struct Cursor {
+    src: Rc<String>,
+    chars: Vec<char>,
+    mark: Mark,
+}
+
+impl Cursor {
+    pub fn new(src: &str) -> Self {
+        Cursor {
+            src: Rc::new(src.to_string()),
+            chars: src.chars().collect(),
+            mark: Mark {
+                pos: 0,
+                line: 1,
+                column: 1,
+            },
+        }
+    }
+
+    pub fn location(&self) -> Location {
+        Location {
+            line: self.mark.line,
+            column: self.mark.column,
+            len: self.cur().map_or(0, char::len_utf8),
+            src: self.src.clone(),
+        }
+    }
+
+    pub fn err(&self, msg: impl std::fmt::Display + 'static) -> anyhow::Error {
+        self.location().augment(msg)
+    }
+
+    pub fn cur(&self) -> Option<char> {
+        self.peek(0)
+    }
+
+    /// The character `n` positions after the current one.
+    pub fn peek(&self, n: usize) -> Option<char> {
+        self.chars.get(self.mark.pos + n).copied()
+    }
+
+    /// Move past the current character (if any).
+    pub fn bump(&mut self) {
+        if let Some(c) = self.cur() {
+            self.mark.pos += 1;
+            if c == '\n' {
+                self.mark.line += 1;
+                self.mark.column = 1;
+            } else {
+                self.mark.column += c.len_utf8();
+            }
+        }
+    }
+
+    pub fn expect(&mut self, c: char) -> Result<()> {
+        match self.cur() {
+            Some(c2) if c2 == c => {
+                self.bump();
+                Ok(())
+            }
+            Some(c2) => Err(self.err(format!("expected {c:?}, got {c2:?}"))),
+            None => Err(self.err(format!("expected {c:?}, got end of input"))),
+        }
+    }
+
+    /// Move past characters while `f` holds; returns them.
+    pub fn take_while(&mut self, f: impl Fn(char) -> bool) -> String {
+        let start = self.mark.pos;
+        while self.cur().is_some_and(&f) {
+            self.bump();
+        }
+        self.chars[start..self.mark.pos].iter().collect()
+    }
+
+    pub fn mark(&self) -> Mark {
+        self.mark
+    }
+
+    /// Go back to a position previously returned by [`Cursor::mark()`].
+    pub fn reset(&mut self, mark: Mark) {
+        self.mark = mark;
+    }
+}
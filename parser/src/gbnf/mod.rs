@@ -0,0 +1,8 @@
+mod compiler;
+mod cursor;
+mod parser;
+
+pub(crate) use compiler::compile_rules;
+pub use compiler::gbnf_to_llguidance;
+pub(crate) use cursor::Cursor;
+pub(crate) use parser::{push_class_char, Expr, Rule};
@@ -0,0 +1,304 @@
+use anyhow::{anyhow, Result};
+
+use super::Cursor;
+use crate::lark::Location;
+
+/// An expression on the right-hand side of a GBNF rule.
+#[derive(Debug, Clone)]
+pub enum Expr {
+    /// A string literal, like `"foo"`.
+    Literal(String),
+    /// A character class, like `[^a-z]`, translated to a regex.
+    CharClass(String),
+    /// Any character (`.`).
+    Any,
+    RuleRef(Location, String),
+    Sequence(Vec<Expr>),
+    Alternatives(Vec<Expr>),
+    Repeat(Box<Expr>, u32, Option<u32>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub name: String,
+    pub location: Location,
+    pub body: Expr,
+}
+
+/// Parse a GBNF grammar (as used by llama.cpp) into a list of rules.
+pub fn parse_gbnf(src: &str) -> Result<Vec<Rule>> {
+    let mut parser = Parser {
+        input: Cursor::new(src),
+    };
+    parser.parse()
+}
+
+struct Parser {
+    input: Cursor,
+}
+
+impl Parser {
+    fn is_word_char(c: char) -> bool {
+        c.is_ascii_alphanumeric() || c == '-' || c == '_'
+    }
+
+    fn skip_space(&mut self, newlines: bool) {
+        while let Some(c) = self.input.cur() {
+            match c {
+                ' ' | '\t' => self.input.bump(),
+                '\r' | '\n' if newlines => self.input.bump(),
+                '#' => {
+                    while self.input.cur().is_some_and(|c| c != '\r' && c != '\n') {
+                        self.input.bump();
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn parse(&mut self) -> Result<Vec<Rule>> {
+        let mut rules = vec![];
+        self.skip_space(true);
+        while self.input.cur().is_some() {
+            rules.push(self.parse_rule()?);
+            self.skip_space(true);
+        }
+        Ok(rules)
+    }
+
+    fn parse_rule(&mut self) -> Result<Rule> {
+        let location = self.input.location();
+        let name = self.parse_name()?;
+        self.skip_space(false);
+        for c in "::=".chars() {
+            self.input.expect(c)?;
+        }
+        self.skip_space(true);
+        let body = self.parse_alternatives(false)?;
+        match self.input.cur() {
+            None | Some('\r' | '\n') => Ok(Rule {
+                name,
+                location,
+                body,
+            }),
+            Some(c) => Err(self.input.err(format!("unexpected {c:?}"))),
+        }
+    }
+
+    fn parse_name(&mut self) -> Result<String> {
+        let name = self.input.take_while(Self::is_word_char);
+        if name.is_empty() {
+            return Err(self.input.err("expected rule name"));
+        }
+        Ok(name)
+    }
+
+    fn parse_int(&mut self) -> Result<u32> {
+        let s = self.input.take_while(|c| c.is_ascii_digit());
+        if s.is_empty() {
+            return Err(self.input.err("expected integer"));
+        }
+        s.parse()
+            .map_err(|e| self.input.err(format!("invalid integer: {e}")))
+    }
+
+    fn parse_alternatives(&mut self, nested: bool) -> Result<Expr> {
+        let mut alternatives = vec![];
+        loop {
+            alternatives.push(self.parse_sequence(nested)?);
+            self.skip_space(nested);
+            if self.input.cur() != Some('|') {
+                break;
+            }
+            self.input.bump();
+            self.skip_space(true);
+        }
+        Ok(if alternatives.len() == 1 {
+            alternatives.pop().unwrap()
+        } else {
+            Expr::Alternatives(alternatives)
+        })
+    }
+
+    fn parse_sequence(&mut self, nested: bool) -> Result<Expr> {
+        let mut items = vec![];
+        while let Some(c) = self.input.cur() {
+            let item = match c {
+                '"' => self.parse_literal()?,
+                '[' => self.parse_char_class()?,
+                '(' => {
+                    self.input.bump();
+                    self.skip_space(true);
+                    let inner = self.parse_alternatives(true)?;
+                    self.input.expect(')')?;
+                    inner
+                }
+                '.' => {
+                    self.input.bump();
+                    Expr::Any
+                }
+                '<' => {
+                    return Err(self
+                        .input
+                        .err("token references (<...>) are not supported in GBNF"));
+                }
+                '|' | ')' | '\r' | '\n' => break,
+                c if Self::is_word_char(c) => {
+                    let location = self.input.location();
+                    Expr::RuleRef(location, self.parse_name()?)
+                }
+                c => return Err(self.input.err(format!("unexpected {c:?}"))),
+            };
+            self.skip_space(nested);
+            let item = self.parse_repetition(item)?;
+            self.skip_space(nested);
+            items.push(item);
+        }
+        Ok(if items.len() == 1 {
+            items.pop().unwrap()
+        } else {
+            Expr::Sequence(items)
+        })
+    }
+
+    fn parse_repetition(&mut self, item: Expr) -> Result<Expr> {
+        let (min, max) = match self.input.cur() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                self.input.bump();
+                self.skip_space(true);
+                let min = self.parse_int()?;
+                self.skip_space(true);
+                let max = if self.input.cur() == Some(',') {
+                    self.input.bump();
+                    self.skip_space(true);
+                    if self.input.cur() == Some('}') {
+                        None
+                    } else {
+                        let max = self.parse_int()?;
+                        self.skip_space(true);
+                        if max < min {
+                            return Err(self.input.err(format!(
+                                "invalid repetition {{{min},{max}}}: max is smaller than min"
+                            )));
+                        }
+                        Some(max)
+                    }
+                } else {
+                    Some(min)
+                };
+                if self.input.cur() != Some('}') {
+                    return Err(self.input.err("expected '}' or ','"));
+                }
+                (min, max)
+            }
+            _ => return Ok(item),
+        };
+        self.input.bump();
+        Ok(Expr::Repeat(Box::new(item), min, max))
+    }
+
+    /// Parse a possibly escaped character; returns the character and whether it was escaped.
+    fn parse_char(&mut self) -> Result<(char, bool)> {
+        let start = self.input.mark();
+        let c = self
+            .input
+            .cur()
+            .ok_or_else(|| self.input.err("unexpected end of input"))?;
+        self.input.bump();
+        if c != '\\' {
+            return Ok((c, false));
+        }
+        let esc = self
+            .input
+            .cur()
+            .ok_or_else(|| self.input.err("unexpected end of input"))?;
+        self.input.bump();
+        let num_hex = match esc {
+            'n' => return Ok(('\n', true)),
+            'r' => return Ok(('\r', true)),
+            't' => return Ok(('\t', true)),
+            '\\' | '"' | '[' | ']' => return Ok((esc, true)),
+            'x' => 2,
+            'u' => 4,
+            'U' => 8,
+            _ => {
+                self.input.reset(start);
+                return Err(self.input.err(format!("invalid escape sequence \\{esc}")));
+            }
+        };
+        let hex: String = (0..num_hex).map_while(|i| self.input.peek(i)).collect();
+        let code = if hex.len() == num_hex && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            u32::from_str_radix(&hex, 16).ok()
+        } else {
+            None
+        };
+        let c = code.and_then(char::from_u32).ok_or_else(|| {
+            self.input.reset(start);
+            self.input
+                .err(format!("invalid escape sequence \\{esc}{hex}"))
+        })?;
+        for _ in 0..num_hex {
+            self.input.bump();
+        }
+        Ok((c, true))
+    }
+
+    fn parse_literal(&mut self) -> Result<Expr> {
+        self.input.expect('"')?;
+        let mut s = String::new();
+        loop {
+            match self.parse_char()? {
+                ('"', false) => break,
+                (c, _) => s.push(c),
+            }
+        }
+        Ok(Expr::Literal(s))
+    }
+
+    fn parse_char_class(&mut self) -> Result<Expr> {
+        let location = self.input.location();
+        self.input.expect('[')?;
+        let mut rx = String::from("[");
+        if self.input.cur() == Some('^') {
+            self.input.bump();
+            rx.push('^');
+        }
+        let mut empty = true;
+        loop {
+            let (c, escaped) = self.parse_char()?;
+            if c == ']' && !escaped {
+                break;
+            }
+            empty = false;
+            push_class_char(&mut rx, c);
+            if self.input.cur() == Some('-') && self.input.peek(1) != Some(']') {
+                self.input.bump();
+                let (c2, _) = self.parse_char()?;
+                if c2 < c {
+                    return Err(self
+                        .input
+                        .err(format!("invalid character range {c:?}-{c2:?}")));
+                }
+                rx.push('-');
+                push_class_char(&mut rx, c2);
+            }
+        }
+        if empty {
+            return Err(location.augment(anyhow!("empty character class")));
+        }
+        rx.push(']');
+        Ok(Expr::CharClass(rx))
+    }
+}
+
+pub(crate) fn push_class_char(rx: &mut String, c: char) {
+    if c.is_ascii_alphanumeric() {
+        rx.push(c);
+    } else {
+        rx.push_str(&format!("\\x{{{:x}}}", c as u32));
+    }
+}
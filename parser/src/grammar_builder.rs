@@ -1,15 +1,16 @@
 use crate::{
     api::{LLGuidanceOptions, ParserLimits, SkipSpec},
+    diagnostics::SourceSpan,
     earley::{
         lexerspec::{token_ranges_to_string, LexemeClass, LexemeIdx, LexerSpec},
         Grammar, ParamCond, ParamExpr, SymIdx, SymbolProps,
     },
     hashcons::{HashCons, HashId},
-    HashMap,
+    HashMap, ImportResolver,
 };
 use anyhow::{bail, ensure, Result};
 use derivre::{ExprRef, RegexAst};
-use std::ops::RangeInclusive;
+use std::{ops::RangeInclusive, sync::Arc};
 use toktrie::{bytes::limit_str, TokEnv, INVALID_TOKEN};
 
 use crate::api::{GenGrammarOptions, GenOptions, NodeProps};
@@ -55,7 +56,7 @@ pub struct GrammarBuilder {
     pub regex: RegexBuilder,
     tok_env: Option<TokEnv>,
     limits: ParserLimits,
-    warnings: HashMap<String, usize>,
+    pub(crate) import_resolver: Option<Arc<dyn ImportResolver>>,
 
     strings: HashMap<String, NodeRef>,
     at_most_cache: HashMap<(NodeRef, usize), NodeRef>,
@@ -94,11 +95,11 @@ impl RegexBuilder {
     }
 
     pub fn add_ast(&mut self, ast: RegexAst) -> Result<RegexId> {
-        self.spec.regex_builder.mk(&ast)
+        self.spec.mk(&ast)
     }
 
     pub fn regex(&mut self, rx: &str) -> Result<RegexId> {
-        self.spec.regex_builder.mk_regex(rx)
+        self.spec.mk_regex(rx)
     }
 
     pub fn literal(&mut self, s: String) -> RegexId {
@@ -176,14 +177,19 @@ impl GrammarBuilder {
             regex: RegexBuilder::new(),
             at_most_cache: HashMap::default(),
             repeat_exact_cache: HashMap::default(),
-            warnings: HashMap::default(),
             limits,
             tok_env,
+            import_resolver: None,
             self_ref,
             params,
         }
     }
 
+    /// Set the resolver for Lark `%import` statements.
+    pub fn set_import_resolver(&mut self, resolver: Option<Arc<dyn ImportResolver>>) {
+        self.import_resolver = resolver;
+    }
+
     pub fn check_limits(&self) -> Result<()> {
         ensure!(
             self.regex.spec.cost() <= self.limits.initial_lexer_fuel,
@@ -201,15 +207,15 @@ impl GrammarBuilder {
         Ok(())
     }
 
-    pub fn add_warning(&mut self, msg: String) {
-        let count = self.warnings.entry(msg).or_insert(0);
-        *count += 1;
-    }
-
-    pub fn get_warnings(&self) -> Vec<(String, usize)> {
-        let mut warnings: Vec<_> = self.warnings.iter().map(|(k, v)| (k.clone(), *v)).collect();
-        warnings.sort_by(|a, b| a.0.cmp(&b.0));
-        warnings
+    /// See [`LexerSpec::add_warning()`].
+    pub fn add_warning(
+        &mut self,
+        code: &str,
+        msg: String,
+        span: Option<SourceSpan>,
+        json_pointer: Option<String>,
+    ) {
+        self.regex.spec.add_warning(code, msg, span, json_pointer);
     }
 
     pub fn add_grammar(&mut self, options: LLGuidanceOptions, skip: RegexAst) -> Result<SymIdx> {
@@ -234,8 +240,7 @@ impl GrammarBuilder {
         // We'll swap these as we add more grammars,
         // so this setting is local to the grammar
         let utf8 = !options.allow_invalid_utf8;
-        self.regex.spec.regex_builder.unicode(utf8);
-        self.regex.spec.regex_builder.utf8(utf8);
+        self.regex.spec.set_utf8(utf8);
 
         // if any grammar sets it, it is inherited by the lexer
         if options.no_forcing {
@@ -244,6 +249,9 @@ impl GrammarBuilder {
         if options.allow_initial_skip {
             self.regex.spec.allow_initial_skip = true;
         }
+        if options.token_healing {
+            self.regex.spec.token_healing = true;
+        }
 
         // add root node
         self.curr_start_idx = self.new_node("start");
@@ -282,6 +290,7 @@ impl GrammarBuilder {
                     false,
                     None,
                     usize::MAX,
+                    0,
                 )
                 .unwrap();
             self.lexeme_to_node(lx_id)
@@ -306,7 +315,12 @@ impl GrammarBuilder {
         }
 
         if trie.is_none() {
-            self.add_warning("no tokenizer - can't validate <[...]>".to_string());
+            self.add_warning(
+                "no_tokenizer",
+                "no tokenizer - can't validate <[...]>".to_string(),
+                None,
+                None,
+            );
         }
 
         let name = token_ranges_to_string(&token_ranges);
@@ -363,7 +377,12 @@ impl GrammarBuilder {
             }
             negated
         } else {
-            self.add_warning("no tokenizer - can't validate <[^...]>".to_string());
+            self.add_warning(
+                "no_tokenizer",
+                "no tokenizer - can't validate <[^...]>".to_string(),
+                None,
+                None,
+            );
             vec![INVALID_TOKEN..=INVALID_TOKEN]
         };
 
@@ -388,7 +407,12 @@ impl GrammarBuilder {
                 );
             }
         } else {
-            self.add_warning("no tokenizer - can't validate <special_token>".to_string());
+            self.add_warning(
+                "no_tokenizer",
+                "no tokenizer - can't validate <special_token>".to_string(),
+                None,
+                None,
+            );
             INVALID_TOKEN
         };
 
@@ -405,7 +429,12 @@ impl GrammarBuilder {
             let trie = te.tok_trie();
             0..=trie.vocab_size() as u32 - 1
         } else {
-            self.add_warning("no tokenizer - can't validate <any_token>".to_string());
+            self.add_warning(
+                "no_tokenizer",
+                "no tokenizer - can't validate <any_token>".to_string(),
+                None,
+                None,
+            );
             INVALID_TOKEN..=INVALID_TOKEN
         };
         let idx = self
@@ -462,6 +491,24 @@ impl GrammarBuilder {
         self.lexeme_ext(rx, None, NodeProps::default())
     }
 
+    /// Like `lexeme()`, but when the lexer can match several lexemes
+    /// on the same input, the ones with highest `priority` are preferred.
+    pub fn lexeme_with_priority(&mut self, rx: ExprRef, priority: i32) -> NodeRef {
+        let idx = self
+            .regex
+            .spec
+            .add_greedy_lexeme(
+                String::new(),
+                RegexAst::ExprRef(rx),
+                false,
+                None,
+                usize::MAX,
+                priority,
+            )
+            .unwrap();
+        self.lexeme_to_node(idx)
+    }
+
     pub fn lexeme_ext(
         &mut self,
         rx: ExprRef,
@@ -477,6 +524,7 @@ impl GrammarBuilder {
                 false,
                 None,
                 props.max_tokens.unwrap_or(usize::MAX),
+                0,
             )
             .unwrap();
         let r = self.lexeme_to_node(idx);
@@ -526,6 +574,10 @@ impl GrammarBuilder {
         self.grammar.rename_symbol(node.idx, name);
     }
 
+    pub fn set_rule_span(&mut self, node: NodeRef, span: SourceSpan) {
+        self.grammar.set_rule_span(node.idx, span);
+    }
+
     pub fn select(&mut self, options: &[NodeRef]) -> NodeRef {
         self.select_with_cond(options, Vec::new())
     }
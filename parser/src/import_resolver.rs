@@ -0,0 +1,70 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+
+use crate::HashMap;
+
+/// Resolves `%import` statements in Lark grammars to grammar sources.
+///
+/// For `%import mylib.json (JSON_STRING, value)` or `%import mylib.json.value`,
+/// the module is `mylib.json`.
+/// The built-in `common` module is handled internally and never resolved.
+/// Set it with [`crate::ParserFactory::set_import_resolver()`].
+pub trait ImportResolver: Send + Sync {
+    /// Return the Lark source of `module`, or `None` if there is no such module.
+    fn resolve(&self, module: &str) -> Result<Option<String>>;
+}
+
+/// Resolves modules to `.lark` files in a directory;
+/// `mylib.json` is read from `<root>/mylib/json.lark`.
+pub struct DirImportResolver {
+    root: PathBuf,
+}
+
+impl DirImportResolver {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        DirImportResolver { root: root.into() }
+    }
+}
+
+impl ImportResolver for DirImportResolver {
+    fn resolve(&self, module: &str) -> Result<Option<String>> {
+        let mut path = self.root.clone();
+        for part in module.split('.') {
+            // the Lark parser only allows names here, but the trait is public
+            if part.is_empty() || part.contains(['/', '\\']) {
+                return Ok(None);
+            }
+            path.push(part);
+        }
+        path.set_extension("lark");
+        match std::fs::read_to_string(&path) {
+            Ok(s) => Ok(Some(s)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(anyhow!("failed to read {}: {}", path.display(), e)),
+        }
+    }
+}
+
+/// Resolves modules from an in-memory map of module names to Lark sources.
+#[derive(Default, Clone)]
+pub struct MemImportResolver {
+    modules: HashMap<String, String>,
+}
+
+impl MemImportResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, module: &str, source: &str) -> &mut Self {
+        self.modules.insert(module.to_string(), source.to_string());
+        self
+    }
+}
+
+impl ImportResolver for MemImportResolver {
+    fn resolve(&self, module: &str) -> Result<Option<String>> {
+        Ok(self.modules.get(module).cloned())
+    }
+}
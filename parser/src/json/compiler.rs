@@ -1,7 +1,8 @@
 use crate::api::{LLGuidanceOptions, SkipSpec};
+use crate::earley::{ParamCond, ParamExpr, ParamRef, ParamValue};
 use crate::grammar_builder::GrammarResult;
 use crate::json::schema::{NumberSchema, StringSchema};
-use crate::{regex_to_lark, HashMap};
+use crate::{diagnostics::SchemaError, regex_rewrite::search_regex_ast, regex_to_lark, HashMap};
 use anyhow::{anyhow, bail, Context, Result};
 use derivre::{ExprRef, JsonQuoteOptions, RegexAst};
 use indexmap::{IndexMap, IndexSet};
@@ -35,10 +36,26 @@ pub struct JsonCompileOptions {
     /// Allow printable Unicode escapes in strings and object keys without regex constraints.
     /// Paired surrogate escapes count as one character for string length limits.
     pub json_allow_general_unicode_escapes: bool,
+    /// Order in which properties listed in "properties" and "required" are generated.
+    pub property_order: PropertyOrder,
+    /// Custom string formats, mapping format name to a regex matching the whole string.
+    /// They are consulted before the built-in formats.
+    pub formats: IndexMap<String, String>,
     #[serde(skip)]
     pub retriever: Option<RetrieveWrapper>,
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PropertyOrder {
+    /// Properties follow the order in the schema.
+    #[default]
+    Fixed,
+    /// Properties can come in any order (but each at most once);
+    /// any additional or pattern properties still come after them.
+    Any,
+}
+
 fn json_dumps(target: &serde_json::Value) -> String {
     serde_json::to_string(target).unwrap()
 }
@@ -81,6 +98,8 @@ struct Compiler {
     general_unicode_string_cache: HashMap<(usize, Option<usize>), ExprRef>,
     item_separator_cache: Option<NodeRef>,
     key_separator_cache: Option<NodeRef>,
+    /// JSON pointer of the definition being compiled, for warnings.
+    def_pointer: Option<String>,
 }
 
 impl Default for JsonCompileOptions {
@@ -94,6 +113,8 @@ impl Default for JsonCompileOptions {
             lenient: false,
             json_allowed_escapes: None,
             json_allow_general_unicode_escapes: false,
+            property_order: PropertyOrder::Fixed,
+            formats: IndexMap::new(),
             retriever: None,
         }
     }
@@ -150,6 +171,7 @@ impl Compiler {
             options,
             definitions: HashMap::default(),
             pending_definitions: vec![],
+            def_pointer: None,
             any_cache: None,
             string_cache: None,
             general_unicode_scalar_cache: None,
@@ -160,6 +182,12 @@ impl Compiler {
         }
     }
 
+    /// Record a warning at the definition being compiled.
+    fn add_warning(&mut self, code: &str, msg: String) {
+        let json_pointer = self.def_pointer.clone();
+        self.builder.add_warning(code, msg, None, json_pointer);
+    }
+
     pub fn execute(mut self, schema: Value) -> Result<GrammarResult> {
         let skip = if let Some(pattern) = &self.options.whitespace_pattern {
             RegexAst::Regex(pattern.clone())
@@ -176,10 +204,14 @@ impl Compiler {
         self.pattern_cache = built.pattern_cache;
 
         for w in built.warnings {
-            self.builder.add_warning(w);
+            self.builder
+                .add_warning(&w.code, w.message, w.span, w.json_pointer);
         }
 
-        let root = self.gen_json(&built.schema)?;
+        self.def_pointer = Some(String::new());
+        let root = self
+            .gen_json(&built.schema)
+            .map_err(|e| SchemaError::at(e, Some(String::new())))?;
         self.builder.set_start_node(root);
 
         while let Some((path, pl)) = self.pending_definitions.pop() {
@@ -187,9 +219,10 @@ impl Compiler {
                 .definitions
                 .get(&path)
                 .ok_or_else(|| anyhow!("Definition not found: {}", path))?;
+            self.def_pointer = built.def_pointers.get(&path).cloned();
             let compiled = self.gen_json(schema).map_err(|e| {
                 let top_level = anyhow!("{e}\n  while processing {path}");
-                e.context(top_level)
+                SchemaError::at(e.context(top_level), built.def_pointers.get(&path).cloned())
             })?;
             self.builder.set_placeholder(pl, compiled);
         }
@@ -221,8 +254,7 @@ impl Compiler {
 
     fn process_one_of(&mut self, options: &[Schema]) -> Result<NodeRef> {
         if self.options.coerce_one_of || self.options.lenient {
-            self.builder
-                .add_warning("oneOf not fully supported, falling back to anyOf. This may cause validation errors in some cases.".to_string());
+            self.add_warning("one_of_as_any_of", "oneOf not fully supported, falling back to anyOf. This may cause validation errors in some cases.".to_string());
             self.process_any_of(options)
         } else {
             Err(anyhow!("oneOf constraints are not supported. Enable 'coerce_one_of' option to approximate oneOf with anyOf"))
@@ -375,16 +407,30 @@ impl Compiler {
         self.any_cache = Some(json_any); // avoid infinite recursion
         let num = self.json_number(&NumberSchema::default())?;
         let tf = self.builder.regex.regex("true|false")?;
-        let options = vec![
+        let mut options = vec![
             self.builder.string("null"),
             self.builder.lexeme(tf),
             self.ast_lexeme(num)?,
             self.json_simple_string()?,
+        ];
+        options.extend(self.gen_json_any_containers()?);
+        let inner = self.builder.select(&options);
+        self.builder.set_placeholder(json_any, inner);
+        Ok(json_any)
+    }
+
+    /// Arrays and objects of any shape.
+    fn gen_json_any_containers(&mut self) -> Result<[NodeRef; 2]> {
+        Ok([
             self.gen_json_array(&ArraySchema {
                 min_items: 0,
                 max_items: None,
                 prefix_items: vec![],
                 items: Schema::any_box(),
+                unique_items: false,
+                contains: None,
+                min_contains: 0,
+                max_contains: None,
             })?,
             self.gen_json_object(&ObjectSchema {
                 properties: IndexMap::new(),
@@ -393,11 +439,25 @@ impl Compiler {
                 pattern_properties: IndexMap::new(),
                 min_properties: 0,
                 max_properties: None,
+                property_names: None,
             })?,
-        ];
-        let inner = self.builder.select(&options);
-        self.builder.set_placeholder(json_any, inner);
-        Ok(json_any)
+        ])
+    }
+
+    /// Regex matching any primitive (not array or object) JSON value.
+    fn json_any_primitive(&mut self) -> Result<RegexAst> {
+        let num = self.json_number(&NumberSchema::default())?;
+        let string = self.gen_json_string(StringSchema {
+            min_length: 0,
+            max_length: None,
+            regex: None,
+        })?;
+        Ok(RegexAst::Or(vec![
+            RegexAst::Literal("null".to_string()),
+            RegexAst::Regex("true|false".to_string()),
+            num,
+            string,
+        ]))
     }
 
     fn gen_json_object(&mut self, obj: &ObjectSchema) -> Result<NodeRef> {
@@ -422,7 +482,18 @@ impl Compiler {
             }
             // Quote (and escape) the name
             let quoted_name = json_dumps(&json!(name));
-            let property = match self.gen_json(property_schema) {
+            let name_allowed = match obj.property_names.as_deref() {
+                Some(names) => property_name_matches(names, name)?,
+                None => true,
+            };
+            let property = if name_allowed {
+                self.gen_json(property_schema)
+            } else {
+                Err(anyhow!(UnsatisfiableSchemaError {
+                    message: format!("property name '{name}' doesn't match propertyNames"),
+                }))
+            };
+            let property = match property {
                 Ok(node) => node,
                 Err(e) => match e.downcast_ref::<UnsatisfiableSchemaError>() {
                     // If it's not an UnsatisfiableSchemaError, just propagate it normally
@@ -451,6 +522,23 @@ impl Compiler {
             }
         }
 
+        if self.options.property_order == PropertyOrder::Any && items.len() > ParamValue::NUM_BITS {
+            let msg = format!(
+                "property_order 'any' supports at most {} properties (got {})",
+                ParamValue::NUM_BITS,
+                items.len()
+            );
+            if self.options.lenient {
+                self.add_warning("limit_exceeded", msg);
+            } else {
+                bail!(msg);
+            }
+        } else if self.options.property_order == PropertyOrder::Any && !items.is_empty() {
+            let pattern_options =
+                self.additional_property_options(obj, taken_names, unquoted_taken_names)?;
+            return self.object_fields_any_order(obj, items, pattern_options);
+        }
+
         let min_properties = obj.min_properties.saturating_sub(num_required);
         let max_properties = obj.max_properties.map(|v| v.saturating_sub(num_required));
 
@@ -520,12 +608,48 @@ impl Compiler {
 
             let msg = "min/maxProperties only supported when all keys listed in \"properties\" are required";
             if self.options.lenient {
-                self.builder.add_warning(msg.to_string());
+                self.add_warning("unsupported_keyword", msg.to_string());
             } else {
                 bail!(msg);
             }
         }
 
+        let pattern_options =
+            self.additional_property_options(obj, taken_names, unquoted_taken_names)?;
+
+        if !pattern_options.is_empty() && max_properties != Some(0) {
+            let pattern = self.builder.select(&pattern_options);
+            let required = min_properties > 0;
+            let seq = self.bounded_sequence(pattern, min_properties, max_properties)?;
+            items.push((seq, required));
+        } else if min_properties > 0 {
+            return Err(anyhow!(UnsatisfiableSchemaError {
+                message: format!(
+                    "minProperties ({min_properties}) is greater than number of properties ({num_required})"
+                ),
+            }));
+        }
+
+        self.object_fields(&items)
+    }
+
+    /// Options for a single property matching `patternProperties` or `additionalProperties`,
+    /// excluding already taken names.
+    fn additional_property_options(
+        &mut self,
+        obj: &ObjectSchema,
+        taken_names: Vec<String>,
+        unquoted_taken_names: Vec<String>,
+    ) -> Result<Vec<NodeRef>> {
+        let colon = self.key_separator()?;
+        let names_rx = match obj.property_names.as_deref() {
+            None => None,
+            Some(names) => match self.property_names_ast(names)? {
+                Some(ast) => Some(self.builder.regex.add_ast(ast)?),
+                // no names allowed
+                None => return Ok(vec![]),
+            },
+        };
         let mut taken_name_ids = taken_names
             .iter()
             .map(|n| self.builder.regex.literal(n.to_string()))
@@ -536,7 +660,7 @@ impl Compiler {
             let regex = self
                 .builder
                 .regex
-                .add_ast(self.json_quote(RegexAst::SearchRegex(regex_to_lark(pattern, "dw"))))?;
+                .add_ast(self.json_quote(search_regex_ast(regex_to_lark(pattern, "dw"))?))?;
             taken_name_ids.push(regex);
 
             let schema = match self.gen_json(schema) {
@@ -555,12 +679,16 @@ impl Compiler {
                 .filter(|(_, name)| self.pattern_cache.is_match(pattern, name).unwrap_or(true))
                 .map(|(idx, _)| taken_name_ids[idx])
                 .collect::<Vec<_>>();
-            let regex = if exclude_names.is_empty() {
+            let mut parts = vec![regex];
+            if !exclude_names.is_empty() {
+                let options = self.builder.regex.select(exclude_names);
+                parts.push(self.builder.regex.not(options));
+            }
+            parts.extend(names_rx);
+            let regex = if parts.len() == 1 {
                 regex
             } else {
-                let options = self.builder.regex.select(exclude_names);
-                let not_taken = self.builder.regex.not(options);
-                self.builder.regex.and(vec![regex, not_taken])
+                self.builder.regex.and(parts)
             };
 
             let name = self.builder.lexeme(regex);
@@ -576,14 +704,17 @@ impl Compiler {
                 // Ignore UnsatisfiableSchemaError for additionalProperties
             }
             Ok(property) => {
-                let name = if taken_name_ids.is_empty() {
+                let name = if taken_name_ids.is_empty() && names_rx.is_none() {
                     self.json_simple_string()?
                 } else {
-                    let taken = self.builder.regex.select(taken_name_ids);
-                    let not_taken = self.builder.regex.not(taken);
                     let valid_ast = self.json_general_unicode_string(0, None)?;
-                    let valid = self.builder.regex.add_ast(valid_ast)?;
-                    let valid_and_not_taken = self.builder.regex.and(vec![valid, not_taken]);
+                    let mut parts = vec![self.builder.regex.add_ast(valid_ast)?];
+                    if !taken_name_ids.is_empty() {
+                        let taken = self.builder.regex.select(taken_name_ids);
+                        parts.push(self.builder.regex.not(taken));
+                    }
+                    parts.extend(names_rx);
+                    let valid_and_not_taken = self.builder.regex.and(parts);
                     self.builder.lexeme(valid_and_not_taken)
                 };
                 let item = self.builder.join(&[name, colon, property]);
@@ -591,20 +722,227 @@ impl Compiler {
             }
         }
 
-        if !pattern_options.is_empty() && max_properties != Some(0) {
-            let pattern = self.builder.select(&pattern_options);
-            let required = min_properties > 0;
-            let seq = self.bounded_sequence(pattern, min_properties, max_properties)?;
-            items.push((seq, required));
-        } else if min_properties > 0 {
-            return Err(anyhow!(UnsatisfiableSchemaError {
-                message: format!(
-                    "minProperties ({min_properties}) is greater than number of properties ({num_required})"
-                ),
-            }));
+        Ok(pattern_options)
+    }
+
+    /// Object with named properties in any order, each at most once,
+    /// interleaved with any pattern or additional properties.
+    /// Bit `i` of the parameter is set once property `i` was generated;
+    /// required properties use the lowest bits.
+    /// When the number of additional properties is bounded, they are counted
+    /// in the bits above the named ones.
+    fn object_fields_any_order(
+        &mut self,
+        obj: &ObjectSchema,
+        mut items: Vec<(NodeRef, bool)>,
+        pattern_options: Vec<NodeRef>,
+    ) -> Result<NodeRef> {
+        // stable sort - required first
+        items.sort_by_key(|(_, required)| !*required);
+        let num_named = items.len();
+        let num_required = items.iter().filter(|(_, r)| *r).count();
+        let named_bits = ParamRef::new(0, num_named as u8);
+        let required_done = if num_required == 0 {
+            ParamCond::True
+        } else {
+            ParamCond::BitCountGE(ParamRef::new(0, num_required as u8), num_required as u8)
+        };
+
+        // min/maxProperties either limit the named properties (when there are no others),
+        // or only the additional ones (when all named properties are required)
+        let mut max_named = num_named;
+        let mut done = required_done.clone();
+        let mut additional = None;
+        if pattern_options.is_empty() {
+            if let Some(max) = obj.max_properties {
+                max_named = max_named.min(max);
+            }
+            if obj.min_properties > num_required {
+                if obj.min_properties > num_named {
+                    return Err(anyhow!(UnsatisfiableSchemaError {
+                        message: format!(
+                            "minProperties ({}) is greater than number of properties ({})",
+                            obj.min_properties, num_named
+                        ),
+                    }));
+                }
+                done = ParamCond::And(
+                    Box::new(done),
+                    Box::new(ParamCond::BitCountGE(named_bits, obj.min_properties as u8)),
+                );
+            }
+        } else {
+            let mut min_properties = obj.min_properties.saturating_sub(num_required);
+            let mut max_properties = obj.max_properties.map(|v| v.saturating_sub(num_required));
+            if num_required < num_named && (min_properties > 0 || max_properties.is_some()) {
+                let msg = "min/maxProperties only supported when all keys listed in \"properties\" are required";
+                if self.options.lenient {
+                    self.add_warning("unsupported_keyword", msg.to_string());
+                } else {
+                    bail!(msg);
+                }
+            }
+            let limit = max_properties.unwrap_or(min_properties);
+            let num_counter_bits = (usize::BITS - limit.leading_zeros()) as usize;
+            if num_named + num_counter_bits > ParamValue::NUM_BITS {
+                let msg = format!(
+                    "property_order 'any' with min/maxProperties supports at most {} properties (got {})",
+                    ParamValue::NUM_BITS - num_counter_bits,
+                    num_named
+                );
+                if self.options.lenient {
+                    self.add_warning("limit_exceeded", msg);
+                    min_properties = 0;
+                    max_properties = None;
+                } else {
+                    bail!(msg);
+                }
+            }
+            if max_properties != Some(0) {
+                let pattern = self.builder.select(&pattern_options);
+                let counter = if min_properties > 0 || max_properties.is_some() {
+                    Some(ParamRef::new(
+                        num_named as u8,
+                        (num_named + num_counter_bits) as u8,
+                    ))
+                } else {
+                    None
+                };
+                additional = Some((pattern, counter, min_properties, max_properties));
+            }
         }
 
-        self.object_fields(&items)
+        let under_max = if max_named < num_named {
+            Some(ParamCond::BitCountLT(named_bits, max_named as u8))
+        } else {
+            None
+        };
+        let named_can_continue = ParamCond::BitCountLT(named_bits, max_named as u8);
+        let mut can_continue = named_can_continue.clone();
+        // additional properties are counted until `min_properties` is reached,
+        // or all the way to `max_properties`; past `min_properties`,
+        // unbounded ones are generated as a plain list
+        let mut counted = None;
+        let mut free_done = ParamCond::True;
+        if let Some((_, Some(counter), min_properties, max_properties)) = &additional {
+            let limit = max_properties.unwrap_or(*min_properties);
+            counted = Some(ParamCond::LT(*counter, ParamValue(limit as u64)));
+            if *min_properties > 0 {
+                free_done = ParamCond::GE(*counter, ParamValue(*min_properties as u64));
+                done = ParamCond::And(Box::new(done), Box::new(free_done.clone()));
+            }
+        }
+        match &additional {
+            None => {}
+            Some((_, _, _, None)) => can_continue = ParamCond::True,
+            Some((_, _, _, Some(_))) => {
+                can_continue =
+                    ParamCond::Or(Box::new(can_continue), Box::new(counted.clone().unwrap()));
+            }
+        }
+
+        // named::p - one of the properties not yet in p, followed by the rest
+        // fields::p - non-empty list of properties not yet in p and additional ones
+        // rest::p - possibly empty list of properties, preceded by a comma
+        // after::p - like rest, but no additional property comes first
+        let named = self.builder.new_param_node("object_named", true);
+        let fields = self.builder.new_param_node("object_fields", true);
+        let rest = self.builder.new_param_node("object_rest", true);
+        let comma = self.item_separator()?;
+
+        let mut options = vec![];
+        let mut conds = vec![];
+        for (idx, (item, _)) in items.iter().enumerate() {
+            let with_bit = ParamExpr::BitOr(ParamValue(1 << idx));
+            let rest_with_bit = self.builder.apply(rest, Some(with_bit))?;
+            options.push(self.builder.join(&[*item, rest_with_bit]));
+            conds.push(match &under_max {
+                Some(under_max) => {
+                    ParamCond::And(Box::new(bit_clear(idx)), Box::new(under_max.clone()))
+                }
+                None => bit_clear(idx),
+            });
+        }
+        let inner = self.builder.select_with_cond(&options, conds);
+        self.builder.set_placeholder(named, inner);
+
+        let mut options = vec![named];
+        let mut conds = vec![ParamCond::True];
+        if let Some((pattern, counter, _, max_properties)) = additional {
+            if let (Some(counter), Some(cond)) = (counter, counted) {
+                let rest_incr = self.builder.apply(rest, Some(ParamExpr::Incr(counter)))?;
+                options.push(self.builder.join(&[pattern, rest_incr]));
+                conds.push(cond);
+            }
+            if max_properties.is_none() {
+                let after = self.builder.new_param_node("object_after", true);
+                let mut after_options = vec![];
+                let mut after_conds = vec![];
+                after_options.push(self.builder.empty());
+                after_conds.push(done.clone());
+                let comma_named = self.builder.join(&[comma, named]);
+                after_options.push(comma_named);
+                after_conds.push(named_can_continue);
+                let inner = self.builder.select_with_cond(&after_options, after_conds);
+                self.builder.set_placeholder(after, inner);
+
+                let pattern_comma = self.builder.join(&[pattern, comma]);
+                let list = self.builder.zero_or_more(pattern_comma);
+                options.push(self.builder.join(&[list, pattern, after]));
+                conds.push(free_done);
+            }
+        }
+        let inner = self.builder.select_with_cond(&options, conds);
+        self.builder.set_placeholder(fields, inner);
+
+        let mut options = vec![];
+        let mut conds = vec![];
+        options.push(self.builder.empty());
+        conds.push(done.clone());
+        options.push(self.builder.join(&[comma, fields]));
+        conds.push(can_continue.clone());
+        let inner = self.builder.select_with_cond(&options, conds);
+        self.builder.set_placeholder(rest, inner);
+
+        // same as rest, but without the leading comma
+        let options = [self.builder.empty(), fields];
+        let conds = vec![done, can_continue];
+        let start = self.builder.new_param_node("object_start", true);
+        let inner = self.builder.select_with_cond(&options, conds);
+        self.builder.set_placeholder(start, inner);
+
+        let opener = self.builder.string("{");
+        let inner = self
+            .builder
+            .apply(start, Some(ParamExpr::Const(ParamValue(0))))?;
+        let closer = self.builder.string("}");
+        Ok(self.builder.join(&[opener, inner, closer]))
+    }
+
+    /// Quoted regex for names allowed by `propertyNames`; `None` if no name is allowed.
+    fn property_names_ast(&mut self, names: &Schema) -> Result<Option<RegexAst>> {
+        match names {
+            Schema::Unsatisfiable(_) => Ok(None),
+            Schema::String(opts) => match self.gen_json_string(opts.clone()) {
+                Ok(ast) => Ok(Some(ast)),
+                Err(e) if e.downcast_ref::<UnsatisfiableSchemaError>().is_some() => Ok(None),
+                Err(e) => Err(e),
+            },
+            Schema::AnyOf(options) => {
+                let mut asts = vec![];
+                for opt in options {
+                    asts.extend(self.property_names_ast(opt)?);
+                }
+                Ok(if asts.is_empty() {
+                    None
+                } else {
+                    Some(RegexAst::Or(asts))
+                })
+            }
+            _ => bail!(
+                "propertyNames not supported unless it is a string schema without $ref or oneOf"
+            ),
+        }
     }
 
     fn object_fields(&mut self, items: &[(NodeRef, bool)]) -> Result<NodeRef> {
@@ -906,6 +1244,38 @@ impl Compiler {
             }
         }
 
+        if let Some(contains) = &arr.contains {
+            if arr.unique_items {
+                let msg = "uniqueItems together with contains is not supported";
+                if self.options.lenient {
+                    self.add_warning("unsupported_keyword", msg.to_string());
+                } else {
+                    bail!(msg);
+                }
+            }
+            if let Some(node) = self.gen_json_array_contains(arr, contains)? {
+                return Ok(node);
+            }
+            let msg = "contains not supported unless it matches only primitive values (no arrays or objects) and items do not use $ref or oneOf";
+            if self.options.lenient {
+                self.add_warning("unsupported_keyword", msg.to_string());
+            } else {
+                bail!(msg);
+            }
+        }
+
+        if arr.unique_items && max_items.is_none_or(|max| max > 1) {
+            if let Some(node) = self.gen_json_unique_array(arr)? {
+                return Ok(node);
+            }
+            let msg = "uniqueItems not supported unless items are consts, enums, booleans, nulls or small integer ranges (at most 64 distinct values)";
+            if self.options.lenient {
+                self.add_warning("unsupported_keyword", msg.to_string());
+            } else {
+                bail!(msg);
+            }
+        }
+
         let additional_item_grm = match self.gen_json(arr.items.schema_ref()) {
             Ok(node) => Some(node),
             Err(e) => match e.downcast_ref::<UnsatisfiableSchemaError>() {
@@ -1006,6 +1376,412 @@ impl Compiler {
         grammars.push(self.builder.string("]"));
         Ok(self.builder.join(&grammars))
     }
+
+    /// Split the schema into regexes for the primitive values it allows,
+    /// and nodes for the arrays and objects it allows.
+    /// Returns `false` if this is not possible (for `$ref` or `oneOf`).
+    fn split_primitives(
+        &mut self,
+        schema: &Schema,
+        primitives: &mut Vec<RegexAst>,
+        containers: &mut Vec<NodeRef>,
+    ) -> Result<bool> {
+        let r = match schema {
+            Schema::Any => {
+                primitives.push(self.json_any_primitive()?);
+                containers.extend(self.gen_json_any_containers()?);
+                Ok(())
+            }
+            Schema::Unsatisfiable(_) => Ok(()),
+            Schema::Ref(_) | Schema::OneOf(_) => return Ok(false),
+            Schema::AnyOf(options) => {
+                for option in options {
+                    if !self.split_primitives(option, primitives, containers)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(())
+            }
+            Schema::Array(_) | Schema::Object(_) => {
+                self.gen_json(schema).map(|node| containers.push(node))
+            }
+            Schema::Null | Schema::Boolean(_) | Schema::String(_) | Schema::Number(_) => self
+                .regex_compile(schema)
+                .map(|rx| primitives.push(rx.unwrap())),
+        };
+        match r {
+            Ok(()) => Ok(true),
+            Err(e) => match e.downcast_ref::<UnsatisfiableSchemaError>() {
+                None => Err(e),
+                Some(_) => Ok(true),
+            },
+        }
+    }
+
+    /// Generate an array grammar for `contains`, with the parameter [0:32]
+    /// counting the items matching `contains` schema.
+    /// Returns `None` if `contains` may match arrays or objects,
+    /// or the items cannot be split with [`Self::split_primitives()`].
+    fn gen_json_array_contains(
+        &mut self,
+        arr: &ArraySchema,
+        contains: &Schema,
+    ) -> Result<Option<NodeRef>> {
+        let mut contains_rx = vec![];
+        let mut containers = vec![];
+        if !self.split_primitives(contains, &mut contains_rx, &mut containers)?
+            || !containers.is_empty()
+        {
+            return Ok(None);
+        }
+        let contains_rx = self.builder.regex.add_ast(RegexAst::Or(contains_rx))?;
+
+        let min_items = arr.min_items;
+        let min_contains = arr.min_contains;
+        if min_contains > arr.max_items.unwrap_or(usize::MAX) {
+            return Err(anyhow!(UnsatisfiableSchemaError {
+                message: format!(
+                    "minContains ({min_contains}) is greater than maxItems ({})",
+                    arr.max_items.unwrap()
+                ),
+            }));
+        }
+
+        // (matching, not matching) nodes for each item position;
+        // only the positions up to minItems/prefixItems are unrolled,
+        // the last one is repeated while maxItems allows it
+        let tail = arr.prefix_items.len().max(min_items);
+        let num_positions = arr.max_items.unwrap_or(usize::MAX).min(tail + 1);
+        let mut positions = vec![];
+        let mut items_parts = None;
+        for idx in 0..num_positions {
+            let parts = if idx < arr.prefix_items.len() {
+                self.contains_parts(&arr.prefix_items[idx], contains_rx)?
+            } else if let Some(parts) = items_parts {
+                parts
+            } else {
+                let parts = self.contains_parts(arr.items.schema_ref(), contains_rx)?;
+                items_parts = Some(parts);
+                parts
+            };
+            match parts {
+                None => return Ok(None),
+                Some((None, None)) if idx < min_items => {
+                    return Err(anyhow!(UnsatisfiableSchemaError {
+                        message: format!("item {idx} is unsatisfiable but minItems is {min_items}"),
+                    }));
+                }
+                Some((None, None)) => break,
+                Some(parts) => positions.push(parts),
+            }
+        }
+        let repeat_last =
+            positions.len() == num_positions && arr.max_items.is_none_or(|max| max > num_positions);
+        // number of repetitions of the last position allowed after the first one
+        let max_repeats = arr
+            .max_items
+            .map(|max| max - num_positions)
+            .filter(|&n| n < u32::MAX as usize);
+
+        let count = ParamRef::new(0, 32);
+        let repeats = ParamRef::new(32, 64);
+        let elements = positions
+            .iter()
+            .map(|_| self.builder.new_param_node("contains_item", true))
+            .collect::<Vec<_>>();
+        let comma = self.item_separator()?;
+        let mut rests = vec![];
+        for idx in 1..=positions.len() {
+            let mut options = vec![];
+            let mut conds = vec![];
+            if idx >= min_items {
+                options.push(self.builder.empty());
+                conds.push(ParamCond::GE(count, ParamValue(min_contains as u64)));
+            }
+            if idx < positions.len() {
+                options.push(self.builder.join(&[comma, elements[idx]]));
+                conds.push(ParamCond::True);
+            } else if repeat_last {
+                let next = self.builder.join(&[comma, elements[idx - 1]]);
+                let next = match max_repeats {
+                    // the repeat counter was already incremented for this item
+                    Some(n) => {
+                        let cond = ParamCond::LE(repeats, ParamValue(n as u64));
+                        let next = self.builder.select_with_cond(&[next], vec![cond]);
+                        self.builder.apply(next, Some(ParamExpr::Incr(repeats)))?
+                    }
+                    None => next,
+                };
+                options.push(next);
+                conds.push(ParamCond::True);
+            }
+            let rest = match options.as_slice() {
+                // avoid a single-rule wrapper, it can't be referenced with a parameter
+                [option] if conds[0].is_true() => *option,
+                _ => self.builder.select_with_cond(&options, conds),
+            };
+            rests.push(rest);
+        }
+
+        let mut first = None;
+        for (idx, (matching, other)) in positions.iter().enumerate() {
+            let rest = rests[idx];
+            let mut options = vec![];
+            let mut conds = vec![];
+            if let Some(matching) = *matching {
+                let rest_incr = self.builder.apply(rest, Some(ParamExpr::Incr(count)))?;
+                options.push(self.builder.join(&[matching, rest_incr]));
+                conds.push(match arr.max_contains {
+                    Some(max) => ParamCond::LT(count, ParamValue(max as u64)),
+                    None => ParamCond::True,
+                });
+            }
+            if let Some(other) = *other {
+                options.push(self.builder.join(&[other, rest]));
+                conds.push(ParamCond::True);
+            }
+            let inner = self.builder.select_with_cond(&options, conds);
+            self.builder.set_placeholder(elements[idx], inner);
+            if idx == 0 {
+                // reference the rules directly, not the placeholder which has a single rule
+                first = Some(inner);
+            }
+        }
+
+        let opener = self.builder.string("[");
+        let closer = self.builder.string("]");
+        let inner = match first {
+            Some(inner) => {
+                let first = self
+                    .builder
+                    .apply(inner, Some(ParamExpr::Const(ParamValue(0))))?;
+                if min_items == 0 && min_contains == 0 {
+                    self.builder.optional(first)
+                } else {
+                    first
+                }
+            }
+            None => self.builder.empty(),
+        };
+        Ok(Some(self.builder.join(&[opener, inner, closer])))
+    }
+
+    /// Split the item schema into nodes matching and not matching `contains`
+    /// (both are `None` if the item is unsatisfiable).
+    /// Returns `None` if the item cannot be split.
+    fn contains_parts(
+        &mut self,
+        item: &Schema,
+        contains_rx: ExprRef,
+    ) -> Result<Option<(Option<NodeRef>, Option<NodeRef>)>> {
+        let mut primitives = vec![];
+        let mut others = vec![];
+        if !self.split_primitives(item, &mut primitives, &mut others)? {
+            return Ok(None);
+        }
+        let mut matching = None;
+        if !primitives.is_empty() {
+            let item_rx = self.builder.regex.add_ast(RegexAst::Or(primitives))?;
+            let not_contains = self.builder.regex.not(contains_rx);
+            let rx = self.builder.regex.and(vec![item_rx, contains_rx]);
+            matching = Some(self.builder.lexeme(rx));
+            let rx = self.builder.regex.and(vec![item_rx, not_contains]);
+            others.push(self.builder.lexeme(rx));
+        }
+        let other = if others.is_empty() {
+            None
+        } else {
+            Some(self.builder.select(&others))
+        };
+        Ok(Some((matching, other)))
+    }
+
+    /// Collect the distinct values allowed by the schema (keyed by their JSON representation),
+    /// provided there is only a small, finite number of them.
+    fn finite_values(&self, schema: &Schema, values: &mut IndexMap<String, Schema>) -> bool {
+        match schema {
+            Schema::Unsatisfiable(_) => {}
+            Schema::Null => {
+                values.insert("null".to_string(), Schema::Null);
+            }
+            Schema::Boolean(value) => {
+                for b in [false, true] {
+                    if value.is_none_or(|v| v == b) {
+                        values.insert(b.to_string(), Schema::Boolean(Some(b)));
+                    }
+                }
+            }
+            Schema::String(StringSchema {
+                regex: Some(RegexAst::Literal(s)),
+                ..
+            }) => {
+                values.insert(json_dumps(&json!(s)), schema.clone());
+            }
+            Schema::Number(num) if num.multiple_of.is_none() => {
+                if num.integer {
+                    let (Some(min), Some(max)) = normalize_integer_bounds(num) else {
+                        return false;
+                    };
+                    if (max as i128) - (min as i128) >= ParamValue::NUM_BITS as i128 {
+                        return false;
+                    }
+                    for v in min..=max {
+                        let num = NumberSchema {
+                            minimum: Some(v as f64),
+                            maximum: Some(v as f64),
+                            integer: true,
+                            ..NumberSchema::default()
+                        };
+                        values.insert(v.to_string(), Schema::Number(num));
+                    }
+                } else {
+                    match (num.minimum, num.maximum) {
+                        (Some(min), Some(max))
+                            if min == max
+                                && num.exclusive_minimum.is_none()
+                                && num.exclusive_maximum.is_none() =>
+                        {
+                            values.insert(min.to_string(), schema.clone());
+                        }
+                        _ => return false,
+                    }
+                }
+            }
+            Schema::AnyOf(options) => {
+                for option in options {
+                    if !self.finite_values(option, values) {
+                        return false;
+                    }
+                }
+            }
+            _ => return false,
+        }
+        values.len() <= ParamValue::NUM_BITS
+    }
+
+    /// Generate a parametric grammar for an array with `uniqueItems`,
+    /// where each of the possible item values is assigned a bit in the parameter.
+    /// Returns `None` if the items don't have a small, finite set of values.
+    fn gen_json_unique_array(&mut self, arr: &ArraySchema) -> Result<Option<NodeRef>> {
+        let mut all_values = IndexSet::new();
+        // the last position stands for all items after prefixItems
+        let mut positions = vec![];
+        for schema in arr
+            .prefix_items
+            .iter()
+            .chain(std::iter::once(arr.items.schema_ref()))
+        {
+            let mut values = IndexMap::new();
+            if !self.finite_values(schema, &mut values) {
+                return Ok(None);
+            }
+            let mut options = vec![];
+            for (key, value) in values {
+                let node = match self.gen_json(&value) {
+                    Ok(node) => node,
+                    Err(e) => match e.downcast_ref::<UnsatisfiableSchemaError>() {
+                        None => return Err(e),
+                        Some(_) => continue,
+                    },
+                };
+                let (bit, _) = all_values.insert_full(key);
+                options.push((bit, node));
+            }
+            positions.push(options);
+        }
+        if all_values.len() > ParamValue::NUM_BITS {
+            return Ok(None);
+        }
+
+        let min_items = arr.min_items;
+        let max_items = arr.max_items.unwrap_or(usize::MAX).min(all_values.len());
+        if min_items > max_items {
+            return Err(anyhow!(UnsatisfiableSchemaError {
+                message: format!(
+                    "minItems ({min_items}) is greater than number of unique items ({max_items})"
+                ),
+            }));
+        }
+
+        // elements[i]::p - item at position i, followed by the rest of the array;
+        // p has bits set for values already used, so its bit count is the number of items so far
+        let elements = positions
+            .iter()
+            .map(|options| {
+                if options.is_empty() {
+                    None
+                } else {
+                    Some(self.builder.new_param_node("unique_item", true))
+                }
+            })
+            .collect::<Vec<_>>();
+        let comma = self.item_separator()?;
+        let mut rests = vec![None; positions.len()];
+        let mut first = None;
+        let all_bits = ParamRef::full();
+
+        for (idx, options) in positions.iter().enumerate() {
+            let Some(element) = elements[idx] else {
+                continue;
+            };
+            let next = (idx + 1).min(positions.len() - 1);
+            let rest = match rests[next] {
+                Some(rest) => rest,
+                None => {
+                    let mut rest_options = vec![self.builder.empty()];
+                    let mut conds = vec![ParamCond::BitCountGE(all_bits, min_items as u8)];
+                    if let Some(next_element) = elements[next] {
+                        rest_options.push(self.builder.join(&[comma, next_element]));
+                        let mut cond = ParamCond::BitCountLT(all_bits, max_items as u8);
+                        if positions[next].len() < all_values.len() {
+                            // make sure there is still a value available for the next item
+                            let available = positions[next]
+                                .iter()
+                                .map(|&(bit, _)| bit_clear(bit))
+                                .reduce(|a, b| ParamCond::Or(Box::new(a), Box::new(b)))
+                                .unwrap();
+                            cond = ParamCond::And(Box::new(cond), Box::new(available));
+                        }
+                        conds.push(cond);
+                    }
+                    let rest = self.builder.select_with_cond(&rest_options, conds);
+                    rests[next] = Some(rest);
+                    rest
+                }
+            };
+            let mut alternatives = vec![];
+            let mut conds = vec![];
+            for &(bit, node) in options {
+                let with_bit = ParamExpr::BitOr(ParamValue(1 << bit));
+                let rest = self.builder.apply(rest, Some(with_bit))?;
+                alternatives.push(self.builder.join(&[node, rest]));
+                conds.push(bit_clear(bit));
+            }
+            let inner = self.builder.select_with_cond(&alternatives, conds);
+            self.builder.set_placeholder(element, inner);
+            if idx == 0 {
+                // reference the rules directly, not the placeholder which has a single rule
+                first = Some(inner);
+            }
+        }
+
+        let opener = self.builder.string("[");
+        let closer = self.builder.string("]");
+        let inner = match first {
+            Some(inner) if max_items > 0 => {
+                let first = self
+                    .builder
+                    .apply(inner, Some(ParamExpr::Const(ParamValue(0))))?;
+                if min_items == 0 {
+                    self.builder.optional(first)
+                } else {
+                    first
+                }
+            }
+            _ => self.builder.empty(),
+        };
+        Ok(Some(self.builder.join(&[opener, inner, closer])))
+    }
 }
 
 /// Build the `multipleOf` regex constraint with sign handling.
@@ -1023,6 +1799,43 @@ fn signed_multiple_of_ast(coef: u32, exp: u32) -> RegexAst {
     ])
 }
 
+/// Check if the (unquoted) property name is allowed by `propertyNames`.
+fn property_name_matches(names: &Schema, name: &str) -> Result<bool> {
+    match names {
+        Schema::Unsatisfiable(_) => Ok(false),
+        Schema::String(opts) => {
+            let len = name.chars().count();
+            if len < opts.min_length || len > opts.max_length.unwrap_or(usize::MAX) {
+                return Ok(false);
+            }
+            match &opts.regex {
+                None => Ok(true),
+                Some(ast) => {
+                    let mut builder = derivre::RegexBuilder::new();
+                    let expr = builder.mk(ast)?;
+                    let mut rx = builder.to_regex_limited(expr, 10_000)?;
+                    Ok(rx.is_match(name))
+                }
+            }
+        }
+        Schema::AnyOf(options) => {
+            for opt in options {
+                if property_name_matches(opt, name)? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+        _ => {
+            bail!("propertyNames not supported unless it is a string schema without $ref or oneOf")
+        }
+    }
+}
+
+fn bit_clear(bit: usize) -> ParamCond {
+    ParamCond::EQ(ParamRef::single_bit(bit as u8), ParamValue(0))
+}
+
 fn always_non_empty(ast: &RegexAst) -> bool {
     match ast {
         RegexAst::Or(asts) => asts.iter().any(always_non_empty),
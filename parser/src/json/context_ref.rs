@@ -65,7 +65,7 @@ impl<'a> Context<'a> {
         let ctx = Context {
             resolver,
             draft: pre_context.draft,
-            shared: Rc::new(RefCell::new(SharedContext::new())),
+            shared: Rc::new(RefCell::new(SharedContext::new(&pre_context.base_uri))),
             options: SchemaBuilderOptions::default(),
         };
 
@@ -46,7 +46,7 @@ impl<'a> Context<'a> {
         let ctx = Context {
             root_doc: Arc::clone(&pre_context.root_doc),
             draft: pre_context.draft,
-            shared: Rc::new(RefCell::new(SharedContext::new())),
+            shared: Rc::new(RefCell::new(SharedContext::new(&pre_context.base_uri))),
             options: SchemaBuilderOptions::default(),
             _marker: std::marker::PhantomData,
         };
@@ -1,3 +1,354 @@
+// Building blocks for RFC 3986 URIs; the argument is added to the character
+// classes of unreserved characters, which is used for RFC 3987 IRIs.
+// Capture groups are not named, since the blocks are used more than once in some formats.
+
+// unreserved / sub-delims
+macro_rules! uri_chars {
+    ($ext:expr) => {
+        concat!(r"a-zA-Z0-9\-._~!$&'()*+,;=", $ext)
+    };
+}
+
+// pchar = unreserved / pct-encoded / sub-delims / ":" / "@"
+macro_rules! uri_pchar {
+    ($ext:expr) => {
+        concat!(r"(?:[", uri_chars!($ext), r":@]|%[0-9a-fA-F]{2})")
+    };
+}
+
+// authority = [ userinfo "@" ] host [ ":" port ]
+macro_rules! uri_authority {
+    ($ext:expr) => {
+        concat!(
+            // userinfo = *( unreserved / pct-encoded / sub-delims / ":" )
+            r"(?:(?:[",
+            uri_chars!($ext),
+            r":]|%[0-9a-fA-F]{2})*@)?",
+            // host = IP-literal / IPv4address / reg-name
+            r"(?:",
+            // IP-literal = "[" ( IPv6address / IPvFuture ) "]"
+            r"\[",
+            r"(?:",
+            // IPv6address (simplified - covers common forms)
+            r"(?:[0-9a-fA-F]{1,4}:){7}[0-9a-fA-F]{1,4}|", // full
+            r"(?:[0-9a-fA-F]{1,4}:){1,7}:|",              // with trailing ::
+            r"(?:[0-9a-fA-F]{1,4}:){1,6}:[0-9a-fA-F]{1,4}|", // :: with 1 group after
+            r"(?:[0-9a-fA-F]{1,4}:){1,5}(?::[0-9a-fA-F]{1,4}){1,2}|", // :: with 2 groups after
+            r"(?:[0-9a-fA-F]{1,4}:){1,4}(?::[0-9a-fA-F]{1,4}){1,3}|", // :: with 3 groups after
+            r"(?:[0-9a-fA-F]{1,4}:){1,3}(?::[0-9a-fA-F]{1,4}){1,4}|", // :: with 4 groups after
+            r"(?:[0-9a-fA-F]{1,4}:){1,2}(?::[0-9a-fA-F]{1,4}){1,5}|", // :: with 5 groups after
+            r"[0-9a-fA-F]{1,4}:(?::[0-9a-fA-F]{1,4}){1,6}|", // :: with 6 groups after
+            r":(?::[0-9a-fA-F]{1,4}){1,7}|",              // ::x:x:x:x:x:x:x
+            r"::|",                                       // :: alone
+            // IPvFuture = "v" 1*HEXDIG "." 1*( unreserved / sub-delims / ":" )
+            r"v[0-9a-fA-F]+\.[a-zA-Z0-9\-._~!$&'()*+,;=:]+",
+            r")",
+            r"\]|",
+            // IPv4address
+            r"(?:(?:25[0-5]|2[0-4][0-9]|1[0-9]{2}|[1-9]?[0-9])\.){3}(?:25[0-5]|2[0-4][0-9]|1[0-9]{2}|[1-9]?[0-9])|",
+            // reg-name = *( unreserved / pct-encoded / sub-delims )
+            // Note: [ and \ are not valid in reg-name, host validation happens at regex level
+            r"(?:[",
+            uri_chars!($ext),
+            r"]|%[0-9a-fA-F]{2})*",
+            r")",
+            // [ ":" port ]
+            r"(?::[0-9]*)?",
+        )
+    };
+}
+
+// path-abempty = *( "/" segment )
+macro_rules! uri_path_abempty {
+    ($ext:expr) => {
+        concat!(r"(?:/", uri_pchar!($ext), r"*)*")
+    };
+}
+
+// [ "?" query ] [ "#" fragment ]
+macro_rules! uri_query_fragment {
+    ($ext:expr) => {
+        concat!(
+            r"(?:\?(?:[",
+            uri_chars!($ext),
+            r":@/?]|%[0-9a-fA-F]{2})*)?",
+            r"(?:\#(?:[",
+            uri_chars!($ext),
+            r":@/?]|%[0-9a-fA-F]{2})*)?",
+        )
+    };
+}
+
+// URI = scheme ":" hier-part [ "?" query ] [ "#" fragment ]
+macro_rules! uri {
+    ($ext:expr) => {
+        concat!(
+            // scheme = ALPHA *( ALPHA / DIGIT / "+" / "-" / "." )
+            r"[a-zA-Z][a-zA-Z0-9+\-.]*:",
+            // hier-part = "//" authority path-abempty / path-absolute / path-rootless / path-empty
+            r"(?:",
+            r"//",
+            uri_authority!($ext),
+            uri_path_abempty!($ext),
+            r"|",
+            // path-absolute = "/" [ segment-nz *( "/" segment ) ]
+            r"/(?:",
+            uri_pchar!($ext),
+            r"+",
+            uri_path_abempty!($ext),
+            r")?",
+            r"|",
+            // path-rootless = segment-nz *( "/" segment )
+            uri_pchar!($ext),
+            r"+",
+            uri_path_abempty!($ext),
+            r"|",
+            // path-empty = ""
+            r")",
+            uri_query_fragment!($ext),
+        )
+    };
+}
+
+// relative-ref = relative-part [ "?" query ] [ "#" fragment ]
+macro_rules! uri_relative_ref {
+    ($ext:expr) => {
+        concat!(
+            // relative-part = "//" authority path-abempty / path-absolute / path-noscheme / path-empty
+            r"(?:",
+            r"//",
+            uri_authority!($ext),
+            uri_path_abempty!($ext),
+            r"|",
+            r"/(?:",
+            uri_pchar!($ext),
+            r"+",
+            uri_path_abempty!($ext),
+            r")?",
+            r"|",
+            // path-noscheme = segment-nz-nc *( "/" segment ) - first segment without ":"
+            r"(?:[",
+            uri_chars!($ext),
+            r"@]|%[0-9a-fA-F]{2})+",
+            uri_path_abempty!($ext),
+            r"|",
+            r")",
+            uri_query_fragment!($ext),
+        )
+    };
+}
+
+// URI-reference = URI / relative-ref
+macro_rules! uri_reference {
+    ($ext:expr) => {
+        concat!("(?:", uri!($ext), ")|(?:", uri_relative_ref!($ext), ")")
+    };
+}
+
+// RFC 3987 ucschar (without private use areas)
+macro_rules! ucschar {
+    () => {
+        r"\x{A0}-\x{D7FF}\x{F900}-\x{FDCF}\x{FDF0}-\x{FFEF}\x{10000}-\x{EFFFD}"
+    };
+}
+
+// Hostname label; the argument is added to allowed characters
+macro_rules! hostname_label {
+    ($ext:expr) => {
+        concat!(
+            r"[a-zA-Z0-9",
+            $ext,
+            r"](?:[a-zA-Z0-9",
+            $ext,
+            r"-]{0,61}[a-zA-Z0-9",
+            $ext,
+            r"])?"
+        )
+    };
+}
+
+// RFC 3490 3.1 label separators: full stop, ideographic full stop,
+// fullwidth full stop and halfwidth ideographic full stop
+macro_rules! idn_separator {
+    () => {
+        r"[.\x{3002}\x{FF0E}\x{FF61}]"
+    };
+}
+
+// ucschar and the first argument, without the separators, the exceptions DISALLOWED
+// in RFC 5892 2.6, the characters with contextual rules (see idn_context!),
+// and the last argument; the Arabic-Indic digits (second argument) cannot be mixed,
+// so only one of the two kinds is added
+macro_rules! idn_chars {
+    ($ext:expr, $digits:expr, $excl:expr) => {
+        concat!(
+            "[[",
+            $ext,
+            ucschar!(),
+            r"--[.\x{3002}\x{FF0E}\x{FF61}\x{640}\x{7FA}\x{302E}\x{302F}\x{3031}-\x{3035}\x{303B}",
+            r"\x{B7}\x{375}\x{5F3}\x{5F4}\x{200D}\x{30FB}\x{660}-\x{669}\x{6F0}-\x{6F9}",
+            $excl,
+            "]]",
+            $digits,
+            "]"
+        )
+    };
+}
+
+// RFC 5892 Appendix A contextual rules (approximately): MIDDLE DOT between 'l's,
+// Greek KERAIA followed by Greek, Hebrew GERESH/GERSHAYIM preceded by Hebrew,
+// KATAKANA MIDDLE DOT next to Hiragana, Katakana or Han
+macro_rules! idn_context {
+    () => {
+        concat!(
+            r"l\x{B7}l|\x{375}[\x{370}-\x{3FF}\x{1F00}-\x{1FFF}]|[\x{591}-\x{5F2}][\x{5F3}\x{5F4}]|",
+            r"[\x{3040}-\x{30FA}\x{30FC}-\x{30FF}\x{3400}-\x{4DBF}\x{4E00}-\x{9FFF}\x{F900}-\x{FAFF}\x{20000}-\x{2FA1F}]\x{30FB}|",
+            r"\x{30FB}[\x{3040}-\x{30FA}\x{30FC}-\x{30FF}\x{3400}-\x{4DBF}\x{4E00}-\x{9FFF}\x{F900}-\x{FAFF}\x{20000}-\x{2FA1F}]"
+        )
+    };
+}
+
+// ZERO WIDTH JOINER preceded by a virama (canonical combining class 9)
+macro_rules! idn_zwj {
+    () => {
+        concat!(
+            r"[\x{94D}\x{9CD}\x{A4D}\x{ACD}\x{B4D}\x{BCD}\x{C4D}\x{CCD}\x{D3B}\x{D3C}\x{D4D}\x{DCA}",
+            r"\x{E3A}\x{EBA}\x{F84}\x{1039}\x{103A}\x{1714}\x{1715}\x{1734}\x{17D2}\x{1A60}\x{1B44}",
+            r"\x{1BAA}\x{1BAB}\x{1BF2}\x{1BF3}\x{2D7F}\x{A806}\x{A82C}\x{A8C4}\x{A953}\x{A9C0}\x{AAF6}\x{ABED}]\x{200D}"
+        )
+    };
+}
+
+// U-label with at least one non-ASCII character; it doesn't start with a combining mark,
+// nor start or end with a hyphen; the limit of 63 characters for the A-label is approximated
+// by allowing at most 20 characters after the first non-ASCII one
+macro_rules! idn_u_label {
+    ($digits:expr) => {
+        concat!(
+            r"(?:",
+            idn_chars!("", $digits, r"\p{M}"),
+            "|",
+            idn_context!(),
+            r"|[a-zA-Z0-9][a-zA-Z0-9\-]{0,19}(?:",
+            idn_chars!("", $digits, ""),
+            "|",
+            idn_context!(),
+            "|",
+            idn_zwj!(),
+            r"))(?:(?:",
+            idn_chars!(r"a-zA-Z0-9\-", $digits, ""),
+            "|",
+            idn_context!(),
+            "|",
+            idn_zwj!(),
+            r"){0,19}(?:",
+            idn_chars!("a-zA-Z0-9", $digits, ""),
+            "|",
+            idn_context!(),
+            "|",
+            idn_zwj!(),
+            r"))?"
+        )
+    };
+}
+
+// ASCII label (RFC 5890 2.3.1): an A-label with lowercase Punycode,
+// or a label without "--" in the 3rd and 4th position
+macro_rules! idn_label {
+    () => {
+        concat!(
+            r"(?:xn--[a-z0-9](?:[a-z0-9\-]{0,57}[a-z0-9])?|",
+            r"[a-zA-Z0-9](?:[a-zA-Z0-9\-]?[a-zA-Z0-9]|[a-zA-Z0-9\-]{2}[a-zA-Z0-9]|",
+            r"[a-zA-Z0-9\-](?:[a-zA-Z0-9][a-zA-Z0-9\-]|-[a-zA-Z0-9])[a-zA-Z0-9\-]{0,58}[a-zA-Z0-9])?|",
+            idn_u_label!(r"\x{660}-\x{669}"),
+            "|",
+            idn_u_label!(r"\x{6F0}-\x{6F9}"),
+            ")"
+        )
+    };
+}
+
+// ECMA-262 regular expressions, with groups nested at most 4 levels deep
+macro_rules! ecma_regex_atom0 {
+    () => {
+        concat!(
+            // escape, character class, or a plain character
+            r"(?:\\.|\[\^?\]?(?:[^\]\\]|\\.)*\]|[^\\\[\](){}|*+?])"
+        )
+    };
+}
+
+macro_rules! ecma_regex_alt {
+    ($atom:ident) => {
+        concat!(
+            r"(?:",
+            $atom!(),
+            r"(?:(?:[*+?]|\{[0-9]+(?:,[0-9]*)?\})\??)?)*",
+            r"(?:\|(?:",
+            $atom!(),
+            r"(?:(?:[*+?]|\{[0-9]+(?:,[0-9]*)?\})\??)?)*)*",
+        )
+    };
+}
+
+macro_rules! ecma_regex_group {
+    ($atom:ident) => {
+        concat!(
+            r"\((?:\?(?::|=|!|<=|<!|<[a-zA-Z_$][a-zA-Z0-9_$]*>))?",
+            ecma_regex_alt!($atom),
+            r"\)"
+        )
+    };
+}
+
+macro_rules! ecma_regex_atom1 {
+    () => {
+        concat!(
+            "(?:",
+            ecma_regex_atom0!(),
+            "|",
+            ecma_regex_group!(ecma_regex_atom0),
+            ")"
+        )
+    };
+}
+
+macro_rules! ecma_regex_atom2 {
+    () => {
+        concat!(
+            "(?:",
+            ecma_regex_atom0!(),
+            "|",
+            ecma_regex_group!(ecma_regex_atom1),
+            ")"
+        )
+    };
+}
+
+macro_rules! ecma_regex_atom3 {
+    () => {
+        concat!(
+            "(?:",
+            ecma_regex_atom0!(),
+            "|",
+            ecma_regex_group!(ecma_regex_atom2),
+            ")"
+        )
+    };
+}
+
+macro_rules! ecma_regex_atom4 {
+    () => {
+        concat!(
+            "(?:",
+            ecma_regex_atom0!(),
+            "|",
+            ecma_regex_group!(ecma_regex_atom3),
+            ")"
+        )
+    };
+}
+
 pub fn lookup_format(name: &str) -> Option<&str> {
     let r = match name {
         "date-time" => concat!(
@@ -57,67 +408,48 @@ pub fn lookup_format(name: &str) -> Option<&str> {
             r"(?P<time_low>[0-9a-fA-F]{8})-(?P<time_mid>[0-9a-fA-F]{4})-(?P<time_high_and_version>[0-9a-fA-F]{4})-(?P<clock_seq_and_reserved>[0-9a-fA-F]{2})(?P<clock_seq_low>[0-9a-fA-F]{2})-(?P<node>[0-9a-fA-F]{12})"
         }
         // RFC 3986 URI - strict compliance
-        // URI = scheme ":" hier-part [ "?" query ] [ "#" fragment ]
-        "uri" => concat!(
-            // scheme = ALPHA *( ALPHA / DIGIT / "+" / "-" / "." )
-            r"(?P<scheme>[a-zA-Z][a-zA-Z0-9+\-.]*)",
-            r":",
-            // hier-part = "//" authority path-abempty / path-absolute / path-rootless / path-empty
-            r"(?:",
-            // "//" authority path-abempty
-            r"//",
-            // authority = [ userinfo "@" ] host [ ":" port ]
-            r"(?:",
-            // userinfo = *( unreserved / pct-encoded / sub-delims / ":" )
-            r"(?P<userinfo>(?:[a-zA-Z0-9\-._~!$&'()*+,;=:]|%[0-9a-fA-F]{2})*)",
-            r"@",
-            r")?",
-            // host = IP-literal / IPv4address / reg-name
-            r"(?P<host>",
-            // IP-literal = "[" ( IPv6address / IPvFuture ) "]"
-            r"\[",
+        "uri" => uri!(""),
+        "uri-reference" => uri_reference!(""),
+        // RFC 3987 IRI - URI with non-ASCII characters
+        "iri" => uri!(ucschar!()),
+        "iri-reference" => uri_reference!(ucschar!()),
+        // RFC 6570 URI Template
+        "uri-template" => concat!(
             r"(?:",
-            // IPv6address (simplified - covers common forms)
-            r"(?:[0-9a-fA-F]{1,4}:){7}[0-9a-fA-F]{1,4}|", // full
-            r"(?:[0-9a-fA-F]{1,4}:){1,7}:|",              // with trailing ::
-            r"(?:[0-9a-fA-F]{1,4}:){1,6}:[0-9a-fA-F]{1,4}|", // :: with 1 group after
-            r"(?:[0-9a-fA-F]{1,4}:){1,5}(?::[0-9a-fA-F]{1,4}){1,2}|", // :: with 2 groups after
-            r"(?:[0-9a-fA-F]{1,4}:){1,4}(?::[0-9a-fA-F]{1,4}){1,3}|", // :: with 3 groups after
-            r"(?:[0-9a-fA-F]{1,4}:){1,3}(?::[0-9a-fA-F]{1,4}){1,4}|", // :: with 4 groups after
-            r"(?:[0-9a-fA-F]{1,4}:){1,2}(?::[0-9a-fA-F]{1,4}){1,5}|", // :: with 5 groups after
-            r"[0-9a-fA-F]{1,4}:(?::[0-9a-fA-F]{1,4}){1,6}|", // :: with 6 groups after
-            r":(?::[0-9a-fA-F]{1,4}){1,7}|",              // ::x:x:x:x:x:x:x
-            r"::|",                                       // :: alone
-            // IPvFuture = "v" 1*HEXDIG "." 1*( unreserved / sub-delims / ":" )
-            r"v[0-9a-fA-F]+\.[a-zA-Z0-9\-._~!$&'()*+,;=:]+",
-            r")",
-            r"\]|",
-            // IPv4address
-            r"(?:(?:25[0-5]|2[0-4][0-9]|1[0-9]{2}|[1-9]?[0-9])\.){3}(?:25[0-5]|2[0-4][0-9]|1[0-9]{2}|[1-9]?[0-9])|",
-            // reg-name = *( unreserved / pct-encoded / sub-delims )
-            // Note: [ and \ are not valid in reg-name, host validation happens at regex level
-            r"(?:[a-zA-Z0-9\-._~!$&'()*+,;=]|%[0-9a-fA-F]{2})*",
-            r")",
-            // [ ":" port ]
-            r"(?::(?P<port>[0-9]*))?",
-            // path-abempty = *( "/" segment )
-            // Disallow invalid characters: \, <, >, {, }, ^, `, |, space, control chars
-            r"(?P<path_abempty>(?:/(?:[a-zA-Z0-9\-._~!$&'()*+,;=:@]|%[0-9a-fA-F]{2})*)*)",
-            r"|",
-            // path-absolute = "/" [ segment-nz *( "/" segment ) ]
-            r"(?P<path_absolute>/(?:(?:[a-zA-Z0-9\-._~!$&'()*+,;=:@]|%[0-9a-fA-F]{2})+(?:/(?:[a-zA-Z0-9\-._~!$&'()*+,;=:@]|%[0-9a-fA-F]{2})*)*)?)",
-            r"|",
-            // path-rootless = segment-nz *( "/" segment )
-            r"(?P<path_rootless>(?:[a-zA-Z0-9\-._~!$&'()*+,;=:@]|%[0-9a-fA-F]{2})+(?:/(?:[a-zA-Z0-9\-._~!$&'()*+,;=:@]|%[0-9a-fA-F]{2})*)*)",
+            // literals
+            r#"[^\x00-\x20"'%<>\\^`{|}\x7F]|%[0-9a-fA-F]{2}|"#,
+            // expression = "{" [ operator ] variable-list "}"
+            r"\{[+#./;?&=,!@|]?",
+            // varspec = varname [ modifier-level4 ]
+            r"(?:[a-zA-Z0-9_]|%[0-9a-fA-F]{2})(?:\.?(?:[a-zA-Z0-9_]|%[0-9a-fA-F]{2}))*(?::[1-9][0-9]{0,3}|\*)?",
+            r"(?:,(?:[a-zA-Z0-9_]|%[0-9a-fA-F]{2})(?:\.?(?:[a-zA-Z0-9_]|%[0-9a-fA-F]{2}))*(?::[1-9][0-9]{0,3}|\*)?)*",
+            r"\}",
+            r")*"
+        ),
+        // RFC 6901 JSON Pointer
+        "json-pointer" => r"(?:/(?:[^/~]|~[01])*)*",
+        // draft-handrews-relative-json-pointer-01
+        "relative-json-pointer" => r"(?:0|[1-9][0-9]*)(?:#|(?:/(?:[^/~]|~[01])*)*)",
+        // ECMA-262 regular expression (approximate)
+        "regex" => ecma_regex_alt!(ecma_regex_atom4),
+        // RFC 6531 - email with UTF-8 local part and internationalized domain
+        "idn-email" => concat!(
+            r"[a-zA-Z0-9!#$%&'*+\-/=?\^_`{|}~",
+            ucschar!(),
+            r"]+(?:\.[a-zA-Z0-9!#$%&'*+\-/=?\^_`{|}~",
+            ucschar!(),
+            r"]+)*",
+            r"@(?:",
+            hostname_label!(ucschar!()),
+            r"(?:\.",
+            hostname_label!(ucschar!()),
+            r")*",
             r"|",
-            // path-empty = ""
-            r"(?P<path_empty>)",
-            r")",
-            // [ "?" query ]
-            r"(?:\?(?P<query>(?:[a-zA-Z0-9\-._~!$&'()*+,;=:@/?]|%[0-9a-fA-F]{2})*))?",
-            // [ "#" fragment ]
-            r"(?:\#(?P<fragment>(?:[a-zA-Z0-9\-._~!$&'()*+,;=:@/?]|%[0-9a-fA-F]{2})*))?"
+            r"\[(?:(?:25[0-5]|2[0-4][0-9]|1[0-9]{2}|[1-9]?[0-9])\.){3}(?:25[0-5]|2[0-4][0-9]|1[0-9]{2}|[1-9]?[0-9])\]",
+            r")"
         ),
+        // RFC 5890 - hostname with A-labels and U-labels
+        "idn-hostname" => concat!(idn_label!(), r"(?:", idn_separator!(), idn_label!(), r")*"),
         "unknown" => r"(?s:.*)",
         _ => return None,
     };
@@ -1,9 +1,12 @@
-use crate::{regex_to_lark, HashMap, JsonCompileOptions};
+use crate::{
+    diagnostics::SchemaError, regex_rewrite::search_regex_ast, regex_to_lark, HashMap, HashSet,
+    JsonCompileOptions,
+};
 use anyhow::{anyhow, bail, ensure, Result};
 use derivre::RegexAst;
 use indexmap::{IndexMap, IndexSet};
 use serde_json::Value;
-use std::mem;
+use std::{mem, rc::Rc};
 
 use super::context::{Context, Draft, PreContext, ResourceRef};
 use super::formats::lookup_format;
@@ -12,8 +15,11 @@ use super::shared_context::BuiltSchema;
 
 const TYPES: [&str; 6] = ["null", "boolean", "number", "string", "array", "object"];
 
+// Each dependency doubles the number of object schemas
+const MAX_DEPENDENCIES: usize = 8;
+
 // Keywords that are implemented in this module
-pub(crate) const IMPLEMENTED: [&str; 27] = [
+pub(crate) const IMPLEMENTED: [&str; 38] = [
     // Core
     "anyOf",
     "oneOf",
@@ -22,12 +28,19 @@ pub(crate) const IMPLEMENTED: [&str; 27] = [
     "const",
     "enum",
     "type",
+    "if",
+    "then",
+    "else",
     // Array
     "items",
     "additionalItems",
     "prefixItems",
     "minItems",
     "maxItems",
+    "uniqueItems",
+    "contains",
+    "minContains",
+    "maxContains",
     // Object
     "properties",
     "additionalProperties",
@@ -35,6 +48,10 @@ pub(crate) const IMPLEMENTED: [&str; 27] = [
     "required",
     "minProperties",
     "maxProperties",
+    "dependentRequired",
+    "dependentSchemas",
+    "propertyNames",
+    "unevaluatedProperties",
     // String
     "minLength",
     "maxLength",
@@ -148,6 +165,10 @@ pub struct ArraySchema {
     pub max_items: Option<usize>,
     pub prefix_items: Vec<Schema>,
     pub items: Option<Box<Schema>>,
+    pub unique_items: bool,
+    pub contains: Option<Box<Schema>>,
+    pub min_contains: usize,
+    pub max_contains: Option<usize>,
 }
 
 #[derive(Debug, Clone)]
@@ -158,6 +179,8 @@ pub struct ObjectSchema {
     pub required: IndexSet<String>,
     pub min_properties: usize,
     pub max_properties: Option<usize>,
+    /// Schema for all property names; always a string schema (or unsatisfiable).
+    pub property_names: Option<Box<Schema>>,
 }
 
 pub trait OptSchemaExt {
@@ -343,27 +366,41 @@ impl Schema {
                 },
             }),
 
-            (Schema::Array(mut a1), Schema::Array(mut a2)) => Schema::Array(ArraySchema {
-                min_items: a1.min_items.max(a2.min_items),
-                max_items: opt_min(a1.max_items, a2.max_items),
-                prefix_items: {
-                    let len = a1.prefix_items.len().max(a2.prefix_items.len());
-                    a1.prefix_items.resize_with(len, || a1.items.schema());
-                    a2.prefix_items.resize_with(len, || a2.items.schema());
-                    a1.prefix_items
-                        .into_iter()
-                        .zip(a2.prefix_items)
-                        .map(|(item1, item2)| item1.intersect(item2, ctx, stack_level + 1))
-                        .collect::<Result<Vec<_>>>()?
-                },
-                items: match (a1.items, a2.items) {
-                    (None, None) => None,
-                    (None, Some(item)) | (Some(item), None) => Some(item),
-                    (Some(item1), Some(item2)) => {
-                        Some(Box::new(item1.intersect(*item2, ctx, stack_level + 1)?))
+            (Schema::Array(mut a1), Schema::Array(mut a2)) => {
+                let (contains, min_contains, max_contains) = match (a1.contains, a2.contains) {
+                    (None, None) => (None, 0, None),
+                    (Some(c), None) => (Some(c), a1.min_contains, a1.max_contains),
+                    (None, Some(c)) => (Some(c), a2.min_contains, a2.max_contains),
+                    (Some(_), Some(_)) => {
+                        bail!("intersection of arrays with multiple 'contains' is not supported")
                     }
-                },
-            }),
+                };
+                Schema::Array(ArraySchema {
+                    min_items: a1.min_items.max(a2.min_items),
+                    max_items: opt_min(a1.max_items, a2.max_items),
+                    prefix_items: {
+                        let len = a1.prefix_items.len().max(a2.prefix_items.len());
+                        a1.prefix_items.resize_with(len, || a1.items.schema());
+                        a2.prefix_items.resize_with(len, || a2.items.schema());
+                        a1.prefix_items
+                            .into_iter()
+                            .zip(a2.prefix_items)
+                            .map(|(item1, item2)| item1.intersect(item2, ctx, stack_level + 1))
+                            .collect::<Result<Vec<_>>>()?
+                    },
+                    items: match (a1.items, a2.items) {
+                        (None, None) => None,
+                        (None, Some(item)) | (Some(item), None) => Some(item),
+                        (Some(item1), Some(item2)) => {
+                            Some(Box::new(item1.intersect(*item2, ctx, stack_level + 1)?))
+                        }
+                    },
+                    unique_items: a1.unique_items || a2.unique_items,
+                    contains,
+                    min_contains,
+                    max_contains,
+                })
+            }
 
             (Schema::Object(mut o1), Schema::Object(o2)) => {
                 let mut properties = IndexMap::new();
@@ -402,6 +439,14 @@ impl Schema {
                 let min_properties = o1.min_properties.max(o2.min_properties);
                 let max_properties = opt_min(o1.max_properties, o2.max_properties);
 
+                let property_names = match (o1.property_names, o2.property_names) {
+                    (None, None) => None,
+                    (None, Some(p)) | (Some(p), None) => Some(p),
+                    (Some(p1), Some(p2)) => {
+                        Some(Box::new((*p1).intersect(*p2, ctx, stack_level + 1)?))
+                    }
+                };
+
                 mk_object_schema(ObjectSchema {
                     properties,
                     pattern_properties,
@@ -409,6 +454,7 @@ impl Schema {
                     required,
                     min_properties,
                     max_properties,
+                    property_names,
                 })
             }
 
@@ -489,8 +535,9 @@ impl Schema {
                 let all_of = v
                     .as_array()
                     .ok_or_else(|| anyhow!("allOf must be an array"))?;
-                for value in all_of {
-                    let schema = compile_resource(ctx, ctx.as_resource_ref(value))?;
+                for (idx, value) in all_of.iter().enumerate() {
+                    let path = ["allOf", &idx.to_string()];
+                    let schema = compile_resource(ctx, ctx.as_resource_ref(value), &path)?;
                     result = result.intersect(schema, ctx, 0)?;
                 }
             }
@@ -500,7 +547,11 @@ impl Schema {
                     .ok_or_else(|| anyhow!("anyOf must be an array"))?;
                 let options = any_of
                     .iter()
-                    .map(|value| compile_resource(ctx, ctx.as_resource_ref(value)))
+                    .enumerate()
+                    .map(|(idx, value)| {
+                        let path = ["anyOf", &idx.to_string()];
+                        compile_resource(ctx, ctx.as_resource_ref(value), &path)
+                    })
                     .collect::<Result<Vec<_>>>()?;
                 result = result.intersect(Schema::AnyOf(options), ctx, 0)?;
             }
@@ -510,7 +561,11 @@ impl Schema {
                     .ok_or_else(|| anyhow!("oneOf must be an array"))?;
                 let options = one_of
                     .iter()
-                    .map(|value| compile_resource(ctx, ctx.as_resource_ref(value)))
+                    .enumerate()
+                    .map(|(idx, value)| {
+                        let path = ["oneOf", &idx.to_string()];
+                        compile_resource(ctx, ctx.as_resource_ref(value), &path)
+                    })
                     .collect::<Result<Vec<_>>>()?;
                 result = result.intersect(Schema::OneOf(options), ctx, 0)?;
             }
@@ -538,6 +593,7 @@ pub struct SchemaBuilderOptions {
     pub max_size: usize,
     pub max_stack_level: usize,
     pub lenient: bool,
+    pub formats: Rc<IndexMap<String, String>>,
 }
 
 impl Default for SchemaBuilderOptions {
@@ -546,6 +602,7 @@ impl Default for SchemaBuilderOptions {
             max_size: 50_000,
             max_stack_level: 128, // consumes ~2.5k of stack per level
             lenient: false,
+            formats: Rc::new(IndexMap::new()),
         }
     }
 }
@@ -564,19 +621,25 @@ pub fn build_schema(contents: Value, options: &JsonCompileOptions) -> Result<Bui
     let mut ctx = Context::new(&pre_ctx)?;
 
     ctx.options.lenient = options.lenient;
+    ctx.options.formats = Rc::new(options.formats.clone());
 
     let root_resource = ctx.lookup_resource(&pre_ctx.base_uri)?;
-    let schema = compile_resource(&ctx, root_resource)?;
+    let schema = compile_resource(&ctx, root_resource, &[])?;
     Ok(ctx.into_result(schema))
 }
 
-fn compile_resource(ctx: &Context, resource: ResourceRef) -> Result<Schema> {
-    let ctx = ctx.in_subresource(resource)?;
-    compile_contents(&ctx, resource.contents())
+/// Compile the subschema at `path` (unescaped keys and indices) below the current one.
+fn compile_resource(ctx: &Context, resource: ResourceRef, path: &[&str]) -> Result<Schema> {
+    ctx.with_json_pointer(ctx.subschema_pointer(path), || {
+        let ctx = ctx.in_subresource(resource)?;
+        compile_contents(&ctx, resource.contents())
+    })
 }
 
 fn compile_contents(ctx: &Context, contents: &Value) -> Result<Schema> {
-    compile_contents_inner(ctx, contents).map(|schema| schema.normalize(ctx))
+    compile_contents_inner(ctx, contents)
+        .map(|schema| schema.normalize(ctx))
+        .map_err(|e| SchemaError::at(e, ctx.json_pointer()))
 }
 
 fn compile_contents_inner(ctx: &Context, contents: &Value) -> Result<Schema> {
@@ -624,7 +687,7 @@ fn compile_contents_map(ctx: &Context, schemadict: IndexMap<&str, &Value>) -> Re
         unimplemented_keys.sort();
         let msg = format!("Unimplemented keys: {unimplemented_keys:?}");
         if ctx.options.lenient {
-            ctx.record_warning(msg);
+            ctx.record_warning("unsupported_keyword", msg);
         } else {
             bail!(msg);
         }
@@ -663,16 +726,24 @@ fn compile_contents_map(ctx: &Context, schemadict: IndexMap<&str, &Value>) -> Re
     let in_place_applicator_kwds = ["const", "enum", "allOf", "anyOf", "oneOf", "$ref"];
     for (k, v) in schemadict.iter() {
         if in_place_applicator_kwds.contains(k) {
-            if !current.is_empty() {
-                if let Some(&types) = schemadict.get("type") {
-                    // Make sure we always give type information to ensure we get the smallest union we can
-                    current.insert("type", types);
-                }
-                let current_schema = compile_contents_simple(ctx, std::mem::take(&mut current))?;
-                result = result.intersect(current_schema, ctx, 0)?;
-            }
+            result = flush_current(ctx, &schemadict, &mut current, result)?;
             // Finally apply the applicator
             result = result.apply((k, v), ctx)?;
+        } else if *k == "if" {
+            result = flush_current(ctx, &schemadict, &mut current, result)?;
+            match compile_if_then_else(ctx, &schemadict, v) {
+                Ok(conditional) => result = result.intersect(conditional, ctx, 0)?,
+                Err(e) if ctx.options.lenient => {
+                    ctx.record_warning(
+                        "unsupported_keyword",
+                        format!("{e}; ignoring if/then/else"),
+                    );
+                }
+                Err(e) => return Err(e),
+            }
+        } else if *k == "then" || *k == "else" || *k == "unevaluatedProperties" {
+            // "then" and "else" are handled together with "if" (and ignored without it);
+            // "unevaluatedProperties" is applied after everything else
         } else if !META_AND_ANNOTATIONS.contains(k) {
             current.insert(k, v);
             if *k == "additionalProperties" && !current.contains_key("properties") {
@@ -693,17 +764,136 @@ fn compile_contents_map(ctx: &Context, schemadict: IndexMap<&str, &Value>) -> Re
             }
         }
     }
-    if !current.is_empty() {
-        if let Some(&types) = schemadict.get("type") {
-            // Make sure we always give type information to ensure we get the smallest union we can
-            current.insert("type", types);
+    result = flush_current(ctx, &schemadict, &mut current, result)?;
+    if let Some(val) = schemadict.get("unevaluatedProperties") {
+        let mut seen = HashSet::default();
+        if has_if_on_property_values(
+            ctx,
+            &mut schemadict.iter().map(|(k, v)| (*k, *v)),
+            &mut seen,
+        )? {
+            let msg = "unevaluatedProperties together with 'if' conditions on properties is not supported";
+            if ctx.options.lenient {
+                ctx.record_warning(
+                    "unsupported_keyword",
+                    format!("{msg}; ignoring unevaluatedProperties"),
+                );
+                return Ok(result);
+            }
+            bail!(msg);
         }
-        let current_schema = compile_contents_simple(ctx, std::mem::take(&mut current))?;
-        result = result.intersect(current_schema, ctx, 0)?;
+        let unevaluated =
+            compile_resource(ctx, ctx.as_resource_ref(val), &["unevaluatedProperties"])?;
+        result = apply_unevaluated_properties(ctx, result, &unevaluated)?;
     }
     Ok(result)
 }
 
+/// Whether the schema or any of its in-place applicators has an `if` with `properties`.
+/// When such an `if` fails, the properties it checked are not evaluated,
+/// but the negated condition used for `else` lists them in `properties` just the same.
+fn has_if_on_property_values<'v>(
+    ctx: &Context,
+    schema: &mut dyn Iterator<Item = (&'v str, &'v Value)>,
+    seen: &mut HashSet<String>,
+) -> Result<bool> {
+    let mut subschemas = vec![];
+    for (k, v) in schema {
+        match k {
+            "if" if v.get("properties").is_some() => return Ok(true),
+            "then" | "else" => subschemas.push(v),
+            "allOf" | "anyOf" | "oneOf" => {
+                subschemas.extend(v.as_array().into_iter().flatten());
+            }
+            "dependentSchemas" => {
+                subschemas.extend(v.as_object().into_iter().flat_map(|o| o.values()));
+            }
+            "$ref" => {
+                let Some(reference) = v.as_str() else {
+                    continue;
+                };
+                let uri = ctx.normalize_ref(reference)?;
+                if seen.insert(uri.clone()) {
+                    subschemas.push(ctx.lookup_resource(&uri)?.contents());
+                }
+            }
+            _ => {}
+        }
+    }
+    for sub in subschemas {
+        if let Some(obj) = sub.as_object() {
+            if has_if_on_property_values(ctx, &mut obj.iter().map(|(k, v)| (k.as_str(), v)), seen)?
+            {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// Compile the keywords collected so far in `current` and intersect them into `result`.
+fn flush_current<'a>(
+    ctx: &Context,
+    schemadict: &IndexMap<&'a str, &'a Value>,
+    current: &mut HashMap<&'a str, &'a Value>,
+    result: Schema,
+) -> Result<Schema> {
+    if current.is_empty() {
+        return Ok(result);
+    }
+    if let Some(&types) = schemadict.get("type") {
+        // Make sure we always give type information to ensure we get the smallest union we can
+        current.insert("type", types);
+    }
+    let current_schema = compile_contents_simple(ctx, std::mem::take(current))?;
+    result.intersect(current_schema, ctx, 0)
+}
+
+/// Properties are only evaluated by `properties`, `patternProperties` and `additionalProperties`
+/// of the schema and its in-place applicators, all of which are merged at this point,
+/// so `unevaluatedProperties` becomes `additionalProperties` unless one is already present.
+fn apply_unevaluated_properties(
+    ctx: &Context,
+    schema: Schema,
+    unevaluated: &Schema,
+) -> Result<Schema> {
+    let r = match schema {
+        Schema::Object(mut obj) => {
+            if obj.additional_properties.is_none() {
+                obj.additional_properties = Some(Box::new(unevaluated.clone()));
+            }
+            Schema::Object(obj)
+        }
+        Schema::Any => {
+            let mut options = non_object_schemas();
+            let mut obj = empty_object_schema();
+            obj.additional_properties = Some(Box::new(unevaluated.clone()));
+            options.push(Schema::Object(obj));
+            Schema::AnyOf(options)
+        }
+        Schema::AnyOf(options) => Schema::AnyOf(
+            options
+                .into_iter()
+                .map(|opt| apply_unevaluated_properties(ctx, opt, unevaluated))
+                .collect::<Result<_>>()?,
+        ),
+        Schema::OneOf(options) => Schema::OneOf(
+            options
+                .into_iter()
+                .map(|opt| apply_unevaluated_properties(ctx, opt, unevaluated))
+                .collect::<Result<_>>()?,
+        ),
+        Schema::Ref(uri) => {
+            let resolved = ctx.get_ref_cloned(&uri).ok_or_else(|| {
+                anyhow!("circular references with unevaluatedProperties are not supported: {uri}")
+            })?;
+            apply_unevaluated_properties(ctx, resolved, unevaluated)?
+        }
+        schema => schema,
+    };
+    Ok(r)
+}
+
 fn compile_contents_simple(ctx: &Context, schemadict: HashMap<&str, &Value>) -> Result<Schema> {
     if schemadict.is_empty() {
         Ok(Schema::Any)
@@ -734,7 +924,9 @@ fn define_ref(ctx: &Context, ref_uri: &str) -> Result<()> {
     if !ctx.been_seen(ref_uri) {
         ctx.mark_seen(ref_uri);
         let resource = ctx.lookup_resource(ref_uri)?;
-        let resolved_schema = compile_resource(ctx, resource)?;
+        let resolved_schema = ctx.with_json_pointer(ctx.ref_pointer(ref_uri), || {
+            compile_resource(ctx, resource, &[])
+        })?;
         ctx.insert_ref(ref_uri, resolved_schema);
     }
     Ok(())
@@ -847,6 +1039,10 @@ fn compile_const(instance: &Value) -> Result<Schema> {
                 max_items: Some(prefix_items.len()),
                 prefix_items,
                 items: Some(Box::new(Schema::false_schema())),
+                unique_items: false,
+                contains: None,
+                min_contains: 0,
+                max_contains: None,
             }))
         }
         Value::Object(mapping) => {
@@ -862,11 +1058,224 @@ fn compile_const(instance: &Value) -> Result<Schema> {
                 required,
                 min_properties: 0,
                 max_properties: None,
+                property_names: None,
             }))
         }
     }
 }
 
+/// Rewrite `if`/`then`/`else` into `anyOf` of `if ∩ then` and `not(if) ∩ else`.
+/// Only conditions on `const`/`enum` values (or presence) of properties are supported,
+/// since for these we can also express the negation.
+fn compile_if_then_else(
+    ctx: &Context,
+    schemadict: &IndexMap<&str, &Value>,
+    if_value: &Value,
+) -> Result<Schema> {
+    let (cond, not_cond) = compile_if_condition(ctx, if_value)?;
+    let branch = |kw: &str| match schemadict.get(kw) {
+        None => Ok(Schema::Any),
+        Some(val) => compile_resource(ctx, ctx.as_resource_ref(val), &[kw]),
+    };
+    let then_branch = cond.intersect(branch("then")?, ctx, 0)?;
+    let else_branch = not_cond.intersect(branch("else")?, ctx, 0)?;
+    Ok(Schema::AnyOf(vec![then_branch, else_branch]))
+}
+
+fn unsupported_if(path: &str, reason: &str) -> anyhow::Error {
+    anyhow!(
+        "if/then/else: 'if' at {path} not supported: {reason}; only 'properties' with 'const' or 'enum' \
+        of strings, integers, booleans or null, 'required' and type 'object' are supported"
+    )
+}
+
+/// Returns schemas for the 'if' condition and its negation.
+fn compile_if_condition(ctx: &Context, if_value: &Value) -> Result<(Schema, Schema)> {
+    if let Some(b) = if_value.as_bool() {
+        return Ok(if b {
+            (Schema::Any, Schema::false_schema())
+        } else {
+            (Schema::false_schema(), Schema::Any)
+        });
+    }
+    let if_obj = if_value
+        .as_object()
+        .ok_or_else(|| unsupported_if("if", "expecting object or boolean"))?;
+
+    let mut object_only = false;
+    let mut conditions: IndexMap<String, (Option<Vec<Value>>, bool)> = IndexMap::new();
+    for (k, v) in if_obj {
+        match k.as_str() {
+            "type" => {
+                if v.as_str() != Some("object") {
+                    return Err(unsupported_if("if/type", "type must be 'object'"));
+                }
+                object_only = true;
+            }
+            "properties" => {
+                let props = v
+                    .as_object()
+                    .ok_or_else(|| unsupported_if("if/properties", "expecting object"))?;
+                for (name, prop) in props {
+                    let values = compile_if_values(&format!("if/properties/{name}"), prop)?;
+                    conditions.entry(name.clone()).or_default().0 = Some(values);
+                }
+            }
+            "required" => {
+                for name in compile_string_set("required", v)? {
+                    conditions.entry(name).or_default().1 = true;
+                }
+            }
+            _ if META_AND_ANNOTATIONS.contains(&k.as_str()) => {}
+            _ => return Err(unsupported_if(&format!("if/{k}"), "unsupported keyword")),
+        }
+    }
+
+    // The condition holds when all the per-property conditions hold.
+    // For the negation, we take the first property condition that fails
+    // (with all the preceding ones holding), which keeps branches disjoint.
+    let mut cond = Schema::Object(empty_object_schema());
+    let mut not_options = vec![];
+    for (name, (values, required)) in conditions {
+        if values.is_none() && !required {
+            continue;
+        }
+        let mut holds = empty_object_schema();
+        let mut fails = empty_object_schema();
+        fails.required.insert(name.clone());
+        match values {
+            Some(values) => {
+                let options = values.iter().map(compile_const).collect::<Result<_>>()?;
+                holds
+                    .properties
+                    .insert(name.clone(), Schema::AnyOf(options));
+                fails
+                    .properties
+                    .insert(name.clone(), complement_of_values(&values));
+            }
+            None => {
+                fails
+                    .properties
+                    .insert(name.clone(), Schema::unsat("'if' property is present"));
+            }
+        }
+        let mut fails = Schema::Object(fails);
+        if required {
+            holds.required.insert(name.clone());
+            let mut absent = empty_object_schema();
+            absent
+                .properties
+                .insert(name, Schema::unsat("'if' property is absent"));
+            fails = Schema::AnyOf(vec![Schema::Object(absent), fails]);
+        }
+        let holds = Schema::Object(holds);
+        not_options.push(cond.clone().intersect(fails, ctx, 0)?);
+        cond = cond.intersect(holds, ctx, 0)?;
+    }
+
+    // 'properties' and 'required' only apply to objects
+    let mut non_objects = non_object_schemas();
+    if object_only {
+        cond = cond.intersect(Schema::Object(empty_object_schema()), ctx, 0)?;
+        non_objects.extend(not_options);
+        Ok((cond, Schema::AnyOf(non_objects)))
+    } else {
+        non_objects.push(cond);
+        Ok((Schema::AnyOf(non_objects), Schema::AnyOf(not_options)))
+    }
+}
+
+fn compile_if_values(path: &str, prop: &Value) -> Result<Vec<Value>> {
+    let prop = prop
+        .as_object()
+        .ok_or_else(|| unsupported_if(path, "expecting object with 'const' or 'enum'"))?;
+    let mut values = None;
+    for (k, v) in prop {
+        match k.as_str() {
+            "const" => values = Some(vec![v.clone()]),
+            "enum" => {
+                let arr = v
+                    .as_array()
+                    .ok_or_else(|| unsupported_if(&format!("{path}/enum"), "expecting array"))?;
+                values = Some(arr.clone());
+            }
+            _ if META_AND_ANNOTATIONS.contains(&k.as_str()) => {}
+            _ => {
+                return Err(unsupported_if(
+                    &format!("{path}/{k}"),
+                    "unsupported keyword",
+                ))
+            }
+        }
+    }
+    let values =
+        values.ok_or_else(|| unsupported_if(path, "expecting object with 'const' or 'enum'"))?;
+    for v in &values {
+        // the complement of non-integer numbers is not exact with our number regexes
+        let is_integer = v.is_i64() || v.is_u64();
+        if !(is_integer || matches!(v, Value::String(_) | Value::Bool(_) | Value::Null)) {
+            return Err(unsupported_if(
+                path,
+                &format!(
+                    "value {} is not a string, integer, boolean or null",
+                    limited_str(v)
+                ),
+            ));
+        }
+    }
+    Ok(values)
+}
+
+/// Any JSON value, except for the given strings, integers, booleans and nulls.
+fn complement_of_values(values: &[Value]) -> Schema {
+    let strings = values
+        .iter()
+        .filter_map(|v| v.as_str().map(|s| RegexAst::Literal(s.to_string())))
+        .collect::<Vec<_>>();
+    let mut options = vec![];
+    if !values.contains(&Value::Null) {
+        options.push(Schema::Null);
+    }
+    match (
+        values.contains(&Value::Bool(true)),
+        values.contains(&Value::Bool(false)),
+    ) {
+        (false, false) => options.push(Schema::Boolean(None)),
+        (true, false) => options.push(Schema::Boolean(Some(false))),
+        (false, true) => options.push(Schema::Boolean(Some(true))),
+        (true, true) => {}
+    }
+    // numbers strictly between the excluded ones
+    let mut numbers = values.iter().filter_map(Value::as_f64).collect::<Vec<_>>();
+    numbers.sort_by(f64::total_cmp);
+    numbers.dedup();
+    let mut lower = None;
+    for upper in numbers.iter().copied().map(Some).chain([None]) {
+        options.push(Schema::Number(NumberSchema {
+            exclusive_minimum: lower,
+            exclusive_maximum: upper,
+            ..NumberSchema::default()
+        }));
+        lower = upper;
+    }
+    options.push(Schema::String(StringSchema {
+        min_length: 0,
+        max_length: None,
+        regex: if strings.is_empty() {
+            None
+        } else {
+            // the negation needs to be limited to strings before JSON quoting
+            Some(RegexAst::And(vec![
+                RegexAst::Regex("(?s:.*)".to_string()),
+                RegexAst::Not(Box::new(RegexAst::Or(strings))),
+            ]))
+        },
+    }));
+    options.push(Schema::Array(any_array_schema()));
+    options.push(Schema::Object(empty_object_schema()));
+    Schema::AnyOf(options)
+}
+
 fn compile_types(
     ctx: &Context,
     types: Vec<&str>,
@@ -978,7 +1387,7 @@ fn compile_string(ctx: &Context, schema: &HashMap<&str, &Value>) -> Result<Schem
                 .as_str()
                 .ok_or_else(|| anyhow!("Expected string for 'pattern', got {}", limited_str(val)))?
                 .to_string();
-            RegexAst::SearchRegex(regex_to_lark(&s, "dw"))
+            search_regex_ast(regex_to_lark(&s, "dw"))?
         }),
     };
     let format_rx = match format {
@@ -989,12 +1398,14 @@ fn compile_string(ctx: &Context, schema: &HashMap<&str, &Value>) -> Result<Schem
                 .ok_or_else(|| anyhow!("Expected string for 'format', got {}", limited_str(val)))?
                 .to_string();
 
-            if let Some(fmt) = lookup_format(&key) {
+            if let Some(fmt) = ctx.options.formats.get(&key) {
+                Some(RegexAst::Regex(fmt.clone()))
+            } else if let Some(fmt) = lookup_format(&key) {
                 Some(RegexAst::Regex(fmt.to_string()))
             } else {
                 let msg = format!("Unknown format: {key}");
                 if ctx.options.lenient {
-                    ctx.record_warning(msg);
+                    ctx.record_warning("unknown_format", msg);
                     None
                 } else {
                     bail!(msg);
@@ -1016,13 +1427,23 @@ fn compile_string(ctx: &Context, schema: &HashMap<&str, &Value>) -> Result<Schem
 }
 
 fn compile_array(ctx: &Context, schema: &HashMap<&str, &Value>) -> Result<Schema> {
-    let min_items = get_usize(schema, "minItems")?.unwrap_or(0);
-    let max_items = get_usize(schema, "maxItems")?;
+    let mut min_items = get_usize(schema, "minItems")?.unwrap_or(0);
+    let mut max_items = get_usize(schema, "maxItems")?;
     let prefix_items = schema.get("prefixItems").copied();
     let items = schema.get("items").copied();
     let additional_items = schema.get("additionalItems").copied();
+    let unique_items = match schema.get("uniqueItems") {
+        None => false,
+        Some(val) => val.as_bool().ok_or_else(|| {
+            anyhow!(
+                "Expected boolean for 'uniqueItems', got {}",
+                limited_str(val)
+            )
+        })?,
+    };
 
-    let (prefix_items, items) = {
+    // the keywords are kept to locate errors
+    let ((prefix_items, prefix_items_kw), (items, items_kw)) = {
         // Note that draft detection falls back to Draft202012 if the draft is unknown, so let's relax the draft constraint a bit
         // and assume we're in an old draft if additionalItems is present or items is an array
         if ctx.draft <= Draft::Draft201909
@@ -1031,12 +1452,14 @@ fn compile_array(ctx: &Context, schema: &HashMap<&str, &Value>) -> Result<Schema
         {
             match (items, additional_items) {
                 // Treat array items as prefixItems and additionalItems as items in draft 2019-09 and earlier
-                (Some(Value::Array(..)), _) => (items, additional_items),
+                (Some(Value::Array(..)), _) => {
+                    ((items, "items"), (additional_items, "additionalItems"))
+                }
                 // items is treated as items, and additionalItems is ignored if items is not an array (or is missing)
-                _ => (None, items),
+                _ => ((None, "prefixItems"), (items, "items")),
             }
         } else {
-            (prefix_items, items)
+            ((prefix_items, "prefixItems"), (items, "items"))
         }
     };
     let prefix_items = match prefix_items {
@@ -1045,18 +1468,54 @@ fn compile_array(ctx: &Context, schema: &HashMap<&str, &Value>) -> Result<Schema
             .as_array()
             .ok_or_else(|| anyhow!("Expected array for 'prefixItems', got {}", limited_str(val)))?
             .iter()
-            .map(|item| compile_resource(ctx, ctx.as_resource_ref(item)))
+            .enumerate()
+            .map(|(idx, item)| {
+                let path = [prefix_items_kw, &idx.to_string()];
+                compile_resource(ctx, ctx.as_resource_ref(item), &path)
+            })
             .collect::<Result<Vec<Schema>>>()?,
     };
     let items = match items {
         None => None,
-        Some(val) => Some(Box::new(compile_resource(ctx, ctx.as_resource_ref(val))?)),
+        Some(val) => Some(Box::new(compile_resource(
+            ctx,
+            ctx.as_resource_ref(val),
+            &[items_kw],
+        )?)),
+    };
+    // minContains and maxContains are ignored without contains
+    let mut min_contains = 0;
+    let mut max_contains = None;
+    let contains = match schema.get("contains") {
+        None => None,
+        Some(val) => {
+            min_contains = get_usize(schema, "minContains")?.unwrap_or(1);
+            max_contains = get_usize(schema, "maxContains")?;
+            match compile_resource(ctx, ctx.as_resource_ref(val), &["contains"])? {
+                // every item matches, so this only limits the number of items
+                Schema::Any => {
+                    min_items = min_items.max(min_contains);
+                    max_items = opt_min(max_items, max_contains);
+                    None
+                }
+                _ if min_contains == 0 && max_contains.is_none() => None,
+                Schema::Unsatisfiable(_) if min_contains == 0 => None,
+                Schema::Unsatisfiable(reason) => {
+                    return Ok(Schema::Unsatisfiable(format!("contains: {reason}")));
+                }
+                contains => Some(Box::new(contains)),
+            }
+        }
     };
     Ok(Schema::Array(ArraySchema {
         min_items,
         max_items,
         prefix_items,
         items,
+        unique_items,
+        contains,
+        min_contains,
+        max_contains,
     }))
 }
 
@@ -1071,7 +1530,9 @@ fn compile_prop_map(
             .as_object()
             .ok_or_else(|| anyhow!("Expected object for '{lbl}', got {}", limited_str(val)))?
             .iter()
-            .map(|(k, v)| compile_resource(ctx, ctx.as_resource_ref(v)).map(|v| (k.clone(), v)))
+            .map(|(k, v)| {
+                compile_resource(ctx, ctx.as_resource_ref(v), &[lbl, k]).map(|v| (k.clone(), v))
+            })
             .collect(),
     }
 }
@@ -1129,35 +1590,179 @@ fn compile_object(ctx: &Context, schema: &HashMap<&str, &Value>) -> Result<Schem
 
     let additional_properties = match additional_properties {
         None => None,
-        Some(val) => Some(Box::new(compile_resource(ctx, ctx.as_resource_ref(val))?)),
+        Some(val) => Some(Box::new(compile_resource(
+            ctx,
+            ctx.as_resource_ref(val),
+            &["additionalProperties"],
+        )?)),
     };
     let required = match required {
         None => IndexSet::new(),
-        Some(val) => val
-            .as_array()
-            .ok_or_else(|| anyhow!("Expected array for 'required', got {}", limited_str(val)))?
-            .iter()
-            .map(|item| {
-                item.as_str()
-                    .ok_or_else(|| {
-                        anyhow!(
-                            "Expected string for 'required' item, got {}",
-                            limited_str(item)
-                        )
-                    })
-                    .map(|s| s.to_string())
-            })
-            .collect::<Result<IndexSet<String>>>()?,
+        Some(val) => compile_string_set("required", val)?,
     };
 
-    Ok(mk_object_schema(ObjectSchema {
+    let mut dependencies: IndexMap<String, (IndexSet<String>, Option<Schema>)> = IndexMap::new();
+    if let Some(val) = schema.get("dependentRequired") {
+        let deps = val.as_object().ok_or_else(|| {
+            anyhow!(
+                "Expected object for 'dependentRequired', got {}",
+                limited_str(val)
+            )
+        })?;
+        for (key, val) in deps {
+            let names = compile_string_set("dependentRequired", val)?;
+            dependencies.insert(key.clone(), (names, None));
+        }
+    }
+    let dependent_schemas = compile_prop_map(
+        ctx,
+        "dependentSchemas",
+        schema.get("dependentSchemas").copied(),
+    )?;
+    for (key, dep_schema) in dependent_schemas {
+        dependencies.entry(key).or_default().1 = Some(dep_schema);
+    }
+
+    let property_names = match schema.get("propertyNames") {
+        None => None,
+        Some(val) => {
+            let names = compile_resource(ctx, ctx.as_resource_ref(val), &["propertyNames"])?;
+            // property names are always strings
+            match names.intersect(any_string_schema(), ctx, 0)? {
+                Schema::String(StringSchema {
+                    min_length: 0,
+                    max_length: None,
+                    regex: None,
+                }) => None,
+                names => Some(Box::new(names)),
+            }
+        }
+    };
+
+    let result = mk_object_schema(ObjectSchema {
         properties,
         pattern_properties,
         additional_properties,
         required,
         min_properties,
         max_properties,
-    }))
+        property_names,
+    });
+    if dependencies.is_empty() {
+        Ok(result)
+    } else {
+        apply_dependencies(ctx, result, dependencies)
+    }
+}
+
+fn compile_string_set(lbl: &str, val: &Value) -> Result<IndexSet<String>> {
+    val.as_array()
+        .ok_or_else(|| anyhow!("Expected array for '{lbl}', got {}", limited_str(val)))?
+        .iter()
+        .map(|item| {
+            item.as_str()
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Expected string for '{lbl}' item, got {}",
+                        limited_str(item)
+                    )
+                })
+                .map(|s| s.to_string())
+        })
+        .collect()
+}
+
+fn any_string_schema() -> Schema {
+    Schema::String(StringSchema {
+        min_length: 0,
+        max_length: None,
+        regex: None,
+    })
+}
+
+/// Schemas matching any JSON value that is not an object.
+fn non_object_schemas() -> Vec<Schema> {
+    vec![
+        Schema::Null,
+        Schema::Boolean(None),
+        Schema::Number(NumberSchema::default()),
+        any_string_schema(),
+        Schema::Array(any_array_schema()),
+    ]
+}
+
+fn any_array_schema() -> ArraySchema {
+    ArraySchema {
+        min_items: 0,
+        max_items: None,
+        prefix_items: vec![],
+        items: None,
+        unique_items: false,
+        contains: None,
+        min_contains: 0,
+        max_contains: None,
+    }
+}
+
+fn empty_object_schema() -> ObjectSchema {
+    ObjectSchema {
+        properties: IndexMap::new(),
+        pattern_properties: IndexMap::new(),
+        additional_properties: None,
+        required: IndexSet::new(),
+        min_properties: 0,
+        max_properties: None,
+        property_names: None,
+    }
+}
+
+/// Express `dependentRequired` and `dependentSchemas` by splitting the object schema
+/// into `anyOf` branches where the property is either absent,
+/// or present together with its dependencies.
+fn apply_dependencies(
+    ctx: &Context,
+    mut result: Schema,
+    dependencies: IndexMap<String, (IndexSet<String>, Option<Schema>)>,
+) -> Result<Schema> {
+    let mut num_branching = 0;
+    for (key, (names, dep_schema)) in dependencies {
+        let is_required = match &result {
+            Schema::Object(obj) => obj.required.contains(&key),
+            _ => false,
+        };
+
+        let mut present = empty_object_schema();
+        present.required.insert(key.clone());
+        present.required.extend(names);
+        let mut present = Schema::Object(present);
+        if let Some(dep_schema) = dep_schema {
+            present = present.intersect(dep_schema, ctx, 0)?;
+        }
+
+        let dependency = if is_required {
+            present
+        } else {
+            num_branching += 1;
+            if num_branching > MAX_DEPENDENCIES {
+                let msg = format!(
+                    "too many dependentRequired/dependentSchemas not supported (max {MAX_DEPENDENCIES} for non-required properties)"
+                );
+                if ctx.options.lenient {
+                    ctx.record_warning("limit_exceeded", msg);
+                    break;
+                } else {
+                    bail!(msg);
+                }
+            }
+            let mut absent = empty_object_schema();
+            absent
+                .properties
+                .insert(key.clone(), Schema::unsat("dependent property is absent"));
+            Schema::AnyOf(vec![Schema::Object(absent), present])
+        };
+        result = result.intersect(dependency, ctx, 0)?;
+    }
+    Ok(result)
 }
 
 fn mk_object_schema(obj: ObjectSchema) -> Schema {
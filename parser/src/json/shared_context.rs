@@ -1,4 +1,7 @@
-use crate::{json::schema::OptSchemaExt, regex_to_lark, HashMap, HashSet};
+use crate::{
+    diagnostics::Diagnostic, json::schema::OptSchemaExt, regex_rewrite::search_regex_ast,
+    regex_to_lark, HashMap, HashSet,
+};
 use anyhow::{anyhow, bail, Result};
 use derivre::{Regex, RegexAst, RegexBuilder};
 
@@ -11,8 +14,13 @@ pub struct SharedContext {
     defs: HashMap<String, Schema>,
     seen: HashSet<String>,
     n_compiled: usize,
-    pending_warnings: Vec<String>,
+    pending_warnings: Vec<Diagnostic>,
     pattern_cache: PatternPropertyCache,
+    // base URI of the root document, to tell which $ref's point into it
+    root_uri: String,
+    // JSON pointer of the subschema being compiled, None if outside of the root document
+    json_pointer: Option<String>,
+    def_pointers: HashMap<String, String>,
 }
 
 #[derive(Default)]
@@ -30,7 +38,7 @@ impl PatternPropertyCache {
         }
 
         let mut builder = RegexBuilder::new();
-        let eref = builder.mk_regex_for_serach(lark_regex.as_str())?;
+        let eref = builder.mk(&search_regex_ast(lark_regex.clone())?)?;
         let mut rx = builder.to_regex_limited(eref, CHECK_LIMIT)?;
         let res = rx.is_match(value);
         self.inner.insert(lark_regex, rx);
@@ -44,7 +52,7 @@ impl PatternPropertyCache {
             .iter()
             .map(|regex| {
                 let regex = regex_to_lark(regex, "dw");
-                builder.mk_regex_for_serach(regex.as_str())
+                builder.mk(&search_regex_ast(regex)?)
             })
             .collect::<Result<Vec<_>>>()?;
         for (ai, a) in erefs.iter().enumerate() {
@@ -94,23 +102,93 @@ impl PatternPropertyCache {
 }
 
 impl SharedContext {
-    pub fn new() -> Self {
+    pub fn new(root_uri: &str) -> Self {
         SharedContext {
             defs: HashMap::default(),
             seen: HashSet::default(),
             n_compiled: 0,
             pending_warnings: Vec::new(),
             pattern_cache: PatternPropertyCache::default(),
+            root_uri: root_uri.to_string(),
+            json_pointer: Some(String::new()),
+            def_pointers: HashMap::default(),
+        }
+    }
+
+    /// JSON pointer to the target of `uri`, if it is a JSON pointer fragment within the root document.
+    fn ref_pointer(&self, uri: &str) -> Option<String> {
+        let base = |u: &str| u.split_once('#').map_or(u, |(b, _)| b).to_string();
+        let (uri_base, fragment) = uri.split_once('#').unwrap_or((uri, ""));
+        if uri_base != base(&self.root_uri) {
+            return None;
+        }
+        let pointer = percent_decode(fragment)?;
+        if pointer.is_empty() || pointer.starts_with('/') {
+            Some(pointer)
+        } else {
+            // $anchor
+            None
+        }
+    }
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut iter = s.bytes();
+    while let Some(b) = iter.next() {
+        if b == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(b);
         }
     }
+    String::from_utf8(bytes).ok()
+}
+
+fn escape_pointer_token(token: &str) -> String {
+    token.replace('~', "~0").replace('/', "~1")
 }
 
 impl Context<'_> {
     pub fn insert_ref(&self, uri: &str, schema: Schema) {
-        self.shared
-            .borrow_mut()
-            .defs
-            .insert(uri.to_string(), schema);
+        let mut shared = self.shared.borrow_mut();
+        if let Some(pointer) = shared.ref_pointer(uri) {
+            shared.def_pointers.insert(uri.to_string(), pointer);
+        }
+        shared.defs.insert(uri.to_string(), schema);
+    }
+
+    /// JSON pointer of the subschema being compiled.
+    pub fn json_pointer(&self) -> Option<String> {
+        self.shared.borrow().json_pointer.clone()
+    }
+
+    /// JSON pointer of the subschema at `path` (unescaped keys and indices) below the current one.
+    pub fn subschema_pointer(&self, path: &[&str]) -> Option<String> {
+        let mut pointer = self.json_pointer()?;
+        for token in path {
+            pointer.push('/');
+            pointer.push_str(&escape_pointer_token(token));
+        }
+        Some(pointer)
+    }
+
+    /// JSON pointer of the target of the (normalized) `$ref` uri.
+    pub fn ref_pointer(&self, uri: &str) -> Option<String> {
+        self.shared.borrow().ref_pointer(uri)
+    }
+
+    /// Run `f` with `pointer` as the JSON pointer of the subschema being compiled.
+    pub fn with_json_pointer<T>(
+        &self,
+        pointer: Option<String>,
+        f: impl FnOnce() -> Result<T>,
+    ) -> Result<T> {
+        let prev = std::mem::replace(&mut self.shared.borrow_mut().json_pointer, pointer);
+        let r = f();
+        self.shared.borrow_mut().json_pointer = prev;
+        r
     }
 
     pub fn get_ref_cloned(&self, uri: &str) -> Option<Schema> {
@@ -144,8 +222,10 @@ impl Context<'_> {
         Ok(())
     }
 
-    pub fn record_warning(&self, msg: String) {
-        self.shared.borrow_mut().pending_warnings.push(msg);
+    /// Record a warning at the subschema being compiled.
+    pub fn record_warning(&self, code: &str, msg: String) {
+        let w = Diagnostic::warning(code, msg, None, self.json_pointer());
+        self.shared.borrow_mut().pending_warnings.push(w);
     }
 
     pub fn property_schema<'a>(&self, obj: &'a ObjectSchema, prop: &str) -> Result<&'a Schema> {
@@ -174,6 +254,7 @@ impl Context<'_> {
         BuiltSchema {
             schema,
             definitions: std::mem::take(&mut shared.defs),
+            def_pointers: std::mem::take(&mut shared.def_pointers),
             warnings: std::mem::take(&mut shared.pending_warnings),
             pattern_cache: std::mem::take(&mut shared.pattern_cache),
         }
@@ -183,7 +264,9 @@ impl Context<'_> {
 pub struct BuiltSchema {
     pub schema: Schema,
     pub definitions: HashMap<String, Schema>,
-    pub warnings: Vec<String>,
+    /// JSON pointers of the definitions within the root document, where known.
+    pub def_pointers: HashMap<String, String>,
+    pub warnings: Vec<Diagnostic>,
     pub pattern_cache: PatternPropertyCache,
 }
 
@@ -192,6 +275,7 @@ impl BuiltSchema {
         BuiltSchema {
             schema,
             definitions: HashMap::default(),
+            def_pointers: HashMap::default(),
             warnings: Vec::new(),
             pattern_cache: PatternPropertyCache::default(),
         }
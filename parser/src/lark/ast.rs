@@ -6,7 +6,7 @@ use crate::{
 use super::lexer::Location;
 
 /// Represents an item in the grammar (rule, token, or statement).
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[allow(clippy::large_enum_variant)]
 pub enum Item {
     Rule(Rule),
@@ -25,7 +25,7 @@ impl Item {
 }
 
 /// Represents a grammar rule.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Rule {
     pub name: String,
     #[allow(dead_code)]
@@ -46,7 +46,7 @@ pub struct Rule {
 }
 
 /// Represents a token definition.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TokenDef {
     pub name: String,
     pub params: Option<TokenParams>,
@@ -55,7 +55,7 @@ pub struct TokenDef {
 }
 
 /// Represents different types of statements.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Statement {
     Ignore(Expansions),
     Import {
@@ -73,8 +73,7 @@ pub enum Statement {
     Declare(Vec<String>),
 }
 
-/// Represents parameters for a rule.
-#[allow(dead_code)]
+/// Represents parameters for a rule (template).
 #[derive(Debug, Clone)]
 pub struct RuleParams(pub Vec<String>);
 
@@ -84,7 +83,7 @@ pub struct RuleParams(pub Vec<String>);
 pub struct TokenParams(pub Vec<String>);
 
 /// Represents an alternative (OR) of productions in a grammar.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Expansions(pub Location, pub Vec<Alias>);
 
 impl Expansions {
@@ -111,7 +110,7 @@ impl Expansions {
 
 /// Represents an alias in the grammar.
 /// Each alias consists of possibly multiple conjuncts (AND).
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Alias {
     pub conjuncts: Vec<Expansion>,
     pub param_cond: ParamCond,
@@ -120,11 +119,11 @@ pub struct Alias {
 }
 
 /// Represents a concatenation of expressions in the grammar.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Expansion(pub Vec<Expr>);
 
 /// Represents an expression.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Expr {
     pub atom: Atom,
     pub op: Option<Op>,
@@ -132,7 +131,7 @@ pub struct Expr {
 }
 
 /// Represents an atom in the grammar.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Atom {
     Group(Expansions),
     Maybe(Expansions),
@@ -141,10 +140,11 @@ pub enum Atom {
 }
 
 /// Represents different values in the grammar.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Value {
     LiteralRange(String, String),
-    Name(String),
+    /// A reference to a rule or token, with the location of the reference.
+    Name(String, Location),
     NameParam(String, ParamExpr),
     LiteralString(String, String),
     LiteralRegex(String, String),
@@ -153,7 +153,6 @@ pub enum Value {
     Json(serde_json::Value),
     NestedLark(Vec<Item>),
     RegexExt(RegexExt),
-    #[allow(dead_code)]
     TemplateUsage {
         name: String,
         values: Vec<Value>,
@@ -1,20 +1,22 @@
 use crate::{
+    diagnostics::SourceSpan,
     earley::{ParamCond, ParamExpr},
     grammar_builder::{GrammarResult, RegexId},
-    substring::substring,
     HashMap,
 };
 use anyhow::{anyhow, bail, ensure, Result};
 use derivre::RegexAst;
 use serde::Deserialize;
+use std::sync::Arc;
 
 use crate::{
     api::{
         GenGrammarOptions, GenOptions, GrammarId, LLGuidanceOptions, NodeProps, RegexExt, SkipSpec,
     },
     json::json_merge,
+    regex_rewrite::lexer_regex_ast,
     substring::{chunk_into_chars, chunk_into_words},
-    GrammarBuilder, JsonCompileOptions, NodeRef,
+    GrammarBuilder, ImportResolver, JsonCompileOptions, NodeRef,
 };
 
 use super::{
@@ -26,14 +28,24 @@ use super::{
 
 const DEBUG: bool = false;
 
-/// Options accepted by Lark's `%llguidance` directive. `ignore_once` is
-/// Lark-specific because it controls how `%ignore` expressions are compiled.
+// Limit on nested instantiation of templates, like t{x} -> t{t{x}} -> ...
+const MAX_TEMPLATE_DEPTH: usize = 50;
+
+// Limit on nested %import of modules
+const MAX_IMPORT_DEPTH: usize = 20;
+
+/// Options accepted by Lark's `%llguidance` directive. `ignore_once` and
+/// `auto_terminals` are Lark-specific because they control how `%ignore`
+/// expressions and rules are compiled.
 #[derive(Debug, Default, Deserialize)]
 struct LarkLLGuidanceOptions {
     #[serde(flatten)]
     general: LLGuidanceOptions,
     #[serde(default)]
     ignore_once: bool,
+    /// Compile non-recursive rules that only use terminals as terminals.
+    #[serde(default)]
+    auto_terminals: bool,
 }
 
 macro_rules! debug {
@@ -48,7 +60,9 @@ macro_rules! debug {
 #[derive(Debug)]
 struct Grammar {
     rules: HashMap<String, Rule>,
+    templates: HashMap<String, Rule>,
     tokens: HashMap<String, TokenDef>,
+    token_priorities: HashMap<String, i32>,
     ignore: Vec<Expansions>,
     llguidance_options: serde_json::Value,
 }
@@ -57,13 +71,22 @@ impl Default for Grammar {
     fn default() -> Self {
         Self {
             rules: HashMap::default(),
+            templates: HashMap::default(),
             tokens: HashMap::default(),
+            token_priorities: HashMap::default(),
             ignore: vec![],
             llguidance_options: serde_json::Value::Object(serde_json::Map::new()),
         }
     }
 }
 
+/// State for resolving `%import` of user modules.
+struct ImportCtx {
+    resolver: Option<Arc<dyn ImportResolver>>,
+    // modules currently being imported, innermost last
+    stack: Vec<String>,
+}
+
 enum PendingGrammar {
     Json(serde_json::Value),
     Lark(Vec<Item>),
@@ -77,6 +100,7 @@ struct Compiler {
     regex_ids: HashMap<String, RegexId>,
     in_progress: HashMap<String, bool>,
     pending_grammars: Vec<(NodeRef, Location, PendingGrammar)>,
+    template_depth: usize,
 }
 
 fn compile_lark(builder: GrammarBuilder, parsed: ParsedLark) -> Result<GrammarResult> {
@@ -88,6 +112,7 @@ fn compile_lark(builder: GrammarBuilder, parsed: ParsedLark) -> Result<GrammarRe
         regex_ids: HashMap::default(),
         in_progress: HashMap::default(),
         pending_grammars: vec![],
+        template_depth: 0,
     };
     c.execute()
 }
@@ -102,7 +127,7 @@ pub fn lark_to_llguidance(mut builder: GrammarBuilder, lark: &str) -> Result<Gra
 }
 
 impl Compiler {
-    fn do_token(&mut self, name: &str) -> Result<RegexId> {
+    fn do_token(&mut self, name: &str, loc: &Location) -> Result<RegexId> {
         if let Some(id) = self.regex_ids.get(name) {
             return Ok(*id);
         }
@@ -114,7 +139,7 @@ impl Compiler {
             .grammar
             .tokens
             .remove(name)
-            .ok_or_else(|| anyhow!("unknown name: {:?}", name))?;
+            .ok_or_else(|| loc.augment(anyhow!("unknown name: {:?}", name)))?;
         let id = self.do_token_expansions(token.expansions)?;
         self.regex_ids.insert(name.to_string(), id);
         self.in_progress.remove(name);
@@ -122,10 +147,22 @@ impl Compiler {
     }
 
     fn mk_regex(&mut self, info: &str, rx: String) -> Result<RegexId> {
-        self.builder
-            .regex
-            .regex(&rx)
-            .map_err(|e| anyhow!("invalid regex {rx:?} (in {info}): {e}"))
+        let mut warnings = vec![];
+        let id = match lexer_regex_ast(&rx, &mut warnings) {
+            Ok(None) => self.builder.regex.regex(&rx),
+            Ok(Some(ast)) => self.builder.regex.add_ast(ast),
+            Err(e) => Err(e),
+        }
+        .map_err(|e| anyhow!("invalid regex {rx:?} (in {info}): {e}"))?;
+        for w in warnings {
+            self.builder.add_warning(
+                "regex",
+                format!("regex {rx:?} (in {info}): {w}"),
+                None,
+                None,
+            );
+        }
+        Ok(id)
     }
 
     fn do_token_atom(&mut self, atom: Atom) -> Result<RegexId> {
@@ -165,7 +202,7 @@ impl Compiler {
                         bail!("invalid range order: {:?}..{:?}", a, b);
                     }
                 }
-                Value::Name(n) => self.do_token(&n),
+                Value::Name(n, loc) => self.do_token(&n, &loc),
                 Value::LiteralString(val, flags) => {
                     if flags.contains("i") {
                         self.mk_regex(
@@ -204,7 +241,9 @@ impl Compiler {
                 Value::NameParam(_, _) => {
                     bail!("name::param cannot be used in terminals");
                 }
-                Value::TemplateUsage { .. } => bail!("template usage not supported yet"),
+                Value::TemplateUsage { name, .. } => {
+                    bail!("templates (like {:?}) cannot be used in terminals", name);
+                }
             },
         }
     }
@@ -314,9 +353,11 @@ impl Compiler {
             }
             Atom::Value(value) => {
                 match &value {
-                    Value::Name(n) => {
+                    Value::Name(n, _) => {
                         if self.is_rule(n) {
                             return self.do_rule(n, None);
+                        } else if self.grammar.templates.contains_key(n) {
+                            bail!("template {:?} used without arguments", n);
                         } else {
                             // OK -> treat as token
                         }
@@ -382,12 +423,20 @@ impl Compiler {
                     | Value::LiteralRegex(_, _) => {
                         // treat as token
                     }
-                    Value::TemplateUsage { .. } => {
-                        bail!("template usage not supported yet");
+                    Value::TemplateUsage { name, values } => {
+                        return self.do_template(name, values);
                     }
                 };
+                let priority = match &value {
+                    Value::Name(n, _) => self.grammar.token_priorities.get(n).copied(),
+                    _ => None,
+                };
                 let rx = self.do_token_atom(Atom::Value(value))?;
-                Ok(self.lift_regex(rx)?)
+                if let Some(priority) = priority {
+                    Ok(self.builder.lexeme_with_priority(rx, priority))
+                } else {
+                    Ok(self.lift_regex(rx)?)
+                }
             }
         }
     }
@@ -471,6 +520,7 @@ impl Compiler {
         }
 
         debug!("BEG rule {}", name);
+        let span = self.grammar.rules.get(name).map(|r| r.expansions.0.span());
         let id = self.do_rule_core(name)?;
 
         if let Some(placeholder) = self.node_ids.get(name) {
@@ -479,10 +529,53 @@ impl Compiler {
         self.node_ids.insert(name.to_string(), id);
         self.in_progress.remove(name);
         self.builder.rename(id, name);
+        if let Some(span) = span {
+            self.builder.set_rule_span(id, span);
+        }
         debug!("END rule {}", name);
         self.builder.apply(id, param)
     }
 
+    fn do_template(&mut self, name: &str, args: &[Value]) -> Result<NodeRef> {
+        // each instance is a regular rule named after the template usage,
+        // so it's only expanded once per argument tuple (and can be recursive)
+        let inst_name = template_usage_name(name, args);
+        if !self.is_rule(&inst_name) {
+            let mut rule = self
+                .grammar
+                .templates
+                .get(name)
+                .ok_or_else(|| anyhow!("template {:?} not found", name))?
+                .clone();
+            let params = rule.params.take().unwrap().0;
+            ensure!(
+                params.len() == args.len(),
+                "template {:?} expects {} arguments, got {}",
+                name,
+                params.len(),
+                args.len()
+            );
+            let subst: HashMap<String, Value> =
+                params.into_iter().zip(args.iter().cloned()).collect();
+            substitute_expansions(&mut rule.expansions, &subst);
+            for v in [&mut rule.stop, &mut rule.suffix].into_iter().flatten() {
+                substitute_value(v, &subst);
+            }
+            rule.name = inst_name.clone();
+            self.grammar.rules.insert(inst_name.clone(), rule);
+        }
+
+        ensure!(
+            self.template_depth < MAX_TEMPLATE_DEPTH,
+            "template {:?} nested too deeply",
+            name
+        );
+        self.template_depth += 1;
+        let r = self.do_rule(&inst_name, None);
+        self.template_depth -= 1;
+        r
+    }
+
     fn gen_grammar(
         &mut self,
         name: &str,
@@ -646,9 +739,14 @@ impl Compiler {
 
     fn execute(mut self) -> Result<GrammarResult> {
         let mut grm = Grammar::default();
+        let imports = ImportCtx {
+            resolver: self.builder.import_resolver.clone(),
+            stack: vec![],
+        };
         for item in std::mem::take(&mut self.parsed.items) {
             let loc = item.location().clone();
-            grm.process_item(item).map_err(|e| loc.augment(e))?;
+            grm.process_item(item, &imports)
+                .map_err(|e| loc.augment(e))?;
         }
         let start_name = "start";
         ensure!(
@@ -663,6 +761,20 @@ impl Compiler {
             serde_json::from_value(self.grammar.llguidance_options.clone())
                 .map_err(|e| anyhow!("failed to parse %llguidance declaration: {}", e))?;
 
+        if opts.auto_terminals {
+            let promoted = self
+                .grammar
+                .promote_terminal_rules(start_name, !ignore.is_empty());
+            for (name, span) in promoted {
+                self.builder.add_warning(
+                    "auto_terminals",
+                    format!("auto_terminals: compiled rule {name:?} as a terminal"),
+                    Some(span),
+                    None,
+                );
+            }
+        }
+
         let ignore = ignore
             .into_iter()
             .map(|exp| Ok(RegexAst::ExprRef(self.do_token_expansions(exp)?)))
@@ -723,22 +835,125 @@ impl Grammar {
         Ok(())
     }
 
-    fn do_statement(&mut self, loc: &Location, statement: Statement) -> Result<()> {
+    /// Turn rules that only reference terminals (and so are not recursive) into terminals,
+    /// until fixpoint; returns the sorted names of promoted rules.
+    /// With `%ignore`, only rules that already are a single lexeme are promoted,
+    /// since the ignored text can't appear inside of a terminal.
+    fn promote_terminal_rules(
+        &mut self,
+        start_name: &str,
+        has_ignore: bool,
+    ) -> Vec<(String, SourceSpan)> {
+        let mut promoted = vec![];
+        loop {
+            let names = self
+                .rules
+                .values()
+                .filter(|r| r.name != start_name && self.can_promote(r, has_ignore))
+                .map(|r| r.name.clone())
+                .collect::<Vec<_>>();
+            if names.is_empty() {
+                break;
+            }
+            for name in names {
+                let rule = self.rules.remove(&name).unwrap();
+                let span = rule.expansions.0.span();
+                let t = TokenDef {
+                    name: name.clone(),
+                    params: None,
+                    priority: None,
+                    expansions: rule.expansions,
+                };
+                self.tokens.insert(name.clone(), t);
+                promoted.push((name, span));
+            }
+        }
+        promoted.sort_by(|a, b| a.0.cmp(&b.0));
+        promoted
+    }
+
+    fn can_promote(&self, rule: &Rule, single_lexeme: bool) -> bool {
+        rule.params.is_none()
+            && !rule.is_parametric
+            && rule.stop_like().is_none()
+            && rule.stop_capture_name.is_none()
+            && rule.capture_name.is_none()
+            && rule.max_tokens.is_none()
+            && rule.temperature.is_none()
+            && self.is_terminal_expansions(&rule.expansions, single_lexeme)
+    }
+
+    fn is_terminal_expansions(&self, expansions: &Expansions, single_lexeme: bool) -> bool {
+        expansions.1.iter().all(|alias| {
+            alias.param_cond.is_true()
+                && alias.conjuncts.iter().all(|exp| {
+                    (!single_lexeme || exp.0.len() == 1)
+                        && exp
+                            .0
+                            .iter()
+                            .all(|e| self.is_terminal_expr(e, single_lexeme))
+                })
+        })
+    }
+
+    fn is_terminal_expr(&self, expr: &Expr, single_lexeme: bool) -> bool {
+        if single_lexeme && (expr.op.is_some() || expr.range.is_some()) {
+            return false;
+        }
+        match &expr.atom {
+            Atom::Group(e) => self.is_terminal_expansions(e, single_lexeme),
+            Atom::Maybe(e) => !single_lexeme && self.is_terminal_expansions(e, false),
+            Atom::Not(_) => true,
+            Atom::Value(v) => match v {
+                // tokens with priority have to stay separate lexemes
+                Value::Name(n, _) => {
+                    self.tokens.contains_key(n) && !self.token_priorities.contains_key(n)
+                }
+                Value::LiteralString(_, _)
+                | Value::LiteralRegex(_, _)
+                | Value::LiteralRange(_, _)
+                | Value::RegexExt(_) => true,
+                _ => false,
+            },
+        }
+    }
+
+    fn do_statement(
+        &mut self,
+        loc: &Location,
+        statement: Statement,
+        imports: &ImportCtx,
+    ) -> Result<()> {
         match statement {
             Statement::Ignore(exp) => {
                 self.ignore.push(exp);
             }
             Statement::Import { path, alias } => {
-                let regex = lookup_common_regex(&path)?;
-                let local_name =
-                    alias.unwrap_or_else(|| path.split('.').next_back().unwrap().to_string());
-                self.add_token_def(loc, local_name, regex)?;
+                let (module, name) = path
+                    .rsplit_once('.')
+                    .filter(|(m, _)| !m.is_empty())
+                    .ok_or_else(|| anyhow!("invalid import path: {:?}", path))?;
+                let local_name = alias.unwrap_or_else(|| name.to_string());
+                if module == "common" {
+                    let regex = lookup_common_regex(&path)?;
+                    self.add_token_def(loc, local_name, regex)?;
+                } else {
+                    self.import_module(module, &[(name.to_string(), local_name)], imports)?;
+                }
             }
             Statement::MultiImport { path, names } => {
-                for n in names {
-                    let qname = format!("{path}.{n}");
-                    let regex = lookup_common_regex(&qname)?;
-                    self.add_token_def(loc, n.to_string(), regex)?;
+                if path == "common" {
+                    for n in names {
+                        let qname = format!("{path}.{n}");
+                        let regex = lookup_common_regex(&qname)?;
+                        self.add_token_def(loc, n.to_string(), regex)?;
+                    }
+                } else {
+                    let names = names
+                        .into_iter()
+                        .map(|n| (n.clone(), n))
+                        .collect::<Vec<_>>();
+                    self.import_module(&path, &names, imports)?;
                 }
             }
             Statement::LLGuidance(json_value) => {
@@ -758,36 +973,272 @@ impl Grammar {
         Ok(())
     }
 
-    fn process_item(&mut self, item: Item) -> Result<()> {
+    /// Import `names` (pairs of name in module and local name) from `module`.
+    /// Other definitions of the module are imported as well, but renamed
+    /// to `module__name`, so they don't clash with local definitions.
+    fn import_module(
+        &mut self,
+        module: &str,
+        names: &[(String, String)],
+        imports: &ImportCtx,
+    ) -> Result<()> {
+        let module = if let Some(rel) = module.strip_prefix('.') {
+            // relative to the package of the importing module
+            match imports.stack.last().and_then(|m| m.rsplit_once('.')) {
+                Some((package, _)) => format!("{package}.{rel}"),
+                None => rel.to_string(),
+            }
+        } else {
+            module.to_string()
+        };
+        let resolver = imports.resolver.clone().ok_or_else(|| {
+            anyhow!(
+                "cannot import {:?}: no import resolver set; only %import common... is built-in",
+                module
+            )
+        })?;
+        if imports.stack.contains(&module) {
+            bail!(
+                "circular import: {} -> {}",
+                imports.stack.join(" -> "),
+                module
+            );
+        }
+        ensure!(
+            imports.stack.len() < MAX_IMPORT_DEPTH,
+            "imports nested too deeply"
+        );
+
+        let source = resolver
+            .resolve(&module)?
+            .ok_or_else(|| anyhow!("module {:?} not found", module))?;
+        let mut sub = Grammar::default();
+        let sub_imports = ImportCtx {
+            resolver: Some(resolver),
+            stack: imports
+                .stack
+                .iter()
+                .cloned()
+                .chain(std::iter::once(module.clone()))
+                .collect(),
+        };
+        let in_module = |e: anyhow::Error| anyhow!("in module {:?}: {}", module, e);
+        for item in parse_lark(&source).map_err(in_module)?.items {
+            let loc = item.location().clone();
+            sub.process_item(item, &sub_imports)
+                .map_err(|e| in_module(loc.augment(e)))?;
+        }
+        // %ignore and %llguidance of the module are not imported, like in Lark
+
+        let prefix = module.replace('.', "__");
+        let mut renames: HashMap<String, String> = sub
+            .rules
+            .keys()
+            .chain(sub.templates.keys())
+            .map(|n| (n.clone(), format!("{prefix}__{n}")))
+            .chain(
+                sub.tokens
+                    .keys()
+                    .map(|n| (n.clone(), format!("{}__{n}", prefix.to_uppercase()))),
+            )
+            .collect();
+        for (name, local_name) in names {
+            ensure!(
+                renames.contains_key(name),
+                "{:?} not found in module {:?}",
+                name,
+                module
+            );
+            renames.insert(name.clone(), local_name.clone());
+        }
+        let is_local = |n: &str| names.iter().any(|(_, l)| l == n);
+
+        for (_, mut rule) in sub.rules.into_iter().chain(sub.templates) {
+            rule.name = renames[&rule.name].clone();
+            let exists =
+                self.rules.contains_key(&rule.name) || self.templates.contains_key(&rule.name);
+            if exists && !is_local(&rule.name) {
+                // already imported from the same module
+                continue;
+            }
+            ensure!(!exists, "duplicate rule (in import): {:?}", rule.name);
+            let template_renames;
+            let renames = if let Some(params) = &rule.params {
+                // template parameters shadow module definitions
+                let mut r = renames.clone();
+                for p in &params.0 {
+                    r.remove(p);
+                }
+                template_renames = r;
+                &template_renames
+            } else {
+                &renames
+            };
+            rename_expansions(&mut rule.expansions, renames);
+            for v in [&mut rule.stop, &mut rule.suffix].into_iter().flatten() {
+                rename_value(v, renames);
+            }
+            if rule.params.is_some() {
+                self.templates.insert(rule.name.clone(), rule);
+            } else {
+                self.rules.insert(rule.name.clone(), rule);
+            }
+        }
+
+        for (name, mut token) in sub.tokens {
+            token.name = renames[&name].clone();
+            let exists = self.tokens.contains_key(&token.name);
+            if exists && !is_local(&token.name) {
+                continue;
+            }
+            ensure!(!exists, "duplicate token (in import): {:?}", token.name);
+            rename_expansions(&mut token.expansions, &renames);
+            if let Some(priority) = sub.token_priorities.get(&name) {
+                self.token_priorities.insert(token.name.clone(), *priority);
+            }
+            self.tokens.insert(token.name.clone(), token);
+        }
+
+        Ok(())
+    }
+
+    fn process_item(&mut self, item: Item, imports: &ImportCtx) -> Result<()> {
         match item {
             Item::Rule(rule) => {
-                ensure!(rule.params.is_none(), "params not supported yet");
-                ensure!(rule.priority.is_none(), "priority not supported yet");
                 ensure!(
-                    !self.rules.contains_key(&rule.name),
+                    rule.priority.is_none(),
+                    "priority of rules is not supported; use priority of terminals"
+                );
+                ensure!(
+                    !self.rules.contains_key(&rule.name)
+                        && !self.templates.contains_key(&rule.name),
                     "duplicate rule: {:?}",
                     rule.name
                 );
-                self.rules.insert(rule.name.clone(), rule);
+                if rule.params.is_some() {
+                    self.templates.insert(rule.name.clone(), rule);
+                } else {
+                    self.rules.insert(rule.name.clone(), rule);
+                }
             }
             Item::Token(token_def) => {
-                ensure!(token_def.params.is_none(), "params not supported yet");
-                ensure!(token_def.priority.is_none(), "priority not supported yet");
+                ensure!(
+                    token_def.params.is_none(),
+                    "templates are only supported for rules, not terminals"
+                );
                 ensure!(
                     !self.tokens.contains_key(&token_def.name),
                     "duplicate token: {:?}",
                     token_def.name
                 );
+                if let Some(priority) = token_def.priority {
+                    self.token_priorities
+                        .insert(token_def.name.clone(), priority);
+                }
                 self.tokens.insert(token_def.name.clone(), token_def);
             }
             Item::Statement(loc, statement) => {
-                self.do_statement(&loc, statement)?;
+                self.do_statement(&loc, statement, imports)?;
             }
         }
         Ok(())
     }
 }
 
+fn template_usage_name(name: &str, args: &[Value]) -> String {
+    let args = args.iter().map(template_arg_name).collect::<Vec<_>>();
+    format!("{}{{{}}}", name, args.join(", "))
+}
+
+fn template_arg_name(v: &Value) -> String {
+    match v {
+        Value::Name(n, _) | Value::GrammarRef(n) | Value::SpecialToken(n) => n.clone(),
+        Value::NameParam(n, p) => format!("{n}::{p}"),
+        Value::LiteralString(s, flags) => format!("{}{}", serde_json::to_string(s).unwrap(), flags),
+        Value::LiteralRegex(rx, flags) => format!("/{rx}/{flags}"),
+        Value::LiteralRange(a, b) => format!(
+            "{}..{}",
+            serde_json::to_string(a).unwrap(),
+            serde_json::to_string(b).unwrap()
+        ),
+        Value::TemplateUsage { name, values } => template_usage_name(name, values),
+        Value::Json(_) | Value::NestedLark(_) | Value::RegexExt(_) => format!("{v:?}"),
+    }
+}
+
+fn substitute_expansions(expansions: &mut Expansions, subst: &HashMap<String, Value>) {
+    for alias in expansions.1.iter_mut() {
+        for expansion in alias.conjuncts.iter_mut() {
+            for expr in expansion.0.iter_mut() {
+                substitute_atom(&mut expr.atom, subst);
+            }
+        }
+    }
+}
+
+fn substitute_atom(atom: &mut Atom, subst: &HashMap<String, Value>) {
+    match atom {
+        Atom::Group(expansions) | Atom::Maybe(expansions) => {
+            substitute_expansions(expansions, subst)
+        }
+        Atom::Not(inner) => substitute_atom(inner, subst),
+        Atom::Value(v) => substitute_value(v, subst),
+    }
+}
+
+fn substitute_value(v: &mut Value, subst: &HashMap<String, Value>) {
+    match v {
+        Value::Name(n, _) => {
+            if let Some(arg) = subst.get(n.as_str()) {
+                *v = arg.clone();
+            }
+        }
+        Value::TemplateUsage { values, .. } => {
+            for v in values.iter_mut() {
+                substitute_value(v, subst);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn rename_expansions(expansions: &mut Expansions, renames: &HashMap<String, String>) {
+    for alias in expansions.1.iter_mut() {
+        for expansion in alias.conjuncts.iter_mut() {
+            for expr in expansion.0.iter_mut() {
+                rename_atom(&mut expr.atom, renames);
+            }
+        }
+    }
+}
+
+fn rename_atom(atom: &mut Atom, renames: &HashMap<String, String>) {
+    match atom {
+        Atom::Group(expansions) | Atom::Maybe(expansions) => rename_expansions(expansions, renames),
+        Atom::Not(inner) => rename_atom(inner, renames),
+        Atom::Value(v) => rename_value(v, renames),
+    }
+}
+
+fn rename_value(v: &mut Value, renames: &HashMap<String, String>) {
+    match v {
+        Value::Name(n, _) | Value::NameParam(n, _) => {
+            if let Some(new_name) = renames.get(n.as_str()) {
+                *n = new_name.clone();
+            }
+        }
+        Value::TemplateUsage { name, values } => {
+            if let Some(new_name) = renames.get(name.as_str()) {
+                *name = new_name.clone();
+            }
+            for v in values.iter_mut() {
+                rename_value(v, renames);
+            }
+        }
+        _ => {}
+    }
+}
+
 fn compile_lark_regex(builder: &mut GrammarBuilder, l: RegexExt) -> Result<RegexId> {
     let mut fields_set = vec![];
     if l.substring_chunks.is_some() {
@@ -806,14 +1257,14 @@ fn compile_lark_regex(builder: &mut GrammarBuilder, l: RegexExt) -> Result<Regex
         bail!("only one field can be set on %regex; got {:?}", fields_set);
     }
 
-    let bld = &mut builder.regex.spec.regex_builder;
+    let spec = &mut builder.regex.spec;
 
     let eref = if let Some(s) = l.substring_words {
-        substring(bld, chunk_into_words(&s))?
+        spec.mk_substring(chunk_into_words(&s))?
     } else if let Some(s) = l.substring_chars {
-        substring(bld, chunk_into_chars(&s))?
+        spec.mk_substring(chunk_into_chars(&s))?
     } else if let Some(s) = l.substring_chunks {
-        substring(bld, s.iter().map(|s| s.as_str()).collect())?
+        spec.mk_substring(s.iter().map(|s| s.as_str()).collect())?
     } else {
         unreachable!()
     };
@@ -1,10 +1,12 @@
 use std::{
+    any::Any,
     fmt::{Debug, Display},
     rc::Rc,
 };
 
 use crate::{
     api::{RegexExt, SkipSpec},
+    diagnostics::{SourceError, SourceSpan},
     HashMap,
 };
 use anyhow::{anyhow, bail, Result};
@@ -142,6 +144,8 @@ impl Lexeme {
 pub struct Location {
     pub line: usize,
     pub column: usize,
+    /// Length in bytes of the located token (0 if unknown).
+    pub len: usize,
     pub src: Rc<String>,
 }
 
@@ -166,19 +170,44 @@ pub(crate) fn highlight_location(src: &str, line_no: usize, col_no: usize) -> St
 }
 
 impl Location {
-    pub fn augment(&self, err: impl Display) -> anyhow::Error {
+    pub fn augment(&self, err: impl Display + 'static) -> anyhow::Error {
+        let located = (&err as &dyn Any)
+            .downcast_ref::<anyhow::Error>()
+            .is_some_and(|e| e.is::<SourceError>());
+        if located {
+            let err: Box<dyn Any> = Box::new(err);
+            return *err.downcast::<anyhow::Error>().unwrap();
+        }
         let err = err.to_string();
         if err.starts_with("at ") {
             // don't add more location info
             anyhow::anyhow!("{err}")
         } else {
-            anyhow::anyhow!(
-                "at {}({}): {}\n{}",
-                self.line,
-                self.column,
-                err,
-                highlight_location(&self.src, self.line, self.column)
-            )
+            anyhow::Error::new(SourceError {
+                span: self.span(),
+                message: err,
+                excerpt: highlight_location(&self.src, self.line, self.column),
+            })
+        }
+    }
+
+    pub fn span(&self) -> SourceSpan {
+        let start = if self.line == 0 {
+            self.src.len()
+        } else {
+            let line_start: usize = self
+                .src
+                .split_inclusive('\n')
+                .take(self.line - 1)
+                .map(|l| l.len())
+                .sum();
+            (line_start + self.column.saturating_sub(1)).min(self.src.len())
+        };
+        SourceSpan {
+            start,
+            end: (start + self.len).min(self.src.len()),
+            line: self.line,
+            column: self.column,
         }
     }
 }
@@ -254,6 +283,7 @@ pub fn lex_lark(input: &str) -> Result<Vec<Lexeme>> {
                 false,
                 None,
                 usize::MAX,
+                0,
             )
             .unwrap();
         lexeme_idx_to_token.insert(l, *token);
@@ -290,20 +320,22 @@ pub fn lex_lark(input: &str) -> Result<Vec<Lexeme>> {
 
         match res {
             LexerResult::Error => {
-                bail!(
-                    "{}({}): lexer error\n{}",
-                    line_no,
-                    column_no,
-                    highlight_location(&input, line_no, column_no)
-                );
+                let loc = Location {
+                    line: line_no,
+                    column: column_no,
+                    len: 1,
+                    src: Rc::new(input),
+                };
+                return Err(loc.augment("lexer error"));
             }
             LexerResult::SpecialToken(_) => {
-                bail!(
-                    "{}({}): lexer special token\n{}",
-                    line_no,
-                    column_no,
-                    highlight_location(&input, line_no, column_no)
-                );
+                let loc = Location {
+                    line: line_no,
+                    column: column_no,
+                    len: 1,
+                    src: Rc::new(input),
+                };
+                return Err(loc.augment("lexer special token"));
             }
             LexerResult::State(s, _) => {
                 state = s;
@@ -5,3 +5,4 @@ mod lexer;
 mod parser;
 
 pub use compiler::lark_to_llguidance;
+pub(crate) use lexer::Location;
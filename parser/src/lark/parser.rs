@@ -1,9 +1,6 @@
 use std::rc::Rc;
 
-use crate::{
-    earley::{BitIdx, ParamCond, ParamExpr, ParamRef, ParamValue},
-    lark::lexer::highlight_location,
-};
+use crate::earley::{BitIdx, ParamCond, ParamExpr, ParamRef, ParamValue};
 
 use super::{
     ast::*,
@@ -40,15 +37,8 @@ impl Parser {
         );
         self.parse_start_inner().map_err(|e| {
             if let Some(tok) = self.peek_token() {
-                anyhow!(
-                    "{}({}): {} (at {} ({}))\n{}",
-                    tok.line,
-                    tok.column,
-                    e,
-                    tok.value,
-                    tok.token,
-                    highlight_location(&self.src, tok.line, tok.column)
-                )
+                let msg = format!("{} (at {} ({}))", e, tok.value, tok.token);
+                self.location().augment(msg)
             } else {
                 anyhow!("at EOF: {}", e)
             }
@@ -83,15 +73,21 @@ impl Parser {
 
     fn location(&self) -> Location {
         if let Some(t) = self.peek_token() {
+            let len = match &t.value {
+                LexemeValue::String(s) => s.len(),
+                _ => 0,
+            };
             Location {
                 line: t.line,
                 column: t.column,
+                len,
                 src: self.src.clone(),
             }
         } else {
             Location {
                 line: 0,
                 column: 0,
+                len: 0,
                 src: self.src.clone(),
             }
         }
@@ -554,6 +550,7 @@ impl Parser {
 
     /// Parses a value.
     fn parse_value(&mut self) -> Result<Value> {
+        let loc = self.location();
         if let Some(string1) = self.match_token_with_value(Token::String) {
             if self.match_token(Token::DotDot) {
                 let string2 = self.expect_token_val(Token::String)?;
@@ -623,7 +620,7 @@ impl Parser {
             if self.has_tokens(&[Token::LBrace, Token::Comma])
                 || self.has_tokens(&[Token::LBrace, Token::Number])
             {
-                Ok(Value::Name(name_token))
+                Ok(Value::Name(name_token, loc))
             } else if self.match_token(Token::LBrace) {
                 // Lark template usage (not supported outside of parser anyways)
                 let mut values = Vec::new();
@@ -640,7 +637,7 @@ impl Parser {
                 let inner = self.parse_param_expr()?;
                 Ok(Value::NameParam(name_token, inner))
             } else {
-                Ok(Value::Name(name_token))
+                Ok(Value::Name(name_token, loc))
             }
         } else {
             bail!("Expected value")
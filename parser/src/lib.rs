@@ -51,6 +51,18 @@ pub use matcher::Matcher;
 mod factory;
 pub use factory::ParserFactory;
 
+mod batch_matcher;
+pub use batch_matcher::BatchMatcher;
+
+mod compiled_grammar;
+pub use compiled_grammar::CompiledGrammar;
+
+mod diagnostics;
+pub use diagnostics::{Diagnostic, Severity, SourceSpan};
+
+mod import_resolver;
+pub use import_resolver::{DirImportResolver, ImportResolver, MemImportResolver};
+
 mod logging;
 pub use logging::Logger;
 
@@ -68,11 +80,15 @@ mod json_validation;
 mod regex_rewrite;
 pub mod substring;
 pub use grammar_builder::{GrammarBuilder, NodeRef};
-pub use json::compiler::JsonCompileOptions;
+pub use json::compiler::{JsonCompileOptions, PropertyOrder};
 pub use json::json_merge;
 pub use stop_controller::StopController;
 pub use tokenizer_json::token_bytes_from_tokenizer_json;
 
+#[cfg(feature = "lark")]
+mod abnf;
+#[cfg(feature = "lark")]
+mod gbnf;
 #[cfg(feature = "lark")]
 mod lark;
 
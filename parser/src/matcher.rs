@@ -1,18 +1,158 @@
 use anyhow::{anyhow, bail, ensure, Result};
 use toktrie::{SimpleVob, TokEnv, TokenId};
 
-use crate::{api::StopReason, earley::ParserStats, panic_utils, TokenParser};
+use crate::{
+    api::StopReason,
+    diagnostics::{Diagnostic, Severity},
+    earley::ParserStats,
+    panic_utils, TokenParser,
+};
+
+// Masks are steered towards the shortest completion once the token budget
+// left is within this many tokens of what the completion needs.
+const TOKEN_BUDGET_SLACK: usize = 4;
 
 #[derive(Clone)]
 struct MatcherInner {
     parser: TokenParser,
+    // value of parser.num_tokens() at which the token budget runs out
+    token_budget_end: Option<usize>,
+    // last completion found for the token budget
+    budget_completion: Option<BudgetCompletion>,
+}
+
+#[derive(Clone)]
+struct BudgetCompletion {
+    // parser.num_tokens() and parser.final_bytes().len() when it was found
+    num_tokens: usize,
+    num_bytes: usize,
+    bytes: Vec<u8>,
+    // number of tokens the completion takes
+    num_needed: usize,
 }
 
 #[derive(Clone)]
 #[allow(clippy::large_enum_variant)]
 enum MatcherState {
     Normal(MatcherInner),
-    Error(String),
+    Error(Diagnostic),
+}
+
+impl MatcherInner {
+    fn compute_mask(&mut self) -> Result<SimpleVob> {
+        let mut mask = self.parser.compute_mask()?;
+        if let Some(end) = self.token_budget_end {
+            let remaining = end.saturating_sub(self.parser.num_tokens());
+            self.apply_token_budget(remaining, &mut mask);
+        }
+        Ok(mask)
+    }
+
+    // Restrict mask to the first tokens of a short completion that still fit
+    // in the remaining budget, if the budget is getting tight.
+    // The mask is never made empty: if the completion doesn't fit,
+    // its first token is allowed.
+    fn apply_token_budget(&mut self, remaining: usize, mask: &mut SimpleVob) {
+        let tok_env = self.parser.token_env.clone();
+        let needed = |bytes: &[u8]| {
+            if bytes.is_empty() {
+                0
+            } else {
+                tok_env.tokenize_bytes(bytes).len()
+            }
+        };
+
+        let num_tokens = self.parser.num_tokens();
+        let final_bytes = self.parser.final_bytes();
+        let mut cached = self
+            .budget_completion
+            .take()
+            .filter(|c| c.num_tokens <= num_tokens && c.num_bytes <= final_bytes.len());
+        if let Some(c) = &mut cached {
+            let consumed = &final_bytes[c.num_bytes..];
+            if c.bytes.starts_with(consumed) {
+                // the output follows the completion, so the rest of it still completes
+                if !consumed.is_empty() {
+                    c.bytes.drain(..consumed.len());
+                    c.num_needed = needed(&c.bytes);
+                    c.num_tokens = num_tokens;
+                    c.num_bytes = final_bytes.len();
+                }
+            } else {
+                // the output left the completion; skip the search while the budget
+                // is more than twice what the completion needed, allowing it
+                // to grow by a token for every token consumed since
+                let drift = num_tokens - c.num_tokens;
+                if 2 * (c.num_needed + drift + TOKEN_BUDGET_SLACK) < remaining {
+                    self.budget_completion = cached;
+                    return;
+                }
+                cached = None;
+            }
+        }
+
+        let completion = match cached {
+            Some(c) => c,
+            None => {
+                // completion_estimate() is only a lower bound (it ignores conflicts
+                // between lexemes), so it can't tell the budget is large enough;
+                // an actual completion is needed for that
+                let num_bytes = final_bytes.len();
+                let Some(bytes) = self.parser.approximate_completion() else {
+                    return;
+                };
+                BudgetCompletion {
+                    num_tokens,
+                    num_bytes,
+                    num_needed: needed(&bytes),
+                    bytes,
+                }
+            }
+        };
+        let too_tight = completion.num_needed + TOKEN_BUDGET_SLACK >= remaining;
+        if too_tight {
+            self.restrict_to_completion(&completion.bytes, remaining, mask);
+        }
+        self.budget_completion = Some(completion);
+    }
+
+    fn restrict_to_completion(&self, completion: &[u8], remaining: usize, mask: &mut SimpleVob) {
+        let tok_env = &self.parser.token_env;
+        let needed = |bytes: &[u8]| {
+            if bytes.is_empty() {
+                0
+            } else {
+                tok_env.tokenize_bytes(bytes).len()
+            }
+        };
+        let trie = tok_env.tok_trie();
+        let mut allowed = trie.alloc_token_set();
+        if completion.is_empty() {
+            for &eos in trie.eos_tokens() {
+                if mask.is_allowed(eos) {
+                    allowed.allow_token(eos);
+                }
+            }
+        }
+        let max_len = std::cmp::min(completion.len(), trie.max_token_len());
+        for len in 1..=max_len {
+            if let Some(tok) = trie.token_id(&completion[..len]) {
+                if mask.is_allowed(tok) && needed(&completion[len..]) < remaining {
+                    allowed.allow_token(tok);
+                }
+            }
+        }
+        if allowed.is_zero() {
+            if let Some(&tok) = tok_env.tokenize_bytes(completion).first() {
+                if mask.is_allowed(tok) {
+                    allowed.allow_token(tok);
+                }
+            }
+        }
+        if !allowed.is_zero() {
+            *mask = allowed;
+        }
+    }
 }
 
 /// This is meant to be used in server-side scenarios.
@@ -32,10 +172,14 @@ impl Matcher {
                     if parser.is_fresh() {
                         parser.start_without_prompt();
                     }
-                    Matcher(MatcherState::Normal(MatcherInner { parser }))
+                    Matcher(MatcherState::Normal(MatcherInner {
+                        parser,
+                        token_budget_end: None,
+                        budget_completion: None,
+                    }))
                 }
             }
-            Err(e) => Matcher(MatcherState::Error(e.to_string())),
+            Err(e) => Matcher(MatcherState::Error(Diagnostic::from_error(&e))),
         }
     }
 
@@ -49,7 +193,11 @@ impl Matcher {
                     Ok(r) => Ok(r),
                     Err(e) => {
                         let msg = inner.parser.augment_err(e);
-                        self.0 = MatcherState::Error(msg.clone());
+                        self.0 = MatcherState::Error(Diagnostic::new(
+                            Severity::Error,
+                            "runtime",
+                            msg.clone(),
+                        ));
                         bail!(msg);
                     }
                 }
@@ -61,8 +209,12 @@ impl Matcher {
     pub fn deep_clone(&self) -> Self {
         match &self.0 {
             MatcherState::Normal(inner) => {
-                let parser = inner.parser.deep_clone();
-                Self::new(Ok(parser))
+                let mut r = Self::new(Ok(inner.parser.deep_clone()));
+                if let MatcherState::Normal(r_inner) = &mut r.0 {
+                    r_inner.token_budget_end = inner.token_budget_end;
+                    r_inner.budget_completion = inner.budget_completion.clone();
+                }
+                r
             }
             MatcherState::Error(_) => self.clone(),
         }
@@ -91,16 +243,59 @@ impl Matcher {
     }
 
     pub fn rollback(&mut self, num_tokens: usize) -> Result<()> {
-        self.with_inner(|inner| inner.parser.rollback(num_tokens))
+        self.with_inner(|inner| {
+            inner.budget_completion = None;
+            inner.parser.rollback(num_tokens)
+        })
     }
 
     pub fn reset(&mut self) -> Result<()> {
-        self.with_inner(|inner| inner.parser.reset())
+        self.with_inner(|inner| {
+            inner.token_budget_end = None;
+            inner.budget_completion = None;
+            inner.parser.reset()
+        })
+    }
+
+    /// Limit the generation to at most `num_tokens` further tokens.
+    /// Once the remaining budget gets within a few tokens of a short
+    /// completion of the grammar, masks only allow tokens that start
+    /// a short completion fitting in the budget, so that the output
+    /// can still be finished in time.
+    /// The completion is remembered while the output follows it;
+    /// otherwise it is searched for again once the budget gets within
+    /// about twice what the last one needed, so masks get slower
+    /// only near the end of the budget.
+    /// The masks never become empty: if the budget can't be met,
+    /// the completion is still allowed.
+    /// The budget is cleared by `reset()`.
+    pub fn set_token_budget(&mut self, num_tokens: usize) -> Result<()> {
+        self.with_inner(|inner| {
+            inner.token_budget_end = Some(inner.parser.num_tokens() + num_tokens);
+            Ok(())
+        })
+    }
+
+    pub fn clear_token_budget(&mut self) {
+        if let MatcherState::Normal(inner) = &mut self.0 {
+            inner.token_budget_end = None;
+            inner.budget_completion = None;
+        }
+    }
+
+    /// Number of tokens left in the budget set with `set_token_budget()`, if any.
+    pub fn remaining_token_budget(&self) -> Option<usize> {
+        match &self.0 {
+            MatcherState::Normal(inner) => inner
+                .token_budget_end
+                .map(|end| end.saturating_sub(inner.parser.num_tokens())),
+            MatcherState::Error(_) => None,
+        }
     }
 
     /// Compute which tokens can be consumed in the current state.
     pub fn compute_mask(&mut self) -> Result<SimpleVob> {
-        self.with_inner(|inner| inner.parser.compute_mask())
+        self.with_inner(|inner| inner.compute_mask())
     }
 
     /// Compute which tokens can be consumed in the current state.
@@ -111,11 +306,59 @@ impl Matcher {
             if inner.parser.stop_reason() != StopReason::NotStopped {
                 Ok(inner.parser.token_env.tok_trie().eos_token_set())
             } else {
-                inner.parser.compute_mask()
+                inner.compute_mask()
             }
         })
     }
 
+    /// Compute masks for speculative decoding: the mask for the current state,
+    /// followed by the mask after each of the `draft` tokens, stopping at the first
+    /// rejected draft token (or when the parser stops).
+    /// Thus, between 1 and `draft.len() + 1` masks are returned.
+    /// Masks in stopped states only allow EOS, as in `compute_mask_or_eos()`.
+    /// The draft tokens are rolled back afterwards.
+    pub fn compute_masks_for_draft(&mut self, draft: &[TokenId]) -> Result<Vec<SimpleVob>> {
+        let mut masks = vec![];
+        self.compute_masks_for_draft_into(draft, &mut masks)?;
+        Ok(masks)
+    }
+
+    /// Like [`Self::compute_masks_for_draft()`], but the masks are appended to `masks`,
+    /// so that the ones computed before an error are kept.
+    pub fn compute_masks_for_draft_into(
+        &mut self,
+        draft: &[TokenId],
+        masks: &mut Vec<SimpleVob>,
+    ) -> Result<()> {
+        let mut consumed = 0;
+        let r = self.draft_masks_inner(draft, masks, &mut consumed);
+        // if the draft failed, the rollback will likely fail too; report the original error
+        let rollback = if consumed > 0 {
+            self.rollback(consumed)
+        } else {
+            Ok(())
+        };
+        r.and(rollback)
+    }
+
+    fn draft_masks_inner(
+        &mut self,
+        draft: &[TokenId],
+        masks: &mut Vec<SimpleVob>,
+        consumed: &mut usize,
+    ) -> Result<()> {
+        loop {
+            masks.push(self.compute_mask_or_eos()?);
+            if *consumed == draft.len() || self.is_stopped() {
+                return Ok(());
+            }
+            if self.try_consume_tokens(&draft[*consumed..*consumed + 1])? == 0 {
+                return Ok(());
+            }
+            *consumed += 1;
+        }
+    }
+
     /// Can the grammar be finished in the current state?
     /// In other words, would the current token mask allow EOS token?
     pub fn is_accepting(&mut self) -> Result<bool> {
@@ -157,6 +400,27 @@ impl Matcher {
             .unwrap_or_else(|_| vec![])
     }
 
+    /// Return a shortest sequence of bytes that takes the parser to an accepting state
+    /// (empty if it is accepting already), starting with any currently forced bytes.
+    /// It is found by searching the states of the Earley parser and lexer,
+    /// and can be used to finish a truncated output.
+    /// If the search gives up, a short, but not always shortest, completion is returned.
+    /// Fails if the matcher is stopped in a non-accepting state,
+    /// or if no completion is found within search limits; the matcher
+    /// is not put in error state then.
+    pub fn shortest_completion(&mut self) -> Result<Vec<u8>> {
+        self.with_inner(|inner| Ok(inner.parser.shortest_completion()))?
+    }
+
+    /// Like `shortest_completion()`, but tokenized.
+    pub fn shortest_completion_tokens(&mut self) -> Result<Vec<TokenId>> {
+        let bytes = self.shortest_completion()?;
+        if bytes.is_empty() {
+            return Ok(vec![]);
+        }
+        Ok(self.tok_env()?.tokenize_bytes(&bytes))
+    }
+
     /// Tries to advance the parser by consuming the given tokens.
     /// Returns the number of tokens consumed.
     /// Also checks if the parser should stop after consuming the tokens
@@ -179,6 +443,51 @@ impl Matcher {
         self.with_inner(|inner| inner.parser.validate_tokens_raw(tokens))
     }
 
+    /// Returns the length of the longest prefix of `bytes` that can be consumed
+    /// in the current state. The bytes do not have to come from the tokenizer.
+    pub fn validate_bytes(&mut self, bytes: &[u8]) -> Result<usize> {
+        self.with_inner(|inner| inner.parser.validate_bytes(bytes))
+    }
+
+    /// Consumes the longest prefix of `bytes` that the grammar allows,
+    /// and returns its length.
+    /// The prefix is recorded as tokens of a greedy tokenization
+    /// (for `rollback()`, token budgets, etc.), and only the tokens
+    /// the grammar allows are consumed.
+    /// Thus, the returned length may be shorter than what `validate_bytes()`
+    /// reports; callers should rely on the returned length and can
+    /// pass the remaining bytes again.
+    /// Also checks if the parser should stop, as `try_consume_tokens()` does.
+    /// Fails without consuming anything if the prefix can't be represented
+    /// as tokens of the vocabulary (e.g., bytes missing from a tokenizer
+    /// without byte fallback); the matcher is not put in error state then.
+    pub fn consume_bytes(&mut self, bytes: &[u8]) -> Result<usize> {
+        self.with_inner(|inner| {
+            let n_valid = inner.parser.validate_bytes(bytes)?;
+            if n_valid == 0 {
+                return Ok(Ok(0));
+            }
+            let tok_env = inner.parser.token_env.clone();
+            let trie = tok_env.tok_trie();
+            let valid = &bytes[..n_valid];
+            let mut tokens = trie.greedy_tokenize(valid);
+            if trie.decode_raw(&tokens) != valid {
+                return Ok(Err(anyhow!(
+                    "bytes {:?} can't be represented as tokens",
+                    String::from_utf8_lossy(valid)
+                )));
+            }
+            let n_tokens = inner.parser.validate_tokens_raw(&tokens)?;
+            tokens.truncate(n_tokens);
+            for &t in &tokens {
+                let bt = inner.parser.consume_token(t)?;
+                ensure!(bt == 0, "unexpected backtracking");
+            }
+            let _ = inner.parser.check_stop()?;
+            Ok(Ok(trie.decode_raw(&tokens).len()))
+        })?
+    }
+
     pub fn is_error(&self) -> bool {
         matches!(self.0, MatcherState::Error(_))
     }
@@ -186,7 +495,7 @@ impl Matcher {
     pub fn get_error(&self) -> Option<String> {
         match &self.0 {
             MatcherState::Normal(_) => None,
-            MatcherState::Error(e) => Some(e.clone()),
+            MatcherState::Error(e) => Some(e.to_string()),
         }
     }
 
@@ -197,6 +506,15 @@ impl Matcher {
         }
     }
 
+    /// Like `grammar_warnings()`, but with structured diagnostics;
+    /// includes the error if the matcher is in error state.
+    pub fn grammar_diagnostics(&mut self) -> Vec<Diagnostic> {
+        match &mut self.0 {
+            MatcherState::Normal(inner) => inner.parser.grammar_warning_diagnostics(),
+            MatcherState::Error(e) => vec![e.clone()],
+        }
+    }
+
     pub fn tok_env(&self) -> Result<TokEnv> {
         match &self.0 {
             MatcherState::Normal(inner) => Ok(inner.parser.token_env.clone()),
@@ -1,3 +1,6 @@
+use anyhow::{anyhow, bail, ensure, Result};
+use derivre::RegexAst;
+
 fn class_for(c: char) -> Option<&'static str> {
     match c {
         'd' => Some("0-9"),
@@ -60,6 +63,642 @@ pub fn regex_to_lark(rx: &str, use_ascii: &str) -> String {
     res
 }
 
+// Matches any (valid UTF-8) string; used to extend lookarounds to the end/start of input.
+const ANY_STRING: &str = "(?s:.*)";
+
+/// Return a `RegexAst` for `rx` (matching the whole input);
+/// lookarounds are translated with [`lookarounds_to_ast()`].
+fn regex_ast(rx: String) -> Result<RegexAst> {
+    Ok(lookarounds_to_ast(&rx, false)?.unwrap_or(RegexAst::Regex(rx)))
+}
+
+/// Return a `RegexAst` searching for `rx` anywhere in the input (unless anchored with `^`/`$`),
+/// as used by JSON schema `pattern`; lookarounds are translated with [`lookarounds_to_ast()`].
+pub(crate) fn search_regex_ast(rx: String) -> Result<RegexAst> {
+    Ok(lookarounds_to_ast(&rx, true)?.unwrap_or(RegexAst::SearchRegex(rx)))
+}
+
+enum Item {
+    Text(String),
+    Look {
+        behind: bool,
+        negated: bool,
+        body: String,
+    },
+}
+
+/// Translate lookarounds (`(?=...)`, `(?!...)`, `(?<=...)`, `(?<!...)`) in `rx`
+/// into `&` and `~` operators.
+/// Returns `None` if there are no lookarounds in `rx`.
+///
+/// A lookahead `(?=L)` followed by `S` becomes `S & (L .*)`, a negative lookahead `S & ~(L .*)`;
+/// similarly, `P` followed by a lookbehind becomes `P & (.* L)`.
+/// Lookarounds cannot be repeated (also within groups), or appear in groups with alternatives;
+/// lookbehinds have to have bounded length.
+/// Lookaheads and lookbehinds can only be combined when all lookaheads are at the start,
+/// or all lookbehinds are at the end of the regex (or of an alternative).
+pub(crate) fn lookarounds_to_ast(rx: &str, for_search: bool) -> Result<Option<RegexAst>> {
+    if !has_lookaround(rx) {
+        return Ok(None);
+    }
+    let alternatives = split_alternatives(rx)?
+        .into_iter()
+        .map(|alt| translate_alternative(alt, for_search))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Some(if alternatives.len() == 1 {
+        alternatives.into_iter().next().unwrap()
+    } else {
+        RegexAst::Or(alternatives)
+    }))
+}
+
+fn translate_alternative(alt: &str, for_search: bool) -> Result<RegexAst> {
+    if !has_lookaround(alt) {
+        return Ok(if for_search {
+            RegexAst::SearchRegex(alt.to_string())
+        } else {
+            RegexAst::Regex(alt.to_string())
+        });
+    }
+
+    // ^ and $ are only supported at the start and end
+    let (anchored_start, alt) = match alt.strip_prefix('^') {
+        Some(rest) => (true, rest),
+        None => (false, alt),
+    };
+    let (anchored_end, alt) = match alt.strip_suffix('$') {
+        Some(rest) if !ends_with_escape(rest) => (true, rest),
+        _ => (false, alt),
+    };
+
+    let mut items = vec![];
+    if for_search && !anchored_start {
+        items.push(Item::Text(ANY_STRING.to_string()));
+    }
+    parse_items(alt, "", &mut items)?;
+    if for_search && !anchored_end {
+        items.push(Item::Text(ANY_STRING.to_string()));
+    }
+
+    let first_text = items.iter().position(|it| matches!(it, Item::Text(_)));
+    let last_text = items.iter().rposition(|it| matches!(it, Item::Text(_)));
+
+    if !for_search {
+        // the whole input is the token, there is nothing to look at past its ends
+        let is_look = |it: &Item, is_behind: bool| matches!(it, Item::Look { behind, .. } if *behind == is_behind);
+        if let Some(l) = last_text {
+            ensure!(
+                !items[l + 1..].iter().any(|it| is_look(it, false)),
+                "lookahead at the end of regex is not supported; it can't look past the end of the token"
+            );
+        }
+        if let Some(f) = first_text {
+            ensure!(
+                !items[..f].iter().any(|it| is_look(it, true)),
+                "lookbehind at the start of regex is not supported; it can't look before the start of the token"
+            );
+        }
+    }
+
+    // lookaheads before any text, and lookbehinds after all text apply to the whole input
+    let mut whole = vec![];
+    let mut seq = vec![];
+    for (idx, item) in items.into_iter().enumerate() {
+        match item {
+            Item::Look {
+                behind: false,
+                negated,
+                body,
+            } if first_text.is_none_or(|f| idx < f) => {
+                whole.push(lookaround(false, negated, &body)?);
+            }
+            Item::Look {
+                behind: true,
+                negated,
+                body,
+            } if last_text.is_none_or(|l| idx > l) => {
+                whole.push(lookaround(true, negated, &body)?);
+            }
+            _ => seq.push(item),
+        }
+    }
+
+    let has_ahead = seq
+        .iter()
+        .any(|it| matches!(it, Item::Look { behind: false, .. }));
+    let has_behind = seq
+        .iter()
+        .any(|it| matches!(it, Item::Look { behind: true, .. }));
+    ensure!(
+        !(has_ahead && has_behind),
+        "lookaheads together with lookbehinds in the middle of regex are not supported"
+    );
+
+    let mut res = RegexAst::EmptyString;
+    if has_behind {
+        // the lookbehind applies to everything before it
+        for item in seq {
+            res = match item {
+                Item::Text(t) => RegexAst::Concat(vec![res, RegexAst::Regex(t)]),
+                Item::Look { negated, body, .. } => {
+                    RegexAst::And(vec![res, lookaround(true, negated, &body)?])
+                }
+            };
+        }
+    } else {
+        // the lookahead applies to everything after it
+        for item in seq.into_iter().rev() {
+            res = match item {
+                Item::Text(t) => RegexAst::Concat(vec![RegexAst::Regex(t), res]),
+                Item::Look { negated, body, .. } => {
+                    RegexAst::And(vec![res, lookaround(false, negated, &body)?])
+                }
+            };
+        }
+    }
+
+    if whole.is_empty() {
+        Ok(res)
+    } else {
+        whole.insert(0, res);
+        Ok(RegexAst::And(whole))
+    }
+}
+
+/// The set of strings satisfying the lookaround (extended to the end or start of input,
+/// unless anchored with `$` or `^` respectively).
+fn lookaround(behind: bool, negated: bool, body: &str) -> Result<RegexAst> {
+    let any = RegexAst::Regex(ANY_STRING.to_string());
+    let r = if behind {
+        ensure!(
+            !has_lookaround(body),
+            "lookarounds nested in lookbehind are not supported"
+        );
+        let hir = regex_syntax::Parser::new()
+            .parse(body)
+            .map_err(|e| anyhow!("invalid lookbehind (?<={}): {}", body, e))?;
+        ensure!(
+            hir.properties().maximum_len().is_some(),
+            "unbounded lookbehind (?<{}{}) is not supported; use bounded repetition like {{0,10}}",
+            if negated { "!" } else { "=" },
+            body
+        );
+        match body.strip_prefix('^') {
+            Some(body) => RegexAst::Regex(body.to_string()),
+            None => RegexAst::Concat(vec![any, RegexAst::Regex(body.to_string())]),
+        }
+    } else {
+        match body.strip_suffix('$') {
+            Some(body) if !ends_with_escape(body) => regex_ast(body.to_string())?,
+            _ => RegexAst::Concat(vec![regex_ast(body.to_string())?, any]),
+        }
+    };
+    Ok(if negated {
+        RegexAst::Not(Box::new(r))
+    } else {
+        r
+    })
+}
+
+/// Split `alt` (with no top-level `|`) into text and lookarounds;
+/// groups containing lookarounds are inlined.
+/// `flags` (like `(?i)`) are prepended to all parts.
+fn parse_items(alt: &str, flags: &str, items: &mut Vec<Item>) -> Result<()> {
+    let b = alt.as_bytes();
+    let mut flags = flags.to_string();
+    let mut text_start = 0;
+    let mut i = 0;
+
+    let flush = |items: &mut Vec<Item>, flags: &str, text: &str| {
+        if !text.is_empty() {
+            items.push(Item::Text(format!("{flags}{text}")));
+        }
+    };
+
+    while i < b.len() {
+        match b[i] {
+            b'\\' => i += 2,
+            b'[' => i = class_end(b, i)?,
+            b'(' => {
+                let end = group_end(b, i)?;
+                let inner = &alt[i + 1..end - 1];
+                let quantified = quantifier_end(b, end) != end;
+                if let Some((behind, negated, body)) = lookaround_body(inner) {
+                    ensure!(!quantified, "repeated lookarounds are not supported");
+                    flush(items, &flags, &alt[text_start..i]);
+                    items.push(Item::Look {
+                        behind,
+                        negated,
+                        body: format!("{flags}{body}"),
+                    });
+                    text_start = end;
+                } else {
+                    let (group_flags, body) = group_body(inner);
+                    if body.is_none() && !group_flags.is_empty() {
+                        // flags-only group, like (?i); applies to all following parts
+                        flush(items, &flags, &alt[text_start..i]);
+                        flags.push_str(&alt[i..end]);
+                        text_start = end;
+                    } else if let Some(body) = body.filter(|body| has_lookaround(body)) {
+                        ensure!(
+                            !quantified,
+                            "lookarounds inside repeated groups are not supported"
+                        );
+                        ensure!(
+                            split_alternatives(body)?.len() == 1,
+                            "lookarounds inside groups with alternatives (|) are not supported"
+                        );
+                        flush(items, &flags, &alt[text_start..i]);
+                        let inner_flags = if group_flags.is_empty() {
+                            flags.clone()
+                        } else {
+                            format!("{flags}(?{group_flags})")
+                        };
+                        parse_items(body, &inner_flags, items)?;
+                        text_start = end;
+                    }
+                }
+                i = end;
+            }
+            _ => i += 1,
+        }
+    }
+    flush(items, &flags, &alt[text_start.min(alt.len())..]);
+    Ok(())
+}
+
+/// For lookaround group contents (without parens), return (behind, negated, body).
+fn lookaround_body(inner: &str) -> Option<(bool, bool, &str)> {
+    for (prefix, behind, negated) in [
+        ("?=", false, false),
+        ("?!", false, true),
+        ("?<=", true, false),
+        ("?<!", true, true),
+    ] {
+        if let Some(body) = inner.strip_prefix(prefix) {
+            return Some((behind, negated, body));
+        }
+    }
+    None
+}
+
+/// For non-lookaround group contents (without parens), return its flags and body;
+/// the body is `None` for flags-only groups like `(?i)`.
+fn group_body(inner: &str) -> (&str, Option<&str>) {
+    let Some(rest) = inner.strip_prefix('?') else {
+        return ("", Some(inner)); // capture group
+    };
+    if let Some(rest) = rest.strip_prefix("P<").or_else(|| rest.strip_prefix('<')) {
+        // named capture group
+        return ("", rest.split_once('>').map(|(_, body)| body));
+    }
+    match rest.split_once(':') {
+        Some((flags, body)) if is_flags(flags) => (flags, Some(body)),
+        _ if is_flags(rest) => (rest, None),
+        _ => ("", None),
+    }
+}
+
+fn is_flags(s: &str) -> bool {
+    s.chars().all(|c| "imsxuUR-".contains(c))
+}
+
+/// Translate lookarounds and non-greedy quantifiers in a lexer regex;
+/// see [`lazy_to_ast()`] and [`lookarounds_to_ast()`].
+/// Returns `None` if the regex can be used as is.
+pub(crate) fn lexer_regex_ast(rx: &str, warnings: &mut Vec<String>) -> Result<Option<RegexAst>> {
+    match lazy_to_ast(rx, warnings)? {
+        Some(ast) => Ok(Some(ast)),
+        None => lookarounds_to_ast(rx, false),
+    }
+}
+
+/// Translate non-greedy quantifiers (`*?`, `+?`, `??`, `{n,m}?`) into `&` and `~` operators.
+/// Returns `None` if there are no non-greedy quantifiers in `rx`.
+///
+/// `P Q*? R` becomes `P ((Q* R) & ~((Q* R) .+))`, that is `P` followed by the shortest
+/// string matching `Q* R`; for example `<tag>.*?</tag>` will stop at the first `</tag>`.
+/// Non-greedy quantifiers inside repeated groups, groups with alternatives or groups with flags
+/// are treated as greedy, with a warning; so are the ones whose body can match past the start
+/// of the text following them, see [`lazy_body_overlaps()`].
+/// They cannot be used together with lookarounds.
+pub(crate) fn lazy_to_ast(rx: &str, warnings: &mut Vec<String>) -> Result<Option<RegexAst>> {
+    if !has_lazy(rx) {
+        return Ok(None);
+    }
+    ensure!(
+        !has_lookaround(rx),
+        "non-greedy quantifiers cannot be combined with lookarounds"
+    );
+    let alternatives = split_alternatives(rx)?
+        .into_iter()
+        .map(|alt| lazy_alternative(alt.to_string(), "", warnings))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Some(if alternatives.len() == 1 {
+        alternatives.into_iter().next().unwrap()
+    } else {
+        RegexAst::Or(alternatives)
+    }))
+}
+
+fn lazy_alternative(mut alt: String, flags: &str, warnings: &mut Vec<String>) -> Result<RegexAst> {
+    let mut flags = flags.to_string();
+    let mut i = 0;
+    while i < alt.len() {
+        let end = atom_end(&alt, i)?;
+        let (q_end, lazy) = quantifier(alt.as_bytes(), end);
+        if alt.as_bytes()[i] == b'(' {
+            let inner = &alt[i + 1..end - 1];
+            let (group_flags, body) = group_body(inner);
+            if body.is_none() && !group_flags.is_empty() {
+                // flags-only group, like (?i); needs to be copied to the non-greedy part
+                flags.push_str(&alt[i..end]);
+            } else if has_lazy(inner) {
+                let body = body.filter(|b| group_flags.is_empty() && !b.starts_with('?'));
+                let unsupported = if q_end != end {
+                    Some("repeated groups")
+                } else if body.is_none() {
+                    Some("groups with flags")
+                } else if split_alternatives(body.unwrap())?.len() != 1 {
+                    Some("groups with alternatives (|)")
+                } else {
+                    None
+                };
+                let group = match unsupported {
+                    Some(place) => {
+                        warnings.push(format!(
+                            "non-greedy quantifiers inside {place} are not supported; \
+                            treating them as greedy (use [lazy] on the terminal instead)"
+                        ));
+                        format!("({})", make_greedy(inner))
+                    }
+                    // inline the group
+                    None => body.unwrap().to_string(),
+                };
+                alt = format!("{}{}{}", &alt[..i], group, &alt[end..]);
+                continue;
+            }
+        }
+        if lazy
+            && lazy_body_overlaps(
+                &format!("{flags}{}", &alt[i..end]),
+                &format!("{flags}{}", make_greedy(&alt[q_end..])),
+            )
+        {
+            warnings.push(format!(
+                "non-greedy quantifier in `{}` can match past the start of what follows it; \
+                treating it as greedy",
+                &alt[i..q_end]
+            ));
+            alt = format!("{}{}", &alt[..q_end - 1], &alt[q_end..]);
+            i = q_end - 1;
+            continue;
+        }
+        if lazy {
+            let prefix = &alt[..i];
+            let tail = RegexAst::Concat(vec![
+                RegexAst::Regex(format!("{}{}", flags, &alt[i..q_end - 1])),
+                lazy_alternative(alt[q_end..].to_string(), &flags, warnings)?,
+            ]);
+            let shortest = RegexAst::And(vec![
+                tail.clone(),
+                RegexAst::Not(Box::new(RegexAst::Concat(vec![
+                    tail,
+                    RegexAst::Regex("(?s:.+)".to_string()),
+                ]))),
+            ]);
+            return Ok(if prefix.is_empty() {
+                shortest
+            } else {
+                RegexAst::Concat(vec![RegexAst::Regex(prefix.to_string()), shortest])
+            });
+        }
+        i = q_end;
+    }
+    Ok(RegexAst::Regex(format!("{flags}{alt}")))
+}
+
+/// Check if `body` can match a string with the first byte of `rest` after its start.
+/// Backtracking engines then keep repeating `body` past that point when it lets
+/// the whole regex match, while the shortest-match translation always stops there
+/// (for example, `"(\\.|[^"])*?"` would not match `"a\"b"`).
+fn lazy_body_overlaps(body: &str, rest: &str) -> bool {
+    let check = || -> Result<bool> {
+        let mut builder = derivre::RegexBuilder::new();
+        let rest = builder.mk_regex(rest)?;
+        let mut rest = builder.to_regex_limited(rest, LAZY_CHECK_LIMIT)?;
+        let initial = rest.initial_state();
+        let mut first_bytes = vec![0u32; 8];
+        for b in 0..=255u8 {
+            if !rest.transition(initial, b).is_dead() {
+                first_bytes[b as usize / 32] |= 1 << (b % 32);
+            }
+        }
+        if first_bytes.iter().all(|&w| w == 0) {
+            return Ok(false);
+        }
+        let overlap = builder.mk(&RegexAst::And(vec![
+            RegexAst::Regex(body.to_string()),
+            RegexAst::Concat(vec![
+                RegexAst::Regex("(?s:.+)".to_string()),
+                RegexAst::ByteSet(first_bytes),
+                RegexAst::Regex("(?s:.*)".to_string()),
+            ]),
+        ]))?;
+        let mut overlap = builder.to_regex_limited(overlap, LAZY_CHECK_LIMIT)?;
+        Ok(!overlap.always_empty())
+    };
+    // if the check is too expensive, assume there is no overlap
+    check().unwrap_or(false)
+}
+
+const LAZY_CHECK_LIMIT: u64 = 10_000;
+
+/// Remove the `?` making quantifiers non-greedy.
+fn make_greedy(rx: &str) -> String {
+    let mut res = String::with_capacity(rx.len());
+    let mut i = 0;
+    while i < rx.len() {
+        let Ok(end) = atom_end(rx, i) else {
+            res.push_str(&rx[i..]);
+            break;
+        };
+        if rx.as_bytes()[i] == b'(' {
+            res.push_str(&format!("({})", make_greedy(&rx[i + 1..end - 1])));
+        } else {
+            res.push_str(&rx[i..end]);
+        }
+        let (q_end, lazy) = quantifier(rx.as_bytes(), end);
+        res.push_str(&rx[end..if lazy { q_end - 1 } else { q_end }]);
+        i = q_end;
+    }
+    res
+}
+
+fn has_lazy(rx: &str) -> bool {
+    let mut i = 0;
+    while i < rx.len() {
+        let Ok(end) = atom_end(rx, i) else {
+            return false;
+        };
+        let (q_end, lazy) = quantifier(rx.as_bytes(), end);
+        if lazy || (rx.as_bytes()[i] == b'(' && has_lazy(&rx[i + 1..end - 1])) {
+            return true;
+        }
+        i = q_end;
+    }
+    false
+}
+
+/// Return the index after the atom (character, escape, class or group) starting at `i`.
+fn atom_end(rx: &str, i: usize) -> Result<usize> {
+    let b = rx.as_bytes();
+    match b[i] {
+        b'\\' => {
+            let mut end = i + 2;
+            match b.get(i + 1) {
+                Some(b'p' | b'P' | b'x' | b'u' | b'U') if b.get(end) == Some(&b'{') => {
+                    end = group_end_with(b, end, b'}')?;
+                }
+                Some(b'x') => end += 2,
+                Some(b'u') => end += 4,
+                Some(b'U') => end += 8,
+                Some(b'p' | b'P') => end += 1,
+                _ => {}
+            }
+            ensure!(end <= b.len(), "invalid escape at the end of regex");
+            Ok(end)
+        }
+        b'[' => class_end(b, i),
+        b'(' => group_end(b, i),
+        _ => Ok(i + rx[i..].chars().next().unwrap().len_utf8()),
+    }
+}
+
+fn group_end_with(b: &[u8], start: usize, close: u8) -> Result<usize> {
+    match b[start..].iter().position(|&c| c == close) {
+        Some(len) => Ok(start + len + 1),
+        None => bail!("unterminated escape in regex"),
+    }
+}
+
+fn has_lookaround(rx: &str) -> bool {
+    let b = rx.as_bytes();
+    let mut i = 0;
+    while i < b.len() {
+        match b[i] {
+            b'\\' => i += 2,
+            b'[' => match class_end(b, i) {
+                Ok(end) => i = end,
+                Err(_) => return false,
+            },
+            b'(' => {
+                let Ok(end) = group_end(b, i) else {
+                    return false;
+                };
+                let inner = &rx[i + 1..end - 1];
+                if lookaround_body(inner).is_some() {
+                    return true;
+                }
+                i += 1;
+            }
+            _ => i += 1,
+        }
+    }
+    false
+}
+
+fn split_alternatives(rx: &str) -> Result<Vec<&str>> {
+    let b = rx.as_bytes();
+    let mut res = vec![];
+    let mut start = 0;
+    let mut i = 0;
+    while i < b.len() {
+        match b[i] {
+            b'\\' => i += 2,
+            b'[' => i = class_end(b, i)?,
+            b'(' => i = group_end(b, i)?,
+            b'|' => {
+                res.push(&rx[start..i]);
+                i += 1;
+                start = i;
+            }
+            _ => i += 1,
+        }
+    }
+    res.push(&rx[start.min(rx.len())..]);
+    Ok(res)
+}
+
+/// Given `[` at `start`, return the index after the matching `]`.
+fn class_end(b: &[u8], start: usize) -> Result<usize> {
+    let mut i = start + 1;
+    if b.get(i) == Some(&b'^') {
+        i += 1;
+    }
+    if b.get(i) == Some(&b']') {
+        i += 1;
+    }
+    while i < b.len() {
+        match b[i] {
+            b'\\' => i += 2,
+            b'[' => i = class_end(b, i)?,
+            b']' => return Ok(i + 1),
+            _ => i += 1,
+        }
+    }
+    bail!("unterminated character class in regex")
+}
+
+/// Given `(` at `start`, return the index after the matching `)`.
+fn group_end(b: &[u8], start: usize) -> Result<usize> {
+    let mut i = start + 1;
+    while i < b.len() {
+        match b[i] {
+            b'\\' => i += 2,
+            b'[' => i = class_end(b, i)?,
+            b'(' => i = group_end(b, i)?,
+            b')' => return Ok(i + 1),
+            _ => i += 1,
+        }
+    }
+    bail!("unterminated group in regex")
+}
+
+/// Return the index after the quantifier (if any) at `start`.
+fn quantifier_end(b: &[u8], start: usize) -> usize {
+    quantifier(b, start).0
+}
+
+/// Return the index after the quantifier (if any) at `start`,
+/// and whether the quantifier is non-greedy (like `*?`).
+fn quantifier(b: &[u8], start: usize) -> (usize, bool) {
+    let mut i = start;
+    match b.get(i) {
+        Some(b'*' | b'+' | b'?') => i += 1,
+        Some(b'{') => match b[i..].iter().position(|&c| c == b'}') {
+            Some(len)
+                if b[i + 1..i + len]
+                    .iter()
+                    .all(|c| c.is_ascii_digit() || *c == b',') =>
+            {
+                i += len + 1
+            }
+            _ => return (start, false),
+        },
+        _ => return (start, false),
+    }
+    if b.get(i) == Some(&b'?') {
+        (i + 1, true)
+    } else {
+        (i, false)
+    }
+}
+
+fn ends_with_escape(s: &str) -> bool {
+    s.bytes().rev().take_while(|&c| c == b'\\').count() % 2 == 1
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -127,4 +766,179 @@ mod tests {
         assert_eq!(regex_to_lark("\"", ""), "\"");
         assert_eq!(regex_to_lark(r#"a"b"#, ""), r#"a"b"#);
     }
+
+    fn check_lookaround(rx: &str, for_search: bool, passing: &[&str], failing: &[&str]) {
+        check_ast(rx, lookarounds_to_ast(rx, for_search), passing, failing);
+    }
+
+    fn check_ast(rx: &str, ast: Result<Option<RegexAst>>, passing: &[&str], failing: &[&str]) {
+        let ast = ast.unwrap().unwrap();
+        let mut builder = derivre::RegexBuilder::new();
+        let e = builder.mk(&ast).unwrap();
+        let mut rx2 = builder.to_regex(e);
+        for s in passing {
+            assert!(rx2.is_match(s), "{rx:?} should match {s:?}");
+        }
+        for s in failing {
+            assert!(!rx2.is_match(s), "{rx:?} should not match {s:?}");
+        }
+    }
+
+    fn lookaround_err(rx: &str) -> String {
+        lookarounds_to_ast(rx, false).err().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_lookarounds() {
+        assert!(lookarounds_to_ast(r"a(b|c)*\(?=[(?=]", false)
+            .unwrap()
+            .is_none());
+
+        // password-style lookaheads
+        check_lookaround(
+            r"(?=.*[0-9])(?=.*[a-z])[a-z0-9]{4,}",
+            false,
+            &["abc1", "1a2b"],
+            &["abcd", "1234", "a1"],
+        );
+        check_lookaround(
+            r"(?!foo)[a-z]+",
+            false,
+            &["fo", "bar", "fox"],
+            &["foo", "foox"],
+        );
+        check_lookaround(r"[a-z]+(?<!ing)", false, &["sin", "ingx"], &["sing", "ing"]);
+        check_lookaround(r"a(?=b)[a-z]+", false, &["ab", "abc"], &["ac", "a"]);
+        check_lookaround(r"[a-z]+(?<=x)[0-9]", false, &["ax1"], &["ab1"]);
+        check_lookaround(
+            r"(?:(?!ab)[a-z]{2})-x|y",
+            false,
+            &["ac-x", "y"],
+            &["ab-x", "ab"],
+        );
+        check_lookaround(r"(?i)(?=A)[a-z]+", false, &["abc", "Abc"], &["bc"]);
+        check_lookaround(
+            r"(?!(if|in)$)[a-z]+",
+            false,
+            &["iff", "i", "x"],
+            &["if", "in"],
+        );
+
+        // search mode
+        check_lookaround(
+            r"^(?!.*--)[a-z-]+$",
+            true,
+            &["a-b", "abc"],
+            &["a--b", "-a--"],
+        );
+        check_lookaround(r"x(?=y)", true, &["axyz", "xy"], &["xz", "ax"]);
+    }
+
+    #[test]
+    fn test_lookaround_errors() {
+        assert!(lookaround_err(r"x(?<=a+)b").contains("unbounded lookbehind"));
+        assert!(lookarounds_to_ast(r"x(?<=a{1,3})b", false).is_ok());
+        assert!(lookaround_err(r"(?=a)*b").contains("repeated lookarounds"));
+        assert!(lookaround_err(r"((?=a)b)+").contains("repeated groups"));
+        assert!(lookaround_err(r"((?=a)b|c)d").contains("alternatives"));
+        assert!(lookaround_err(r"a(?=b)c(?<=c)d").contains("lookaheads together with lookbehinds"));
+        let err = lookarounds_to_ast(r"(?<![a-z])x(?=y)y", true)
+            .err()
+            .unwrap();
+        assert!(err
+            .to_string()
+            .contains("lookaheads together with lookbehinds"));
+
+        // tokens can't look past their ends
+        assert!(lookaround_err(r"[a-z]+(?=:)").contains("lookahead at the end"));
+        assert!(lookaround_err(r"[a-z]+(?!:)(?<!x)").contains("lookahead at the end"));
+        assert!(lookaround_err(r"(?<=a)b").contains("lookbehind at the start"));
+        assert!(lookarounds_to_ast(r"[a-z]+(?=:)", true).is_ok());
+        assert!(lookarounds_to_ast(r"(?<=a)b", true).is_ok());
+    }
+
+    fn check_lazy(rx: &str, passing: &[&str], failing: &[&str]) {
+        let mut warnings = vec![];
+        check_ast(rx, lazy_to_ast(rx, &mut warnings), passing, failing);
+        assert!(warnings.is_empty(), "{warnings:?}");
+    }
+
+    fn check_greedy_fallback(rx: &str, warning: &str, passing: &[&str], failing: &[&str]) {
+        let mut warnings = vec![];
+        check_ast(rx, lazy_to_ast(rx, &mut warnings), passing, failing);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains(warning), "{warnings:?}");
+    }
+
+    #[test]
+    fn test_lazy() {
+        assert!(lazy_to_ast(r"a*b+c?d{2,3}\*?[*?](?:x)", &mut vec![])
+            .unwrap()
+            .is_none());
+
+        check_lazy(
+            r"<t>.*?</t>",
+            &["<t></t>", "<t>a</t>", "<t><t></t>"],
+            &["<t>a</t>b</t>", "<t>a</t></t>", "<t>a"],
+        );
+        check_lazy(r"a+?", &["a"], &["aa", ""]);
+        check_lazy(r"[a-z]{2,4}?[0-9]", &["ab1", "abc1"], &["a1", "ab1c2"]);
+        check_lazy(r"x(<.*?>)y", &["x<a>y", "x<a>>y"], &["x<a>y>y"]);
+        check_lazy(r"(?s)<.*?>|[0-9]+", &["<\n>", "123"], &["<a>>"]);
+        check_lazy(r"\p{L}*?a", &["a", "ba"], &["aa", "baa"]);
+        check_lazy(
+            r"\{.*?\}.*?;",
+            &["{a};", "{a}b;", "{a}};"],
+            &["{a};;", "{a}b;c;"],
+        );
+    }
+
+    #[test]
+    fn test_lazy_greedy_fallback() {
+        check_greedy_fallback(
+            r"(a.*?b)+",
+            "repeated groups",
+            &["ab", "axbb", "abab"],
+            &["a"],
+        );
+        check_greedy_fallback(
+            r"(a.*?b|c)d",
+            "alternatives",
+            &["abd", "abbd", "cd"],
+            &["ad"],
+        );
+        check_greedy_fallback(r"(?s:a.*?b)", "flags", &["a\nb", "abb"], &["a"]);
+        // the group can also match `>`, so its own quantifier is treated as greedy too
+        let rx = r"<(a.*?b)*?>.*?;";
+        let mut warnings = vec![];
+        check_ast(
+            rx,
+            lazy_to_ast(rx, &mut warnings),
+            &["<>;", "<abab>x;", "<a>b>;"],
+            &["<>;;"],
+        );
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("repeated groups"), "{warnings:?}");
+        assert!(
+            warnings[1].contains("can match past the start"),
+            "{warnings:?}"
+        );
+        // the body can match an escaped quote, so the shortest match would stop too early
+        check_greedy_fallback(
+            r#""(\\.|[^"])*?""#,
+            "can match past the start of what follows it",
+            &[r#""a""#, r#""a\"b""#, r#""""#],
+            &[r#""a"b""#],
+        );
+        check_greedy_fallback(
+            r"<(.|\n\n)*?\n>",
+            "can match past the start",
+            &["<\n\n\n>"],
+            &["<\n>\n>"],
+        );
+        assert_eq!(make_greedy(r"(?:a+?|[?]{2}?)\??b??"), r"(?:a+|[?]{2})\??b?");
+
+        let err = lazy_to_ast(r"(?=a).*?b", &mut vec![]).err().unwrap();
+        assert!(err.to_string().contains("lookarounds"));
+    }
 }
@@ -2,12 +2,16 @@ use std::{fmt::Display, hint::black_box, panic::AssertUnwindSafe, sync::Arc, tim
 
 use crate::{
     api::{GrammarInit, ParserLimits, StopReason},
+    diagnostics::Diagnostic,
     earley::{BiasComputer, Parser, ParserError, ParserStats},
     infoln, panic_utils, warn, Instant, Logger, ParserFactory,
 };
-use anyhow::{ensure, Result};
+use anyhow::{bail, ensure, Result};
 use toktrie::{InferenceCapabilities, SimpleVob, TokEnv, TokenId, INVALID_TOKEN};
 
+// Limit on parser states visited when searching for the shortest completion.
+const SHORTEST_COMPLETION_MAX_STATES: usize = 2_000;
+
 /// Token-level parser that drives a single constrained-generation session.
 ///
 /// Created by [`ParserFactory::create_parser()`] and typically wrapped in a
@@ -78,16 +82,29 @@ impl TokenParser {
 
         let compute_mask_start_time = Instant::now();
         let mut max_tokens = usize::MAX;
-        if let GrammarInit::Serialized(input) = &grammar_init {
-            if let Some(m) = input.max_tokens {
-                max_tokens = m;
+        match &grammar_init {
+            GrammarInit::Serialized(input) => {
+                if let Some(m) = input.max_tokens {
+                    max_tokens = m;
+                }
+            }
+            GrammarInit::Compiled(c) => {
+                ensure!(
+                    c.tokenizer_hash == factory.tokenizer_hash(),
+                    "compiled grammar was created for a different tokenizer"
+                );
+                if let Some(m) = c.max_tokens {
+                    max_tokens = m;
+                }
             }
+            GrammarInit::Internal(..) => {}
         }
         let compiled_grammar = grammar_init.to_cgrammar(
             Some(token_env.clone()),
             &mut logger,
             limits.clone(),
             factory.extra_lexemes(),
+            factory.import_resolver(),
         )?;
         let parser = Parser::new(
             token_env.clone(),
@@ -128,6 +145,11 @@ impl TokenParser {
         self.parser.grammar_warnings()
     }
 
+    /// Like `grammar_warnings()`, but with their codes and locations.
+    pub fn grammar_warning_diagnostics(&mut self) -> Vec<Diagnostic> {
+        self.parser.grammar_warning_diagnostics()
+    }
+
     pub fn get_capture(&self, name: &str) -> Option<&[u8]> {
         self.parser.get_capture(name)
     }
@@ -226,6 +248,30 @@ impl TokenParser {
         (tokens, chop_bytes)
     }
 
+    /// Find the number of trailing tokens (and their length in bytes),
+    /// that could be extended into a longer token, regardless of the grammar.
+    fn token_healing_chop(&self, tokens: &[TokenId]) -> (usize, usize) {
+        let max_token_lookback = 4;
+        let trie = self.token_env.tok_trie();
+        let mut suffix = Vec::new();
+        let mut res = (0, 0);
+        for (idx, &tok) in tokens.iter().rev().take(max_token_lookback).enumerate() {
+            if trie.is_special_token(tok) {
+                break;
+            }
+            let mut bytes = trie.token(tok).to_vec();
+            bytes.extend_from_slice(&suffix);
+            suffix = bytes;
+            if suffix.len() > trie.max_token_len() {
+                break;
+            }
+            if trie.has_extensions(&suffix) {
+                res = (idx + 1, suffix.len());
+            }
+        }
+        res
+    }
+
     pub fn process_prompt(&mut self, prompt: Vec<TokenId>) -> Vec<TokenId> {
         infoln!(self, "initial lexer cost: {}", self.parser.lexer_stats());
 
@@ -245,7 +291,22 @@ impl TokenParser {
         prompt_bytes.extend_from_slice(&grm_bytes);
 
         let (tokens, num_fixed) = self.token_env.tokenize_bytes_marker(&prompt_bytes);
-        let (res_prompt, chop_bytes) = self.tokenize_and_chop(tokens, num_fixed);
+        let (mut res_prompt, mut chop_bytes) = self.tokenize_and_chop(tokens, num_fixed);
+
+        if self.parser.grammar().lexer_spec().token_healing {
+            let num_fixed = std::cmp::min(num_fixed, res_prompt.len());
+            let (heal_tokens, heal_bytes) = self.token_healing_chop(&res_prompt[num_fixed..]);
+            if heal_tokens > 0 {
+                let trie = self.token_env.tok_trie();
+                infoln!(
+                    self,
+                    "token healing: {}",
+                    trie.tokens_dbg(&res_prompt[res_prompt.len() - heal_tokens..])
+                );
+                res_prompt.truncate(res_prompt.len() - heal_tokens);
+                chop_bytes += heal_bytes;
+            }
+        }
 
         let trie = self.token_env.tok_trie();
         infoln!(
@@ -449,6 +510,25 @@ impl TokenParser {
         Ok(n_valid)
     }
 
+    /// Returns the length of the longest prefix of `bytes` accepted by the parser.
+    pub fn validate_bytes(&mut self, bytes: &[u8]) -> Result<usize> {
+        if self.stopped() {
+            return Ok(0);
+        }
+        self.check_initialized("validate_bytes")?;
+
+        let prefix = self.pending_grm_prefix();
+        let n_prefix = std::cmp::min(prefix.len(), bytes.len());
+        if prefix[..n_prefix] != bytes[..n_prefix] {
+            return Ok(prefix.iter().zip(bytes).take_while(|(a, b)| a == b).count());
+        }
+        if n_prefix == bytes.len() {
+            return Ok(n_prefix);
+        }
+
+        Ok(n_prefix + self.parser.validate_bytes(&bytes[n_prefix..]))
+    }
+
     fn anyhow_error(&self) -> anyhow::Error {
         anyhow::anyhow!(self
             .error_message
@@ -910,6 +990,57 @@ impl TokenParser {
         Ok(ff_tokens)
     }
 
+    /// Estimated length in bytes of the shortest completion of the grammar,
+    /// including any bytes currently forced by the grammar.
+    /// Returns None if no estimate is available.
+    pub fn completion_estimate(&mut self) -> Option<usize> {
+        let mut trg = Vec::new();
+        self.compute_ff_bytes_to(&mut trg);
+        Some(trg.len() + self.parser.completion_estimate()?)
+    }
+
+    /// Sequence of bytes after which the grammar can be finished,
+    /// starting with any bytes currently forced by the grammar.
+    /// It is built greedily using `completion_estimate()`, so it is usually,
+    /// but not always, the shortest one.
+    pub fn approximate_completion(&mut self) -> Option<Vec<u8>> {
+        let mut trg = Vec::new();
+        self.compute_ff_bytes_to(&mut trg);
+        trg.extend_from_slice(&self.parser.approximate_completion()?);
+        Some(trg)
+    }
+
+    /// Shortest sequence of bytes after which the grammar can be finished,
+    /// starting with any bytes currently forced by the grammar.
+    /// If the search gives up, the (not always shortest) completion
+    /// from `approximate_completion()` is returned instead.
+    /// Fails if the parser is stopped in a non-accepting state,
+    /// or if no completion is found within search limits.
+    pub fn shortest_completion(&mut self) -> Result<Vec<u8>> {
+        if self.stopped() {
+            if self.is_accepting() {
+                return Ok(vec![]);
+            }
+            bail!(
+                "parser stopped in a non-accepting state ({}); it cannot be completed",
+                self.stop_reason
+            );
+        }
+        let mut trg = Vec::new();
+        self.compute_ff_bytes_to(&mut trg);
+        let rest = self
+            .parser
+            .shortest_completion(SHORTEST_COMPLETION_MAX_STATES)
+            .or_else(|| self.parser.approximate_completion());
+        match rest {
+            Some(rest) => {
+                trg.extend_from_slice(&rest);
+                Ok(trg)
+            }
+            None => bail!("no completion found within search limits"),
+        }
+    }
+
     /// This function documents typical use of this interface.
     /// The `tokens` array simulates tokens being sampled.
     #[allow(dead_code)]
@@ -0,0 +1,136 @@
+use llg_test_utils::{byte_grammar_err, byte_grammar_test_many};
+use llguidance::api::TopLevelGrammar;
+
+fn check(abnf: &str, passing: &[&str], failing: &[&str]) {
+    byte_grammar_test_many(
+        &TopLevelGrammar::from_abnf(abnf.to_string()),
+        passing,
+        failing,
+    );
+}
+
+fn check_err(abnf: &str, msg: &str) {
+    let e = byte_grammar_err(TopLevelGrammar::from_abnf(abnf.to_string()));
+    assert!(e.contains(msg), "expected {msg:?} in {e:?}");
+}
+
+#[test]
+fn test_abnf_basic() {
+    // HTTP-like headers, with core rules and comments
+    check(
+        r#"
+headers     = 1*header CRLF      ; at least one header
+header      = field-name ":" OWS field-value OWS CRLF
+field-name  = 1*( ALPHA / DIGIT / "-" )
+field-value = *( VCHAR / SP )
+OWS         = *( SP / HTAB )
+        "#,
+        &[
+            "Content-Type: text/html\r\n\r\n",
+            "Host:example.com\r\nX-Foo:  a b\t\r\n\r\n",
+        ],
+        &["Content-Type: text/html\n\n", "Bad Name: x\r\n\r\n", "\r\n"],
+    );
+
+    // numeric values, ranges, concatenation and repetition forms
+    check(
+        r#"
+start = %x41-43 2DIGIT *2"x" %d13.10 [ "!" ] 1*3%b110001 %x4E2D
+        "#,
+        &["A12\r\n1\u{4e2d}", "C99xx\r\n!111\u{4e2d}"],
+        &[
+            "D12\r\n1\u{4e2d}",
+            "A1\r\n1\u{4e2d}",
+            "A12xxx\r\n1\u{4e2d}",
+            "A12\r\n\u{4e2d}",
+        ],
+    );
+
+    // case-insensitive strings and rule names, case-sensitive %s strings
+    check(
+        r#"
+Method = "get" / %s"POST" / %i"Put"
+        "#,
+        &["GET", "get", "gEt", "POST", "pUT"],
+        &["post", "Post"],
+    );
+    check(
+        "start = Foo\nFOO = \"a\" foo-Bar\nfoo-bar = \"b\"",
+        &["ab", "AB"],
+        &["a"],
+    );
+}
+
+#[test]
+fn test_abnf_rules() {
+    // incremental alternatives, and multi-line rules
+    check(
+        r#"
+command = "go" SP direction
+direction = "north"
+direction =/ "south" /
+              "east"
+direction =/ "west"
+        "#,
+        &["go north", "go south", "go east", "GO WEST"],
+        &["go up", "go  north"],
+    );
+
+    // recursion and optional groups
+    check(
+        r#"
+list  = "(" [ item *( "," item ) ] ")"
+item  = list / 1*ALPHA
+        "#,
+        &["()", "(a,(b,()),cd)"],
+        &["(a,)", "(()"],
+    );
+
+    // core rules can be redefined
+    check("start = 1*DIGIT\nDIGIT = %x30-31", &["0110"], &["012"]);
+}
+
+#[test]
+fn test_abnf_api() {
+    byte_grammar_test_many(
+        &TopLevelGrammar::from_tagged_str("abnf", "start = 3DIGIT").unwrap(),
+        &["123"],
+        &["12", "1234"],
+    );
+    let grm: TopLevelGrammar =
+        serde_json::from_str(r#"{"grammars": [{"abnf_grammar": "start = 1*%x61"}]}"#).unwrap();
+    byte_grammar_test_many(&grm, &["aaa"], &["b"]);
+
+    let grm: TopLevelGrammar = serde_json::from_str(
+        r#"{"grammars": [{"abnf_grammar": "start = \"a\"", "gbnf_grammar": "root ::= \"a\""}]}"#,
+    )
+    .unwrap();
+    let e = byte_grammar_err(grm);
+    assert!(e.contains("can only have one of"), "{e}");
+}
+
+#[test]
+fn test_abnf_errors() {
+    check_err("; only a comment\n", "no rules found");
+    check_err("start = foo", "rule \"foo\" not found");
+    check_err("start = \"a\"\nSTART = \"b\"", "duplicate rule \"start\"");
+    check_err(
+        "start = \"a\"\nfoo =/ \"b\"",
+        "rule \"foo\" not defined before =/",
+    );
+    check_err("start = \"abc", "unterminated string");
+    check_err("start = %x5A-41", "invalid range");
+    check_err("start = %xD800", "invalid character value");
+    check_err("start = %q41", "expected one of %x");
+    check_err(
+        "start = \"a\"\n  / \"b\"\n  / %q41",
+        "at 3(6): expected one of %x",
+    );
+    check_err("start = 3*2\"a\"", "max is smaller than min");
+    check_err("start = ( \"a\"", "expected ')'");
+    check_err("start = \"a\" )", "unexpected ')'");
+    check_err("start : \"a\"", "expected '='");
+    check_err("start = <some prose>", "prose values");
+    check_err("  start = \"a\"", "expected rule name");
+    check_err("\n\nstart = foo", "at 3(9)");
+}
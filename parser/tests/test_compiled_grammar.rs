@@ -0,0 +1,211 @@
+use lazy_static::lazy_static;
+use llguidance::{
+    api::TopLevelGrammar,
+    toktrie::{ApproximateTokEnv, TokEnv},
+    CompiledGrammar, ParserFactory, TokenParser,
+};
+use serde_json::json;
+
+lazy_static! {
+    static ref PARSER_FACTORY: ParserFactory = {
+        let mut fact = ParserFactory::new_simple(&ApproximateTokEnv::single_byte_env()).unwrap();
+        fact.quiet();
+        fact
+    };
+}
+
+fn get_parser_factory() -> &'static ParserFactory {
+    &PARSER_FACTORY
+}
+
+fn get_tok_env() -> &'static TokEnv {
+    PARSER_FACTORY.tok_env()
+}
+
+fn roundtrip(grm: TopLevelGrammar) -> CompiledGrammar {
+    let compiled = get_parser_factory().compile_grammar(grm).unwrap();
+    let bytes = compiled.to_bytes().unwrap();
+    let loaded = CompiledGrammar::from_bytes(&bytes).unwrap();
+    assert_eq!(
+        loaded.tokenizer_hash(),
+        get_parser_factory().tokenizer_hash()
+    );
+    loaded
+}
+
+fn accepts(mut parser: TokenParser, input: &str) -> bool {
+    parser.start_without_prompt();
+    for tok in get_tok_env().tokenize(input) {
+        let m = parser.compute_mask().unwrap();
+        if !m.is_allowed(tok) {
+            return false;
+        }
+        let n = parser.consume_token(tok).unwrap();
+        assert_eq!(n, 0);
+    }
+    parser.is_accepting()
+}
+
+fn check(grm: TopLevelGrammar, passing: &[&str], failing: &[&str]) {
+    let compiled = roundtrip(grm);
+    for s in passing {
+        let p = get_parser_factory()
+            .create_parser_from_compiled(compiled.clone())
+            .unwrap();
+        assert!(accepts(p, s), "should accept {s:?}");
+    }
+    for s in failing {
+        let p = get_parser_factory()
+            .create_parser_from_compiled(compiled.clone())
+            .unwrap();
+        assert!(!accepts(p, s), "should reject {s:?}");
+    }
+}
+
+#[test]
+fn test_compiled_lark() {
+    check(
+        TopLevelGrammar::from_lark(
+            r#"
+                start: "foo" NUM ("," NUM)* | %regex { "substring_words": "the quick brown fox" }
+                NUM: /[0-9]+/
+                %ignore /[ \t]+/
+            "#
+            .to_string(),
+        ),
+        &["foo 1, 2,3", "foo12", "quick brown", ""],
+        &["foo", "foo 1,", "quick fox", "bar"],
+    );
+}
+
+#[test]
+fn test_compiled_nested() {
+    check(
+        TopLevelGrammar::from_lark(
+            r#"
+                start: "x=" obj
+                obj: %json { "type": "object", "properties": { "a": { "type": "integer" } } }
+            "#
+            .to_string(),
+        ),
+        &["x={}", r#"x={"a":1}"#],
+        &["x=", r#"x={"a":"b"}"#],
+    );
+}
+
+#[test]
+fn test_compiled_priorities() {
+    check(
+        TopLevelGrammar::from_lark(
+            r#"
+                start: IF IDENT | IDENT "=" IDENT
+                IF.2: "if"
+                IDENT: /[a-z]+/
+                %ignore " "
+            "#
+            .to_string(),
+        ),
+        &["if x", "x = if", "iff = x"],
+        &["if = x"],
+    );
+}
+
+#[test]
+fn test_compiled_json() {
+    check(
+        TopLevelGrammar::from_json_schema(json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string", "pattern": "^[a-z]+$" },
+                "age": { "type": "integer", "minimum": 0 }
+            },
+            "required": ["name"],
+            "additionalProperties": false
+        })),
+        &[r#"{"name":"bob"}"#, r#"{"name":"bob","age":42}"#],
+        &[
+            r#"{"age":42}"#,
+            r#"{"name":"Bob"}"#,
+            r#"{"name":"bob","age":-1}"#,
+        ],
+    );
+}
+
+#[test]
+fn test_compiled_errors() {
+    let compiled = get_parser_factory()
+        .compile_grammar(TopLevelGrammar::from_lark("start: \"a\"".to_string()))
+        .unwrap();
+    let mut bytes = compiled.to_bytes().unwrap();
+
+    assert!(CompiledGrammar::from_bytes(b"{}").is_err());
+
+    let mut other_version = bytes.clone();
+    other_version[4] ^= 0xff;
+    let e = CompiledGrammar::from_bytes(&other_version).err().unwrap();
+    assert!(e.to_string().contains("version mismatch"));
+
+    let factory = get_parser_factory()
+        .with_slices(&["[a-z]+".to_string()])
+        .unwrap();
+    let e = factory
+        .create_parser_from_compiled(compiled.clone())
+        .err()
+        .unwrap();
+    assert!(e.to_string().contains("different slices"));
+
+    bytes.truncate(bytes.len() - 1);
+    assert!(CompiledGrammar::from_bytes(&bytes).is_err());
+}
+
+#[test]
+fn test_compiled_corrupted() {
+    for (lark, input) in [
+        ("start: \"a\" x\nx: /[0-9]+/", "a123"),
+        ("start: \"a\" x\nx: %json { \"type\": \"integer\" }", "a12"),
+    ] {
+        let compiled = get_parser_factory()
+            .compile_grammar(TopLevelGrammar::from_lark(lark.to_string()))
+            .unwrap();
+        let bytes = compiled.to_bytes().unwrap();
+
+        for len in (0..bytes.len()).step_by(7) {
+            assert!(CompiledGrammar::from_bytes(&bytes[..len]).is_err());
+        }
+
+        // flipped bytes either fail to load or give a grammar that can be used without panicking
+        for pos in 8..bytes.len() {
+            let mut mutated = bytes.clone();
+            mutated[pos] ^= 0x5a;
+            if let Ok(compiled) = CompiledGrammar::from_bytes(&mutated) {
+                if let Ok(p) = get_parser_factory().create_parser_from_compiled(compiled) {
+                    accepts(p, input);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_compiled_warnings() {
+    let grm = || {
+        TopLevelGrammar::from_lark(
+            r#"
+                %llguidance {"auto_terminals": true}
+                start: "a" num
+                num: /[0-9]+/
+            "#
+            .to_string(),
+        )
+    };
+    let mut parser = get_parser_factory().create_parser(grm()).unwrap();
+    let expected = parser.grammar_warning_diagnostics();
+    assert_eq!(expected.len(), 1);
+    assert!(expected[0].span.is_some());
+
+    let compiled = roundtrip(grm());
+    let mut parser = get_parser_factory()
+        .create_parser_from_compiled(compiled)
+        .unwrap();
+    assert_eq!(parser.grammar_warning_diagnostics(), expected);
+}
@@ -0,0 +1,148 @@
+use llg_test_utils::{byte_grammar_err, byte_grammar_test_many, get_byte_parser_factory};
+use llguidance::api::TopLevelGrammar;
+
+fn check(gbnf: &str, passing: &[&str], failing: &[&str]) {
+    byte_grammar_test_many(
+        &TopLevelGrammar::from_gbnf(gbnf.to_string()),
+        passing,
+        failing,
+    );
+}
+
+fn check_err(gbnf: &str, msg: &str) {
+    let e = byte_grammar_err(TopLevelGrammar::from_gbnf(gbnf.to_string()));
+    assert!(e.contains(msg), "expected {msg:?} in {e:?}");
+}
+
+fn llama_cpp_grammar(name: &str) -> String {
+    let path = format!(
+        "{}/../sample_parser/data/from-llama.cpp/{name}.gbnf",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    std::fs::read_to_string(path).unwrap()
+}
+
+#[test]
+fn test_gbnf_basic() {
+    check(
+        r#"
+            # arithmetic
+            root  ::= expr
+            expr  ::= term ([-+*/] term)*
+            term  ::= num | "(" ws expr ws ")"
+            num   ::= [0-9]+
+            ws    ::= [ \t]*
+        "#,
+        &["1+2", "(1*2)/3", "( 12 )", "42"],
+        &["1+", "(1", "a", ""],
+    );
+
+    // escapes, negated classes, repetition ranges, any character, multi-line rules
+    check(
+        r#"
+root ::= "\x41é\"" [^\n\]]{2,3} "-" . (
+    "a" |
+    "b"
+  ){0,2}
+        "#,
+        &["A\u{e9}\"xy-\n", "A\u{e9}\"xy-Zab", "A\u{e9}\"xyz-zbb"],
+        &[
+            "A\u{e9}\"x-z",
+            "A\u{e9}\"x\ny-z",
+            "A\u{e9}\"x]y-z",
+            "A\u{e9}\"xy-zabc",
+        ],
+    );
+
+    // recursion
+    check(
+        r#"
+            root ::= list
+            list ::= "[" (item ("," item)*)? "]"
+            item ::= list | [a-z]+
+        "#,
+        &["[]", "[a,[b,[]],cd]"],
+        &["[a,]", "[[]"],
+    );
+
+    // empty alternatives
+    check(r#"root ::= "a" ( | "b" ) "c""#, &["ac", "abc"], &["abbc"]);
+}
+
+#[test]
+fn test_gbnf_llama_cpp() {
+    check(
+        &llama_cpp_grammar("json"),
+        &[r#"{"a": [1, 2.5, true, null], "b": {"c": "d\n"}}"#, r#"{}"#],
+        &[r#"{"a": }"#, "[1]"],
+    );
+    check(
+        &llama_cpp_grammar("chess"),
+        &["1. e4 e5\n2. Nf3 Nc6\n"],
+        &["1. e9 e5\n"],
+    );
+    for name in ["c", "english", "japanese", "json_arr", "list", "vllm-sql"] {
+        get_byte_parser_factory()
+            .create_parser(TopLevelGrammar::from_gbnf(llama_cpp_grammar(name)))
+            .unwrap_or_else(|e| panic!("{name}: {e}"));
+    }
+}
+
+#[test]
+fn test_gbnf_alias_names() {
+    // an alias must not rename the rule it refers to
+    let gbnf = "root ::= x\nx ::= \"a\" x | \"\"";
+    check(gbnf, &["", "a", "aaa"], &["b"]);
+    let warnings = get_byte_parser_factory()
+        .create_parser(TopLevelGrammar::from_gbnf(gbnf.to_string()))
+        .unwrap()
+        .grammar_warnings();
+    assert_eq!(
+        warnings,
+        vec!["rewrote right-recursive rule \"x\" into left-recursive form"]
+    );
+}
+
+#[test]
+fn test_gbnf_api() {
+    let gbnf = r#"root ::= "a"+"#;
+    byte_grammar_test_many(
+        &TopLevelGrammar::from_tagged_str("gbnf", gbnf).unwrap(),
+        &["aaa"],
+        &["b"],
+    );
+    let grm: TopLevelGrammar =
+        serde_json::from_str(r#"{"grammars": [{"gbnf_grammar": "root ::= [0-9]{3}"}]}"#).unwrap();
+    byte_grammar_test_many(&grm, &["123"], &["12", "1234"]);
+
+    let grm: TopLevelGrammar = serde_json::from_str(
+        r#"{"grammars": [{"gbnf_grammar": "root ::= \"a\"", "lark_grammar": "start: \"a\""}]}"#,
+    )
+    .unwrap();
+    let e = byte_grammar_err(grm);
+    assert!(e.contains("can only have one of"), "{e}");
+}
+
+#[test]
+fn test_gbnf_errors() {
+    check_err(r#"foo ::= "a""#, "no root rule found");
+    check_err("root ::= foo", "rule \"foo\" not found");
+    check_err("root ::= \"a\"\nroot ::= \"b\"", "duplicate rule \"root\"");
+    check_err(r#"root ::= "\q""#, "invalid escape sequence \\q");
+    // columns are in bytes
+    check_err(
+        "root ::= \"a\"\nx ::= \"\u{e9}\\q\"",
+        "at 2(10): invalid escape sequence \\q",
+    );
+    check_err(r#"root ::= "\x4""#, "invalid escape sequence");
+    check_err(r#"root ::= "abc"#, "unexpected end of input");
+    check_err(r#"root ::= [z-a]"#, "invalid character range");
+    check_err(r#"root ::= []"#, "empty character class");
+    check_err(r#"root ::= "a"{3,2}"#, "max is smaller than min");
+    check_err(r#"root ::= ("a""#, "expected ')'");
+    check_err(r#"root ::= "a" )"#, "unexpected ')'");
+    check_err(r#"root = "a""#, "expected ':'");
+    check_err(r#"root ::= <think>"#, "token references");
+    check_err(r#"root ::= "a" @"#, "unexpected '@'");
+    check_err("\n\nroot ::= foo", "at 3(10)");
+}
@@ -2,7 +2,7 @@ use lazy_static::lazy_static;
 use rstest::*;
 use serde_json::{json, Value};
 
-use llg_test_utils::{json_err_test, json_schema_check};
+use llg_test_utils::{json_err_test, json_schema_check, json_test_many};
 
 lazy_static! {
     static ref INTEGER_ARRAY: Value = json!({"type":"array", "items": {"type":"integer"}});
@@ -213,3 +213,190 @@ fn array_with_prefix_items_length_constrained_failures(#[case] sample_array: &Va
         false,
     );
 }
+
+lazy_static! {
+    static ref UNIQUE_ENUM_ARRAY: Value = json!({
+        "type": "array",
+        "items": {"enum": ["a", "b", "c", 1, true]},
+        "uniqueItems": true,
+        "minItems": 1,
+        "maxItems": 3
+    });
+}
+
+#[rstest]
+#[case(&json!(["a"]))]
+#[case(&json!(["c", "a"]))]
+#[case(&json!([true, 1, "b"]))]
+fn array_unique_items(#[case] sample_array: &Value) {
+    json_schema_check(&UNIQUE_ENUM_ARRAY, sample_array, true);
+}
+
+#[rstest]
+#[case::empty_list(&json!([]))]
+#[case::duplicate(&json!(["a", "a"]))]
+#[case::duplicate_later(&json!(["a", true, "a"]))]
+#[case::too_long(&json!(["a", "b", "c", 1]))]
+#[case::not_in_enum(&json!(["d"]))]
+fn array_unique_items_failures(#[case] sample_array: &Value) {
+    json_schema_check(&UNIQUE_ENUM_ARRAY, sample_array, false);
+}
+
+lazy_static! {
+    static ref UNIQUE_PREFIXED_ARRAY: Value = json!({
+        "type": "array",
+        "prefixItems": [{"type": "boolean"}],
+        "items": {"type": "integer", "minimum": 0, "maximum": 3},
+        "uniqueItems": true
+    });
+}
+
+#[rstest]
+#[case::empty_list(&json!([]))]
+#[case(&json!([true]))]
+#[case(&json!([false, 0, 1, 2, 3]))]
+#[case(&json!([false, 3, 1]))]
+fn array_unique_items_prefixed(#[case] sample_array: &Value) {
+    json_schema_check(&UNIQUE_PREFIXED_ARRAY, sample_array, true);
+}
+
+#[rstest]
+#[case(&json!([1]))]
+#[case(&json!([true, 2, 2]))]
+#[case(&json!([true, 0, 1, 2, 3, 1]))]
+#[case(&json!([true, 4]))]
+fn array_unique_items_prefixed_failures(#[case] sample_array: &Value) {
+    json_schema_check(&UNIQUE_PREFIXED_ARRAY, sample_array, false);
+}
+
+#[test]
+fn array_unique_items_errors() {
+    json_err_test(
+        &json!({"type": "array", "items": {"type": "string"}, "uniqueItems": true}),
+        "uniqueItems not supported unless items are consts, enums",
+    );
+    json_err_test(
+        &json!({"type": "array", "items": {"type": "boolean"}, "uniqueItems": true, "minItems": 3}),
+        "minItems (3) is greater than number of unique items (2)",
+    );
+    // at most one item is always unique
+    json_schema_check(
+        &json!({"type": "array", "items": {"type": "string"}, "uniqueItems": true, "maxItems": 1}),
+        &json!(["x"]),
+        true,
+    );
+}
+
+lazy_static! {
+    static ref CONTAINS_ARRAY: Value = json!({
+        "type": "array",
+        "contains": {"type": "integer", "minimum": 10}
+    });
+}
+
+#[rstest]
+#[case(&json!([10]))]
+#[case(&json!([1, "a", 42]))]
+#[case(&json!([[11], {"a": 12}, 12]))]
+#[case(&json!([11, 12, 13]))]
+fn array_contains(#[case] sample_array: &Value) {
+    json_schema_check(&CONTAINS_ARRAY, sample_array, true);
+}
+
+#[rstest]
+#[case::empty_list(&json!([]))]
+#[case(&json!([1, 2, 3]))]
+#[case::nested(&json!([[11], {"a": 12}]))]
+#[case::string(&json!(["11"]))]
+fn array_contains_failures(#[case] sample_array: &Value) {
+    json_schema_check(&CONTAINS_ARRAY, sample_array, false);
+}
+
+lazy_static! {
+    static ref MIN_MAX_CONTAINS_ARRAY: Value = json!({
+        "type": "array",
+        "items": {"enum": ["a", "b", "c"]},
+        "contains": {"const": "a"},
+        "minContains": 2,
+        "maxContains": 3,
+        "maxItems": 5
+    });
+}
+
+#[rstest]
+#[case(&json!(["a", "a"]))]
+#[case(&json!(["b", "a", "c", "a"]))]
+#[case(&json!(["a", "a", "a", "b", "b"]))]
+fn array_min_max_contains(#[case] sample_array: &Value) {
+    json_schema_check(&MIN_MAX_CONTAINS_ARRAY, sample_array, true);
+}
+
+#[rstest]
+#[case::too_few(&json!(["a", "b"]))]
+#[case::too_many(&json!(["a", "a", "a", "a"]))]
+#[case::too_long(&json!(["a", "a", "b", "b", "b", "b"]))]
+#[case::not_in_enum(&json!(["a", "a", "d"]))]
+fn array_min_max_contains_failures(#[case] sample_array: &Value) {
+    json_schema_check(&MIN_MAX_CONTAINS_ARRAY, sample_array, false);
+}
+
+lazy_static! {
+    static ref PREFIXED_CONTAINS_ARRAY: Value = json!({
+        "type": "array",
+        "prefixItems": [{"type": "string"}],
+        "items": {"type": "integer"},
+        "contains": {"type": "integer", "maximum": 0},
+        "maxContains": 1
+    });
+}
+
+#[rstest]
+#[case(&json!(["x", 0]))]
+#[case(&json!(["x", 1, 2, -5, 3]))]
+fn array_prefixed_contains(#[case] sample_array: &Value) {
+    json_schema_check(&PREFIXED_CONTAINS_ARRAY, sample_array, true);
+}
+
+#[rstest]
+#[case(&json!(["x"]))]
+#[case(&json!([0]))]
+#[case(&json!(["x", 1, 2]))]
+#[case(&json!(["x", 0, -1]))]
+fn array_prefixed_contains_failures(#[case] sample_array: &Value) {
+    json_schema_check(&PREFIXED_CONTAINS_ARRAY, sample_array, false);
+}
+
+#[test]
+fn array_contains_special_cases() {
+    // contains: true only counts the items
+    json_test_many(
+        &json!({"type": "array", "contains": true, "minContains": 2, "maxContains": 3}),
+        &[json!([1, 2]), json!([1, 2, 3])],
+        &[json!([1]), json!([1, 2, 3, 4])],
+    );
+    // minContains: 0 makes contains trivially satisfied
+    json_test_many(
+        &json!({"type": "array", "contains": {"type": "string"}, "minContains": 0}),
+        &[json!([]), json!([1, "a"])],
+        &[],
+    );
+    json_err_test(
+        &json!({"type": "array", "contains": {"type": "object"}}),
+        "contains not supported unless it matches only primitive values",
+    );
+    json_err_test(
+        &json!({"type": "array", "contains": {"type": "string"}, "minContains": 3, "maxItems": 2}),
+        "minContains (3) is greater than maxItems (2)",
+    );
+    // maxItems is counted, not unrolled
+    json_test_many(
+        &json!({"type": "array", "contains": {"const": 1}, "maxItems": 100000000}),
+        &[json!([1]), json!([2, 3, 1, 4, 5, 6])],
+        &[json!([]), json!([2, 3])],
+    );
+    json_test_many(
+        &json!({"type": "array", "contains": {"const": 1}, "minItems": 2, "maxItems": 4}),
+        &[json!([1, 2]), json!([2, 1, 3]), json!([2, 3, 4, 1])],
+        &[json!([1]), json!([2, 3, 4, 5]), json!([1, 2, 3, 4, 5])],
+    );
+}
@@ -200,3 +200,203 @@ fn linked_list(#[case] obj: &Value) {
 fn linked_list_failures(#[case] obj: &Value) {
     json_schema_check(&LINKED_LIST, obj, false);
 }
+
+lazy_static! {
+    static ref DEPENDENT_REQUIRED_SCHEMA: Value = json!({
+        "type": "object",
+        "properties": {
+            "name": {"type": "string"},
+            "credit_card": {"type": "integer"},
+            "billing_address": {"type": "string"}
+        },
+        "required": ["name"],
+        "dependentRequired": {"credit_card": ["billing_address"]},
+        "additionalProperties": false
+    });
+}
+
+#[rstest]
+#[case(&json!({"name": "a"}))]
+#[case(&json!({"name": "a", "billing_address": "x"}))]
+#[case(&json!({"name": "a", "credit_card": 42, "billing_address": "x"}))]
+fn dependent_required(#[case] obj: &Value) {
+    json_schema_check(&DEPENDENT_REQUIRED_SCHEMA, obj, true);
+}
+
+#[rstest]
+#[case(&json!({"name": "a", "credit_card": 42}))]
+#[case(&json!({"credit_card": 42, "billing_address": "x"}))]
+fn dependent_required_failures(#[case] obj: &Value) {
+    json_schema_check(&DEPENDENT_REQUIRED_SCHEMA, obj, false);
+}
+
+lazy_static! {
+    static ref DEPENDENT_SCHEMAS_SCHEMA: Value = json!({
+        "type": "object",
+        "properties": {
+            "kind": {"enum": ["a", "b"]},
+            "size": {"type": "integer"},
+            "unit": {"type": "string"}
+        },
+        "dependentSchemas": {
+            "size": {
+                "properties": {
+                    "kind": {"const": "a"}
+                },
+                "required": ["unit"]
+            }
+        },
+        "additionalProperties": false
+    });
+}
+
+#[rstest]
+#[case(&json!({}))]
+#[case(&json!({"kind": "b"}))]
+#[case(&json!({"kind": "b", "unit": "cm"}))]
+#[case(&json!({"kind": "a", "size": 3, "unit": "cm"}))]
+#[case(&json!({"size": 3, "unit": "cm"}))]
+fn dependent_schemas(#[case] obj: &Value) {
+    json_schema_check(&DEPENDENT_SCHEMAS_SCHEMA, obj, true);
+}
+
+#[rstest]
+#[case(&json!({"kind": "b", "size": 3, "unit": "cm"}))]
+#[case(&json!({"kind": "a", "size": 3}))]
+#[case(&json!({"kind": "b", "size": 3}))]
+fn dependent_schemas_failures(#[case] obj: &Value) {
+    json_schema_check(&DEPENDENT_SCHEMAS_SCHEMA, obj, false);
+}
+
+#[test]
+fn dependent_required_limit() {
+    let deps = (0..10)
+        .map(|i| (format!("p{i}"), json!(["q"])))
+        .collect::<serde_json::Map<_, _>>();
+    json_err_test(
+        &json!({"type": "object", "dependentRequired": deps}),
+        "too many dependentRequired/dependentSchemas",
+    );
+}
+
+lazy_static! {
+    static ref PROPERTY_NAMES_SCHEMA: Value = json!({
+        "type": "object",
+        "properties": {
+            "Foo": {"type": "integer"},
+            "bar": {"type": "integer"}
+        },
+        "patternProperties": {
+            "^x": {"type": "string"}
+        },
+        "additionalProperties": {"type": "boolean"},
+        "propertyNames": {"pattern": "^[a-z]+$", "maxLength": 5}
+    });
+}
+
+#[rstest]
+#[case(&json!({}), true)]
+#[case(&json!({"bar": 1}), true)]
+#[case(&json!({"bar": 1, "xy": "z", "abc": true}), true)]
+#[case(&json!({"Foo": 1}), false)]
+#[case(&json!({"ABC": true}), false)]
+#[case(&json!({"abcdef": true}), false)]
+#[case(&json!({"xyzxyz": "a"}), false)]
+#[case(&json!({"x_y": "a"}), false)]
+fn property_names(#[case] obj: &Value, #[case] expected_pass: bool) {
+    json_schema_check(&PROPERTY_NAMES_SCHEMA, obj, expected_pass);
+}
+
+#[rstest]
+#[case(&json!({"a": 1, "b": 2}), true)]
+#[case(&json!({"b": 2}), true)]
+#[case(&json!({"c": 1}), false)]
+fn property_names_enum(#[case] obj: &Value, #[case] expected_pass: bool) {
+    let schema = json!({
+        "type": "object",
+        "additionalProperties": {"type": "integer"},
+        "propertyNames": {"enum": ["a", "b"]}
+    });
+    json_schema_check(&schema, obj, expected_pass);
+}
+
+#[test]
+fn property_names_required_mismatch() {
+    json_err_test(
+        &json!({
+            "type": "object",
+            "properties": {"Foo": {"type": "integer"}},
+            "required": ["Foo"],
+            "propertyNames": {"pattern": "^[a-z]+$"}
+        }),
+        "required property 'Foo' is unsatisfiable",
+    );
+}
+
+#[rstest]
+#[case(&json!({"a": 1, "b": "x"}), true)]
+#[case(&json!({"b": "x"}), true)]
+#[case(&json!({"a": 1, "b": "x", "c": 1}), false)]
+#[case(&json!({"c": 1}), false)]
+fn unevaluated_properties_all_of(#[case] obj: &Value, #[case] expected_pass: bool) {
+    let schema = json!({
+        "type": "object",
+        "allOf": [{"properties": {"a": {"type": "integer"}}}],
+        "properties": {"b": {"type": "string"}},
+        "unevaluatedProperties": false
+    });
+    json_schema_check(&schema, obj, expected_pass);
+}
+
+#[rstest]
+#[case(&json!({"a": "x", "c": 1}), true)]
+#[case(&json!({"b": "x", "a": 1}), true)]
+#[case(&json!({"a": "x", "c": "y"}), false)]
+fn unevaluated_properties_any_of(#[case] obj: &Value, #[case] expected_pass: bool) {
+    let schema = json!({
+        "type": "object",
+        "anyOf": [
+            {"properties": {"a": {"type": "string"}}, "required": ["a"]},
+            {"properties": {"b": {"type": "string"}}, "required": ["b"]}
+        ],
+        "unevaluatedProperties": {"type": "integer"}
+    });
+    json_schema_check(&schema, obj, expected_pass);
+}
+
+#[rstest]
+#[case(&json!({"a": 1, "z": "x"}), true)]
+#[case(&json!({"z": [1]}), true)]
+fn unevaluated_properties_with_additional(#[case] obj: &Value, #[case] expected_pass: bool) {
+    // additionalProperties evaluates all remaining properties
+    let schema = json!({
+        "type": "object",
+        "properties": {"a": {"type": "integer"}},
+        "additionalProperties": true,
+        "unevaluatedProperties": false
+    });
+    json_schema_check(&schema, obj, expected_pass);
+}
+
+#[rstest]
+#[case(json!({
+    "type": "object",
+    "if": {"properties": {"foo": {"const": "then"}}, "required": ["foo"]},
+    "then": {"properties": {"bar": {"type": "string"}}, "required": ["bar"]},
+    "else": {"properties": {"baz": {"type": "string"}}, "required": ["baz"]},
+    "unevaluatedProperties": false
+}))]
+#[case(json!({
+    "type": "object",
+    "allOf": [{"if": {"properties": {"foo": {"const": "then"}}}, "then": {"required": ["bar"]}}],
+    "properties": {"bar": true},
+    "unevaluatedProperties": false
+}))]
+fn unevaluated_properties_with_if(#[case] schema: Value) {
+    // {"foo": "else", "baz": "baz"} would be accepted: the negated 'if' lists "foo" in properties,
+    // but a failed 'if' doesn't evaluate it
+    json_err_test(
+        &schema,
+        "unevaluatedProperties together with 'if' conditions on properties is not supported",
+    );
+}
@@ -524,6 +524,16 @@ fn string_regex_failures(#[case] sample_value: &Value) {
     json_schema_check(schema, sample_value, false);
 }
 
+#[rstest]
+#[case(&json!("ab1"), true)]
+#[case(&json!("x-ab1-"), true)]
+#[case(&json!("ab"), false)]
+#[case(&json!("a--b1"), false)]
+fn string_regex_lookarounds(#[case] sample_value: &Value, #[case] expected_pass: bool) {
+    let schema = &json!({"type":"string", "pattern": r"^(?!.*--)(?=.*\d).*$"});
+    json_schema_check(schema, sample_value, expected_pass);
+}
+
 #[rstest]
 #[case(&json!("abc"))]
 #[case(&json!("abcd"))]
@@ -369,3 +369,113 @@ fn allof_anyof_oneof_combined() {
         json_schema_check(schema, &value, expected_pass);
     }
 }
+
+lazy_static! {
+    static ref IF_THEN_ELSE_SCHEMA: Value = json!({
+        "type": "object",
+        "properties": {
+            "kind": {"enum": ["circle", "square", "other"]},
+            "radius": {"type": "number"},
+            "side": {"type": "number"}
+        },
+        "required": ["kind"],
+        "additionalProperties": false,
+        "if": {"properties": {"kind": {"const": "circle"}}},
+        "then": {"required": ["radius"]},
+        "else": {"properties": {"radius": false}}
+    });
+}
+
+#[rstest]
+#[case(&json!({"kind": "circle", "radius": 1}), true)]
+#[case(&json!({"kind": "circle", "radius": 1, "side": 2}), true)]
+#[case(&json!({"kind": "square", "side": 2}), true)]
+#[case(&json!({"kind": "other"}), true)]
+#[case(&json!({"kind": "circle"}), false)]
+#[case(&json!({"kind": "circle", "side": 2}), false)]
+#[case(&json!({"kind": "square", "radius": 1}), false)]
+#[case(&json!({"kind": "triangle"}), false)]
+fn if_then_else_discriminator(#[case] value: &Value, #[case] expected_pass: bool) {
+    json_schema_check(&IF_THEN_ELSE_SCHEMA, value, expected_pass);
+}
+
+#[rstest]
+#[case(&json!({"a": "x", "b": true, "c": 1}), true)]
+#[case(&json!({"a": "x", "b": true, "c": "1"}), false)]
+#[case(&json!({"a": "x", "b": false, "c": "1"}), true)]
+#[case(&json!({"a": "y", "b": true, "c": "1"}), true)]
+#[case(&json!({"a": "xx", "c": "1"}), true)]
+#[case(&json!({"a": null, "c": "1"}), true)]
+#[case(&json!({"c": "1"}), true)]
+#[case(&json!({"c": 1}), false)]
+#[case(&json!({"a": "x", "c": 1}), true)]
+#[case(&json!({"a": "x", "c": "1"}), false)]
+fn if_then_else_multiple_properties(#[case] value: &Value, #[case] expected_pass: bool) {
+    // 'b' is not required, so the condition holds when it is absent;
+    // 'a' is required, so the condition fails when it is absent
+    let schema = json!({
+        "properties": {
+            "a": {"type": ["string", "null"]},
+            "b": {"type": "boolean"},
+            "c": true
+        },
+        "if": {
+            "properties": {"a": {"const": "x"}, "b": {"enum": [true]}},
+            "required": ["a"]
+        },
+        "then": {"properties": {"c": {"type": "integer"}}},
+        "else": {"properties": {"c": {"type": "string"}}}
+    });
+    json_schema_check(&schema, value, expected_pass);
+}
+
+#[rstest]
+#[case(&json!(42), true)]
+#[case(&json!("foo"), true)]
+#[case(&json!({"kind": "a", "x": 1}), true)]
+#[case(&json!({"kind": "a"}), false)]
+#[case(&json!({"kind": "b"}), true)]
+fn if_then_without_else(#[case] value: &Value, #[case] expected_pass: bool) {
+    // 'if' without 'type' holds for non-objects
+    let schema = json!({
+        "if": {"properties": {"kind": {"const": "a"}}, "required": ["kind"]},
+        "then": {"required": ["x"]}
+    });
+    json_schema_check(&schema, value, expected_pass);
+}
+
+#[rstest]
+#[case(&json!({"version": -1, "a": "x"}), true)]
+#[case(&json!({"version": -1, "b": "x"}), false)]
+#[case(&json!({"version": 2, "a": "x"}), true)]
+#[case(&json!({"version": 2, "b": "x"}), false)]
+#[case(&json!({"version": 1, "b": "x"}), true)]
+#[case(&json!({"version": 1.5, "b": "x"}), true)]
+#[case(&json!({"version": -3, "b": "x"}), true)]
+#[case(&json!({"version": 0, "b": "x"}), true)]
+#[case(&json!({"version": 7, "b": "x"}), true)]
+#[case(&json!({"version": 7, "a": "x"}), false)]
+fn if_then_else_numeric_discriminator(#[case] value: &Value, #[case] expected_pass: bool) {
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "version": {"type": "number"},
+            "a": {"type": "string"},
+            "b": {"type": "string"}
+        },
+        "required": ["version"],
+        "if": {"properties": {"version": {"enum": [-1, 2]}}},
+        "then": {"required": ["a"], "properties": {"b": false}},
+        "else": {"required": ["b"], "properties": {"a": false}}
+    });
+    json_schema_check(&schema, value, expected_pass);
+}
+
+#[rstest]
+#[case(json!({"if": {"properties": {"n": {"minimum": 3}}}, "then": {"required": ["n"]}}), "at if/properties/n/minimum not supported: unsupported keyword")]
+#[case(json!({"if": {"properties": {"n": {"const": 1.5}}}, "then": {"required": ["n"]}}), "at if/properties/n not supported: value 1.5 is not a string, integer, boolean or null")]
+#[case(json!({"if": {"minProperties": 2}, "then": {"required": ["n"]}}), "at if/minProperties not supported: unsupported keyword")]
+#[case(json!({"if": {"type": "string"}, "then": {"minLength": 2}}), "at if/type not supported: type must be 'object'")]
+fn if_then_else_unsupported(#[case] schema: Value, #[case] expected_error: &str) {
+    json_err_test(&schema, expected_error);
+}
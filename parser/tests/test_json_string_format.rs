@@ -8,7 +8,11 @@
 //   - RFC 1123 §2.1 (hostname) — https://www.rfc-editor.org/rfc/rfc1123#section-2.1
 //   - RFC 4291 (ipv6) — https://www.rfc-editor.org/rfc/rfc4291
 //   - RFC 4122 (uuid) — https://www.rfc-editor.org/rfc/rfc4122
-//   - RFC 3986 (uri) — https://www.rfc-editor.org/rfc/rfc3986
+//   - RFC 3986 (uri, uri-reference) — https://www.rfc-editor.org/rfc/rfc3986
+//   - RFC 3987 (iri, iri-reference) — https://www.rfc-editor.org/rfc/rfc3987
+//   - RFC 6570 (uri-template) — https://www.rfc-editor.org/rfc/rfc6570
+//   - RFC 6901 (json-pointer) — https://www.rfc-editor.org/rfc/rfc6901
+//   - RFC 6531 (idn-email), RFC 5890 (idn-hostname)
 //   - JSON Schema Test Suite (draft2020-12) — https://github.com/json-schema-org/JSON-Schema-Test-Suite
 //   - Python tests (guidance) — https://github.com/guidance-ai/guidance/blob/main/tests/unit/library/json/test_string_format.py
 //
@@ -453,6 +457,224 @@ pub fn bad_uri(#[case] s: &str) {
     json_schema_check(&schema, &json!(s), false);
 }
 
+#[rstest]
+#[case("http://foo.bar/?baz=qux#quux")] // Test Suite: "a valid URI"
+#[case("//foo.bar/?baz=qux#quux")] // Test Suite: "a valid protocol-relative URI Reference"
+#[case("/abc")] // Test Suite: "a valid relative URI Reference"
+#[case("abc")] // Test Suite: "a valid relative URI Reference" (path-noscheme)
+#[case("#fragment")] // Test Suite: "a valid URI fragment"
+#[case("")] // RFC 3986 §4.2: empty relative reference
+#[case("../a/b?x=1")] // RFC 3986 §5.4.1 example
+pub fn valid_uri_reference(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"uri-reference"});
+    json_schema_check(&schema, &json!(s), true);
+}
+
+#[rstest]
+#[case("\\\\WINDOWS\\fileshare")] // Test Suite: "an invalid URI Reference"
+#[case("#frag\\ment")] // Test Suite: "an invalid URI fragment"
+#[case("a:b:c d")] // Space in path
+#[case("http://example.com/%GG")] // Invalid percent-encoding
+pub fn bad_uri_reference(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"uri-reference"});
+    json_schema_check(&schema, &json!(s), false);
+}
+
+#[rstest]
+#[case("http://ƒøø.ßår/?∂éœ=πîx#πîüx")] // Test Suite: "a valid IRI with anchor tag"
+#[case("http://ƒøø.com/blah_(wîkïpédiå)_blah#ßité-1")] // Test Suite: "a valid IRI with anchor tag and parentheses"
+#[case("http://ƒøø.ßår/?q=Test%20URL-encoded%20stuff")] // Test Suite: "a valid IRI with URL-encoded stuff"
+#[case("http://[2001:0db8:85a3:0000:0000:8a2e:0370:7334]")] // Test Suite: "a valid IRI based on IPv6"
+#[case("mailto:user@example.com")] // ASCII-only IRI
+pub fn valid_iri(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"iri"});
+    json_schema_check(&schema, &json!(s), true);
+}
+
+#[rstest]
+#[case("/abc")] // Test Suite: "an invalid relative IRI Reference"
+#[case("\\\\WINDOWS\\filëßåré")] // Test Suite: "an invalid IRI"
+#[case("âππ")] // Test Suite: "an invalid IRI though valid IRI reference"
+#[case("http://2001:0db8:85a3:0000:0000:8a2e:0370:7334")] // Test Suite: "an invalid IRI based on IPv6"
+pub fn bad_iri(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"iri"});
+    json_schema_check(&schema, &json!(s), false);
+}
+
+#[rstest]
+#[case("http://ƒøø.ßår/?∂éœ=πîx#πîüx")] // Test Suite: "a valid IRI"
+#[case("//ƒøø.ßår/?∂éœ=πîx#πîüx")] // Test Suite: "a valid protocol-relative IRI Reference"
+#[case("/âππ")] // Test Suite: "a valid relative IRI Reference"
+#[case("âππ")] // Test Suite: "a valid IRI Reference"
+#[case("#ƒrägmênt")] // Test Suite: "a valid IRI fragment"
+pub fn valid_iri_reference(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"iri-reference"});
+    json_schema_check(&schema, &json!(s), true);
+}
+
+#[rstest]
+#[case("\\\\WINDOWS\\filëßåré")] // Test Suite: "an invalid IRI Reference"
+#[case("#ƒräg\\mênt")] // Test Suite: "an invalid IRI fragment"
+pub fn bad_iri_reference(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"iri-reference"});
+    json_schema_check(&schema, &json!(s), false);
+}
+
+#[rstest]
+#[case("http://example.com/dictionary/{term:1}/{term}")] // Test Suite: "a valid uri-template"
+#[case("http://example.com/dictionary")] // Test Suite: "a valid uri-template without variables"
+#[case("dictionary/{term:1}/{term}")] // Test Suite: "a valid relative uri-template"
+#[case("{/list*}")] // RFC 6570 §3.2.6 example
+#[case("{?x,y}")] // RFC 6570 §3.2.8 example
+#[case("{+path:6}/here")] // RFC 6570 §3.2.3 example
+pub fn valid_uri_template(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"uri-template"});
+    json_schema_check(&schema, &json!(s), true);
+}
+
+#[rstest]
+#[case("http://example.com/dictionary/{term:1}/{term")] // Test Suite: "an invalid uri-template"
+#[case("{}")] // Empty expression
+#[case("{x:0}")] // Prefix length must be positive
+#[case("{x y}")] // Space in varname
+#[case("a b")] // Space in literal
+pub fn bad_uri_template(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"uri-template"});
+    json_schema_check(&schema, &json!(s), false);
+}
+
+#[rstest]
+#[case("/foo/bar~0/baz~1/%a")] // Test Suite: "a valid JSON-pointer"
+#[case("/foo//bar")] // Test Suite: "valid JSON-pointer with empty segment"
+#[case("/foo/bar/")] // Test Suite: "valid JSON-pointer with the last empty segment"
+#[case("")] // Test Suite: RFC 6901 §5 - whole document
+#[case("/")] // Test Suite: RFC 6901 §5
+#[case("/a~1b")] // Test Suite: RFC 6901 §5
+#[case("/ ")] // Test Suite: RFC 6901 §5
+#[case("/m~0n")] // Test Suite: RFC 6901 §5
+#[case("/foo/-")] // Test Suite: "valid JSON-pointer used adding to the last array position"
+pub fn valid_json_pointer(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"json-pointer"});
+    json_schema_check(&schema, &json!(s), true);
+}
+
+#[rstest]
+#[case("/foo/bar~")] // Test Suite: "not a valid JSON-pointer (~ not escaped)"
+#[case("#")] // Test Suite: "not a valid JSON-pointer (URI Fragment Identifier)"
+#[case("#/a")] // Test Suite: "not a valid JSON-pointer (URI Fragment Identifier)"
+#[case("/~-1")] // Test Suite: "not a valid JSON-pointer (some escaped, but not all)"
+#[case("/~2")] // Test Suite: "not a valid JSON-pointer (wrong escape character)"
+#[case("a")] // Test Suite: "not a valid JSON-pointer (isn't empty nor starts with /)"
+pub fn bad_json_pointer(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"json-pointer"});
+    json_schema_check(&schema, &json!(s), false);
+}
+
+#[rstest]
+#[case("1")] // Test Suite: "a valid upwards RJP"
+#[case("0/foo/bar")] // Test Suite: "a valid downwards RJP"
+#[case("2/0/baz/1/zip")] // Test Suite: "a valid up and then down RJP, with array index"
+#[case("0#")] // Test Suite: "a valid RJP taking the member or index name"
+#[case("120/foo/bar")] // Test Suite: "multi-digit integer prefix"
+pub fn valid_relative_json_pointer(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"relative-json-pointer"});
+    json_schema_check(&schema, &json!(s), true);
+}
+
+#[rstest]
+#[case("/foo/bar")] // Test Suite: "an invalid RJP that is a valid JSON Pointer"
+#[case("-1/foo/bar")] // Test Suite: "negative prefix"
+#[case("+1/foo/bar")] // Test Suite: "explicit positive prefix"
+#[case("0##")] // Test Suite: "## is not a valid json-pointer"
+#[case("01/a")] // Test Suite: "zero cannot be followed by other digits"
+#[case("01#")] // Test Suite: "zero cannot be followed by other digits"
+#[case("")] // Test Suite: "empty string"
+pub fn bad_relative_json_pointer(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"relative-json-pointer"});
+    json_schema_check(&schema, &json!(s), false);
+}
+
+#[rstest]
+#[case("([abc])+\\s+$")] // Test Suite: "a valid regular expression"
+#[case("^a(?:b|c)*d{2,3}?$")] // Non-capturing group and lazy bounded quantifier
+#[case("(?<year>\\d{4})-(?=\\d)")] // Named group and lookahead
+#[case("[\\]\\[]")] // Escaped brackets in character class
+#[case("((a|b)(c|(d|(e))))")] // Nested groups
+#[case("")] // Empty regex
+pub fn valid_regex(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"regex"});
+    json_schema_check(&schema, &json!(s), true);
+}
+
+#[rstest]
+#[case("^(abc]")] // Test Suite: "a regular expression with unclosed parens is invalid"
+#[case("abc)")] // Unbalanced closing paren
+#[case("[abc")] // Unclosed character class
+#[case("(?i)abc")] // Inline flags are not ECMA-262 syntax
+#[case("*a")] // Quantifier without atom
+pub fn bad_regex(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"regex"});
+    json_schema_check(&schema, &json!(s), false);
+}
+
+#[rstest]
+#[case("joe.bloggs@example.com")] // Test Suite: "a valid e-mail address"
+#[case("실례@실례.테스트")] // Test Suite: "a valid idn e-mail (example@example.test in Hangul)"
+#[case("用户@例子.广告")] // RFC 6531 style address
+pub fn valid_idn_email(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"idn-email"});
+    json_schema_check(&schema, &json!(s), true);
+}
+
+#[rstest]
+#[case("2962")] // Test Suite: "an invalid idn e-mail address"
+#[case("실례")] // No @
+#[case("a@b@c.com")] // Double @
+#[case(".user@example.com")] // Leading dot in local part
+pub fn bad_idn_email(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"idn-email"});
+    json_schema_check(&schema, &json!(s), false);
+}
+
+#[rstest]
+#[case("실례.테스트")] // Test Suite: "a valid host name (example.test in Hangul)"
+#[case("xn--ihqwcrb4cv8a8dqg056pqjye")] // Test Suite: "valid Chinese Punycode"
+#[case("hostname")] // Test Suite: "single label"
+#[case("host-name")] // Test Suite: "single label with hyphen"
+#[case("h0stn4me")] // Test Suite: "single label with digits"
+#[case("1host")] // Test Suite: "single label starting with digit"
+#[case("l\u{b7}l")] // Test Suite: "MIDDLE DOT with surrounding 'l's"
+#[case("\u{5d0}\u{5f3}\u{5d1}")] // Test Suite: "Hebrew GERESH preceded by Hebrew"
+#[case("\u{30fb}\u{3041}")] // Test Suite: "KATAKANA MIDDLE DOT with Hiragana"
+#[case("\u{915}\u{94d}\u{200d}\u{937}")] // Test Suite: "ZERO WIDTH JOINER preceded by Virama"
+#[case("a\u{3002}b")] // Test Suite: "ideographic full stop as label separator"
+pub fn valid_idn_hostname(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"idn-hostname"});
+    json_schema_check(&schema, &json!(s), true);
+}
+
+#[rstest]
+#[case("-hello")] // Test Suite: "begins with hyphen"
+#[case("hello-")] // Test Suite: "ends with hyphen"
+#[case("")] // Test Suite: "empty string"
+#[case(".")] // Test Suite: "single dot"
+#[case("a b")] // Space
+#[case("〮실례.테스트")] // Test Suite: "illegal first char U+302E Hangul single dot tone mark"
+#[case("실〮례.테스트")] // Test Suite: "contains illegal char U+302E Hangul single dot tone mark"
+#[case("\u{300}hello")] // Test Suite: "Begins with a Nonspacing Mark"
+#[case("XN--aa---o47jg78q")] // Test Suite: "U-label contains \"--\" in the 3rd and 4th position"
+#[case("xn--X")] // Test Suite: "invalid Punycode"
+#[case("a\u{b7}l")] // Test Suite: "MIDDLE DOT with no preceding 'l'"
+#[case("\u{3b1}\u{375}S")] // Test Suite: "Greek KERAIA not followed by Greek"
+#[case("def\u{30fb}abc")] // Test Suite: "KATAKANA MIDDLE DOT with no Hiragana, Katakana, or Han"
+#[case("\u{660}\u{6f0}")] // Test Suite: "Arabic-Indic digits mixed with Extended Arabic-Indic digits"
+#[case("\u{915}\u{200d}\u{937}")] // Test Suite: "ZERO WIDTH JOINER not preceded by Virama"
+#[case("example\u{3002}")] // Test Suite: "trailing ideographic full stop"
+pub fn bad_idn_hostname(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"idn-hostname"});
+    json_schema_check(&schema, &json!(s), false);
+}
+
 // ===== Accepted-but-invalid tests =====
 //
 // These values are INVALID per the relevant RFC but ACCEPTED by the current regex.
@@ -507,6 +729,16 @@ pub fn regex_accepts_but_invalid_hostname() {
     json_schema_check(&schema, &json!(long_hostname), true);
 }
 
+// TODO: The idn-hostname regex only checks a few of the code points RFC 5892
+// disallows, and not all of the contextual rules.
+#[rstest]
+#[case("a\u{200c}b")] // RFC 5892 A.1: ZERO WIDTH NON-JOINER not preceded by Virama
+#[case("\u{2603}")] // RFC 5892 2.6: symbols are DISALLOWED
+pub fn regex_accepts_but_invalid_idn_hostname(#[case] s: &str) {
+    let schema = json!({"type":"string", "format":"idn-hostname"});
+    json_schema_check(&schema, &json!(s), true);
+}
+
 #[rstest]
 #[case("Some string")]
 pub fn valid_unknown(#[case] s: &str) {
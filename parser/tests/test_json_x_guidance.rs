@@ -385,3 +385,157 @@ fn whitespace_pattern_bounds_entire_gap(#[case] input: &str, #[case] should_succ
     let lark = format!("start: %json {schema}");
     lark_str_test(&lark, should_succeed, input, true);
 }
+
+#[rstest]
+#[case::schema_order(r#"{"a":1,"b":"x","c":true}"#, true)]
+#[case::reversed(r#"{"c":true,"b":"x","a":1}"#, true)]
+#[case::required_only(r#"{"b":"x","a":1}"#, true)]
+#[case::optional_first(r#"{"c":false,"a":1,"b":"y"}"#, true)]
+#[case::missing_required(r#"{"c":true,"a":1}"#, false)]
+#[case::duplicate(r#"{"a":1,"b":"x","a":2}"#, false)]
+#[case::unknown(r#"{"a":1,"b":"x","d":2}"#, false)]
+fn property_order_any(#[case] input: &str, #[case] should_succeed: bool) {
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "a": { "type": "integer" },
+            "b": { "type": "string" },
+            "c": { "type": "boolean" }
+        },
+        "required": ["a", "b"],
+        "additionalProperties": false,
+        "x-guidance": {
+            "whitespace_flexible": false,
+            "property_order": "any"
+        }
+    });
+    let lark = format!("start: %json {schema}");
+    lark_str_test(&lark, should_succeed, input, true);
+}
+
+#[rstest]
+#[case::one(r#"{"b":2}"#, true)]
+#[case::two(r#"{"b":2,"a":1}"#, true)]
+#[case::empty(r#"{}"#, false)]
+#[case::three(r#"{"c":3,"b":2,"a":1}"#, false)]
+#[case::additional_after(r#"{"b":2,"x":"y"}"#, false)]
+fn property_order_any_min_max(#[case] input: &str, #[case] should_succeed: bool) {
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "a": { "type": "integer" },
+            "b": { "type": "integer" },
+            "c": { "type": "integer" }
+        },
+        "minProperties": 1,
+        "maxProperties": 2,
+        "additionalProperties": false,
+        "x-guidance": {
+            "whitespace_flexible": false,
+            "property_order": "any"
+        }
+    });
+    let lark = format!("start: %json {schema}");
+    lark_str_test(&lark, should_succeed, input, true);
+}
+
+#[rstest]
+#[case::named_only(r#"{"b":2,"a":1}"#, true)]
+#[case::additional_between(r#"{"b":2,"x":"y","a":1}"#, true)]
+#[case::additional_first(r#"{"z":"w","a":1}"#, true)]
+#[case::additional(r#"{"b":2,"a":1,"x":"y","z":"w"}"#, true)]
+#[case::additional_interleaved(r#"{"x":"y","b":2,"z":"w","a":1,"u":"v"}"#, true)]
+#[case::additional_no_optional(r#"{"a":1,"x":"y"}"#, true)]
+#[case::additional_missing_required(r#"{"x":"y"}"#, false)]
+#[case::additional_bad_value(r#"{"a":1,"x":1}"#, false)]
+#[case::additional_taken_name(r#"{"a":1,"x":"y","a":2}"#, false)]
+fn property_order_any_additional(#[case] input: &str, #[case] should_succeed: bool) {
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "a": { "type": "integer" },
+            "b": { "type": "integer" }
+        },
+        "required": ["a"],
+        "additionalProperties": { "type": "string" },
+        "x-guidance": {
+            "whitespace_flexible": false,
+            "property_order": "any"
+        }
+    });
+    let lark = format!("start: %json {schema}");
+    lark_str_test(&lark, should_succeed, input, true);
+}
+
+#[rstest]
+#[case::none(r#"{"a":1,"b":2}"#, false)]
+#[case::one_first(r#"{"x":"y","b":2,"a":1}"#, true)]
+#[case::two_between(r#"{"a":1,"x":"y","z":"w","b":2}"#, true)]
+#[case::three(r#"{"x":"y","a":1,"z":"w","b":2,"u":"v"}"#, false)]
+#[case::missing_required(r#"{"x":"y","a":1}"#, false)]
+fn property_order_any_additional_min_max(#[case] input: &str, #[case] should_succeed: bool) {
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "a": { "type": "integer" },
+            "b": { "type": "integer" }
+        },
+        "required": ["a", "b"],
+        "minProperties": 3,
+        "maxProperties": 4,
+        "additionalProperties": { "type": "string" },
+        "x-guidance": {
+            "whitespace_flexible": false,
+            "property_order": "any"
+        }
+    });
+    let lark = format!("start: %json {schema}");
+    lark_str_test(&lark, should_succeed, input, true);
+}
+
+#[rstest]
+#[case::none(r#"{"a":1}"#, false)]
+#[case::one(r#"{"x":"y","a":1}"#, true)]
+#[case::many(r#"{"x":"y","a":1,"z":"w","u":"v"}"#, true)]
+fn property_order_any_additional_min(#[case] input: &str, #[case] should_succeed: bool) {
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "a": { "type": "integer" }
+        },
+        "required": ["a"],
+        "minProperties": 2,
+        "additionalProperties": { "type": "string" },
+        "x-guidance": {
+            "whitespace_flexible": false,
+            "property_order": "any"
+        }
+    });
+    let lark = format!("start: %json {schema}");
+    lark_str_test(&lark, should_succeed, input, true);
+}
+
+#[rstest]
+#[case::sku(r#"{"id":"ABC-1234","price":"12"}"#, true)]
+#[case::bad_sku(r#"{"id":"abc-1234","price":"12"}"#, false)]
+#[case::short_sku(r#"{"id":"ABC-123","price":"12"}"#, false)]
+#[case::overridden_builtin(r#"{"id":"ABC-1234","price":"1.5"}"#, false)]
+fn custom_formats(#[case] input: &str, #[case] should_succeed: bool) {
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "id": { "type": "string", "format": "sku" },
+            "price": { "type": "string", "format": "date" }
+        },
+        "required": ["id", "price"],
+        "x-guidance": {
+            "whitespace_flexible": false,
+            "formats": {
+                "sku": "[A-Z]{3}-[0-9]{4}",
+                "date": "[0-9]+"
+            }
+        }
+    });
+    let lark = format!("start: %json {schema}");
+    lark_str_test(&lark, should_succeed, input, true);
+}
@@ -1,4 +1,8 @@
-use llguidance::substring::chunk_into_words;
+use llguidance::{
+    api::{GrammarInit, ParserLimits, TopLevelGrammar},
+    substring::chunk_into_words,
+    Diagnostic, Severity,
+};
 use rand::{rngs::SmallRng, Rng, SeedableRng};
 use serde_json::json;
 
@@ -235,7 +239,7 @@ fn test_lark_syntax_perc() {
     lark_err_test(r#"start: %json { foo"#, "key must be a string");
     lark_err_test(r#"start: %json []"#, "failed to compile JSON schema");
     lark_err_test(
-        r#"start: %json { "if": {} }"#,
+        r#"start: %json { "if": { "minLength": 1 } }"#,
         "failed to compile JSON schema",
     );
 
@@ -372,6 +376,295 @@ fn test_repeat() {
     );
 }
 
+#[test]
+fn test_templates() {
+    lark_str_test_many(
+        r#"start: _separated{num, ","} ";" _separated{word, "|"}
+           _separated{x, sep}: x (sep x)*
+           num: /[0-9]+/
+           word: /[a-z]+/
+        "#,
+        &["1;a", "1,22,3;ab|c"],
+        &["1|2;a", "1;a,b", "FINAL_REJECT:1,"],
+    );
+
+    // recursive template, template as argument, and terminal arguments
+    lark_str_test_many(
+        r#"start: list{pair{A, "="}}
+           list{item}: item | item "," list{item}
+           pair{k, sep}: k sep B
+           A: "a"
+           B: "b"
+        "#,
+        &["a=b", "a=b,a=b,a=b"],
+        &["a=a", "FINAL_REJECT:a=b,"],
+    );
+
+    lark_err_test(
+        r#"start: foo{"a"}
+           foo{x, y}: x y
+        "#,
+        "template \"foo\" expects 2 arguments, got 1",
+    );
+    lark_err_test(
+        r#"start: foo
+           foo{x}: x
+        "#,
+        "template \"foo\" used without arguments",
+    );
+    lark_err_test(
+        r#"start: deep{"a"}
+           deep{x}: x | deep{deep{x}}
+        "#,
+        "nested too deeply",
+    );
+    lark_err_test(
+        r#"start: FOO{"a"}
+           FOO{X}: X
+        "#,
+        "templates are only supported for rules",
+    );
+}
+
+#[test]
+fn test_terminal_priorities() {
+    let grm = r#"
+        start: stmt+
+        stmt: IF IDENT ";" | IDENT "=" IDENT ";"
+        IF.2: "if"
+        IDENT: /[a-z]+/
+        %ignore " "
+    "#;
+    lark_str_test_many(
+        grm,
+        &[
+            "if x;",
+            "iff = x;",
+            "x = if;",
+            "if x; y = z;",
+            "x = y; if z;",
+        ],
+        &["if = x;", "x = y; if = z;"],
+    );
+
+    // without priority, "if" is ambiguous, and both parses are allowed
+    lark_str_test_many(
+        &grm.replace("IF.2", "IF"),
+        &["if x;", "if = x;", "iff = x;"],
+        &["if;"],
+    );
+
+    // negative priority loses against the default
+    lark_str_test_many(
+        &grm.replace("IF.2", "IF.-1"),
+        &["if = x;", "iff = x;"],
+        &["if x;"],
+    );
+
+    lark_err_test(r#"start.2: "a""#, "priority of rules is not supported");
+}
+
+#[test]
+fn test_regex_lookarounds() {
+    lark_str_test_many(
+        r#"
+            start: PASSWORD
+            PASSWORD: /(?=.*[0-9])(?=.*[A-Z])[a-zA-Z0-9]{6,}/
+        "#,
+        &["abcD12", "1234Ab"],
+        &[
+            "FINAL_REJECT:abcdef",
+            "FINAL_REJECT:abc123",
+            "FINAL_REJECT:aB1",
+            "abc-1A",
+        ],
+    );
+
+    lark_str_test_many(
+        r#"
+            start: IDENT
+            IDENT: /(?!(if|else)$)[a-z]+/
+        "#,
+        &["iff", "el", "x"],
+        &["FINAL_REJECT:if", "FINAL_REJECT:else", "if-"],
+    );
+
+    lark_str_test_many(
+        r#"
+            start: WORD
+            WORD: /[a-z]+(?<!ing)/
+        "#,
+        &["sin", "ringo"],
+        &["FINAL_REJECT:sing", "FINAL_REJECT:ring"],
+    );
+
+    lark_err_test(
+        r#"
+            start: A
+            A: /a(?<=a+)b/
+        "#,
+        "unbounded lookbehind",
+    );
+
+    lark_err_test(
+        r#"
+            start: KEY ":"
+            KEY: /[a-z]+(?=:)/
+        "#,
+        "lookahead at the end of regex is not supported",
+    );
+
+    lark_err_test(
+        r#"
+            start: "a" B
+            B: /(?<=a)b/
+        "#,
+        "lookbehind at the start of regex is not supported",
+    );
+}
+
+#[test]
+fn test_regex_non_greedy() {
+    lark_str_test_many(
+        r#"
+            start: TAG TEXT
+            TAG: /<b>.*?<\/b>/
+            TEXT: /[^<]*/
+        "#,
+        &["<b>x</b>", "<b>x</b>tail", "<b><i></b>"],
+        &["<b>x</b><", "FINAL_REJECT:<b>x"],
+    );
+
+    // unsupported non-greedy quantifiers are treated as greedy
+    let grm = r#"
+        start: A
+        A: /(a.*?b)+/
+    "#;
+    lark_str_test_many(grm, &["ab", "abab", "axbb"], &["FINAL_REJECT:a"]);
+    let warnings = lark_warnings(grm);
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].contains("non-greedy quantifiers inside repeated groups"));
+    assert!(warnings[0].contains("treating them as greedy"));
+
+    // the body can match an escaped quote, so the quantifier is treated as greedy
+    let grm = r#"
+        start: STR
+        STR: /"(\\.|[^"])*?"/
+    "#;
+    lark_str_test_many(grm, &[r#""a""#, r#""a\"b""#], &[r#""a"b""#]);
+    let warnings = lark_warnings(grm);
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].contains("can match past the start of what follows it"));
+}
+
+fn lark_warnings(lark: &str) -> Vec<String> {
+    get_parser_factory()
+        .create_parser(TopLevelGrammar::from_lark(lark.to_string()))
+        .unwrap()
+        .grammar_warnings()
+}
+
+#[test]
+fn test_auto_terminals() {
+    let grm = r#"
+        %llguidance {"auto_terminals": true}
+        start: assignment+ | list
+        assignment: name "=" number ";"
+        name: letter (letter | digit)*
+        number: digit+ ("." digit+)?
+        letter: /[a-z]/
+        digit: /[0-9]/
+        list: "(" list* ")"
+        capture[capture]: "@" name
+    "#;
+    let passing = &["x1=12.5;", "ab=1;cd=2;", "(()())"];
+    let failing = &["1x=1;", "x=1.;", "x=1;)"];
+    lark_str_test_many(grm, passing, failing);
+    // same language without the option
+    lark_str_test_many(&grm.replace("true", "false"), passing, failing);
+
+    // recursive and captured rules are not promoted
+    assert_eq!(
+        lark_warnings(grm),
+        vec![
+            "auto_terminals: compiled rule \"assignment\" as a terminal",
+            "auto_terminals: compiled rule \"digit\" as a terminal",
+            "auto_terminals: compiled rule \"letter\" as a terminal",
+            "auto_terminals: compiled rule \"name\" as a terminal",
+            "auto_terminals: compiled rule \"number\" as a terminal"
+        ]
+    );
+    assert!(lark_warnings(&grm.replace("true", "false")).is_empty());
+
+    // with %ignore, only rules that are already a single lexeme are promoted
+    let grm = r#"
+        %llguidance {"auto_terminals": true}
+        start: pair+
+        pair: key ":" value
+        key: /[a-z]+/ | "_"
+        value: (INT)
+        digits: INT+
+        INT: /[0-9]+/
+        %ignore " "
+    "#;
+    lark_str_test_many(grm, &["a : 1", "a:1 _ :2"], &["a:b"]);
+    assert_eq!(
+        lark_warnings(grm),
+        vec![
+            "auto_terminals: compiled rule \"key\" as a terminal",
+            "auto_terminals: compiled rule \"value\" as a terminal"
+        ]
+    );
+}
+
+#[test]
+fn test_right_recursion() {
+    let grm = r#"
+        start: list | "[" items "]"
+        list: item list | item
+        items: item "," items | item ";" items | item
+        item: /[a-z]+/ | "(" list ")"
+        %ignore " "
+    "#;
+    let long_list = "ab ".repeat(3000);
+    let long_items = format!("[{}x]", "a,b;".repeat(2000));
+    lark_str_test_many(
+        grm,
+        &[
+            "a",
+            "a(b c)d",
+            &long_list,
+            "[a]",
+            "[a,(b c);d]",
+            &long_items,
+        ],
+        &["[a,]", "[a;;b]"],
+    );
+    assert_eq!(
+        lark_warnings(grm),
+        vec![
+            "rewrote right-recursive rule \"items\" into left-recursive form",
+            "rewrote right-recursive rule \"list\" into left-recursive form"
+        ]
+    );
+    // also reported when only validating the grammar, at the rewritten rules
+    let tok_env = get_parser_factory().tok_env().clone();
+    let (is_err, msgs) = GrammarInit::Serialized(TopLevelGrammar::from_lark(grm.to_string()))
+        .validate(Some(tok_env), ParserLimits::default())
+        .into_tuple();
+    assert!(!is_err);
+    assert_eq!(
+        msgs,
+        vec![
+            "at 4(16): rewrote right-recursive rule \"items\" into left-recursive form",
+            "at 3(15): rewrote right-recursive rule \"list\" into left-recursive form"
+        ]
+    );
+
+    // not tail-recursive
+    assert!(lark_warnings("start: \"(\" start \")\" | \"x\"").is_empty());
+}
+
 #[test]
 fn test_lexeme_substring_general() {
     for grm in &[
@@ -2085,3 +2378,118 @@ fn test_lark_hex_escape() {
     // a malformed \xZZ (non-hex digits) must be rejected at grammar-compile time.
     lark_err_test(r#"start: "\xZZ""#, "lexer error");
 }
+
+fn diagnostics(grm: TopLevelGrammar) -> Vec<Diagnostic> {
+    let tok_env = get_parser_factory().tok_env().clone();
+    let res = GrammarInit::Serialized(grm).validate(Some(tok_env), ParserLimits::default());
+    let diags = res.diagnostics();
+    let (_, msgs) = res.into_tuple();
+    let rendered: Vec<String> = diags.iter().map(|d| d.to_string()).collect();
+    assert_eq!(rendered, msgs);
+    diags
+}
+
+#[test]
+fn test_grammar_diagnostics() {
+    let src = "start: foo\nfoo: \"a\" <[200 - 100]>\n";
+    let d = diagnostics(TopLevelGrammar::from_lark(src.to_string()));
+    assert_eq!(d.len(), 1);
+    assert_eq!(d[0].severity, Severity::Error);
+    assert_eq!(d[0].code, "lexer_error");
+    assert_eq!(d[0].message, "lexer error");
+    let span = d[0].span.as_ref().unwrap();
+    assert_eq!((span.line, span.column), (2, 15));
+    assert_eq!(&src[span.start..span.end], " ");
+    assert!(d[0].notes[0].contains("   2 | foo: \"a\" <[200 - 100]>"));
+
+    let src = "start: foo\nfoo: \"a\"\n    | bar \"b\"\n";
+    let d = diagnostics(TopLevelGrammar::from_lark(src.to_string()));
+    assert_eq!(d[0].code, "unknown_name");
+    assert_eq!(d[0].message, "unknown name: \"bar\"");
+    let span = d[0].span.as_ref().unwrap();
+    assert_eq!((span.line, span.column), (3, 7));
+    assert_eq!(&src[span.start..span.end], "bar");
+
+    let d = diagnostics(TopLevelGrammar::from_gbnf(
+        "root ::= \"a\" x\nx ::= [é-a]".to_string(),
+    ));
+    assert_eq!(d[0].code, "source");
+    let span = d[0].span.as_ref().unwrap();
+    assert_eq!((span.line, span.column, span.start), (2, 12, 26));
+
+    let d = diagnostics(TopLevelGrammar::from_json_schema(json!({
+        "properties": {"a/b": {"items": [{"type": "foo"}]}}
+    })));
+    assert_eq!(d[0].code, "invalid_type");
+    assert_eq!(d[0].message, "Invalid type: foo");
+    assert_eq!(
+        d[0].json_pointer.as_deref(),
+        Some("/properties/a~1b/items/0")
+    );
+    assert!(d[0].span.is_none());
+
+    let d = diagnostics(TopLevelGrammar::from_json_schema(json!({
+        "$defs": {"x/y": {"anyOf": [true, {"type": "bar"}]}},
+        "properties": {"a": {"$ref": "#/$defs/x~1y"}}
+    })));
+    assert_eq!(d[0].message, "Invalid type: bar");
+    assert_eq!(d[0].json_pointer.as_deref(), Some("/$defs/x~1y/anyOf/1"));
+
+    let d = diagnostics(TopLevelGrammar::from_json_schema(json!({
+        "$defs": {"a": {"oneOf": [{"type": "string"}, {"type": "string", "minLength": 1}]}},
+        "properties": {"a": {"$ref": "#/$defs/a"}}
+    })));
+    assert!(d[0]
+        .message
+        .starts_with("oneOf constraints are not supported"));
+    assert_eq!(d[0].json_pointer.as_deref(), Some("/$defs/a"));
+
+    let d = diagnostics(TopLevelGrammar::from_lark(
+        "start: foo\nstart: \"y\"\nfoo: \"x\"".to_string(),
+    ));
+    assert_eq!(d[0].code, "duplicate_rule");
+
+    let src = "%llguidance {\"auto_terminals\": true}\nstart: foo\nfoo: \"x\"";
+    let d = diagnostics(TopLevelGrammar::from_lark(src.to_string()));
+    assert_eq!(d.len(), 1);
+    assert_eq!(d[0].severity, Severity::Warning);
+    assert_eq!(d[0].code, "auto_terminals");
+    assert_eq!(
+        d[0].message,
+        "auto_terminals: compiled rule \"foo\" as a terminal"
+    );
+    let span = d[0].span.as_ref().unwrap();
+    assert_eq!((span.line, span.column), (3, 6));
+    assert_eq!(&src[span.start..span.end], "\"x\"");
+
+    let json = serde_json::to_value(&d[0]).unwrap();
+    assert_eq!(json["severity"], "warning");
+    assert_eq!(json["span"]["line"], 3);
+    assert!(json.get("json_pointer").is_none());
+
+    // keywords ignored in lenient mode are reported where they are
+    let d = diagnostics(TopLevelGrammar::from_json_schema(json!({
+        "x-guidance": {"lenient": true},
+        "properties": {
+            "a": {"type": "string", "format": "foo"},
+            "b": {"type": "string", "not": {"const": "x"}}
+        }
+    })));
+    assert_eq!(d.len(), 2);
+    assert!(d.iter().all(|d| d.severity == Severity::Warning));
+    assert_eq!(d[0].code, "unsupported_keyword");
+    assert_eq!(d[0].message, "Unimplemented keys: [\"not\"]");
+    assert_eq!(d[0].json_pointer.as_deref(), Some("/properties/b"));
+    assert_eq!(d[1].code, "unknown_format");
+    assert_eq!(d[1].json_pointer.as_deref(), Some("/properties/a"));
+    assert!(d[1].span.is_none());
+
+    let d = diagnostics(TopLevelGrammar::from_json_schema(json!({
+        "x-guidance": {"coerce_one_of": true},
+        "$defs": {"a": {"oneOf": [{"type": "string"}, {"type": "string", "minLength": 1}]}},
+        "properties": {"a": {"$ref": "#/$defs/a"}}
+    })));
+    assert_eq!(d.len(), 1);
+    assert_eq!(d[0].code, "one_of_as_any_of");
+    assert_eq!(d[0].json_pointer.as_deref(), Some("/$defs/a"));
+}
@@ -0,0 +1,217 @@
+use std::sync::Arc;
+
+use llguidance::{
+    api::{GrammarInit, ParserLimits, TopLevelGrammar},
+    toktrie::ApproximateTokEnv,
+    DirImportResolver, ImportResolver, MemImportResolver, ParserFactory, TokenParser,
+};
+
+fn library() -> MemImportResolver {
+    let mut lib = MemImportResolver::new();
+    lib.insert(
+        "lib.json",
+        r#"
+            value: object | array | STRING | NUMBER
+            object: "{" [pair ("," pair)*] "}"
+            pair: STRING ":" value
+            array: "[" [value ("," value)*] "]"
+            STRING: "\"" /[a-z]*/ "\""
+            NUMBER: DIGIT+
+            DIGIT: /[0-9]/
+            %ignore " "
+        "#,
+    )
+    .insert(
+        "lib.dates",
+        r#"
+            date: YEAR "-" MONTH "-" DAY
+            YEAR: /[0-9]{4}/
+            MONTH: /0[1-9]|1[0-2]/
+            DAY: /[0-2][0-9]|3[01]/
+            %import .util (SEP)
+        "#,
+    )
+    .insert("lib.util", r#"SEP: ",""#)
+    .insert(
+        "lib.list",
+        r#"
+            sep_list{item}: item ("," item)*
+        "#,
+    )
+    .insert("cycle.a", "%import cycle.b (b)\na: b")
+    .insert("cycle.b", "%import cycle.a (a)\nb: a");
+    lib
+}
+
+fn make_factory(resolver: Arc<dyn ImportResolver>) -> ParserFactory {
+    let mut fact = ParserFactory::new_simple(&ApproximateTokEnv::single_byte_env()).unwrap();
+    fact.quiet();
+    fact.set_import_resolver(resolver);
+    fact
+}
+
+fn accepts(fact: &ParserFactory, mut parser: TokenParser, input: &str) -> bool {
+    parser.start_without_prompt();
+    for tok in fact.tok_env().tokenize(input) {
+        let m = parser.compute_mask().unwrap();
+        if !m.is_allowed(tok) {
+            return false;
+        }
+        let n = parser.consume_token(tok).unwrap();
+        assert_eq!(n, 0);
+    }
+    parser.is_accepting()
+}
+
+fn check(fact: &ParserFactory, lark: &str, passing: &[&str], failing: &[&str]) {
+    let grm = TopLevelGrammar::from_lark(lark.to_string());
+    for s in passing {
+        let p = fact.create_parser(grm.clone()).unwrap();
+        assert!(accepts(fact, p, s), "should accept {s:?}");
+    }
+    for s in failing {
+        let p = fact.create_parser(grm.clone()).unwrap();
+        assert!(!accepts(fact, p, s), "should reject {s:?}");
+    }
+}
+
+fn check_err(fact: &ParserFactory, lark: &str, msg: &str) {
+    let e = fact
+        .create_parser(TopLevelGrammar::from_lark(lark.to_string()))
+        .err()
+        .unwrap()
+        .to_string();
+    assert!(e.contains(msg), "expected {msg:?} in {e:?}");
+}
+
+#[test]
+fn test_import_names() {
+    let fact = make_factory(Arc::new(library()));
+
+    check(
+        &fact,
+        r#"
+            %import lib.json (value, STRING)
+            start: STRING "=" value
+        "#,
+        &[r#""a"={"b":[1,2]}"#, r#""a"=12"#],
+        &[r#""a"={"b":}"#, "a=1"],
+    );
+
+    // the module's own helpers don't clash with local definitions
+    check(
+        &fact,
+        r#"
+            %import lib.json.value -> json
+            start: json ";" NUMBER
+            NUMBER: /[a-z]+/
+        "#,
+        &["[1];abc"],
+        &["[1];12"],
+    );
+
+    // separate imports from the same module
+    check(
+        &fact,
+        r#"
+            %import lib.json (array)
+            %import lib.json (object)
+            start: array | object
+        "#,
+        &["[{}]", "{\"a\":[]}"],
+        &["12"],
+    );
+
+    // nested and relative imports, templates
+    check(
+        &fact,
+        r#"
+            %import lib.dates (date, SEP)
+            %import lib.list (sep_list)
+            start: date SEP sep_list{date}
+        "#,
+        &["2024-01-31,2024-12-01", "2024-01-31,2024-12-01,2025-02-28"],
+        &["2024-13-01,2024-12-01"],
+    );
+}
+
+#[test]
+fn test_import_errors() {
+    let fact = make_factory(Arc::new(library()));
+    check_err(
+        &fact,
+        "%import lib.json (foo)\nstart: foo",
+        "\"foo\" not found in module \"lib.json\"",
+    );
+    check_err(
+        &fact,
+        "%import lib.nope (foo)\nstart: foo",
+        "module \"lib.nope\" not found",
+    );
+    check_err(
+        &fact,
+        "%import cycle.a (a)\nstart: a",
+        "circular import: cycle.a -> cycle.b -> cycle.a",
+    );
+    check_err(
+        &fact,
+        "%import lib.json (value)\nstart: value\nvalue: \"x\"",
+        "duplicate rule",
+    );
+
+    let fact = ParserFactory::new_simple(&ApproximateTokEnv::single_byte_env()).unwrap();
+    check_err(
+        &fact,
+        "%import lib.json (value)\nstart: value",
+        "no import resolver set",
+    );
+}
+
+#[test]
+fn test_import_validate() {
+    let fact = make_factory(Arc::new(library()));
+    let validate = |lark: &str, resolver: Option<Arc<dyn ImportResolver>>| {
+        GrammarInit::Serialized(TopLevelGrammar::from_lark(lark.to_string()))
+            .validate_ext(
+                Some(fact.tok_env().clone()),
+                ParserLimits::default(),
+                resolver,
+            )
+            .into_tuple()
+    };
+
+    let lark = "%import lib.json (value)\nstart: value";
+    assert_eq!(validate(lark, fact.import_resolver()), (false, vec![]));
+    let (is_err, msgs) = validate(lark, None);
+    assert!(is_err);
+    assert!(msgs[0].contains("no import resolver set"), "{msgs:?}");
+
+    let (is_err, msgs) = validate("%import lib.nope (foo)\nstart: foo", fact.import_resolver());
+    assert!(is_err);
+    assert!(
+        msgs[0].contains("module \"lib.nope\" not found"),
+        "{msgs:?}"
+    );
+}
+
+#[test]
+fn test_import_dir() {
+    let dir = std::env::temp_dir().join(format!("llg_import_{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    std::fs::write(dir.join("lib").join("nums.lark"), "num: /[0-9]+/\n").unwrap();
+
+    let fact = make_factory(Arc::new(DirImportResolver::new(&dir)));
+    check(
+        &fact,
+        "%import lib.nums (num)\nstart: num \"+\" num",
+        &["1+22"],
+        &["1+", "a+1"],
+    );
+    check_err(
+        &fact,
+        "%import lib.other (num)\nstart: num",
+        "module \"lib.other\" not found",
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -2,8 +2,10 @@ use lazy_static::lazy_static;
 use llguidance::{
     api::TopLevelGrammar,
     earley::SlicedBiasComputer,
-    toktrie::{ApproximateTokEnv, InferenceCapabilities, TokEnv, TokenizerEnv},
-    Matcher, ParserFactory, TokenParser,
+    toktrie::{
+        ApproximateTokEnv, InferenceCapabilities, TokEnv, TokRxInfo, TokTrie, TokenId, TokenizerEnv,
+    },
+    BatchMatcher, Matcher, ParserFactory, TokenParser,
 };
 use serde_json::{json, Value};
 use std::sync::Arc;
@@ -437,3 +439,609 @@ fn test_multi_eos_validate_tokens() {
         "extra EOS must also be accepted by validate_tokens"
     );
 }
+
+#[test]
+fn test_compute_masks_for_draft() {
+    let tok_env = ApproximateTokEnv::single_byte_env();
+    let factory = ParserFactory::new_simple(&tok_env).unwrap();
+    let grm = TopLevelGrammar::from_lark(r#"start: "ab" /[0-9]/ | "ac""#.to_string());
+    let mut parser = factory.create_parser(grm).unwrap();
+    parser.start_without_prompt();
+    let mut matcher = Matcher::new(Ok(parser));
+    let eos = tok_env.tok_trie().eos_token();
+    let tok = |b: u8| b as TokenId;
+
+    // all draft tokens accepted, one mask per position
+    let masks = matcher
+        .compute_masks_for_draft(&[tok(b'a'), tok(b'b')])
+        .unwrap();
+    assert_eq!(masks.len(), 3);
+    assert!(masks[0].is_allowed(tok(b'a')) && !masks[0].is_allowed(tok(b'b')));
+    assert!(masks[1].is_allowed(tok(b'b')) && masks[1].is_allowed(tok(b'c')));
+    assert!(masks[2].is_allowed(tok(b'7')) && !masks[2].is_allowed(tok(b'c')));
+
+    // state is rolled back
+    assert_eq!(matcher.compute_mask().unwrap(), masks[0]);
+    matcher.consume_token(tok(b'a')).unwrap();
+
+    // stop at first rejected token
+    let masks = matcher
+        .compute_masks_for_draft(&[tok(b'b'), tok(b'x'), tok(b'1')])
+        .unwrap();
+    assert_eq!(masks.len(), 2);
+
+    // stop when the parser stops; the last mask only allows EOS
+    let masks = matcher
+        .compute_masks_for_draft(&[tok(b'c'), tok(b'd')])
+        .unwrap();
+    assert_eq!(masks.len(), 2);
+    assert!(masks[1].is_allowed(eos) && !masks[1].is_allowed(tok(b'd')));
+
+    assert!(!matcher.is_stopped());
+    matcher.consume_tokens(&[tok(b'b'), tok(b'1')]).unwrap();
+    assert!(matcher.is_stopped());
+}
+
+#[test]
+fn test_compute_masks_for_draft_error() {
+    let tok_env = ApproximateTokEnv::single_byte_env();
+    let mut factory = ParserFactory::new_simple(&tok_env).unwrap();
+    factory.limits_mut().max_items_in_row = 5;
+    let grm = TopLevelGrammar::from_lark(
+        r#"start: "a" "b" x
+           x: "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8""#
+            .to_string(),
+    );
+    let new_matcher = || {
+        let mut parser = factory.create_parser(grm.clone()).unwrap();
+        parser.start_without_prompt();
+        Matcher::new(Ok(parser))
+    };
+    let mut matcher = new_matcher();
+    let tok = |b: u8| b as TokenId;
+
+    // the mask after "a" fails; the masks before the error are kept, and the original
+    // error is reported (not the one from rolling back the draft tokens)
+    let mut masks = vec![];
+    let err = matcher
+        .compute_masks_for_draft_into(&[tok(b'a'), tok(b'b'), tok(b'1')], &mut masks)
+        .unwrap_err();
+    assert!(err.to_string().contains("Current row has 9 items"), "{err}");
+    assert_eq!(masks.len(), 1);
+    assert!(masks[0].is_allowed(tok(b'a')) && !masks[0].is_allowed(tok(b'b')));
+    assert!(matcher.is_error());
+
+    let mut matcher = new_matcher();
+    let n_words = tok_env.tok_trie().vocab_size().div_ceil(32);
+    let mut out = vec![u32::MAX; 3 * n_words];
+    let draft = [tok(b'a'), tok(b'b')];
+    let r =
+        BatchMatcher::new().compute_masks_for_draft_each(vec![(&mut matcher, &draft, &mut out)]);
+    assert_eq!(r[0].0, 1);
+    assert!(r[0].1.is_err());
+    assert_eq!(&out[..n_words], masks[0].as_slice());
+    assert!(out[n_words..2 * n_words].iter().all(|&w| w == 0));
+}
+
+#[test]
+fn test_batch_matcher() {
+    let tok_env = ApproximateTokEnv::single_byte_env();
+    let factory = ParserFactory::new_simple(&tok_env).unwrap();
+    let new_matcher = |lark: &str| {
+        let grm = TopLevelGrammar::from_lark(lark.to_string());
+        let mut parser = factory.create_parser(grm).unwrap();
+        parser.start_without_prompt();
+        Matcher::new(Ok(parser))
+    };
+    let mut matchers = vec![
+        new_matcher(r#"start: "a" /[0-9]+/"#),
+        new_matcher(r#"start: "b""#),
+        new_matcher(r#"start: /[xy]+/"#),
+    ];
+    let eos = tok_env.tok_trie().eos_token();
+    let n_words = tok_env.tok_trie().vocab_size().div_ceil(32);
+    let is_allowed = |masks: &[u32], row: usize, tok: TokenId| {
+        let tok = tok as usize;
+        masks[row * n_words + tok / 32] & (1 << (tok % 32)) != 0
+    };
+    let batch = BatchMatcher::with_num_threads(2).unwrap();
+
+    let mut masks = vec![0u32; matchers.len() * n_words];
+    let r = batch.compute_masks(&mut matchers, &mut masks);
+    assert!(r.iter().all(|r| r.is_ok()));
+    assert!(is_allowed(&masks, 0, b'a' as TokenId) && !is_allowed(&masks, 0, b'b' as TokenId));
+    assert!(is_allowed(&masks, 1, b'b' as TokenId) && !is_allowed(&masks, 1, b'a' as TokenId));
+    assert!(is_allowed(&masks, 2, b'y' as TokenId) && !is_allowed(&masks, 2, eos));
+
+    let r = batch.consume_tokens(
+        &mut matchers,
+        &[b'a' as TokenId, b'b' as TokenId, b'a' as TokenId],
+    );
+    assert!(r[0].is_ok() && r[1].is_ok() && r[2].is_err());
+    assert!(matchers[1].is_stopped());
+
+    // EOS is accepted by stopped matchers
+    let r = batch.consume_tokens(&mut matchers[..2], &[b'1' as TokenId, eos]);
+    assert!(r.iter().all(|r| r.is_ok()));
+
+    let r = batch.compute_masks(&mut matchers, &mut masks);
+    assert!(r[0].is_ok() && r[1].is_ok() && r[2].is_err());
+    assert!(matchers[2].is_error());
+    assert!(is_allowed(&masks, 0, b'2' as TokenId) && is_allowed(&masks, 0, eos));
+    assert!(is_allowed(&masks, 1, eos) && !is_allowed(&masks, 1, b'b' as TokenId));
+    assert!(masks[2 * n_words..].iter().all(|&w| w == 0));
+
+    let r = batch.rollback(&mut matchers[..2], &[2, 1]);
+    assert!(r.iter().all(|r| r.is_ok()));
+    assert!(!matchers[1].is_stopped());
+    batch.compute_masks(&mut matchers[..2], &mut masks[..2 * n_words]);
+    assert!(is_allowed(&masks, 0, b'a' as TokenId) && is_allowed(&masks, 1, b'b' as TokenId));
+
+    // size mismatch
+    let r = batch.consume_tokens(&mut matchers, &[b'a' as TokenId]);
+    assert!(r.len() == 3 && r.iter().all(|r| r.is_err()));
+
+    // row length doesn't match the vocabulary
+    let mut short_masks = vec![u32::MAX; 2 * (n_words - 1)];
+    let r = batch.compute_masks(&mut matchers[..2], &mut short_masks);
+    assert!(r.iter().all(|r| r.is_err()));
+    assert!(short_masks.iter().all(|&w| w == 0));
+    assert!(!matchers[0].is_error() && !matchers[1].is_error());
+}
+
+#[test]
+fn test_token_budget() {
+    let tok_env = ApproximateTokEnv::single_byte_env();
+    let factory = ParserFactory::new_simple(&tok_env).unwrap();
+    let grm = TopLevelGrammar::from_lark(
+        r#"
+            start: "[" (NUM ("," NUM)*)? "]"
+            NUM: /[0-9]+/
+        "#
+        .to_string(),
+    );
+    let mut parser = factory.create_parser(grm).unwrap();
+    parser.start_without_prompt();
+    let mut matcher = Matcher::new(Ok(parser));
+    let eos = tok_env.tok_trie().eos_token();
+    let tok = |b: u8| b as TokenId;
+
+    matcher.consume_token(tok(b'[')).unwrap();
+    matcher.set_token_budget(10).unwrap();
+    let mask = matcher.compute_mask().unwrap();
+    assert!(mask.is_allowed(tok(b'1')) && mask.is_allowed(tok(b']')));
+
+    matcher.consume_tokens(b"1,23".map(tok).as_slice()).unwrap();
+    assert_eq!(matcher.remaining_token_budget(), Some(6));
+    let mask = matcher.compute_mask().unwrap();
+    assert!(mask.is_allowed(tok(b'4')) && mask.is_allowed(tok(b',')));
+
+    // only the shortest completion fits once the budget gets tight
+    matcher.consume_tokens(b"4,".map(tok).as_slice()).unwrap();
+    let mask = matcher.compute_mask().unwrap();
+    assert!(mask.is_allowed(tok(b'0')) && !mask.is_allowed(tok(b']')));
+    matcher.consume_token(tok(b'5')).unwrap();
+    let mask = matcher.compute_mask().unwrap();
+    assert!(mask.is_allowed(tok(b']')) && !mask.is_allowed(tok(b'6')));
+
+    // the mask is not empty even when the budget is exceeded
+    matcher.set_token_budget(0).unwrap();
+    let mask = matcher.compute_mask().unwrap();
+    assert!(mask.is_allowed(tok(b']')) && !mask.is_allowed(tok(b',')));
+
+    // rollback gives tokens back to the budget
+    matcher.set_token_budget(2).unwrap();
+    matcher.rollback(5).unwrap();
+    assert_eq!(matcher.remaining_token_budget(), Some(7));
+
+    matcher.consume_tokens(b"1]".map(tok).as_slice()).unwrap();
+    let mask = matcher.compute_mask_or_eos().unwrap();
+    assert!(mask.is_allowed(eos));
+
+    matcher.clear_token_budget();
+    assert_eq!(matcher.remaining_token_budget(), None);
+}
+
+#[test]
+fn test_token_budget_nested() {
+    let tok_env = ApproximateTokEnv::single_byte_env();
+    let factory = ParserFactory::new_simple(&tok_env).unwrap();
+    let grm = TopLevelGrammar::from_lark(r#"start: "(" start? ")""#.to_string());
+    let mut parser = factory.create_parser(grm).unwrap();
+    parser.start_without_prompt();
+    let mut matcher = Matcher::new(Ok(parser));
+    let eos = tok_env.tok_trie().eos_token();
+    let tok = |b: u8| b as TokenId;
+
+    // the output keeps leaving the remembered completion, which must not
+    // keep the budget from being enforced
+    matcher.set_token_budget(30).unwrap();
+    let mut num_tokens = 0;
+    loop {
+        let mask = matcher.compute_mask_or_eos().unwrap();
+        if mask.is_allowed(eos) {
+            break;
+        }
+        let t = if mask.is_allowed(tok(b'(')) {
+            tok(b'(')
+        } else {
+            tok(b')')
+        };
+        matcher.consume_token(t).unwrap();
+        num_tokens += 1;
+    }
+    assert!(num_tokens <= 30 && num_tokens > 20, "{num_tokens}");
+
+    // after rollback, the completion is searched for again
+    matcher.rollback(num_tokens).unwrap();
+    matcher.consume_tokens(b"((((".map(tok).as_slice()).unwrap();
+    matcher.set_token_budget(5).unwrap();
+    let mask = matcher.compute_mask().unwrap();
+    assert!(mask.is_allowed(tok(b')')) && !mask.is_allowed(tok(b'(')));
+}
+
+#[test]
+fn test_token_budget_parametric() {
+    let tok_env = ApproximateTokEnv::single_byte_env();
+    let factory = ParserFactory::new_simple(&tok_env).unwrap();
+    // the estimates ignore conditions on grammar parameters,
+    // so they would say `]` can follow right away
+    for schema in [
+        json!({
+            "type": "array",
+            "items": { "type": "integer" },
+            "contains": { "const": 1 },
+            "minContains": 5
+        }),
+        json!({
+            "type": "array",
+            "items": { "enum": [1, 2, 3] },
+            "uniqueItems": true,
+            "minItems": 3
+        }),
+    ] {
+        let mut parser = factory
+            .create_parser(TopLevelGrammar::from_json_schema(schema.clone()))
+            .unwrap();
+        parser.start_without_prompt();
+        let mut matcher = Matcher::new(Ok(parser));
+        matcher.set_token_budget(12).unwrap();
+        // always pick the last allowed token, which is never the one leading to `]`
+        while !matcher.is_stopped() {
+            let mask = matcher.compute_mask().unwrap();
+            let tok = (0..mask.len() as TokenId)
+                .rev()
+                .find(|&t| mask.is_allowed(t))
+                .unwrap();
+            matcher.consume_token(tok).unwrap();
+        }
+        assert!(matcher.is_accepting().unwrap(), "{schema}");
+        assert!(!matcher.is_error());
+    }
+}
+
+#[test]
+fn test_token_budget_lexer_limits() {
+    let tok_env = ApproximateTokEnv::single_byte_env();
+    let mut factory = ParserFactory::new_simple(&tok_env).unwrap();
+    // the estimate needs all states of this lexeme, which don't fit
+    factory.limits_mut().max_lexer_states = 50;
+    let grm = TopLevelGrammar::from_lark(
+        r#"
+            start: /(a|b)*a(a|b){7}/ "!"
+        "#
+        .to_string(),
+    );
+    let mut parser = factory.create_parser(grm).unwrap();
+    parser.start_without_prompt();
+    let mut matcher = Matcher::new(Ok(parser));
+    let tok = |b: u8| b as TokenId;
+
+    matcher.set_token_budget(1).unwrap();
+    let mask = matcher.compute_mask().unwrap();
+    assert!(mask.is_allowed(tok(b'a')) && mask.is_allowed(tok(b'b')));
+
+    // running out of lexer states is not sticky
+    matcher
+        .consume_tokens(b"abaabbaa".map(tok).as_slice())
+        .unwrap();
+    let mask = matcher.compute_mask().unwrap();
+    assert!(mask.is_allowed(tok(b'!')) && mask.is_allowed(tok(b'b')));
+    matcher.consume_token(tok(b'!')).unwrap();
+    assert!(matcher.is_accepting().unwrap());
+    assert!(!matcher.is_error());
+}
+
+#[test]
+fn test_shortest_completion() {
+    let tok_env = ApproximateTokEnv::single_byte_env();
+    let factory = ParserFactory::new_simple(&tok_env).unwrap();
+    let new_matcher = |grm: TopLevelGrammar| {
+        let mut parser = factory.create_parser(grm).unwrap();
+        parser.start_without_prompt();
+        Matcher::new(Ok(parser))
+    };
+    let consume_str = |matcher: &mut Matcher, s: &str| {
+        let toks = s.bytes().map(|b| b as TokenId).collect::<Vec<_>>();
+        matcher.consume_tokens(&toks).unwrap();
+    };
+
+    let mut matcher = new_matcher(TopLevelGrammar::from_lark(
+        r#"
+            start: "(" start ")" | ID
+            ID: /[a-z]+/
+            %ignore " "
+        "#
+        .to_string(),
+    ));
+    assert_eq!(matcher.shortest_completion().unwrap(), b"a");
+    consume_str(&mut matcher, "(( ");
+    assert_eq!(matcher.shortest_completion().unwrap(), b"a))");
+    consume_str(&mut matcher, "foo");
+    assert_eq!(matcher.shortest_completion().unwrap(), b"))");
+    consume_str(&mut matcher, "))");
+    assert_eq!(matcher.shortest_completion().unwrap(), b"");
+    assert!(matcher.shortest_completion_tokens().unwrap().is_empty());
+
+    let mut matcher = new_matcher(TopLevelGrammar::from_json_schema(json!({
+        "type": "object",
+        "properties": {
+            "name": { "type": "string" },
+            "tags": { "type": "array", "items": { "type": "string" } }
+        },
+        "required": ["name", "tags"],
+        "additionalProperties": false
+    })));
+    consume_str(&mut matcher, r#"{"name": "Jo"#);
+    let completion = matcher.shortest_completion().unwrap();
+    assert_eq!(String::from_utf8(completion).unwrap(), r#"","tags":[]}"#);
+    let tokens = matcher.shortest_completion_tokens().unwrap();
+    matcher.consume_tokens(&tokens).unwrap();
+    assert!(matcher.is_accepting().unwrap());
+    assert!(!matcher.is_error());
+
+    // several properties to go from the start
+    let mut matcher = new_matcher(TopLevelGrammar::from_json_schema(json!({
+        "type": "object",
+        "properties": {
+            "name": { "type": "string" },
+            "tags": {
+                "type": "array",
+                "items": { "enum": ["alpha", "beta"] },
+                "minItems": 2
+            }
+        },
+        "required": ["name", "tags"]
+    })));
+    let completion = matcher.shortest_completion().unwrap();
+    assert_eq!(
+        String::from_utf8(completion).unwrap(),
+        r#"{"name":"","tags":["beta","beta"]}"#
+    );
+
+    let mut grm = TopLevelGrammar::from_lark(r#"start: "abc""#.to_string());
+    grm.max_tokens = Some(2);
+    let mut parser = factory.create_parser(grm).unwrap();
+    parser.start_without_prompt();
+    for b in *b"abc" {
+        let _ = parser.consume_token(b as TokenId);
+    }
+    assert!(parser.stopped() && !parser.is_accepting());
+    let err = parser.shortest_completion().unwrap_err().to_string();
+    assert!(err.contains("non-accepting"), "{err}");
+}
+
+#[test]
+fn test_shortest_completion_parametric() {
+    let tok_env = ApproximateTokEnv::single_byte_env();
+    let factory = ParserFactory::new_simple(&tok_env).unwrap();
+    for (schema, expected) in [
+        (
+            json!({
+                "type": "array",
+                "items": { "type": "integer" },
+                "contains": { "const": 1 },
+                "minContains": 5
+            }),
+            "[1,1,1,1,1]",
+        ),
+        (
+            json!({
+                "type": "array",
+                "items": { "enum": [1, 2, 3] },
+                "uniqueItems": true,
+                "minItems": 3
+            }),
+            "[1,2,3]",
+        ),
+    ] {
+        let mut parser = factory
+            .create_parser(TopLevelGrammar::from_json_schema(schema))
+            .unwrap();
+        parser.start_without_prompt();
+        let mut matcher = Matcher::new(Ok(parser));
+        let completion = matcher.shortest_completion().unwrap();
+        assert_eq!(String::from_utf8(completion).unwrap(), expected);
+        let tokens = matcher.shortest_completion_tokens().unwrap();
+        matcher.consume_tokens(&tokens).unwrap();
+        assert!(matcher.is_accepting().unwrap());
+    }
+}
+
+#[test]
+fn test_shortest_completion_limits() {
+    let tok_env = ApproximateTokEnv::single_byte_env();
+    let mut factory = ParserFactory::new_simple(&tok_env).unwrap();
+    // the search needs all states of this lexeme, which don't fit
+    factory.limits_mut().max_lexer_states = 50;
+    let grm = TopLevelGrammar::from_lark(
+        r#"
+            start: /(a|b)*a(a|b){7}/ "!"
+        "#
+        .to_string(),
+    );
+    let mut parser = factory.create_parser(grm).unwrap();
+    parser.start_without_prompt();
+    let mut matcher = Matcher::new(Ok(parser));
+    let tok = |b: u8| b as TokenId;
+
+    let err = matcher.shortest_completion().unwrap_err().to_string();
+    assert!(err.contains("search limits"), "{err}");
+    assert!(!matcher.is_error());
+
+    // running out of lexer states is not sticky
+    matcher
+        .consume_tokens(b"abaabbaa!".map(tok).as_slice())
+        .unwrap();
+    assert!(matcher.is_accepting().unwrap());
+    assert_eq!(matcher.shortest_completion().unwrap(), b"");
+}
+
+#[test]
+fn test_consume_bytes() {
+    let tok_env = ApproximateTokEnv::single_byte_env();
+    let factory = ParserFactory::new_simple(&tok_env).unwrap();
+    let grm = TopLevelGrammar::from_lark(r#"start: "hello " /[a-z]+/ "!""#.to_string());
+    let mut parser = factory.create_parser(grm).unwrap();
+    parser.start_without_prompt();
+    let mut matcher = Matcher::new(Ok(parser));
+
+    // forced bytes are checked too
+    let _ = matcher.compute_mask().unwrap();
+    assert_eq!(matcher.validate_bytes(b"help").unwrap(), 3);
+    assert_eq!(matcher.validate_bytes(b"hello wor1d").unwrap(), 9);
+    assert_eq!(matcher.validate_bytes(b"hello \xFF").unwrap(), 6);
+
+    assert_eq!(matcher.consume_bytes(b"hello world").unwrap(), 11);
+    assert_eq!(matcher.validate_bytes(b"s!x").unwrap(), 2);
+    assert!(!matcher.is_stopped());
+
+    assert_eq!(matcher.consume_bytes(b"!?").unwrap(), 1);
+    assert!(matcher.is_stopped());
+    assert!(matcher.is_accepting().unwrap());
+    assert_eq!(matcher.consume_bytes(b"!").unwrap(), 0);
+
+    // consumed bytes can be rolled back as tokens
+    matcher.rollback(3).unwrap();
+    assert_eq!(matcher.validate_bytes(b"ld!").unwrap(), 3);
+    assert!(!matcher.is_error());
+}
+
+#[test]
+fn test_consume_bytes_multibyte() {
+    // 'z' is only available as part of "zz"
+    let mut words = (0..=255)
+        .map(|x| if x == b'z' { b"zz".to_vec() } else { vec![x] })
+        .collect::<Vec<_>>();
+    words.push(b"hello".to_vec());
+    words.push(b"wor".to_vec());
+    words.push(b"ld!".to_vec());
+    words.push(b"\xFF<|end|>".to_vec());
+    let info = TokRxInfo::new(words.len() as u32, words.len() as u32 - 1);
+    let tok_env: TokEnv = Arc::new(GreedyTokEnv {
+        trie: TokTrie::from(&info, &words),
+    });
+    let factory = ParserFactory::new_simple(&tok_env).unwrap();
+    let grm = TopLevelGrammar::from_lark(r#"start: "hello " /[a-z]+/ "!""#.to_string());
+    let mut parser = factory.create_parser(grm).unwrap();
+    parser.start_without_prompt();
+    let mut matcher = Matcher::new(Ok(parser));
+
+    // a byte missing from the tokenizer is not an error state,
+    // and nothing is consumed
+    assert!(matcher.consume_bytes(b"hello z").is_err());
+    assert!(!matcher.is_error());
+    assert_eq!(matcher.validate_bytes(b"hello").unwrap(), 5);
+
+    assert_eq!(matcher.consume_bytes(b"hello world!?").unwrap(), 12);
+    assert!(matcher.is_stopped() && matcher.is_accepting().unwrap());
+
+    // "hello", " ", "wor", "ld!"
+    matcher.rollback(2).unwrap();
+    assert_eq!(matcher.validate_bytes(b"world!").unwrap(), 6);
+    assert_eq!(matcher.consume_bytes(b"zzzz!").unwrap(), 5);
+    assert!(matcher.is_accepting().unwrap());
+    assert!(!matcher.is_error());
+}
+
+struct GreedyTokEnv {
+    trie: TokTrie,
+}
+
+impl TokenizerEnv for GreedyTokEnv {
+    fn tok_trie(&self) -> &TokTrie {
+        &self.trie
+    }
+
+    fn tokenize_bytes(&self, s: &[u8]) -> Vec<TokenId> {
+        self.trie.greedy_tokenize(s)
+    }
+
+    fn tokenize_is_canonical(&self) -> bool {
+        true
+    }
+}
+
+#[test]
+fn test_token_healing() {
+    let mut words = (0..=255).map(|x| vec![x]).collect::<Vec<_>>();
+    words.push(b"http".to_vec());
+    words.push(b"://".to_vec());
+    words.push(b":a".to_vec());
+    words.push(b"\xFF<|end|>".to_vec());
+    let info = TokRxInfo::new(words.len() as u32, words.len() as u32 - 1);
+    let tok_env: TokEnv = Arc::new(GreedyTokEnv {
+        trie: TokTrie::from(&info, &words),
+    });
+    let trie = tok_env.tok_trie();
+    let colon_slashes = trie.token_id(b"://").unwrap();
+    let colon_a = trie.token_id(b":a").unwrap();
+
+    let factory = ParserFactory::new(
+        &tok_env,
+        InferenceCapabilities::default(),
+        &SlicedBiasComputer::general_slices(),
+    )
+    .unwrap();
+
+    let prompt = tok_env.tokenize("http:");
+    assert_eq!(prompt, vec![trie.token_id(b"http").unwrap(), b':' as u32]);
+
+    for healing in [false, true] {
+        let make_parser = |start: &str| {
+            let grm = TopLevelGrammar::from_lark(format!(
+                "%llguidance {{ \"token_healing\": {healing} }}\nstart: {start}"
+            ));
+            let mut parser = factory.create_parser(grm).unwrap();
+            let new_prompt = parser.process_prompt(prompt.clone());
+            let mask = parser.compute_mask().unwrap();
+            (new_prompt, mask)
+        };
+
+        // "://" continues into the grammar, so ':' is re-tokenized at the
+        // prompt boundary with or without healing
+        let (new_prompt, mask) = make_parser(r#""//" /[a-z]+/ | /[0-9]+/"#);
+        assert_eq!(new_prompt, prompt[..1]);
+        assert!(mask.is_allowed(b':' as u32));
+        assert!(mask.is_allowed(colon_slashes));
+        assert!(!mask.is_allowed(colon_a));
+        assert!(!mask.is_allowed(b'/' as u32));
+
+        let (new_prompt, mask) = make_parser("/[0-9]+/");
+        if healing {
+            // ':' can be extended into longer tokens, so it is removed
+            // from the prompt and the grammar is forced to re-generate it
+            assert_eq!(new_prompt, prompt[..1]);
+            assert!(mask.is_allowed(b':' as u32));
+            assert!(!mask.is_allowed(colon_a));
+            assert!(!mask.is_allowed(colon_slashes));
+            assert!(!mask.is_allowed(b'1' as u32));
+        } else {
+            // none of the extensions of ':' is allowed by the grammar
+            assert_eq!(new_prompt, prompt);
+            assert!(!mask.is_allowed(b':' as u32));
+            assert!(!mask.is_allowed(colon_slashes));
+            assert!(mask.is_allowed(b'1' as u32));
+        }
+    }
+}
@@ -1,4 +1,4 @@
-from typing import List, Tuple, Mapping, Optional, Sequence, Union, TypedDict, Dict, Any
+from typing import List, Tuple, Mapping, Optional, Sequence, Union, TypedDict, Dict, Any, Literal, overload
 from ._util import TokenId, StopReason
 from ._tokenizer import TokenizerWrapper
 
@@ -273,20 +273,39 @@ class LLMatcher:
         or LLMatcher.parse_validate_message() to parse the message.
         """
 
+    @overload
     @staticmethod
     def validate_grammar_with_warnings(
         grammar: str,
         tokenizer: Optional[LLTokenizer] = None,
         *,
         limits: Optional[LLParserLimits] = None,
+        diagnostics: Literal[False] = False,
     ) -> Tuple[bool, List[str]]:
         """
         Validate the grammar, for example one returned by LLMatcher.grammar_from_*().
         Returns a tuple of (is_error, [list of errors or warnings]).
         If is_error is true, the list will contain exactly one error message.
         If there are no errors, nor warnings, it will return (False, []).
+
+        With diagnostics=True, the errors and warnings are dicts with
+        "severity" ("error" or "warning"), "code", "message",
+        "span" (None, or a dict with "start" and "end" byte offsets,
+        and 1-based "line" and "column" in the grammar source),
+        "json_pointer" into the JSON schema (or None), and "notes" (list of strings).
+        The "code" tells the kind of the diagnostic, like "unknown_name" or "unsupported_keyword".
         """
 
+    @overload
+    @staticmethod
+    def validate_grammar_with_warnings(
+        grammar: str,
+        tokenizer: Optional[LLTokenizer] = None,
+        *,
+        limits: Optional[LLParserLimits] = None,
+        diagnostics: Literal[True],
+    ) -> Tuple[bool, List[Dict[str, Any]]]: ...
+
     @staticmethod
     def grammar_from_json_schema(
         schema: Union[str, Dict[str, Any]],
@@ -340,6 +359,12 @@ class LLMatcher:
         Returns an empty list if there are no warnings.
         """
 
+    def get_grammar_diagnostics(self) -> List[Dict[str, Any]]:
+        """
+        Get the warnings about the grammar, and the error if any, as dicts;
+        see validate_grammar_with_warnings(..., diagnostics=True).
+        """
+
     def deep_copy(self) -> "LLMatcher":
         """
         Create a deep copy of the matcher.
@@ -383,6 +408,20 @@ class LLMatcher:
         Compute and return the forced bytes available in the current state.
         """
 
+    def shortest_completion(self) -> Optional[bytes]:
+        """
+        Return a shortest byte string that completes the grammar from the current state
+        (empty if the matcher is accepting), e.g., to finish a truncated output.
+        If the search gives up, a short, but not always shortest, completion is returned.
+        Returns None if there is no completion: the matcher is in an error state,
+        stopped in a non-accepting state, or no completion is found within search limits.
+        """
+
+    def shortest_completion_tokens(self) -> Optional[List[TokenId]]:
+        """
+        Like shortest_completion(), but tokenized.
+        """
+
     def try_consume_tokens(self, tokens: List[TokenId]) -> int:
         """
         Try consuming a list of tokens and return how many were successfully consumed.
@@ -407,6 +446,23 @@ class LLMatcher:
         Check how many of the tokens in the list can be committed in the current state.
         """
 
+    def validate_bytes(self, data: bytes) -> int:
+        """
+        Return the length of the longest prefix of data that can be committed
+        in the current state. The bytes do not need to come from the tokenizer.
+        """
+
+    def consume_bytes(self, data: bytes) -> int:
+        """
+        Consume the longest valid prefix of data and return its length.
+        The bytes are recorded as tokens of a greedy tokenization (e.g., for rollback()),
+        and only tokens allowed by the grammar are consumed, so the result may be
+        shorter than validate_bytes(); the remaining bytes can be passed again.
+        Returns 0 if the matcher is in an error state (or enters one; check is_error()),
+        or if the prefix can't be represented as tokens of the vocabulary,
+        in which case nothing is consumed and the matcher stays usable.
+        """
+
     def compute_bitmask(self) -> bytes:
         """
         Compute the token mask, with one bit per tokenizer word, for the next parsing step.
@@ -626,6 +682,10 @@ class JsonCompileOptions(TypedDict, total=False):
     json_allowed_escapes: Optional[str]
     # permit printable Unicode escapes in strings without regex constraints
     json_allow_general_unicode_escapes: Optional[bool]
+    # "fixed" (default) - properties in schema order; "any" - any order
+    property_order: Optional[Literal["fixed", "any"]]
+    # custom string formats: format name -> regex; checked before built-in formats
+    formats: Optional[Dict[str, str]]
 
 
 class LLParserLimits:
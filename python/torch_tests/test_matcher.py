@@ -105,6 +105,23 @@ def test_json() -> None:
                   ["{}", "FINAL_REJECT:{", ' {"foo":1}', '{"bar":1}'])
 
 
+def test_json_custom_formats() -> None:
+    schema = {"type": "string", "format": "sku"}
+    grm = LLMatcher.grammar_from_json_schema(
+        schema, {"formats": {
+            "sku": r"[A-Z]{3}-[0-9]{4}",
+            "uuid": r"[0-9]+"
+        }})
+    check_grammar(grm, ['"ABC-1234"'], ['"abc-1234"', '"ABC-123"'])
+
+    schema = {"type": "string", "format": "uuid"}
+    grm = LLMatcher.grammar_from_json_schema(
+        schema, overrides={"formats": {
+            "uuid": r"[0-9]+"
+        }})
+    check_grammar(grm, ['"123"'], ['"abc"'])
+
+
 def test_lark() -> None:
     check_grammar(
         'start: /.../ "abc" /.../',
@@ -517,6 +534,38 @@ def test_try_consume_tokens() -> None:
     assert m.is_stopped() and m.is_accepting() and not m.is_error()
 
 
+def test_consume_bytes() -> None:
+    m = LLMatcher(tokenizer(), "start: /(foo[12]23|bar)/")
+    assert m.validate_bytes(b"foo723") == 3
+    assert m.consume_bytes(b"foo723") == 3
+    assert not m.is_stopped() and not m.is_error()
+    assert m.consume_bytes(b"123!") == 3
+    assert m.is_stopped() and m.is_accepting()
+    assert m.consume_bytes(b"x") == 0
+    m.rollback(2)
+    assert m.validate_bytes(b"23") == 2
+    assert not m.is_error()
+
+    m.consume_token(tokenizer().vocab_size + 100)
+    assert m.is_error()
+    assert m.consume_bytes(b"23") == 0
+    assert m.is_error()
+
+
+def test_shortest_completion() -> None:
+    m = LLMatcher(tokenizer(), "start: /(foo[12]23|bar)/")
+    assert m.consume_bytes(b"foo") == 3
+    assert m.shortest_completion() in (b"123", b"223")
+    assert m.consume_bytes(b"123") == 3
+    assert m.shortest_completion() == b""
+    assert m.shortest_completion_tokens() == []
+
+    m.consume_token(tokenizer().vocab_size + 100)
+    assert m.is_error()
+    assert m.shortest_completion() is None
+    assert m.shortest_completion_tokens() is None
+
+
 def test_consume_token_error() -> None:
     m = LLMatcher(tokenizer(), "start: /(foo[12]23|bar)/")
     m2 = m.deep_copy()
@@ -667,6 +716,37 @@ def test_grammar_warnings() -> None:
                             }})
 
 
+def test_grammar_diagnostics() -> None:
+    grm = "start: foo\nfoo: \"a\"\n    | bar \"b\"\n"
+    is_err, diags = LLMatcher.validate_grammar_with_warnings(grm,
+                                                             diagnostics=True)
+    assert is_err
+    assert diags[0]["severity"] == "error"
+    assert diags[0]["code"] == "unknown_name"
+    assert diags[0]["message"] == 'unknown name: "bar"'
+    span = diags[0]["span"]
+    assert (span["line"], span["column"]) == (3, 7)
+    assert grm[span["start"]:span["end"]] == "bar"
+    assert diags[0]["json_pointer"] is None
+
+    m = LLMatcher(tokenizer(), grm)
+    assert m.is_error()
+    assert m.get_grammar_diagnostics()[0]["span"] == span
+
+    grm = LLMatcher.grammar_from_json_schema(
+        {"properties": {
+            "a": {
+                "type": "foo"
+            }
+        }})
+    is_err, diags = LLMatcher.validate_grammar_with_warnings(grm,
+                                                             diagnostics=True)
+    assert is_err
+    assert diags[0]["code"] == "invalid_type"
+    assert diags[0]["json_pointer"] == "/properties/a"
+    assert diags[0]["span"] is None
+
+
 def test_get_capture() -> None:
     grm = r"""start: "hello " group1 group2+ group3 " end"
 group1[capture,lazy]: /[a-z]+/
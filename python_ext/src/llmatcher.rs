@@ -3,11 +3,11 @@ use std::fmt::Display;
 use std::ops::DerefMut;
 
 use anyhow::Result;
-use llguidance::api::GrammarInit;
 use llguidance::api::TopLevelGrammar;
+use llguidance::api::{GrammarInit, ValidationResult};
 use llguidance::toktrie::{InferenceCapabilities, SimpleVob, TokEnv, TokenId};
-use llguidance::{json_merge, Logger, Matcher, ParserFactory};
-use pyo3::types::{PyList, PyTuple};
+use llguidance::{json_merge, BatchMatcher, Diagnostic, Logger, Matcher, ParserFactory, Severity};
+use pyo3::types::{PyDict, PyList, PyTuple};
 use pyo3::{exceptions::PyValueError, prelude::*};
 use serde_json::json;
 
@@ -24,7 +24,7 @@ struct LLMatcher {
 
 #[pyclass(frozen)]
 struct LLExecutor {
-    pool: rayon::ThreadPool,
+    batch: BatchMatcher,
 }
 
 #[pymethods]
@@ -37,11 +37,8 @@ impl LLExecutor {
             // by default run on 80% of available threads but not more than 32
             (n * 80 / 100).clamp(1, 32)
         });
-        let pool = rayon::ThreadPoolBuilder::new()
-            .num_threads(num_threads)
-            .build()
-            .map_err(val_error)?;
-        Ok(LLExecutor { pool })
+        let batch = BatchMatcher::with_num_threads(num_threads).map_err(val_error)?;
+        Ok(LLExecutor { batch })
     }
 
     fn unsafe_compute_mask_ptr(
@@ -80,22 +77,22 @@ impl LLExecutor {
             );
         }
 
-        let mut_refs2: Vec<_> = mut_refs
+        let mut rows: Vec<_> = mut_refs
             .iter_mut()
-            .map(|(x, idx)| (x.deref_mut(), *idx))
+            .map(|(x, idx)| (x.deref_mut(), trg_ptr + *idx * one_mask_bytes))
             .collect();
 
-        use rayon::prelude::*;
-
         py.detach(|| {
-            self.pool.install(|| {
-                mut_refs2.into_par_iter().for_each(|(interp, idx)| {
-                    interp.unsafe_compute_mask_ptr_inner(
-                        trg_ptr + idx * one_mask_bytes,
-                        one_mask_bytes,
-                    )
-                })
-            })
+            let results = self.batch.compute_masks_each(
+                rows.iter_mut()
+                    .map(|(m, ptr)| (&mut m.inner, unsafe { mask_slice(*ptr, one_mask_bytes) }))
+                    .collect(),
+            );
+            for ((m, ptr), r) in rows.iter().zip(results) {
+                if r.is_err() {
+                    write_mask(&m.eos_token_set(), *ptr, one_mask_bytes);
+                }
+            }
         });
 
         Ok(())
@@ -130,6 +127,9 @@ impl LLExecutor {
             if draft_tokens.is_empty() {
                 return Err(PyValueError::new_err("Draft tokens must not be empty"));
             }
+            if idx + draft_tokens.len() >= trg_batch_size {
+                return Err(PyValueError::new_err("Target index out of bounds"));
+            }
             interp.validate_mask_ptr(trg_ptr, one_mask_bytes)?;
             mut_refs.push((interp, idx, draft_tokens));
         }
@@ -144,25 +144,38 @@ impl LLExecutor {
             );
         }
 
-        let mut_refs2: Vec<_> = mut_refs
+        let mut rows: Vec<_> = mut_refs
             .iter_mut()
-            .map(|(x, idx, draft_tokens)| (x.deref_mut(), *idx, draft_tokens.clone()))
+            .map(|(x, idx, draft_tokens)| {
+                (
+                    x.deref_mut(),
+                    trg_ptr + *idx * one_mask_bytes,
+                    std::mem::take(draft_tokens),
+                )
+            })
             .collect();
 
-        use rayon::prelude::*;
-
         py.detach(|| {
-            self.pool.install(|| {
-                mut_refs2
-                    .into_par_iter()
-                    .for_each(|(interp, idx, draft_tokens)| {
-                        interp.unsafe_compute_mask_ptr_inner_with_draft_tokens(
-                            trg_ptr + idx * one_mask_bytes,
-                            one_mask_bytes,
-                            draft_tokens,
-                        );
+            let results = self.batch.compute_masks_for_draft_each(
+                rows.iter_mut()
+                    .map(|(m, ptr, draft)| {
+                        let n_bytes = (draft.len() + 1) * one_mask_bytes;
+                        (&mut m.inner, &draft[..], unsafe {
+                            mask_slice(*ptr, n_bytes)
+                        })
                     })
-            })
+                    .collect(),
+            );
+            for ((m, ptr, draft), (n_rows, r)) in rows.iter().zip(results) {
+                // the row where the error happened allows EOS
+                if r.is_err() && n_rows <= draft.len() {
+                    write_mask(
+                        &m.eos_token_set(),
+                        *ptr + n_rows * one_mask_bytes,
+                        one_mask_bytes,
+                    );
+                }
+            }
         });
 
         Ok(())
@@ -193,23 +206,14 @@ impl LLExecutor {
             return Ok(vec![interp.consume_token(token)]);
         }
 
-        let mut_refs2: Vec<_> = mut_refs
+        let items: Vec<_> = mut_refs
             .iter_mut()
-            .map(|(x, token)| (x.deref_mut(), *token))
+            .map(|(x, token)| (&mut x.deref_mut().inner, *token))
             .collect();
 
-        use rayon::prelude::*;
+        let results = py.detach(|| self.batch.consume_token_each(items));
 
-        let results = py.detach(|| {
-            self.pool.install(|| {
-                mut_refs2
-                    .into_par_iter()
-                    .map(|(interp, token)| interp.consume_token_inner(token))
-                    .collect()
-            })
-        });
-
-        Ok(results)
+        Ok(results.iter().map(|r| r.is_ok()).collect())
     }
 }
 
@@ -230,10 +234,7 @@ impl LLMatcher {
 
     fn unsafe_compute_mask_ptr_inner(&mut self, trg_ptr: usize, trg_bytes: usize) {
         let r = self.compute_mask_or_eos();
-        let trg_slice =
-            unsafe { std::slice::from_raw_parts_mut(trg_ptr as *mut u32, trg_bytes / 4) };
-        let src = r.as_slice();
-        trg_slice.copy_from_slice(&src[0..trg_slice.len()]);
+        write_mask(&r, trg_ptr, trg_bytes);
     }
 
     fn unsafe_compute_mask_ptr_inner_with_draft_tokens(
@@ -242,25 +243,16 @@ impl LLMatcher {
         trg_bytes: usize,
         draft_tokens: Vec<TokenId>,
     ) {
-        let mut state_advancements = 0;
-        let spec_k = draft_tokens.len();
-        #[allow(clippy::needless_range_loop)]
-        for token_idx in 0..=spec_k {
-            self.unsafe_compute_mask_ptr_inner(trg_ptr + token_idx * trg_bytes, trg_bytes);
-
-            if token_idx == spec_k || self.inner.is_stopped() {
-                break;
-            }
-
-            let token = draft_tokens[token_idx];
-
-            match self.inner.try_consume_tokens(&[token]) {
-                Ok(cosumed) if cosumed > 0 => state_advancements += 1,
-                _ => break,
-            }
+        let mut masks = vec![];
+        let r = self
+            .inner
+            .compute_masks_for_draft_into(&draft_tokens, &mut masks);
+        // keep the masks computed before the error, and allow EOS where it happened
+        if r.is_err() && masks.len() <= draft_tokens.len() {
+            masks.push(self.eos_token_set());
         }
-        if state_advancements > 0 {
-            self.rollback(state_advancements);
+        for (idx, mask) in masks.iter().enumerate() {
+            write_mask(mask, trg_ptr + idx * trg_bytes, trg_bytes);
         }
     }
 
@@ -293,6 +285,17 @@ impl LLMatcher {
     }
 }
 
+/// The pointer must be checked with `validate_mask_ptr()`,
+/// and `trg_bytes` bytes must be available there.
+unsafe fn mask_slice<'a>(trg_ptr: usize, trg_bytes: usize) -> &'a mut [u32] {
+    unsafe { std::slice::from_raw_parts_mut(trg_ptr as *mut u32, trg_bytes / 4) }
+}
+
+fn write_mask(mask: &SimpleVob, trg_ptr: usize, trg_bytes: usize) {
+    let trg_slice = unsafe { mask_slice(trg_ptr, trg_bytes) };
+    trg_slice.copy_from_slice(&mask.as_slice()[0..trg_slice.len()]);
+}
+
 fn new_matcher(
     fact: &ParserFactory,
     grammar: TopLevelGrammar,
@@ -365,9 +368,10 @@ impl LLMatcher {
         match extract_grammar(grammar) {
             Ok((_, grammar)) => py.detach(|| {
                 GrammarInit::Serialized(grammar)
-                    .validate(
+                    .validate_ext(
                         tokenizer.map(|t| t.factory().tok_env().clone()),
                         LLParserLimits::from_option(limits),
+                        tokenizer.and_then(|t| t.factory().import_resolver()),
                     )
                     .into_error()
                     .unwrap_or_default()
@@ -377,23 +381,31 @@ impl LLMatcher {
     }
 
     #[staticmethod]
-    #[pyo3(signature = (grammar, tokenizer=None, *, limits=None))]
-    fn validate_grammar_with_warnings(
-        grammar: Bound<'_, PyAny>,
+    #[pyo3(signature = (grammar, tokenizer=None, *, limits=None, diagnostics=false))]
+    fn validate_grammar_with_warnings<'py>(
+        grammar: Bound<'py, PyAny>,
         tokenizer: Option<&LLTokenizer>,
         limits: Option<&LLParserLimits>,
-        py: Python<'_>,
-    ) -> (bool, Vec<String>) {
-        match extract_grammar(grammar) {
+        diagnostics: bool,
+        py: Python<'py>,
+    ) -> PyResult<(bool, Bound<'py, PyList>)> {
+        let res = match extract_grammar(grammar) {
             Ok((_, grammar)) => py.detach(|| {
-                GrammarInit::Serialized(grammar)
-                    .validate(
-                        tokenizer.map(|t| t.factory().tok_env().clone()),
-                        LLParserLimits::from_option(limits),
-                    )
-                    .into_tuple()
+                GrammarInit::Serialized(grammar).validate_ext(
+                    tokenizer.map(|t| t.factory().tok_env().clone()),
+                    LLParserLimits::from_option(limits),
+                    tokenizer.and_then(|t| t.factory().import_resolver()),
+                )
             }),
-            Err(e) => (true, vec![e.to_string()]),
+            Err(e) => ValidationResult::from_error(&e),
+        };
+        if diagnostics {
+            let diags = res.diagnostics();
+            let is_error = diags.iter().any(|d| d.is_error());
+            Ok((is_error, diagnostics_to_list(py, &diags)?))
+        } else {
+            let (is_error, msgs) = res.into_tuple();
+            Ok((is_error, PyList::new(py, msgs)?))
         }
     }
 
@@ -448,6 +460,10 @@ impl LLMatcher {
         self.inner.grammar_warnings()
     }
 
+    fn get_grammar_diagnostics<'py>(&mut self, py: Python<'py>) -> PyResult<Bound<'py, PyList>> {
+        diagnostics_to_list(py, &self.inner.grammar_diagnostics())
+    }
+
     fn deep_copy(&self) -> Self {
         Self {
             inner: self.inner.clone(),
@@ -478,6 +494,14 @@ impl LLMatcher {
         })
     }
 
+    fn validate_bytes(&mut self, data: &[u8]) -> usize {
+        self.inner.validate_bytes(data).unwrap_or(0)
+    }
+
+    fn consume_bytes(&mut self, data: &[u8]) -> usize {
+        self.inner.consume_bytes(data).unwrap_or(0)
+    }
+
     fn unsafe_compute_mask_ptr(
         &mut self,
         trg_ptr: usize,
@@ -547,6 +571,14 @@ impl LLMatcher {
         Cow::Owned(bytes)
     }
 
+    fn shortest_completion(&mut self) -> Option<Cow<'_, [u8]>> {
+        self.inner.shortest_completion().ok().map(Cow::Owned)
+    }
+
+    fn shortest_completion_tokens(&mut self) -> Option<Vec<TokenId>> {
+        self.inner.shortest_completion_tokens().ok()
+    }
+
     fn try_consume_tokens(&mut self, tokens: Vec<TokenId>) -> usize {
         self.inner.try_consume_tokens(&tokens).unwrap_or(0)
     }
@@ -578,6 +610,40 @@ pub(crate) fn init(m: &Bound<'_, PyModule>) -> PyResult<()> {
     Ok(())
 }
 
+/// Convert diagnostics to a list of dicts with the same keys as their JSON form.
+fn diagnostics_to_list<'py>(
+    py: Python<'py>,
+    diagnostics: &[Diagnostic],
+) -> PyResult<Bound<'py, PyList>> {
+    let list = PyList::empty(py);
+    for d in diagnostics {
+        let dict = PyDict::new(py);
+        let severity = match d.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        dict.set_item("severity", severity)?;
+        dict.set_item("code", &d.code)?;
+        dict.set_item("message", &d.message)?;
+        let span = match &d.span {
+            Some(span) => {
+                let span_dict = PyDict::new(py);
+                span_dict.set_item("start", span.start)?;
+                span_dict.set_item("end", span.end)?;
+                span_dict.set_item("line", span.line)?;
+                span_dict.set_item("column", span.column)?;
+                Some(span_dict)
+            }
+            None => None,
+        };
+        dict.set_item("span", span)?;
+        dict.set_item("json_pointer", &d.json_pointer)?;
+        dict.set_item("notes", &d.notes)?;
+        list.append(dict)?;
+    }
+    Ok(list)
+}
+
 fn val_error(e: impl Display) -> PyErr {
     PyValueError::new_err(format!("{e}"))
 }
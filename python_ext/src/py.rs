@@ -8,7 +8,7 @@ use llguidance::toktrie::{
     self, AnythingGoes, ApproximateTokEnv, InferenceCapabilities, TokEnv, TokRxInfo, TokTrie,
     TokenId, TokenizerEnv,
 };
-use llguidance::{HashMap, JsonCompileOptions, ParserFactory};
+use llguidance::{HashMap, JsonCompileOptions, ParserFactory, PropertyOrder};
 use pyo3::{exceptions::PyValueError, prelude::*};
 use serde_json::Value;
 use toktrie_hf_tokenizers::ByteTokenizer;
@@ -428,6 +428,8 @@ impl JsonCompiler {
             lenient: false,
             json_allowed_escapes: self.json_allowed_escapes.clone(),
             json_allow_general_unicode_escapes: self.json_allow_general_unicode_escapes,
+            property_order: PropertyOrder::Fixed,
+            formats: Default::default(),
             retriever: None,
         };
         compile_options.apply_to(&mut schema);